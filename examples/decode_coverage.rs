@@ -0,0 +1,538 @@
+#![allow(non_camel_case_types)]
+
+use std::{
+    collections::BTreeMap,
+    panic::{self, AssertUnwindSafe},
+};
+
+use rvemu_riir::{decode::insn_decode, insn::insn_t, isa::isa_t, random::random_t};
+
+/*
+    check insn_decode against a reference description of the ISA: every
+    16-bit encoding and random samples of the 32-bit ones are decoded and
+    sorted into
+
+      ok        an instruction decoded as the reference says
+      illegal   a reserved encoding decoded as insn_illegal
+      mismatch  an instruction decoded as something else, or panicked
+      accepted  a reserved encoding decoded as an instruction
+      panic     a reserved encoding panicked instead of decoding as illegal
+
+    cargo run --example decode_coverage [-- <random 32-bit samples>]
+
+    V is not in the reference, OP-V and the vector loads and stores are
+    skipped. the exit status is 1 unless everything is ok or illegal.
+*/
+
+/*
+    one instruction per line in the style of riscv-opcodes: the mnemonic,
+    the insn_type_t it decodes to without the insn_ prefix and its encoding.
+    hi..lo=value fixes a bit field, a field list such as 12,6..2!=0 excludes
+    one value of the fields put together, bits not named are operands.
+*/
+const REFERENCE: &str = "
+# RV64I
+lui         lui             6..2=0x0d 1..0=3
+auipc       auipc           6..2=0x05 1..0=3
+jal         jal             6..2=0x1b 1..0=3
+jalr        jalr            14..12=0 6..2=0x19 1..0=3
+beq         beq             14..12=0 6..2=0x18 1..0=3
+bne         bne             14..12=1 6..2=0x18 1..0=3
+blt         blt             14..12=4 6..2=0x18 1..0=3
+bge         bge             14..12=5 6..2=0x18 1..0=3
+bltu        bltu            14..12=6 6..2=0x18 1..0=3
+bgeu        bgeu            14..12=7 6..2=0x18 1..0=3
+lb          lb              14..12=0 6..2=0x00 1..0=3
+lh          lh              14..12=1 6..2=0x00 1..0=3
+lw          lw              14..12=2 6..2=0x00 1..0=3
+ld          ld              14..12=3 6..2=0x00 1..0=3
+lbu         lbu             14..12=4 6..2=0x00 1..0=3
+lhu         lhu             14..12=5 6..2=0x00 1..0=3
+lwu         lwu             14..12=6 6..2=0x00 1..0=3
+sb          sb              14..12=0 6..2=0x08 1..0=3
+sh          sh              14..12=1 6..2=0x08 1..0=3
+sw          sw              14..12=2 6..2=0x08 1..0=3
+sd          sd              14..12=3 6..2=0x08 1..0=3
+addi        addi            14..12=0 6..2=0x04 1..0=3
+slti        slti            14..12=2 6..2=0x04 1..0=3
+sltiu       sltiu           14..12=3 6..2=0x04 1..0=3
+xori        xori            14..12=4 6..2=0x04 1..0=3
+ori         ori             14..12=6 6..2=0x04 1..0=3
+andi        andi            14..12=7 6..2=0x04 1..0=3
+slli        slli            31..26=0x00 14..12=1 6..2=0x04 1..0=3
+srli        srli            31..26=0x00 14..12=5 6..2=0x04 1..0=3
+srai        srai            31..26=0x10 14..12=5 6..2=0x04 1..0=3
+add         add             31..25=0x00 14..12=0 6..2=0x0c 1..0=3
+sub         sub             31..25=0x20 14..12=0 6..2=0x0c 1..0=3
+sll         sll             31..25=0x00 14..12=1 6..2=0x0c 1..0=3
+slt         slt             31..25=0x00 14..12=2 6..2=0x0c 1..0=3
+sltu        sltu            31..25=0x00 14..12=3 6..2=0x0c 1..0=3
+xor         xor             31..25=0x00 14..12=4 6..2=0x0c 1..0=3
+srl         srl             31..25=0x00 14..12=5 6..2=0x0c 1..0=3
+sra         sra             31..25=0x20 14..12=5 6..2=0x0c 1..0=3
+or          or              31..25=0x00 14..12=6 6..2=0x0c 1..0=3
+and         and             31..25=0x00 14..12=7 6..2=0x0c 1..0=3
+addiw       addiw           14..12=0 6..2=0x06 1..0=3
+slliw       slliw           31..25=0x00 14..12=1 6..2=0x06 1..0=3
+srliw       srliw           31..25=0x00 14..12=5 6..2=0x06 1..0=3
+sraiw       sraiw           31..25=0x20 14..12=5 6..2=0x06 1..0=3
+addw        addw            31..25=0x00 14..12=0 6..2=0x0e 1..0=3
+subw        subw            31..25=0x20 14..12=0 6..2=0x0e 1..0=3
+sllw        sllw            31..25=0x00 14..12=1 6..2=0x0e 1..0=3
+srlw        srlw            31..25=0x00 14..12=5 6..2=0x0e 1..0=3
+sraw        sraw            31..25=0x20 14..12=5 6..2=0x0e 1..0=3
+fence       fence           14..12=0 6..2=0x03 1..0=3
+fence.i     fence_i         14..12=1 6..2=0x03 1..0=3
+ecall       ecall           31..20=0x000 19..15=0 14..12=0 11..7=0 6..2=0x1c 1..0=3
+ebreak      ebreak          31..20=0x001 19..15=0 14..12=0 11..7=0 6..2=0x1c 1..0=3
+# Zicsr
+csrrw       csrrw           14..12=1 6..2=0x1c 1..0=3
+csrrs       csrrs           14..12=2 6..2=0x1c 1..0=3
+csrrc       csrrc           14..12=3 6..2=0x1c 1..0=3
+csrrwi      csrrwi          14..12=5 6..2=0x1c 1..0=3
+csrrsi      csrrsi          14..12=6 6..2=0x1c 1..0=3
+csrrci      csrrci          14..12=7 6..2=0x1c 1..0=3
+# M
+mul         mul             31..25=0x01 14..12=0 6..2=0x0c 1..0=3
+mulh        mulh            31..25=0x01 14..12=1 6..2=0x0c 1..0=3
+mulhsu      mulhsu          31..25=0x01 14..12=2 6..2=0x0c 1..0=3
+mulhu       mulhu           31..25=0x01 14..12=3 6..2=0x0c 1..0=3
+div         div             31..25=0x01 14..12=4 6..2=0x0c 1..0=3
+divu        divu            31..25=0x01 14..12=5 6..2=0x0c 1..0=3
+rem         rem             31..25=0x01 14..12=6 6..2=0x0c 1..0=3
+remu        remu            31..25=0x01 14..12=7 6..2=0x0c 1..0=3
+mulw        mulw            31..25=0x01 14..12=0 6..2=0x0e 1..0=3
+divw        divw            31..25=0x01 14..12=4 6..2=0x0e 1..0=3
+divuw       divuw           31..25=0x01 14..12=5 6..2=0x0e 1..0=3
+remw        remw            31..25=0x01 14..12=6 6..2=0x0e 1..0=3
+remuw       remuw           31..25=0x01 14..12=7 6..2=0x0e 1..0=3
+# A
+lr.w        lr_w            31..27=0x02 24..20=0 14..12=2 6..2=0x0b 1..0=3
+sc.w        sc_w            31..27=0x03 14..12=2 6..2=0x0b 1..0=3
+amoswap.w   amoswap_w       31..27=0x01 14..12=2 6..2=0x0b 1..0=3
+amoadd.w    amoadd_w        31..27=0x00 14..12=2 6..2=0x0b 1..0=3
+amoxor.w    amoxor_w        31..27=0x04 14..12=2 6..2=0x0b 1..0=3
+amoand.w    amoand_w        31..27=0x0c 14..12=2 6..2=0x0b 1..0=3
+amoor.w     amoor_w         31..27=0x08 14..12=2 6..2=0x0b 1..0=3
+amomin.w    amomin_w        31..27=0x10 14..12=2 6..2=0x0b 1..0=3
+amomax.w    amomax_w        31..27=0x14 14..12=2 6..2=0x0b 1..0=3
+amominu.w   amominu_w       31..27=0x18 14..12=2 6..2=0x0b 1..0=3
+amomaxu.w   amomaxu_w       31..27=0x1c 14..12=2 6..2=0x0b 1..0=3
+lr.d        lr_d            31..27=0x02 24..20=0 14..12=3 6..2=0x0b 1..0=3
+sc.d        sc_d            31..27=0x03 14..12=3 6..2=0x0b 1..0=3
+amoswap.d   amoswap_d       31..27=0x01 14..12=3 6..2=0x0b 1..0=3
+amoadd.d    amoadd_d        31..27=0x00 14..12=3 6..2=0x0b 1..0=3
+amoxor.d    amoxor_d        31..27=0x04 14..12=3 6..2=0x0b 1..0=3
+amoand.d    amoand_d        31..27=0x0c 14..12=3 6..2=0x0b 1..0=3
+amoor.d     amoor_d         31..27=0x08 14..12=3 6..2=0x0b 1..0=3
+amomin.d    amomin_d        31..27=0x10 14..12=3 6..2=0x0b 1..0=3
+amomax.d    amomax_d        31..27=0x14 14..12=3 6..2=0x0b 1..0=3
+amominu.d   amominu_d       31..27=0x18 14..12=3 6..2=0x0b 1..0=3
+amomaxu.d   amomaxu_d       31..27=0x1c 14..12=3 6..2=0x0b 1..0=3
+# F, D and Zfh, fmt in 26..25 is 0 for s, 1 for d and 2 for h
+flw         flw             14..12=2 6..2=0x01 1..0=3
+fld         fld             14..12=3 6..2=0x01 1..0=3
+flh         flh             14..12=1 6..2=0x01 1..0=3
+fsw         fsw             14..12=2 6..2=0x09 1..0=3
+fsd         fsd             14..12=3 6..2=0x09 1..0=3
+fsh         fsh             14..12=1 6..2=0x09 1..0=3
+fmadd.s     fmadd_s         26..25=0 6..2=0x10 1..0=3
+fmsub.s     fmsub_s         26..25=0 6..2=0x11 1..0=3
+fnmsub.s    fnmsub_s        26..25=0 6..2=0x12 1..0=3
+fnmadd.s    fnmadd_s        26..25=0 6..2=0x13 1..0=3
+fmadd.d     fmadd_d         26..25=1 6..2=0x10 1..0=3
+fmsub.d     fmsub_d         26..25=1 6..2=0x11 1..0=3
+fnmsub.d    fnmsub_d        26..25=1 6..2=0x12 1..0=3
+fnmadd.d    fnmadd_d        26..25=1 6..2=0x13 1..0=3
+fmadd.h     fmadd_h         26..25=2 6..2=0x10 1..0=3
+fmsub.h     fmsub_h         26..25=2 6..2=0x11 1..0=3
+fnmsub.h    fnmsub_h        26..25=2 6..2=0x12 1..0=3
+fnmadd.h    fnmadd_h        26..25=2 6..2=0x13 1..0=3
+fadd.s      fadd_s          31..27=0x00 26..25=0 6..2=0x14 1..0=3
+fsub.s      fsub_s          31..27=0x01 26..25=0 6..2=0x14 1..0=3
+fmul.s      fmul_s          31..27=0x02 26..25=0 6..2=0x14 1..0=3
+fdiv.s      fdiv_s          31..27=0x03 26..25=0 6..2=0x14 1..0=3
+fsqrt.s     fsqrt_s         31..27=0x0b 26..25=0 24..20=0 6..2=0x14 1..0=3
+fsgnj.s     fsgnj_s         31..27=0x04 26..25=0 14..12=0 6..2=0x14 1..0=3
+fsgnjn.s    fsgnjn_s        31..27=0x04 26..25=0 14..12=1 6..2=0x14 1..0=3
+fsgnjx.s    fsgnjx_s        31..27=0x04 26..25=0 14..12=2 6..2=0x14 1..0=3
+fmin.s      fmin_s          31..27=0x05 26..25=0 14..12=0 6..2=0x14 1..0=3
+fmax.s      fmax_s          31..27=0x05 26..25=0 14..12=1 6..2=0x14 1..0=3
+fcvt.w.s    fcvt_w_s        31..27=0x18 26..25=0 24..20=0 6..2=0x14 1..0=3
+fcvt.wu.s   fcvt_wu_s       31..27=0x18 26..25=0 24..20=1 6..2=0x14 1..0=3
+fcvt.l.s    fcvt_l_s        31..27=0x18 26..25=0 24..20=2 6..2=0x14 1..0=3
+fcvt.lu.s   fcvt_lu_s       31..27=0x18 26..25=0 24..20=3 6..2=0x14 1..0=3
+fmv.x.w     fmv_x_w         31..27=0x1c 26..25=0 24..20=0 14..12=0 6..2=0x14 1..0=3
+fclass.s    fclass_s        31..27=0x1c 26..25=0 24..20=0 14..12=1 6..2=0x14 1..0=3
+feq.s       feq_s           31..27=0x14 26..25=0 14..12=2 6..2=0x14 1..0=3
+flt.s       flt_s           31..27=0x14 26..25=0 14..12=1 6..2=0x14 1..0=3
+fle.s       fle_s           31..27=0x14 26..25=0 14..12=0 6..2=0x14 1..0=3
+fcvt.s.w    fcvt_s_w        31..27=0x1a 26..25=0 24..20=0 6..2=0x14 1..0=3
+fcvt.s.wu   fcvt_s_wu       31..27=0x1a 26..25=0 24..20=1 6..2=0x14 1..0=3
+fcvt.s.l    fcvt_s_l        31..27=0x1a 26..25=0 24..20=2 6..2=0x14 1..0=3
+fcvt.s.lu   fcvt_s_lu       31..27=0x1a 26..25=0 24..20=3 6..2=0x14 1..0=3
+fmv.w.x     fmv_w_x         31..27=0x1e 26..25=0 24..20=0 14..12=0 6..2=0x14 1..0=3
+fadd.d      fadd_d          31..27=0x00 26..25=1 6..2=0x14 1..0=3
+fsub.d      fsub_d          31..27=0x01 26..25=1 6..2=0x14 1..0=3
+fmul.d      fmul_d          31..27=0x02 26..25=1 6..2=0x14 1..0=3
+fdiv.d      fdiv_d          31..27=0x03 26..25=1 6..2=0x14 1..0=3
+fsqrt.d     fsqrt_d         31..27=0x0b 26..25=1 24..20=0 6..2=0x14 1..0=3
+fsgnj.d     fsgnj_d         31..27=0x04 26..25=1 14..12=0 6..2=0x14 1..0=3
+fsgnjn.d    fsgnjn_d        31..27=0x04 26..25=1 14..12=1 6..2=0x14 1..0=3
+fsgnjx.d    fsgnjx_d        31..27=0x04 26..25=1 14..12=2 6..2=0x14 1..0=3
+fmin.d      fmin_d          31..27=0x05 26..25=1 14..12=0 6..2=0x14 1..0=3
+fmax.d      fmax_d          31..27=0x05 26..25=1 14..12=1 6..2=0x14 1..0=3
+fcvt.s.d    fcvt_s_d        31..27=0x08 26..25=0 24..20=1 6..2=0x14 1..0=3
+fcvt.d.s    fcvt_d_s        31..27=0x08 26..25=1 24..20=0 6..2=0x14 1..0=3
+feq.d       feq_d           31..27=0x14 26..25=1 14..12=2 6..2=0x14 1..0=3
+flt.d       flt_d           31..27=0x14 26..25=1 14..12=1 6..2=0x14 1..0=3
+fle.d       fle_d           31..27=0x14 26..25=1 14..12=0 6..2=0x14 1..0=3
+fclass.d    fclass_d        31..27=0x1c 26..25=1 24..20=0 14..12=1 6..2=0x14 1..0=3
+fcvt.w.d    fcvt_w_d        31..27=0x18 26..25=1 24..20=0 6..2=0x14 1..0=3
+fcvt.wu.d   fcvt_wu_d       31..27=0x18 26..25=1 24..20=1 6..2=0x14 1..0=3
+fcvt.l.d    fcvt_l_d        31..27=0x18 26..25=1 24..20=2 6..2=0x14 1..0=3
+fcvt.lu.d   fcvt_lu_d       31..27=0x18 26..25=1 24..20=3 6..2=0x14 1..0=3
+fcvt.d.w    fcvt_d_w        31..27=0x1a 26..25=1 24..20=0 6..2=0x14 1..0=3
+fcvt.d.wu   fcvt_d_wu       31..27=0x1a 26..25=1 24..20=1 6..2=0x14 1..0=3
+fcvt.d.l    fcvt_d_l        31..27=0x1a 26..25=1 24..20=2 6..2=0x14 1..0=3
+fcvt.d.lu   fcvt_d_lu       31..27=0x1a 26..25=1 24..20=3 6..2=0x14 1..0=3
+fmv.x.d     fmv_x_d         31..27=0x1c 26..25=1 24..20=0 14..12=0 6..2=0x14 1..0=3
+fmv.d.x     fmv_d_x         31..27=0x1e 26..25=1 24..20=0 14..12=0 6..2=0x14 1..0=3
+fadd.h      fadd_h          31..27=0x00 26..25=2 6..2=0x14 1..0=3
+fsub.h      fsub_h          31..27=0x01 26..25=2 6..2=0x14 1..0=3
+fmul.h      fmul_h          31..27=0x02 26..25=2 6..2=0x14 1..0=3
+fdiv.h      fdiv_h          31..27=0x03 26..25=2 6..2=0x14 1..0=3
+fsqrt.h     fsqrt_h         31..27=0x0b 26..25=2 24..20=0 6..2=0x14 1..0=3
+fsgnj.h     fsgnj_h         31..27=0x04 26..25=2 14..12=0 6..2=0x14 1..0=3
+fsgnjn.h    fsgnjn_h        31..27=0x04 26..25=2 14..12=1 6..2=0x14 1..0=3
+fsgnjx.h    fsgnjx_h        31..27=0x04 26..25=2 14..12=2 6..2=0x14 1..0=3
+fmin.h      fmin_h          31..27=0x05 26..25=2 14..12=0 6..2=0x14 1..0=3
+fmax.h      fmax_h          31..27=0x05 26..25=2 14..12=1 6..2=0x14 1..0=3
+fcvt.s.h    fcvt_s_h        31..27=0x08 26..25=0 24..20=2 6..2=0x14 1..0=3
+fcvt.h.s    fcvt_h_s        31..27=0x08 26..25=2 24..20=0 6..2=0x14 1..0=3
+fcvt.d.h    fcvt_d_h        31..27=0x08 26..25=1 24..20=2 6..2=0x14 1..0=3
+fcvt.h.d    fcvt_h_d        31..27=0x08 26..25=2 24..20=1 6..2=0x14 1..0=3
+feq.h       feq_h           31..27=0x14 26..25=2 14..12=2 6..2=0x14 1..0=3
+flt.h       flt_h           31..27=0x14 26..25=2 14..12=1 6..2=0x14 1..0=3
+fle.h       fle_h           31..27=0x14 26..25=2 14..12=0 6..2=0x14 1..0=3
+fclass.h    fclass_h        31..27=0x1c 26..25=2 24..20=0 14..12=1 6..2=0x14 1..0=3
+fcvt.w.h    fcvt_w_h        31..27=0x18 26..25=2 24..20=0 6..2=0x14 1..0=3
+fcvt.wu.h   fcvt_wu_h       31..27=0x18 26..25=2 24..20=1 6..2=0x14 1..0=3
+fcvt.l.h    fcvt_l_h        31..27=0x18 26..25=2 24..20=2 6..2=0x14 1..0=3
+fcvt.lu.h   fcvt_lu_h       31..27=0x18 26..25=2 24..20=3 6..2=0x14 1..0=3
+fcvt.h.w    fcvt_h_w        31..27=0x1a 26..25=2 24..20=0 6..2=0x14 1..0=3
+fcvt.h.wu   fcvt_h_wu       31..27=0x1a 26..25=2 24..20=1 6..2=0x14 1..0=3
+fcvt.h.l    fcvt_h_l        31..27=0x1a 26..25=2 24..20=2 6..2=0x14 1..0=3
+fcvt.h.lu   fcvt_h_lu       31..27=0x1a 26..25=2 24..20=3 6..2=0x14 1..0=3
+fmv.x.h     fmv_x_h         31..27=0x1c 26..25=2 24..20=0 14..12=0 6..2=0x14 1..0=3
+fmv.h.x     fmv_h_x         31..27=0x1e 26..25=2 24..20=0 14..12=0 6..2=0x14 1..0=3
+# Zba
+sh1add      sh1add          31..25=0x10 14..12=2 6..2=0x0c 1..0=3
+sh2add      sh2add          31..25=0x10 14..12=4 6..2=0x0c 1..0=3
+sh3add      sh3add          31..25=0x10 14..12=6 6..2=0x0c 1..0=3
+add.uw      add_uw          31..25=0x04 14..12=0 6..2=0x0e 1..0=3
+sh1add.uw   sh1add_uw       31..25=0x10 14..12=2 6..2=0x0e 1..0=3
+sh2add.uw   sh2add_uw       31..25=0x10 14..12=4 6..2=0x0e 1..0=3
+sh3add.uw   sh3add_uw       31..25=0x10 14..12=6 6..2=0x0e 1..0=3
+slli.uw     slli_uw         31..26=0x02 14..12=1 6..2=0x06 1..0=3
+# Zbb
+andn        andn            31..25=0x20 14..12=7 6..2=0x0c 1..0=3
+orn         orn             31..25=0x20 14..12=6 6..2=0x0c 1..0=3
+xnor        xnor            31..25=0x20 14..12=4 6..2=0x0c 1..0=3
+clz         clz             31..20=0x600 14..12=1 6..2=0x04 1..0=3
+ctz         ctz             31..20=0x601 14..12=1 6..2=0x04 1..0=3
+cpop        cpop            31..20=0x602 14..12=1 6..2=0x04 1..0=3
+clzw        clzw            31..20=0x600 14..12=1 6..2=0x06 1..0=3
+ctzw        ctzw            31..20=0x601 14..12=1 6..2=0x06 1..0=3
+cpopw       cpopw           31..20=0x602 14..12=1 6..2=0x06 1..0=3
+max         max             31..25=0x05 14..12=6 6..2=0x0c 1..0=3
+maxu        maxu            31..25=0x05 14..12=7 6..2=0x0c 1..0=3
+min         min             31..25=0x05 14..12=4 6..2=0x0c 1..0=3
+minu        minu            31..25=0x05 14..12=5 6..2=0x0c 1..0=3
+sext.b      sext_b          31..20=0x604 14..12=1 6..2=0x04 1..0=3
+sext.h      sext_h          31..20=0x605 14..12=1 6..2=0x04 1..0=3
+zext.h      zext_h          31..20=0x080 14..12=4 6..2=0x0e 1..0=3
+rol         rol             31..25=0x30 14..12=1 6..2=0x0c 1..0=3
+ror         ror             31..25=0x30 14..12=5 6..2=0x0c 1..0=3
+rori        rori            31..26=0x18 14..12=5 6..2=0x04 1..0=3
+rolw        rolw            31..25=0x30 14..12=1 6..2=0x0e 1..0=3
+rorw        rorw            31..25=0x30 14..12=5 6..2=0x0e 1..0=3
+roriw       roriw           31..25=0x30 14..12=5 6..2=0x06 1..0=3
+orc.b       orc_b           31..20=0x287 14..12=5 6..2=0x04 1..0=3
+rev8        rev8            31..20=0x6b8 14..12=5 6..2=0x04 1..0=3
+# Zbc
+clmul       clmul           31..25=0x05 14..12=1 6..2=0x0c 1..0=3
+clmulr      clmulr          31..25=0x05 14..12=2 6..2=0x0c 1..0=3
+clmulh      clmulh          31..25=0x05 14..12=3 6..2=0x0c 1..0=3
+# Zbs
+bclr        bclr            31..25=0x24 14..12=1 6..2=0x0c 1..0=3
+bclri       bclri           31..26=0x12 14..12=1 6..2=0x04 1..0=3
+bext        bext            31..25=0x24 14..12=5 6..2=0x0c 1..0=3
+bexti       bexti           31..26=0x12 14..12=5 6..2=0x04 1..0=3
+binv        binv            31..25=0x34 14..12=1 6..2=0x0c 1..0=3
+binvi       binvi           31..26=0x1a 14..12=1 6..2=0x04 1..0=3
+bset        bset            31..25=0x14 14..12=1 6..2=0x0c 1..0=3
+bseti       bseti           31..26=0x0a 14..12=1 6..2=0x04 1..0=3
+# C, decoded as the instruction it expands to
+c.addi4spn  addi            15..13=0 12..5!=0 1..0=0
+c.fld       fld             15..13=1 1..0=0
+c.lw        lw              15..13=2 1..0=0
+c.ld        ld              15..13=3 1..0=0
+c.fsd       fsd             15..13=5 1..0=0
+c.sw        sw              15..13=6 1..0=0
+c.sd        sd              15..13=7 1..0=0
+c.addi      addi            15..13=0 1..0=1
+c.addiw     addiw           15..13=1 11..7!=0 1..0=1
+c.li        addi            15..13=2 1..0=1
+c.addi16sp  addi            15..13=3 11..7=2 12,6..2!=0 1..0=1
+c.lui       lui             15..13=3 11..7!=2 12,6..2!=0 1..0=1
+c.srli      srli            15..13=4 11..10=0 1..0=1
+c.srai      srai            15..13=4 11..10=1 1..0=1
+c.andi      andi            15..13=4 11..10=2 1..0=1
+c.sub       sub             15..13=4 12=0 11..10=3 6..5=0 1..0=1
+c.xor       xor             15..13=4 12=0 11..10=3 6..5=1 1..0=1
+c.or        or              15..13=4 12=0 11..10=3 6..5=2 1..0=1
+c.and       and             15..13=4 12=0 11..10=3 6..5=3 1..0=1
+c.subw      subw            15..13=4 12=1 11..10=3 6..5=0 1..0=1
+c.addw      addw            15..13=4 12=1 11..10=3 6..5=1 1..0=1
+c.j         jal             15..13=5 1..0=1
+c.beqz      beq             15..13=6 1..0=1
+c.bnez      bne             15..13=7 1..0=1
+c.slli      slli            15..13=0 1..0=2
+c.fldsp     fld             15..13=1 1..0=2
+c.lwsp      lw              15..13=2 11..7!=0 1..0=2
+c.ldsp      ld              15..13=3 11..7!=0 1..0=2
+c.jr        jalr            15..13=4 12=0 11..7!=0 6..2=0 1..0=2
+c.mv        add             15..13=4 12=0 6..2!=0 1..0=2
+c.ebreak    ebreak          15..13=4 12=1 11..7=0 6..2=0 1..0=2
+c.jalr      jalr            15..13=4 12=1 11..7!=0 6..2=0 1..0=2
+c.add       add             15..13=4 12=1 6..2!=0 1..0=2
+c.fsdsp     fsd             15..13=5 1..0=2
+c.swsp      sw              15..13=6 1..0=2
+c.sdsp      sd              15..13=7 1..0=2
+";
+
+// samples of each reference instruction's operands
+const SAMPLES_PER_INSN: usize = 256;
+const DEFAULT_RANDOM_SAMPLES: usize = 1 << 20;
+// kinds of result printed per category
+const SHOWN: usize = 12;
+
+struct encoding_t {
+    name: &'static str,
+    type_: &'static str,
+    mask: u32,
+    match_: u32,
+    excluded: Vec<(Vec<(u32, u32)>, u32)>, // fields and the value they must not have
+}
+
+fn parse_range(s: &str) -> (u32, u32) {
+    match s.split_once("..") {
+        Some((hi, lo)) => (hi.parse().unwrap(), lo.parse().unwrap()),
+        None => (s.parse().unwrap(), s.parse().unwrap()),
+    }
+}
+
+fn parse_value(s: &str) -> u32 {
+    match s.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).unwrap(),
+        None => s.parse().unwrap(),
+    }
+}
+
+fn field_mask((hi, lo): (u32, u32)) -> u32 {
+    (u32::MAX >> (31 - hi + lo)) << lo
+}
+
+// the fields of bits put together, first one highest
+fn extract(bits: u32, fields: &[(u32, u32)]) -> u32 {
+    fields.iter().fold(0, |v, &(hi, lo)| {
+        (v << (hi - lo + 1)) | ((bits & field_mask((hi, lo))) >> lo)
+    })
+}
+
+impl encoding_t {
+    fn parse(line: &'static str) -> encoding_t {
+        let mut tokens = line.split_whitespace();
+        let mut e = encoding_t {
+            name: tokens.next().unwrap(),
+            type_: tokens.next().unwrap(),
+            mask: 0,
+            match_: 0,
+            excluded: Vec::new(),
+        };
+        for token in tokens {
+            if let Some((fields, value)) = token.split_once("!=") {
+                let fields = fields.split(',').map(parse_range).collect();
+                e.excluded.push((fields, parse_value(value)));
+            } else {
+                let (range, value) = token.split_once('=').unwrap();
+                let range = parse_range(range);
+                e.mask |= field_mask(range);
+                e.match_ |= parse_value(value) << range.1;
+            }
+        }
+        e
+    }
+
+    fn is_compressed(&self) -> bool {
+        self.match_ & 0x3 != 0x3
+    }
+
+    fn matches(&self, bits: u32) -> bool {
+        bits & self.mask == self.match_
+            && self
+                .excluded
+                .iter()
+                .all(|(fields, value)| extract(bits, fields) != *value)
+    }
+}
+
+enum outcome_t {
+    decoded(String),
+    panicked(String),
+}
+
+fn decode(bits: u32, isa: &isa_t) -> outcome_t {
+    let mut insn = insn_t::default();
+    match panic::catch_unwind(AssertUnwindSafe(|| insn_decode(&mut insn, bits, isa))) {
+        Ok(()) => outcome_t::decoded(format!("{:?}", insn.type_)),
+        Err(e) => {
+            let msg = match e.downcast_ref::<&str>() {
+                Some(s) => s.to_string(),
+                None => e.downcast_ref::<String>().cloned().unwrap_or_default(),
+            };
+            outcome_t::panicked(msg)
+        }
+    }
+}
+
+// OP-V and the vector loads and stores in LOAD-FP and STORE-FP
+fn is_vector(bits: u32) -> bool {
+    let opcode = bits & 0x7f;
+    let width = (bits >> 12) & 0x7;
+    opcode == 0x57 || ((opcode == 0x07 || opcode == 0x27) && matches!(width, 0 | 5 | 6 | 7))
+}
+
+// per category, the kinds of result with their count and first encoding
+#[derive(Default)]
+struct report_t {
+    categories: BTreeMap<&'static str, BTreeMap<String, (usize, u32)>>,
+}
+
+impl report_t {
+    fn add(&mut self, category: &'static str, kind: String, bits: u32) {
+        let kinds = self.categories.entry(category).or_default();
+        kinds.entry(kind).or_insert((0, bits)).0 += 1;
+    }
+
+    fn count(&self, category: &str) -> usize {
+        self.categories
+            .get(category)
+            .map_or(0, |kinds| kinds.values().map(|(n, _)| n).sum())
+    }
+
+    fn check(&mut self, bits: u32, reference: &[encoding_t], isa: &isa_t) {
+        let expected = reference.iter().find(|e| e.matches(bits));
+        match (expected, decode(bits, isa)) {
+            (Some(e), outcome_t::decoded(got)) if got[5..] == *e.type_ => {
+                self.add("ok", String::new(), bits)
+            }
+            (Some(e), outcome_t::decoded(got)) => {
+                self.add("mismatch", format!("{} decoded as {}", e.name, got), bits)
+            }
+            (Some(e), outcome_t::panicked(msg)) => {
+                self.add("mismatch", format!("{} panicked: {}", e.name, msg), bits)
+            }
+            (None, outcome_t::decoded(got)) if got == "insn_illegal" => {
+                self.add("illegal", String::new(), bits)
+            }
+            (None, outcome_t::decoded(got)) => self.add("accepted", format!("as {}", got), bits),
+            (None, outcome_t::panicked(msg)) => self.add("panic", msg, bits),
+        }
+    }
+
+    fn print(&self, title: &str) {
+        println!("{}:", title);
+        for (category, kinds) in &self.categories {
+            println!("  {:<9} {}", category, self.count(category));
+            let mut kinds: Vec<_> = kinds.iter().filter(|(kind, _)| !kind.is_empty()).collect();
+            kinds.sort_by_key(|(_, (n, _))| std::cmp::Reverse(*n));
+            for (kind, (n, bits)) in kinds.iter().take(SHOWN) {
+                let width = if bits & 0x3 == 0x3 { 10 } else { 6 };
+                println!("    {:>8} {}, e.g. {:#0w$x}", n, kind, bits, w = width);
+            }
+            if kinds.len() > SHOWN {
+                println!("    {:>8} more kinds", kinds.len() - SHOWN);
+            }
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let random_samples = match args.get(1).map(|s| s.parse::<usize>()) {
+        None => DEFAULT_RANDOM_SAMPLES,
+        Some(Ok(n)) => n,
+        Some(Err(_)) => {
+            eprintln!("usage: {} [random 32-bit samples]", args[0]);
+            std::process::exit(2);
+        }
+    };
+
+    let reference: Vec<encoding_t> = REFERENCE
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(encoding_t::parse)
+        .collect();
+    let isa = isa_t::new();
+    let mut random = random_t::new(Some(0x5eed));
+    let mut next_u32 = || {
+        let mut buf = [0u8; 4];
+        random.fill(&mut buf).unwrap();
+        u32::from_le_bytes(buf)
+    };
+
+    // the decoder's panics are the report, not noise on stderr
+    panic::set_hook(Box::new(|_| {}));
+
+    let mut compressed = report_t::default();
+    for bits in (0..=0xffffu32).filter(|bits| bits & 0x3 != 0x3) {
+        compressed.check(bits, &reference, &isa);
+    }
+
+    // operands of each instruction, then anything at all
+    let mut sampled = report_t::default();
+    let mut skipped = 0;
+    for e in reference.iter().filter(|e| !e.is_compressed()) {
+        for _ in 0..SAMPLES_PER_INSN {
+            let bits = (next_u32() & !e.mask) | e.match_;
+            if e.matches(bits) {
+                sampled.check(bits, &reference, &isa);
+            }
+        }
+    }
+    for _ in 0..random_samples {
+        let bits = next_u32() | 0x3;
+        // 48-bit and longer encodings are not RV64GC
+        if bits & 0x1c == 0x1c {
+            continue;
+        }
+        if is_vector(bits) {
+            skipped += 1;
+            continue;
+        }
+        sampled.check(bits, &reference, &isa);
+    }
+
+    let _ = panic::take_hook();
+    compressed.print("16-bit encodings, all of them");
+    sampled.print("32-bit encodings, sampled");
+    println!("  skipped   {} (vector, not in the reference)", skipped);
+
+    let failures: usize = ["mismatch", "accepted", "panic"]
+        .iter()
+        .map(|category| compressed.count(category) + sampled.count(category))
+        .sum();
+    std::process::exit((failures > 0) as i32);
+}
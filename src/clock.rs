@@ -0,0 +1,143 @@
+use std::mem;
+
+use libc::{
+    CLOCK_BOOTTIME, CLOCK_MONOTONIC, CLOCK_MONOTONIC_COARSE, CLOCK_MONOTONIC_RAW,
+    CLOCK_PROCESS_CPUTIME_ID, CLOCK_REALTIME, CLOCK_REALTIME_COARSE, CLOCK_THREAD_CPUTIME_ID,
+    EINVAL,
+};
+
+const NSEC_PER_SEC: u64 = 1_000_000_000;
+
+#[derive(Clone, Copy)]
+pub enum clock_mode_t {
+    host,
+    // guest time advances with retired instructions, freq of them per second
+    virtual_ { epoch: u64, freq: u64 },
+}
+
+/*
+    guest clocks. with a virtual clock nothing depends on the host, sleeping
+    just moves the clock forward, so runs are reproducible.
+*/
+pub struct clock_t {
+    pub mode: clock_mode_t,
+    pub timebase: u64, // Hz of the time CSR
+    slept: u64,        // ns skipped by sleeps in virtual mode
+}
+
+fn host_clock(clockid: i32) -> (i64, i64) {
+    let mut ts: libc::timespec = unsafe { mem::zeroed() };
+    unsafe { libc::clock_gettime(clockid, &mut ts) };
+    (ts.tv_sec, ts.tv_nsec)
+}
+
+// a guest timespec in ns, saturating instead of wrapping past u64
+pub fn timespec_ns(sec: i64, nsec: i64) -> u64 {
+    (sec as u64)
+        .saturating_mul(NSEC_PER_SEC)
+        .saturating_add(nsec as u64)
+}
+
+// the largest --clock-epoch, the realtime clock must fit in u64 ns
+pub const MAX_EPOCH: u64 = u64::MAX / NSEC_PER_SEC / 2;
+
+fn split_ns(ns: u64) -> (i64, i64) {
+    ((ns / NSEC_PER_SEC) as i64, (ns % NSEC_PER_SEC) as i64)
+}
+
+impl clock_t {
+    pub fn new(mode: clock_mode_t, timebase: u64) -> clock_t {
+        clock_t {
+            mode,
+            timebase,
+            slept: 0,
+        }
+    }
+
+    // ns since the guest started, virtual mode only
+    fn elapsed(&self, instret: u64, freq: u64) -> u64 {
+        let ns = instret as u128 * NSEC_PER_SEC as u128 / freq as u128;
+        (ns as u64).saturating_add(self.slept)
+    }
+
+    // (seconds, nanoseconds) of a guest clock
+    pub fn gettime(&self, clockid: i32, instret: u64) -> Result<(i64, i64), i32> {
+        let realtime = match clockid {
+            CLOCK_REALTIME | CLOCK_REALTIME_COARSE => true,
+            CLOCK_MONOTONIC
+            | CLOCK_MONOTONIC_RAW
+            | CLOCK_MONOTONIC_COARSE
+            | CLOCK_BOOTTIME
+            | CLOCK_PROCESS_CPUTIME_ID
+            | CLOCK_THREAD_CPUTIME_ID => false,
+            _ => return Err(EINVAL),
+        };
+        match self.mode {
+            clock_mode_t::host => Ok(host_clock(clockid)),
+            clock_mode_t::virtual_ { epoch, freq } => {
+                let elapsed = self.elapsed(instret, freq);
+                Ok(split_ns(if realtime {
+                    timespec_ns(epoch as i64, 0).saturating_add(elapsed)
+                } else {
+                    elapsed
+                }))
+            }
+        }
+    }
+
+    pub fn getres(&self, clockid: i32) -> Result<(i64, i64), i32> {
+        self.gettime(clockid, 0)?;
+        match self.mode {
+            clock_mode_t::host => {
+                let mut ts: libc::timespec = unsafe { mem::zeroed() };
+                unsafe { libc::clock_getres(clockid, &mut ts) };
+                Ok((ts.tv_sec, ts.tv_nsec))
+            }
+            clock_mode_t::virtual_ { freq, .. } => Ok(split_ns((NSEC_PER_SEC / freq).max(1))),
+        }
+    }
+
+    // the time CSR, the monotonic clock counted in timebase ticks
+    pub fn ticks(&self, instret: u64) -> u64 {
+        let (sec, nsec) = self.gettime(CLOCK_MONOTONIC, instret).unwrap();
+        let ns = sec as u128 * NSEC_PER_SEC as u128 + nsec as u128;
+        (ns * self.timebase as u128 / NSEC_PER_SEC as u128) as u64
+    }
+
+    // sleep for ns, on the host or by advancing the virtual clock
+    pub fn sleep(&mut self, ns: u64) {
+        match self.mode {
+            clock_mode_t::host => std::thread::sleep(std::time::Duration::from_nanos(ns)),
+            clock_mode_t::virtual_ { .. } => self.slept = self.slept.saturating_add(ns),
+        }
+    }
+
+    // a host wait timed out after ns, only the virtual clock has to catch up
+    pub fn waited(&mut self, ns: u64) {
+        if let clock_mode_t::virtual_ { .. } = self.mode {
+            self.slept = self.slept.saturating_add(ns);
+        }
+    }
+
+    // instructions retired per guest second, nominally 1e9 on the host clock
+    pub fn insns_per_sec(&self) -> u64 {
+        match self.mode {
+            clock_mode_t::host => NSEC_PER_SEC,
+            clock_mode_t::virtual_ { freq, .. } => freq,
+        }
+    }
+
+    pub fn is_virtual(&self) -> bool {
+        matches!(self.mode, clock_mode_t::virtual_ { .. })
+    }
+
+    // sleep until clockid reaches (sec, nsec)
+    pub fn sleep_until(&mut self, clockid: i32, instret: u64, sec: i64, nsec: i64) {
+        let (now_sec, now_nsec) = self.gettime(clockid, instret).unwrap();
+        let now = now_sec as i128 * NSEC_PER_SEC as i128 + now_nsec as i128;
+        let target = sec as i128 * NSEC_PER_SEC as i128 + nsec as i128;
+        if target > now {
+            self.sleep((target - now).min(u64::MAX as i128) as u64);
+        }
+    }
+}
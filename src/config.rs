@@ -0,0 +1,382 @@
+use std::{path::PathBuf, time::Duration};
+
+use crate::{
+    clock::{MAX_EPOCH, clock_mode_t},
+    fd::MAX_FDS,
+    isa::isa_t,
+    machine::STACK_SIZE,
+    personality::personality_t,
+};
+
+pub enum vfs_mode_t {
+    host,             // passthrough to the host filesystem
+    mem,              // empty in-memory filesystem
+    overlay(PathBuf), // read-only host directory with in-memory changes
+}
+
+// what an ebreak machine_step stops at does in the emulator itself
+#[derive(Clone, Copy, PartialEq)]
+pub enum ebreak_policy_t {
+    sigtrap,     // SIGTRAP at the guest handler, or death as on Linux without one
+    skip,        // carry on after it
+    semihosting, // serve semihosting calls, other ebreaks are sigtrap
+}
+
+// caps on what a guest may use, None leaves the host's limit
+pub struct limits_t {
+    pub heap: Option<u64>,  // program break size, RLIMIT_DATA
+    pub mem: Option<u64>,   // all guest memory, RLIMIT_AS
+    pub stack: u64,         // RLIMIT_STACK
+    pub fds: u64,           // RLIMIT_NOFILE
+    pub insns: Option<u64>, // retired instructions, RLIMIT_CPU
+}
+
+pub struct config_t {
+    pub sysroot: Option<PathBuf>, // -L, guest root for absolute paths
+    pub vfs: vfs_mode_t,
+    pub personality: Option<personality_t>, // None to detect from the ELF file
+    pub clock: clock_mode_t,
+    pub timebase: u64, // Hz of the time CSR
+    pub isa: isa_t,
+    pub vlen: u64, // vector register bits
+    pub ebreak: ebreak_policy_t,
+    pub htif: bool,       // serve tohost/fromhost for bare-metal programs
+    pub release: String,  // uname -r reported to the guest
+    pub uid: Option<u32>, // overrides for the host uid and gid
+    pub gid: Option<u32>,
+    pub seed: Option<u64>, // deterministic guest randomness
+    pub limits: limits_t,
+    pub timeout: Option<Duration>, // wall clock time before the guest is stopped
+    pub stdio: [Option<PathBuf>; 3], // host files for guest stdin, stdout and stderr
+    pub inherit_fds: Vec<(u64, i32)>, // (guest fd, host fd) passed on by execve
+    pub argv0: Option<String>,     // guest argv[0] instead of the program path
+    pub envp: Vec<String>,         // guest environment, empty by default
+    pub riscv_tests: Vec<PathBuf>, // run the riscv-tests under these paths instead of a program
+    pub emu_args: Vec<String>,     // options given before the program, replayed on execve
+}
+
+impl config_t {
+    pub fn new() -> config_t {
+        config_t {
+            sysroot: None,
+            vfs: vfs_mode_t::host,
+            personality: None,
+            clock: clock_mode_t::host,
+            timebase: 10_000_000,
+            isa: isa_t::new(),
+            vlen: 128,
+            ebreak: ebreak_policy_t::sigtrap,
+            htif: false,
+            release: String::from("6.6.0"),
+            uid: None,
+            gid: None,
+            seed: None,
+            timeout: None,
+            limits: limits_t {
+                heap: None,
+                mem: None,
+                stack: STACK_SIZE,
+                fds: MAX_FDS,
+                insns: None,
+            },
+            stdio: [None, None, None],
+            inherit_fds: Vec::new(),
+            argv0: None,
+            envp: Vec::new(),
+            riscv_tests: Vec::new(),
+            emu_args: Vec::new(),
+        }
+    }
+}
+
+impl Default for config_t {
+    fn default() -> config_t {
+        config_t::new()
+    }
+}
+
+pub fn usage(prog: &str) -> ! {
+    println!("Usage: {} [options] <program> [args...]", prog);
+    println!("       {} [options] --riscv-tests <dir|elf>...", prog);
+    println!("Options:");
+    println!("  -L <sysroot>    look up absolute guest paths in <sysroot> first");
+    println!("  --vfs <mode>    guest filesystem: host (default), mem or overlay:<dir>");
+    println!("  --personality <abi> syscall ABI: newlib (riscv-pk) or linux, detected by default");
+    println!("  --virtual-clock derive guest time from the instruction count");
+    println!("  --clock-epoch <secs> virtual clock start, seconds since 1970 (default 0)");
+    println!("  --clock-freq <hz> virtual clock instructions per second (default 1e9)");
+    println!("  --timebase <hz> frequency of the time CSR (default 10000000)");
+    println!("  --isa <isa>     extensions to decode, e.g. rv64gc (default rv64gcbv_zbc_zfh)");
+    println!("  --vlen <bits>   vector register width, a power of two from 128 (default 128)");
+    println!("  --ebreak <policy> on ebreak: sigtrap (default), skip or semihosting");
+    println!("  --htif          serve the tohost and fromhost symbols like spike");
+    println!("  --release <rel> kernel release reported by uname (default 6.6.0)");
+    println!("  --uid <uid>     user id reported to the guest");
+    println!("  --gid <gid>     group id reported to the guest");
+    println!("  --seed <n>      reproducible random bytes for the guest instead of the host's");
+    println!("  --max-heap <size> limit the program break (RLIMIT_DATA)");
+    println!("  --max-mem <size> limit all guest memory (RLIMIT_AS)");
+    println!("  --stack-size <size> guest stack size (default 32M)");
+    println!("  --max-fds <n>   limit open guest fds (default 1024)");
+    println!("  --max-insns <n> kill the guest with SIGXCPU after <n> instructions");
+    println!("  --timeout <secs> stop the guest after <secs> seconds of wall clock time");
+    println!("  --stdin <file>  read guest stdin from a host file");
+    println!("  --stdout <file> write guest stdout to a host file");
+    println!("  --stderr <file> write guest stderr to a host file");
+    println!("  --argv0 <name>  pass <name> to the guest as argv[0] instead of <program>");
+    println!("  --env <var=value> add <var=value> to the guest environment, repeatable");
+    println!("  --riscv-tests <path> run the riscv-tests binaries under <path>, repeatable");
+    std::process::exit(1);
+}
+
+// a byte count with an optional K, M or G suffix
+fn parse_size(s: &str) -> Option<u64> {
+    let (num, shift) = match s.as_bytes().last()? {
+        b'k' | b'K' => (&s[..s.len() - 1], 10),
+        b'm' | b'M' => (&s[..s.len() - 1], 20),
+        b'g' | b'G' => (&s[..s.len() - 1], 30),
+        _ => (s, 0),
+    };
+    num.parse::<u64>().ok()?.checked_mul(1 << shift)
+}
+
+/*
+    parse emulator options up to the guest program,
+    returns the config and the index of the program in args
+*/
+pub fn parse_args(args: &[String]) -> (config_t, usize) {
+    let mut config = config_t::new();
+    let mut i = 1;
+
+    while i < args.len() && args[i].starts_with('-') {
+        let opt = args[i].as_str();
+        let value = |i: usize| -> &str {
+            match args.get(i + 1) {
+                Some(v) => v.as_str(),
+                None => {
+                    eprintln!("missing argument for {}", opt);
+                    usage(&args[0]);
+                }
+            }
+        };
+
+        match opt {
+            "-L" => {
+                config.sysroot = Some(PathBuf::from(value(i)));
+                config.emu_args.extend_from_slice(&args[i..i + 2]);
+                i += 2;
+            }
+            "--vfs" => {
+                config.vfs = match value(i) {
+                    "host" => vfs_mode_t::host,
+                    "mem" => vfs_mode_t::mem,
+                    mode => match mode.strip_prefix("overlay:") {
+                        Some(dir) => vfs_mode_t::overlay(PathBuf::from(dir)),
+                        None => {
+                            eprintln!("unknown vfs mode: {}", mode);
+                            usage(&args[0]);
+                        }
+                    },
+                };
+                config.emu_args.extend_from_slice(&args[i..i + 2]);
+                i += 2;
+            }
+            "--personality" => {
+                config.personality = match personality_t::from_name(value(i)) {
+                    Some(personality) => Some(personality),
+                    None => {
+                        eprintln!("unknown personality: {}", value(i));
+                        usage(&args[0]);
+                    }
+                };
+                config.emu_args.extend_from_slice(&args[i..i + 2]);
+                i += 2;
+            }
+            "--virtual-clock" | "--clock-epoch" | "--clock-freq" => {
+                let (mut epoch, mut freq) = match config.clock {
+                    clock_mode_t::virtual_ { epoch, freq } => (epoch, freq),
+                    clock_mode_t::host => (0, 1_000_000_000),
+                };
+                let mut n = 1;
+                if opt != "--virtual-clock" {
+                    let num = match value(i).parse::<u64>() {
+                        Ok(num) if opt == "--clock-epoch" && num <= MAX_EPOCH => num,
+                        Ok(num) if opt == "--clock-freq" && num > 0 => num,
+                        _ => {
+                            eprintln!("invalid value for {}: {}", opt, value(i));
+                            usage(&args[0]);
+                        }
+                    };
+                    if opt == "--clock-epoch" {
+                        epoch = num;
+                    } else {
+                        freq = num;
+                    }
+                    n = 2;
+                }
+                config.clock = clock_mode_t::virtual_ { epoch, freq };
+                config.emu_args.extend_from_slice(&args[i..i + n]);
+                i += n;
+            }
+            "--timebase" => {
+                config.timebase = match value(i).parse::<u64>() {
+                    Ok(hz) if hz > 0 => hz,
+                    _ => {
+                        eprintln!("invalid value for {}: {}", opt, value(i));
+                        usage(&args[0]);
+                    }
+                };
+                config.emu_args.extend_from_slice(&args[i..i + 2]);
+                i += 2;
+            }
+            "--isa" => {
+                config.isa = match isa_t::parse(value(i)) {
+                    Some(isa) => isa,
+                    None => {
+                        eprintln!("unsupported isa: {}", value(i));
+                        usage(&args[0]);
+                    }
+                };
+                config.emu_args.extend_from_slice(&args[i..i + 2]);
+                i += 2;
+            }
+            "--ebreak" => {
+                config.ebreak = match value(i) {
+                    "sigtrap" => ebreak_policy_t::sigtrap,
+                    "skip" => ebreak_policy_t::skip,
+                    "semihosting" => ebreak_policy_t::semihosting,
+                    policy => {
+                        eprintln!("unknown ebreak policy: {}", policy);
+                        usage(&args[0]);
+                    }
+                };
+                config.emu_args.extend_from_slice(&args[i..i + 2]);
+                i += 2;
+            }
+            "--htif" => {
+                config.htif = true;
+                config.emu_args.push(args[i].clone());
+                i += 1;
+            }
+            "--vlen" => {
+                config.vlen = match value(i).parse::<u64>() {
+                    Ok(vlen) if vlen.is_power_of_two() && (128..=65536).contains(&vlen) => vlen,
+                    _ => {
+                        eprintln!("invalid value for {}: {}", opt, value(i));
+                        usage(&args[0]);
+                    }
+                };
+                config.emu_args.extend_from_slice(&args[i..i + 2]);
+                i += 2;
+            }
+            "--release" => {
+                config.release = String::from(value(i));
+                config.emu_args.extend_from_slice(&args[i..i + 2]);
+                i += 2;
+            }
+            "--uid" | "--gid" => {
+                let id = match value(i).parse::<u32>() {
+                    Ok(id) => Some(id),
+                    Err(_) => {
+                        eprintln!("invalid value for {}: {}", opt, value(i));
+                        usage(&args[0]);
+                    }
+                };
+                if opt == "--uid" {
+                    config.uid = id;
+                } else {
+                    config.gid = id;
+                }
+                config.emu_args.extend_from_slice(&args[i..i + 2]);
+                i += 2;
+            }
+            "--seed" => {
+                config.seed = match value(i).parse::<u64>() {
+                    Ok(seed) => Some(seed),
+                    Err(_) => {
+                        eprintln!("invalid value for {}: {}", opt, value(i));
+                        usage(&args[0]);
+                    }
+                };
+                config.emu_args.extend_from_slice(&args[i..i + 2]);
+                i += 2;
+            }
+            "--max-heap" | "--max-mem" | "--stack-size" | "--max-fds" | "--max-insns" => {
+                let num = match opt {
+                    "--max-fds" | "--max-insns" => value(i).parse::<u64>().ok(),
+                    _ => parse_size(value(i)),
+                };
+                let Some(num) = num.filter(|&num| num > 0) else {
+                    eprintln!("invalid value for {}: {}", opt, value(i));
+                    usage(&args[0]);
+                };
+                match opt {
+                    "--max-heap" => config.limits.heap = Some(num),
+                    "--max-mem" => config.limits.mem = Some(num),
+                    "--stack-size" => config.limits.stack = num,
+                    "--max-fds" => config.limits.fds = num,
+                    _ => config.limits.insns = Some(num),
+                }
+                config.emu_args.extend_from_slice(&args[i..i + 2]);
+                i += 2;
+            }
+            "--timeout" => {
+                config.timeout = match value(i).parse::<f64>().map(Duration::try_from_secs_f64) {
+                    Ok(Ok(timeout)) => Some(timeout),
+                    _ => {
+                        eprintln!("invalid value for {}: {}", opt, value(i));
+                        usage(&args[0]);
+                    }
+                };
+                config.emu_args.extend_from_slice(&args[i..i + 2]);
+                i += 2;
+            }
+            "--stdin" | "--stdout" | "--stderr" => {
+                let fd = ["--stdin", "--stdout", "--stderr"]
+                    .iter()
+                    .position(|o| *o == opt)
+                    .unwrap();
+                config.stdio[fd] = Some(PathBuf::from(value(i)));
+                i += 2;
+            }
+            "--argv0" => {
+                config.argv0 = Some(String::from(value(i)));
+                i += 2;
+            }
+            "--env" => {
+                config.envp.push(String::from(value(i)));
+                i += 2;
+            }
+            // the runner passes the other options on to each test
+            "--riscv-tests" => {
+                config.riscv_tests.push(PathBuf::from(value(i)));
+                i += 2;
+            }
+            // internal, the guest fds that survive an execve
+            "--inherit-fd" => {
+                let fds = value(i)
+                    .split_once(':')
+                    .and_then(|(guest, host)| Some((guest.parse().ok()?, host.parse().ok()?)));
+                match fds {
+                    Some(fds) => config.inherit_fds.push(fds),
+                    None => usage(&args[0]),
+                }
+                i += 2;
+            }
+            "--" => {
+                i += 1;
+                break;
+            }
+            _ => {
+                eprintln!("unknown option: {}", opt);
+                usage(&args[0]);
+            }
+        }
+    }
+
+    // a program or the riscv-tests, not both
+    if (i >= args.len()) == config.riscv_tests.is_empty() {
+        usage(&args[0]);
+    }
+    (config, i)
+}
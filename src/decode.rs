@@ -1,11 +1,11 @@
-use crate::insn::*;
+use crate::{insn::*, isa::isa_t};
 
 #[inline]
 fn quadrant(data: u32) -> u32 {
     (data >> 0) & 0x3
 }
 
-pub fn insn_decode(insn: &mut insn_t, data: u32) {
+pub fn insn_decode(insn: &mut insn_t, data: u32, isa: &isa_t) {
     // for i in (0..32).rev() {
     //     let bit = (data >> i) & 1;
     //     print!("{}", bit);
@@ -21,7 +21,9 @@ pub fn insn_decode(insn: &mut insn_t, data: u32) {
                     insn.insn_ciwtype_read(data as u16);
                     insn.rs1 = 2; // sp
                     insn.type_ = insn_type_t::insn_addi;
-                    assert!(insn.imm != 0);
+                    if insn.imm == 0 {
+                        insn.type_ = insn_type_t::insn_illegal;
+                    }
                 }
                 0x1 => {
                     // C.FLD
@@ -54,7 +56,7 @@ pub fn insn_decode(insn: &mut insn_t, data: u32) {
                     insn.insn_cstype_read(data as u16);
                     insn.type_ = insn_type_t::insn_sd;
                 }
-                _ => panic!("unimplemented copcode: {}", copcode),
+                _ => insn.type_ = insn_type_t::insn_illegal,
             }
         }
         0x1 => {
@@ -70,9 +72,11 @@ pub fn insn_decode(insn: &mut insn_t, data: u32) {
                     // C.ADDIW
                     // println!("hit ! addiw");
                     insn.insn_citype_read(data as u16);
-                    assert!(insn.rd != 0);
                     insn.rs1 = insn.rd;
                     insn.type_ = insn_type_t::insn_addiw;
+                    if insn.rd == 0 {
+                        insn.type_ = insn_type_t::insn_illegal;
+                    }
                 }
                 0x2 => {
                     // C.LI
@@ -85,15 +89,19 @@ pub fn insn_decode(insn: &mut insn_t, data: u32) {
                     if rd == 2 {
                         // C.ADDI16SP
                         insn.insn_citype_read3(data as u16);
-                        assert!(insn.imm != 0);
                         insn.rs1 = insn.rd;
                         insn.type_ = insn_type_t::insn_addi;
+                        if insn.imm == 0 {
+                            insn.type_ = insn_type_t::insn_illegal;
+                        }
                     } else {
                         // C.LUI
                         // println!("hit !");
                         insn.insn_citype_read5(data as u16);
-                        assert!(insn.imm != 0);
                         insn.type_ = insn_type_t::insn_lui;
+                        if insn.imm == 0 {
+                            insn.type_ = insn_type_t::insn_illegal;
+                        }
                     }
                 }
                 0x4 => {
@@ -129,7 +137,7 @@ pub fn insn_decode(insn: &mut insn_t, data: u32) {
                                         0x1 => insn.type_ = insn_type_t::insn_xor,
                                         0x2 => insn.type_ = insn_type_t::insn_or,
                                         0x3 => insn.type_ = insn_type_t::insn_and,
-                                        _ => unreachable!(),
+                                        _ => insn.type_ = insn_type_t::insn_illegal,
                                     }
                                 }
                                 0x1 => {
@@ -139,13 +147,13 @@ pub fn insn_decode(insn: &mut insn_t, data: u32) {
                                     match cfunct2low {
                                         0x0 => insn.type_ = insn_type_t::insn_subw,
                                         0x1 => insn.type_ = insn_type_t::insn_addw,
-                                        _ => unreachable!(),
+                                        _ => insn.type_ = insn_type_t::insn_illegal,
                                     }
                                 }
-                                _ => unreachable!(),
+                                _ => insn.type_ = insn_type_t::insn_illegal,
                             }
                         }
-                        _ => unreachable!(),
+                        _ => insn.type_ = insn_type_t::insn_illegal,
                     }
                 }
                 0x5 => {
@@ -167,7 +175,7 @@ pub fn insn_decode(insn: &mut insn_t, data: u32) {
                     insn.rs2 = 0; // zero
                     insn.type_ = insn_type_t::insn_bne;
                 }
-                _ => panic!("unrecognized copcode: {}", copcode),
+                _ => insn.type_ = insn_type_t::insn_illegal,
             }
         }
         0x2 => {
@@ -190,17 +198,21 @@ pub fn insn_decode(insn: &mut insn_t, data: u32) {
                     // C.LWSP
                     // println!("hit !");
                     insn.insn_citype_read4(data as u16);
-                    assert!(insn.rd != 0);
                     insn.rs1 = 2; // sp
                     insn.type_ = insn_type_t::insn_lw;
+                    if insn.rd == 0 {
+                        insn.type_ = insn_type_t::insn_illegal;
+                    }
                 }
                 0x3 => {
                     // C.LDSP
                     // println!("hit ! c ldsp");
                     insn.insn_citype_read2(data as u16);
-                    assert!(insn.rd != 0);
                     insn.rs1 = 2; // sp
                     insn.type_ = insn_type_t::insn_ld;
+                    if insn.rd == 0 {
+                        insn.type_ = insn_type_t::insn_illegal;
+                    }
                 }
                 0x4 => {
                     let cfunct1 = cfunct1(data as u16);
@@ -209,10 +221,12 @@ pub fn insn_decode(insn: &mut insn_t, data: u32) {
                             insn.insn_crtype_read(data as u16);
                             if insn.rs2 == 0 {
                                 // C.JR
-                                assert!(insn.rs1 != 0);
                                 insn.rd = 0; // zero
                                 insn.type_ = insn_type_t::insn_jalr;
                                 insn.cont = true;
+                                if insn.rs1 == 0 {
+                                    insn.type_ = insn_type_t::insn_illegal;
+                                }
                             } else {
                                 // C.MV
                                 // println!("hit !");
@@ -225,7 +239,8 @@ pub fn insn_decode(insn: &mut insn_t, data: u32) {
                             insn.insn_crtype_read(data as u16);
                             if insn.rs1 == 0 && insn.rs2 == 0 {
                                 // C.EBREAK
-                                panic!("unimplemented C.EBREAK");
+                                insn.type_ = insn_type_t::insn_ebreak;
+                                insn.cont = true;
                             } else if insn.rs2 == 0 {
                                 // C.JALR
                                 // println!("hit !");
@@ -238,7 +253,7 @@ pub fn insn_decode(insn: &mut insn_t, data: u32) {
                                 insn.type_ = insn_type_t::insn_add;
                             }
                         }
-                        _ => unreachable!(),
+                        _ => insn.type_ = insn_type_t::insn_illegal,
                     }
                 }
                 0x5 => {
@@ -259,7 +274,7 @@ pub fn insn_decode(insn: &mut insn_t, data: u32) {
                     insn.rs1 = 2; // sp
                     insn.type_ = insn_type_t::insn_sd;
                 }
-                _ => panic!("unrecognized copcode: {}", copcode),
+                _ => insn.type_ = insn_type_t::insn_illegal,
             }
         }
         0x3 => {
@@ -276,16 +291,18 @@ pub fn insn_decode(insn: &mut insn_t, data: u32) {
                         0x4 => insn.type_ = insn_type_t::insn_lbu,
                         0x5 => insn.type_ = insn_type_t::insn_lhu,
                         0x6 => insn.type_ = insn_type_t::insn_lwu,
-                        _ => unreachable!(),
+                        _ => insn.type_ = insn_type_t::insn_illegal,
                     }
                 }
                 0x1 => {
                     let funct3 = funct3(data);
                     insn.insn_itype_read(data);
                     match funct3 {
+                        0x1 if isa.zfhmin => insn.type_ = insn_type_t::insn_flh,
                         0x2 => insn.type_ = insn_type_t::insn_flw,
                         0x3 => insn.type_ = insn_type_t::insn_fld,
-                        _ => unreachable!(),
+                        0x0 | 0x5 | 0x6 | 0x7 if isa.v => vmem_decode(insn, data, false),
+                        _ => insn.type_ = insn_type_t::insn_illegal,
                     }
                 }
                 0x3 => {
@@ -305,7 +322,7 @@ pub fn insn_decode(insn: &mut insn_t, data: u32) {
                                 ..Default::default()
                             };
                         }
-                        _ => unreachable!(),
+                        _ => insn.type_ = insn_type_t::insn_illegal,
                     }
                 }
                 0x4 => {
@@ -316,30 +333,43 @@ pub fn insn_decode(insn: &mut insn_t, data: u32) {
                             insn.type_ = insn_type_t::insn_addi;
                             // println!("hit !");
                         }
-                        0x1 => {
-                            let imm116 = imm116(data);
-                            if imm116 == 0 {
-                                insn.type_ = insn_type_t::insn_slli;
-                            } else {
-                                unreachable!();
-                            }
-                        }
+                        0x1 => match imm116(data) {
+                            0x0 => insn.type_ = insn_type_t::insn_slli,
+                            0x0a if isa.zbs => insn.type_ = insn_type_t::insn_bseti,
+                            0x12 if isa.zbs => insn.type_ = insn_type_t::insn_bclri,
+                            0x1a if isa.zbs => insn.type_ = insn_type_t::insn_binvi,
+                            0x18 if isa.zbb => match (data >> 20) & 0xfff {
+                                0x600 => insn.type_ = insn_type_t::insn_clz,
+                                0x601 => insn.type_ = insn_type_t::insn_ctz,
+                                0x602 => insn.type_ = insn_type_t::insn_cpop,
+                                0x604 => insn.type_ = insn_type_t::insn_sext_b,
+                                0x605 => insn.type_ = insn_type_t::insn_sext_h,
+                                _ => insn.type_ = insn_type_t::insn_illegal,
+                            },
+                            _ => insn.type_ = insn_type_t::insn_illegal,
+                        },
                         0x2 => insn.type_ = insn_type_t::insn_slti,
                         0x3 => insn.type_ = insn_type_t::insn_sltiu,
                         0x4 => insn.type_ = insn_type_t::insn_xori,
                         0x5 => {
-                            let imm116 = imm116(data);
-                            if imm116 == 0x0 {
-                                insn.type_ = insn_type_t::insn_srli;
-                            } else if imm116 == 0x10 {
-                                insn.type_ = insn_type_t::insn_srai;
-                            } else {
-                                unreachable!();
+                            let imm12 = (data >> 20) & 0xfff;
+                            match imm116(data) {
+                                0x0 => insn.type_ = insn_type_t::insn_srli,
+                                0x10 => insn.type_ = insn_type_t::insn_srai,
+                                0x12 if isa.zbs => insn.type_ = insn_type_t::insn_bexti,
+                                0x18 if isa.zbb => insn.type_ = insn_type_t::insn_rori,
+                                0x0a if isa.zbb && imm12 == 0x287 => {
+                                    insn.type_ = insn_type_t::insn_orc_b
+                                }
+                                0x1a if isa.zbb && imm12 == 0x6b8 => {
+                                    insn.type_ = insn_type_t::insn_rev8
+                                }
+                                _ => insn.type_ = insn_type_t::insn_illegal,
                             }
                         }
                         0x6 => insn.type_ = insn_type_t::insn_ori,
                         0x7 => insn.type_ = insn_type_t::insn_andi,
-                        _ => panic!("unrecognized funct3"),
+                        _ => insn.type_ = insn_type_t::insn_illegal,
                     }
                 }
                 0x5 => {
@@ -353,16 +383,25 @@ pub fn insn_decode(insn: &mut insn_t, data: u32) {
                     insn.insn_itype_read(data);
                     match funct3 {
                         0x0 => insn.type_ = insn_type_t::insn_addiw,
-                        0x1 => {
-                            assert!(funct7 == 0);
-                            insn.type_ = insn_type_t::insn_slliw;
-                        }
+                        0x1 => match funct7 {
+                            0x0 => insn.type_ = insn_type_t::insn_slliw,
+                            // funct6 0x02, the low bit is shamt[5]
+                            0x4 | 0x5 if isa.zba => insn.type_ = insn_type_t::insn_slli_uw,
+                            0x30 if isa.zbb => match rs2(data) {
+                                0x0 => insn.type_ = insn_type_t::insn_clzw,
+                                0x1 => insn.type_ = insn_type_t::insn_ctzw,
+                                0x2 => insn.type_ = insn_type_t::insn_cpopw,
+                                _ => insn.type_ = insn_type_t::insn_illegal,
+                            },
+                            _ => insn.type_ = insn_type_t::insn_illegal,
+                        },
                         0x5 => match funct7 {
                             0x0 => insn.type_ = insn_type_t::insn_srliw,
                             0x20 => insn.type_ = insn_type_t::insn_sraiw,
-                            _ => unreachable!(),
+                            0x30 if isa.zbb => insn.type_ = insn_type_t::insn_roriw,
+                            _ => insn.type_ = insn_type_t::insn_illegal,
                         },
-                        _ => panic!("unimplemented"),
+                        _ => insn.type_ = insn_type_t::insn_illegal,
                     }
                 }
                 0x8 => {
@@ -376,18 +415,51 @@ pub fn insn_decode(insn: &mut insn_t, data: u32) {
                         0x1 => insn.type_ = insn_type_t::insn_sh,
                         0x2 => insn.type_ = insn_type_t::insn_sw,
                         0x3 => insn.type_ = insn_type_t::insn_sd,
-                        _ => unreachable!(),
+                        _ => insn.type_ = insn_type_t::insn_illegal,
                     }
                 }
                 0x9 => {
                     let funct3 = funct3(data);
                     insn.insn_stype_read(data);
                     match funct3 {
+                        0x1 if isa.zfhmin => insn.type_ = insn_type_t::insn_fsh,
                         0x2 => insn.type_ = insn_type_t::insn_fsw,
                         0x3 => insn.type_ = insn_type_t::insn_fsd,
-                        _ => unreachable!(),
+                        0x0 | 0x5 | 0x6 | 0x7 if isa.v => vmem_decode(insn, data, true),
+                        _ => insn.type_ = insn_type_t::insn_illegal,
                     }
                 }
+                0xb => {
+                    // AMO, aq and rl order nothing with a single hart
+                    insn.insn_rtype_read(data);
+                    let funct3 = funct3(data);
+                    let funct5 = funct7(data) >> 2;
+                    insn.type_ = match (funct3, funct5) {
+                        (0x2, 0x02) if insn.rs2 == 0 => insn_type_t::insn_lr_w,
+                        (0x2, 0x03) => insn_type_t::insn_sc_w,
+                        (0x2, 0x01) => insn_type_t::insn_amoswap_w,
+                        (0x2, 0x00) => insn_type_t::insn_amoadd_w,
+                        (0x2, 0x04) => insn_type_t::insn_amoxor_w,
+                        (0x2, 0x0c) => insn_type_t::insn_amoand_w,
+                        (0x2, 0x08) => insn_type_t::insn_amoor_w,
+                        (0x2, 0x10) => insn_type_t::insn_amomin_w,
+                        (0x2, 0x14) => insn_type_t::insn_amomax_w,
+                        (0x2, 0x18) => insn_type_t::insn_amominu_w,
+                        (0x2, 0x1c) => insn_type_t::insn_amomaxu_w,
+                        (0x3, 0x02) if insn.rs2 == 0 => insn_type_t::insn_lr_d,
+                        (0x3, 0x03) => insn_type_t::insn_sc_d,
+                        (0x3, 0x01) => insn_type_t::insn_amoswap_d,
+                        (0x3, 0x00) => insn_type_t::insn_amoadd_d,
+                        (0x3, 0x04) => insn_type_t::insn_amoxor_d,
+                        (0x3, 0x0c) => insn_type_t::insn_amoand_d,
+                        (0x3, 0x08) => insn_type_t::insn_amoor_d,
+                        (0x3, 0x10) => insn_type_t::insn_amomin_d,
+                        (0x3, 0x14) => insn_type_t::insn_amomax_d,
+                        (0x3, 0x18) => insn_type_t::insn_amominu_d,
+                        (0x3, 0x1c) => insn_type_t::insn_amomaxu_d,
+                        _ => insn_type_t::insn_illegal,
+                    };
+                }
                 0xc => {
                     insn.insn_rtype_read(data);
                     let funct3 = funct3(data);
@@ -402,7 +474,7 @@ pub fn insn_decode(insn: &mut insn_t, data: u32) {
                             0x5 => insn.type_ = insn_type_t::insn_srl,
                             0x6 => insn.type_ = insn_type_t::insn_or,
                             0x7 => insn.type_ = insn_type_t::insn_and,
-                            _ => unreachable!(),
+                            _ => insn.type_ = insn_type_t::insn_illegal,
                         },
                         0x1 => match funct3 {
                             0x0 => insn.type_ = insn_type_t::insn_mul,
@@ -413,14 +485,45 @@ pub fn insn_decode(insn: &mut insn_t, data: u32) {
                             0x5 => insn.type_ = insn_type_t::insn_divu,
                             0x6 => insn.type_ = insn_type_t::insn_rem,
                             0x7 => insn.type_ = insn_type_t::insn_remu,
-                            _ => unreachable!(),
+                            _ => insn.type_ = insn_type_t::insn_illegal,
                         },
                         0x20 => match funct3 {
                             0x0 => insn.type_ = insn_type_t::insn_sub,
                             0x5 => insn.type_ = insn_type_t::insn_sra,
-                            _ => unreachable!(),
+                            0x4 if isa.zbb => insn.type_ = insn_type_t::insn_xnor,
+                            0x6 if isa.zbb => insn.type_ = insn_type_t::insn_orn,
+                            0x7 if isa.zbb => insn.type_ = insn_type_t::insn_andn,
+                            _ => insn.type_ = insn_type_t::insn_illegal,
+                        },
+                        0x5 => match funct3 {
+                            0x1 if isa.zbc => insn.type_ = insn_type_t::insn_clmul,
+                            0x2 if isa.zbc => insn.type_ = insn_type_t::insn_clmulr,
+                            0x3 if isa.zbc => insn.type_ = insn_type_t::insn_clmulh,
+                            0x4 if isa.zbb => insn.type_ = insn_type_t::insn_min,
+                            0x5 if isa.zbb => insn.type_ = insn_type_t::insn_minu,
+                            0x6 if isa.zbb => insn.type_ = insn_type_t::insn_max,
+                            0x7 if isa.zbb => insn.type_ = insn_type_t::insn_maxu,
+                            _ => insn.type_ = insn_type_t::insn_illegal,
+                        },
+                        0x10 if isa.zba => match funct3 {
+                            0x2 => insn.type_ = insn_type_t::insn_sh1add,
+                            0x4 => insn.type_ = insn_type_t::insn_sh2add,
+                            0x6 => insn.type_ = insn_type_t::insn_sh3add,
+                            _ => insn.type_ = insn_type_t::insn_illegal,
+                        },
+                        0x30 if isa.zbb => match funct3 {
+                            0x1 => insn.type_ = insn_type_t::insn_rol,
+                            0x5 => insn.type_ = insn_type_t::insn_ror,
+                            _ => insn.type_ = insn_type_t::insn_illegal,
+                        },
+                        0x14 if isa.zbs && funct3 == 0x1 => insn.type_ = insn_type_t::insn_bset,
+                        0x24 if isa.zbs => match funct3 {
+                            0x1 => insn.type_ = insn_type_t::insn_bclr,
+                            0x5 => insn.type_ = insn_type_t::insn_bext,
+                            _ => insn.type_ = insn_type_t::insn_illegal,
                         },
-                        _ => unreachable!(),
+                        0x34 if isa.zbs && funct3 == 0x1 => insn.type_ = insn_type_t::insn_binv,
+                        _ => insn.type_ = insn_type_t::insn_illegal,
                     }
                 }
                 0xd => {
@@ -437,7 +540,7 @@ pub fn insn_decode(insn: &mut insn_t, data: u32) {
                             0x0 => insn.type_ = insn_type_t::insn_addw,
                             0x1 => insn.type_ = insn_type_t::insn_sllw,
                             0x5 => insn.type_ = insn_type_t::insn_srlw,
-                            _ => unreachable!(),
+                            _ => insn.type_ = insn_type_t::insn_illegal,
                         },
                         0x1 => match funct3 {
                             0x0 => insn.type_ = insn_type_t::insn_mulw,
@@ -445,14 +548,32 @@ pub fn insn_decode(insn: &mut insn_t, data: u32) {
                             0x5 => insn.type_ = insn_type_t::insn_divuw,
                             0x6 => insn.type_ = insn_type_t::insn_remw,
                             0x7 => insn.type_ = insn_type_t::insn_remuw,
-                            _ => unreachable!(),
+                            _ => insn.type_ = insn_type_t::insn_illegal,
                         },
                         0x20 => match funct3 {
                             0x0 => insn.type_ = insn_type_t::insn_subw,
                             0x5 => insn.type_ = insn_type_t::insn_sraw,
-                            _ => unreachable!(),
+                            _ => insn.type_ = insn_type_t::insn_illegal,
                         },
-                        _ => unreachable!(),
+                        0x4 => match funct3 {
+                            0x0 if isa.zba => insn.type_ = insn_type_t::insn_add_uw,
+                            0x4 if isa.zbb && insn.rs2 == 0 => {
+                                insn.type_ = insn_type_t::insn_zext_h
+                            }
+                            _ => insn.type_ = insn_type_t::insn_illegal,
+                        },
+                        0x10 if isa.zba => match funct3 {
+                            0x2 => insn.type_ = insn_type_t::insn_sh1add_uw,
+                            0x4 => insn.type_ = insn_type_t::insn_sh2add_uw,
+                            0x6 => insn.type_ = insn_type_t::insn_sh3add_uw,
+                            _ => insn.type_ = insn_type_t::insn_illegal,
+                        },
+                        0x30 if isa.zbb => match funct3 {
+                            0x1 => insn.type_ = insn_type_t::insn_rolw,
+                            0x5 => insn.type_ = insn_type_t::insn_rorw,
+                            _ => insn.type_ = insn_type_t::insn_illegal,
+                        },
+                        _ => insn.type_ = insn_type_t::insn_illegal,
                     }
                 }
                 0x10 => {
@@ -461,7 +582,8 @@ pub fn insn_decode(insn: &mut insn_t, data: u32) {
                     match funct2 {
                         0x0 => insn.type_ = insn_type_t::insn_fmadd_s,
                         0x1 => insn.type_ = insn_type_t::insn_fmadd_d,
-                        _ => unreachable!(),
+                        0x2 if isa.zfh => insn.type_ = insn_type_t::insn_fmadd_h,
+                        _ => insn.type_ = insn_type_t::insn_illegal,
                     }
                 }
                 0x11 => {
@@ -470,7 +592,8 @@ pub fn insn_decode(insn: &mut insn_t, data: u32) {
                     match funct2 {
                         0x0 => insn.type_ = insn_type_t::insn_fmsub_s,
                         0x1 => insn.type_ = insn_type_t::insn_fmsub_d,
-                        _ => unreachable!(),
+                        0x2 if isa.zfh => insn.type_ = insn_type_t::insn_fmsub_h,
+                        _ => insn.type_ = insn_type_t::insn_illegal,
                     }
                 }
                 0x12 => {
@@ -479,7 +602,8 @@ pub fn insn_decode(insn: &mut insn_t, data: u32) {
                     match funct2 {
                         0x0 => insn.type_ = insn_type_t::insn_fnmsub_s,
                         0x1 => insn.type_ = insn_type_t::insn_fnmsub_d,
-                        _ => unreachable!(),
+                        0x2 if isa.zfh => insn.type_ = insn_type_t::insn_fnmsub_h,
+                        _ => insn.type_ = insn_type_t::insn_illegal,
                     }
                 }
                 0x13 => {
@@ -488,12 +612,14 @@ pub fn insn_decode(insn: &mut insn_t, data: u32) {
                     match funct2 {
                         0x0 => insn.type_ = insn_type_t::insn_fnmadd_s,
                         0x1 => insn.type_ = insn_type_t::insn_fnmadd_d,
-                        _ => unreachable!(),
+                        0x2 if isa.zfh => insn.type_ = insn_type_t::insn_fnmadd_h,
+                        _ => insn.type_ = insn_type_t::insn_illegal,
                     }
                 }
                 0x14 => {
                     let funct7 = funct7(data);
                     insn.insn_rtype_read(data);
+                    insn.rm = funct3(data) as u8;
                     match funct7 {
                         0x0 => insn.type_ = insn_type_t::insn_fadd_s,
                         0x1 => insn.type_ = insn_type_t::insn_fadd_d,
@@ -507,105 +633,142 @@ pub fn insn_decode(insn: &mut insn_t, data: u32) {
                             0x0 => insn.type_ = insn_type_t::insn_fsgnj_s,
                             0x1 => insn.type_ = insn_type_t::insn_fsgnjn_s,
                             0x2 => insn.type_ = insn_type_t::insn_fsgnjx_s,
-                            _ => unreachable!(),
+                            _ => insn.type_ = insn_type_t::insn_illegal,
                         },
                         0x11 => match funct3(data) {
                             0x0 => insn.type_ = insn_type_t::insn_fsgnj_d,
                             0x1 => insn.type_ = insn_type_t::insn_fsgnjn_d,
                             0x2 => insn.type_ = insn_type_t::insn_fsgnjx_d,
-                            _ => unreachable!(),
+                            _ => insn.type_ = insn_type_t::insn_illegal,
                         },
                         0x14 => match funct3(data) {
                             0x0 => insn.type_ = insn_type_t::insn_fmin_s,
                             0x1 => insn.type_ = insn_type_t::insn_fmax_s,
-                            _ => unreachable!(),
+                            _ => insn.type_ = insn_type_t::insn_illegal,
                         },
                         0x15 => match funct3(data) {
                             0x0 => insn.type_ = insn_type_t::insn_fmin_d,
                             0x1 => insn.type_ = insn_type_t::insn_fmax_d,
-                            _ => unreachable!(),
+                            _ => insn.type_ = insn_type_t::insn_illegal,
                         },
-                        0x20 => {
-                            assert!(rs2(data) == 1);
-                            insn.type_ = insn_type_t::insn_fcvt_s_d;
-                        }
-                        0x21 => {
-                            assert!(rs2(data) == 0);
-                            insn.type_ = insn_type_t::insn_fcvt_d_s;
-                        }
-                        0x2c => {
-                            assert!(insn.rs2 == 0);
-                            insn.type_ = insn_type_t::insn_fsqrt_s;
-                        }
-                        0x2d => {
-                            assert!(insn.rs2 == 0);
-                            insn.type_ = insn_type_t::insn_fsqrt_d;
-                        }
+                        0x20 => match rs2(data) {
+                            0x1 => insn.type_ = insn_type_t::insn_fcvt_s_d,
+                            0x2 if isa.zfhmin => insn.type_ = insn_type_t::insn_fcvt_s_h,
+                            _ => insn.type_ = insn_type_t::insn_illegal,
+                        },
+                        0x21 => match rs2(data) {
+                            0x0 => insn.type_ = insn_type_t::insn_fcvt_d_s,
+                            0x2 if isa.zfhmin => insn.type_ = insn_type_t::insn_fcvt_d_h,
+                            _ => insn.type_ = insn_type_t::insn_illegal,
+                        },
+                        0x22 if isa.zfhmin => match rs2(data) {
+                            0x0 => insn.type_ = insn_type_t::insn_fcvt_h_s,
+                            0x1 => insn.type_ = insn_type_t::insn_fcvt_h_d,
+                            _ => insn.type_ = insn_type_t::insn_illegal,
+                        },
+                        0x2c if insn.rs2 == 0 => insn.type_ = insn_type_t::insn_fsqrt_s,
+                        0x2d if insn.rs2 == 0 => insn.type_ = insn_type_t::insn_fsqrt_d,
                         0x50 => match funct3(data) {
                             0x0 => insn.type_ = insn_type_t::insn_fle_s,
                             0x1 => insn.type_ = insn_type_t::insn_flt_s,
                             0x2 => insn.type_ = insn_type_t::insn_feq_s,
-                            _ => unreachable!(),
+                            _ => insn.type_ = insn_type_t::insn_illegal,
                         },
                         0x51 => match funct3(data) {
                             0x0 => insn.type_ = insn_type_t::insn_fle_d,
                             0x1 => insn.type_ = insn_type_t::insn_flt_d,
                             0x2 => insn.type_ = insn_type_t::insn_feq_d,
-                            _ => unreachable!(),
+                            _ => insn.type_ = insn_type_t::insn_illegal,
                         },
                         0x60 => match rs2(data) {
                             0x0 => insn.type_ = insn_type_t::insn_fcvt_w_s,
                             0x1 => insn.type_ = insn_type_t::insn_fcvt_wu_s,
                             0x2 => insn.type_ = insn_type_t::insn_fcvt_l_s,
                             0x3 => insn.type_ = insn_type_t::insn_fcvt_lu_s,
-                            _ => unreachable!(),
+                            _ => insn.type_ = insn_type_t::insn_illegal,
                         },
                         0x61 => match rs2(data) {
                             0x0 => insn.type_ = insn_type_t::insn_fcvt_w_d,
                             0x1 => insn.type_ = insn_type_t::insn_fcvt_wu_d,
                             0x2 => insn.type_ = insn_type_t::insn_fcvt_l_d,
                             0x3 => insn.type_ = insn_type_t::insn_fcvt_lu_d,
-                            _ => unreachable!(),
+                            _ => insn.type_ = insn_type_t::insn_illegal,
                         },
                         0x68 => match rs2(data) {
                             0x0 => insn.type_ = insn_type_t::insn_fcvt_s_w,
                             0x1 => insn.type_ = insn_type_t::insn_fcvt_s_wu,
                             0x2 => insn.type_ = insn_type_t::insn_fcvt_s_l,
                             0x3 => insn.type_ = insn_type_t::insn_fcvt_s_lu,
-                            _ => unreachable!(),
+                            _ => insn.type_ = insn_type_t::insn_illegal,
                         },
                         0x69 => match rs2(data) {
                             0x0 => insn.type_ = insn_type_t::insn_fcvt_d_w,
                             0x1 => insn.type_ = insn_type_t::insn_fcvt_d_wu,
                             0x2 => insn.type_ = insn_type_t::insn_fcvt_d_l,
                             0x3 => insn.type_ = insn_type_t::insn_fcvt_d_lu,
-                            _ => unreachable!(),
-                        },
-                        0x70 => {
-                            assert!(rs2(data) == 0);
-                            match funct3(data) {
-                                0x0 => insn.type_ = insn_type_t::insn_fmv_x_w,
-                                0x1 => insn.type_ = insn_type_t::insn_fclass_s,
-                                _ => unreachable!(),
-                            }
-                        }
-                        0x71 => {
-                            assert!(rs2(data) == 0);
-                            match funct3(data) {
-                                0x0 => insn.type_ = insn_type_t::insn_fmv_x_d,
-                                0x1 => insn.type_ = insn_type_t::insn_fclass_d,
-                                _ => unreachable!(),
-                            }
+                            _ => insn.type_ = insn_type_t::insn_illegal,
+                        },
+                        0x70 if rs2(data) == 0 => match funct3(data) {
+                            0x0 => insn.type_ = insn_type_t::insn_fmv_x_w,
+                            0x1 => insn.type_ = insn_type_t::insn_fclass_s,
+                            _ => insn.type_ = insn_type_t::insn_illegal,
+                        },
+                        0x71 if rs2(data) == 0 => match funct3(data) {
+                            0x0 => insn.type_ = insn_type_t::insn_fmv_x_d,
+                            0x1 => insn.type_ = insn_type_t::insn_fclass_d,
+                            _ => insn.type_ = insn_type_t::insn_illegal,
+                        },
+                        0x78 if rs2(data) == 0 && funct3(data) == 0 => {
+                            insn.type_ = insn_type_t::insn_fmv_w_x
                         }
-                        0x78 => {
-                            assert!(rs2(data) == 0 && funct3(data) == 0);
-                            insn.type_ = insn_type_t::insn_fmv_w_x;
+                        0x79 if rs2(data) == 0 && funct3(data) == 0 => {
+                            insn.type_ = insn_type_t::insn_fmv_d_x
                         }
-                        0x79 => {
-                            assert!(rs2(data) == 0 && funct3(data) == 0);
-                            insn.type_ = insn_type_t::insn_fmv_d_x;
+                        0x02 if isa.zfh => insn.type_ = insn_type_t::insn_fadd_h,
+                        0x06 if isa.zfh => insn.type_ = insn_type_t::insn_fsub_h,
+                        0x0a if isa.zfh => insn.type_ = insn_type_t::insn_fmul_h,
+                        0x0e if isa.zfh => insn.type_ = insn_type_t::insn_fdiv_h,
+                        0x12 if isa.zfh => match funct3(data) {
+                            0x0 => insn.type_ = insn_type_t::insn_fsgnj_h,
+                            0x1 => insn.type_ = insn_type_t::insn_fsgnjn_h,
+                            0x2 => insn.type_ = insn_type_t::insn_fsgnjx_h,
+                            _ => insn.type_ = insn_type_t::insn_illegal,
+                        },
+                        0x16 if isa.zfh => match funct3(data) {
+                            0x0 => insn.type_ = insn_type_t::insn_fmin_h,
+                            0x1 => insn.type_ = insn_type_t::insn_fmax_h,
+                            _ => insn.type_ = insn_type_t::insn_illegal,
+                        },
+                        0x2e if isa.zfh && rs2(data) == 0 => insn.type_ = insn_type_t::insn_fsqrt_h,
+                        0x52 if isa.zfh => match funct3(data) {
+                            0x0 => insn.type_ = insn_type_t::insn_fle_h,
+                            0x1 => insn.type_ = insn_type_t::insn_flt_h,
+                            0x2 => insn.type_ = insn_type_t::insn_feq_h,
+                            _ => insn.type_ = insn_type_t::insn_illegal,
+                        },
+                        0x62 if isa.zfh => match rs2(data) {
+                            0x0 => insn.type_ = insn_type_t::insn_fcvt_w_h,
+                            0x1 => insn.type_ = insn_type_t::insn_fcvt_wu_h,
+                            0x2 => insn.type_ = insn_type_t::insn_fcvt_l_h,
+                            0x3 => insn.type_ = insn_type_t::insn_fcvt_lu_h,
+                            _ => insn.type_ = insn_type_t::insn_illegal,
+                        },
+                        0x6a if isa.zfh => match rs2(data) {
+                            0x0 => insn.type_ = insn_type_t::insn_fcvt_h_w,
+                            0x1 => insn.type_ = insn_type_t::insn_fcvt_h_wu,
+                            0x2 => insn.type_ = insn_type_t::insn_fcvt_h_l,
+                            0x3 => insn.type_ = insn_type_t::insn_fcvt_h_lu,
+                            _ => insn.type_ = insn_type_t::insn_illegal,
+                        },
+                        0x72 if isa.zfhmin && rs2(data) == 0 => match funct3(data) {
+                            0x0 => insn.type_ = insn_type_t::insn_fmv_x_h,
+                            0x1 if isa.zfh => insn.type_ = insn_type_t::insn_fclass_h,
+                            _ => insn.type_ = insn_type_t::insn_illegal,
+                        },
+                        0x7a if isa.zfhmin && rs2(data) == 0 && funct3(data) == 0 => {
+                            insn.type_ = insn_type_t::insn_fmv_h_x
                         }
-                        _ => unreachable!(),
+                        _ => insn.type_ = insn_type_t::insn_illegal,
                     }
                 }
                 0x18 => {
@@ -618,10 +781,10 @@ pub fn insn_decode(insn: &mut insn_t, data: u32) {
                         0x5 => insn.type_ = insn_type_t::insn_bge,
                         0x6 => insn.type_ = insn_type_t::insn_bltu,
                         0x7 => insn.type_ = insn_type_t::insn_bgeu,
-                        _ => unreachable!(),
+                        _ => insn.type_ = insn_type_t::insn_illegal,
                     }
                 }
-                0x19 => {
+                0x19 if funct3(data) == 0 => {
                     // JALR
                     insn.insn_itype_read(data);
                     insn.type_ = insn_type_t::insn_jalr;
@@ -634,11 +797,16 @@ pub fn insn_decode(insn: &mut insn_t, data: u32) {
                     insn.type_ = insn_type_t::insn_jal;
                     insn.cont = true;
                 }
+                0x15 if isa.v => opv_decode(insn, data),
                 0x1c => {
                     if data == 0x73 {
                         // ECALL
                         insn.type_ = insn_type_t::insn_ecall;
                         insn.cont = true;
+                    } else if data == 0x100073 {
+                        // EBREAK
+                        insn.type_ = insn_type_t::insn_ebreak;
+                        insn.cont = true;
                     } else {
                         let funct3 = funct3(data);
                         insn.insn_csrtype_read(data);
@@ -649,17 +817,154 @@ pub fn insn_decode(insn: &mut insn_t, data: u32) {
                             0x5 => insn.type_ = insn_type_t::insn_csrrwi,
                             0x6 => insn.type_ = insn_type_t::insn_csrrsi,
                             0x7 => insn.type_ = insn_type_t::insn_csrrci,
-                            _ => unreachable!(),
+                            _ => insn.type_ = insn_type_t::insn_illegal,
                         }
                     }
                 }
-                _ => unreachable!(),
+                _ => insn.type_ = insn_type_t::insn_illegal,
             }
         }
-        _ => unreachable!(),
+        _ => insn.type_ = insn_type_t::insn_illegal,
     }
     // println!("insn_type={:?}", insn.type_ as insn_type_t);
     // println!("type={:#}", insn.type_ as u32);
     // println!("{:?}", insn);
     // println!("{} {} {} {} {} {} {} {} {}", insn.rd, insn.rs1, insn.rs2, insn.rs3, insn.imm, insn.csr, insn.type_ as u32, insn.rvc as u32, insn.cont as u32);
 }
+
+/*
+    vector loads and stores, imm is the element width in bytes
+*/
+fn vmem_decode(insn: &mut insn_t, data: u32, store: bool) {
+    insn.insn_vtype_read(data);
+    insn.imm = match funct3(data) {
+        0x0 => 1,
+        0x5 => 2,
+        0x6 => 4,
+        _ => 8,
+    };
+    insn.nf = (data >> 29) as u8;
+    let mew = (data >> 28) & 0x1;
+    let mop = (data >> 26) & 0x3;
+    insn.type_ = match (store, mew, mop, insn.rs2) {
+        (false, 0, 0x0, 0x00) => insn_type_t::insn_vle,
+        (false, 0, 0x0, 0x08) => insn_type_t::insn_vlr,
+        (false, 0, 0x0, 0x0b) => insn_type_t::insn_vlm,
+        (false, 0, 0x0, 0x10) => insn_type_t::insn_vleff,
+        (false, 0, 0x2, _) => insn_type_t::insn_vlse,
+        (false, 0, 0x1 | 0x3, _) => insn_type_t::insn_vlxei,
+        (true, 0, 0x0, 0x00) => insn_type_t::insn_vse,
+        (true, 0, 0x0, 0x08) => insn_type_t::insn_vsr,
+        (true, 0, 0x0, 0x0b) => insn_type_t::insn_vsm,
+        (true, 0, 0x2, _) => insn_type_t::insn_vsse,
+        (true, 0, 0x1 | 0x3, _) => insn_type_t::insn_vsxei,
+        _ => insn_type_t::insn_illegal,
+    };
+}
+
+/*
+    OP-V, funct3 picks the operand category and funct6 the operation,
+    which the vector unit dispatches on
+*/
+fn opv_decode(insn: &mut insn_t, data: u32) {
+    insn.insn_vtype_read(data);
+    let funct3 = funct3(data);
+    if funct3 == 0x7 {
+        if data >> 31 == 0 {
+            insn.type_ = insn_type_t::insn_vsetvli;
+            insn.imm = ((data >> 20) & 0x7ff) as i32;
+        } else if data >> 30 == 0x3 {
+            insn.type_ = insn_type_t::insn_vsetivli;
+            insn.imm = ((data >> 20) & 0x3ff) as i32;
+        } else if data >> 25 == 0x40 {
+            insn.type_ = insn_type_t::insn_vsetvl;
+        } else {
+            insn.type_ = insn_type_t::insn_illegal;
+        }
+        return;
+    }
+
+    let (f6, vs1, vs2) = (insn.funct6, insn.rs1, insn.rs2);
+    let defined = match funct3 {
+        // OPIVV
+        0x0 => {
+            matches!(f6, 0x00 | 0x02 | 0x04..=0x07 | 0x09..=0x0c | 0x0e | 0x10..=0x13)
+                || matches!(f6, 0x17..=0x1d | 0x20..=0x23 | 0x25 | 0x27..=0x31)
+        }
+        // OPIVX
+        0x4 => {
+            matches!(f6, 0x00 | 0x02..=0x07 | 0x09..=0x0c | 0x0e..=0x13 | 0x17..=0x23)
+                || matches!(f6, 0x25 | 0x27..=0x2f)
+        }
+        // OPIVI, vmv<nr>r takes 1, 2, 4 or 8 registers
+        0x3 => match f6 {
+            0x27 => matches!(vs1, 0 | 1 | 3 | 7),
+            _ => {
+                matches!(f6, 0x00 | 0x03 | 0x09..=0x0c | 0x0e..=0x11 | 0x17..=0x19)
+                    || matches!(f6, 0x1c..=0x21 | 0x25 | 0x28..=0x2f)
+            }
+        },
+        // OPMVV
+        0x2 => match f6 {
+            0x10 => matches!(vs1, 0x00 | 0x10 | 0x11),
+            0x12 => matches!(vs1, 0x02..=0x07),
+            0x14 => matches!(vs1, 0x01..=0x03 | 0x10 | 0x11),
+            _ => {
+                matches!(f6, 0x00..=0x0b | 0x17..=0x27 | 0x29 | 0x2b | 0x2d | 0x2f)
+                    || matches!(f6, 0x30..=0x38 | 0x3a..=0x3d | 0x3f)
+            }
+        },
+        // OPMVX
+        0x6 => match f6 {
+            0x10 => vs2 == 0,
+            _ => {
+                matches!(f6, 0x08..=0x0b | 0x0e | 0x0f | 0x20..=0x27 | 0x29 | 0x2b | 0x2d)
+                    || matches!(f6, 0x2f..=0x38 | 0x3a..=0x3f)
+            }
+        },
+        // OPFVV, without the vfrsqrt7 and vfrec7 estimates
+        0x1 => match f6 {
+            0x10 => vs1 == 0,
+            0x12 => matches!(vs1, 0x00..=0x03 | 0x06..=0x0c | 0x0e..=0x17),
+            0x13 => matches!(vs1, 0x00 | 0x10),
+            _ => {
+                matches!(f6, 0x00..=0x0a | 0x18 | 0x19 | 0x1b | 0x1c | 0x20 | 0x24)
+                    || matches!(f6, 0x28..=0x34 | 0x36 | 0x38 | 0x3c..=0x3f)
+            }
+        },
+        // OPFVF
+        _ => match f6 {
+            0x10 => vs2 == 0,
+            _ => {
+                matches!(
+                    f6,
+                    0x00 | 0x02 | 0x04 | 0x06 | 0x08..=0x0a | 0x0e | 0x0f | 0x17
+                ) || matches!(
+                    f6,
+                    0x18 | 0x19 | 0x1b..=0x1d | 0x1f | 0x20 | 0x21 | 0x24 | 0x27
+                ) || matches!(f6, 0x28..=0x30 | 0x32 | 0x34 | 0x36 | 0x38 | 0x3c..=0x3f)
+            }
+        },
+    };
+    if !defined {
+        insn.type_ = insn_type_t::insn_illegal;
+        return;
+    }
+
+    insn.type_ = match funct3 {
+        0x0 => insn_type_t::insn_opivv,
+        0x1 => insn_type_t::insn_opfvv,
+        0x2 => insn_type_t::insn_opmvv,
+        0x3 => insn_type_t::insn_opivi,
+        0x4 => insn_type_t::insn_opivx,
+        0x5 => insn_type_t::insn_opfvf,
+        _ => insn_type_t::insn_opmvx,
+    };
+    if funct3 == 0x3 {
+        // shifts, slides, gathers and vmv<nr>r take the immediate unsigned
+        insn.imm = match f6 {
+            0x0c | 0x0e | 0x0f | 0x25 | 0x27..=0x2f => vs1 as i32,
+            _ => ((vs1 as i32) << 27) >> 27,
+        };
+    }
+}
@@ -1,7 +1,9 @@
 use std::{
+    collections::{HashMap, HashSet},
     fs::File,
     io::{Read, Seek},
     mem,
+    os::unix::fs::FileExt,
 };
 
 use libc::{PROT_EXEC, PROT_READ, PROT_WRITE};
@@ -18,6 +20,11 @@ pub const ELFCLASS64: u8 = 2;
 pub const ELFCLASSNUM: u8 = 3;
 
 pub const PT_LOAD: u32 = 1;
+pub const PT_INTERP: u32 = 3;
+pub const PT_TLS: u32 = 7;
+pub const PT_GNU_STACK: u32 = 0x6474e551;
+
+pub const SHT_SYMTAB: u32 = 2;
 
 pub const PF_X: u32 = 0x1;
 pub const PF_W: u32 = 0x2;
@@ -53,6 +60,30 @@ pub struct elf64_phdr_t {
     pub p_align: u64,
 }
 
+#[repr(C)]
+pub struct elf64_shdr_t {
+    pub sh_name: u32,
+    pub sh_type: u32,
+    pub sh_flags: u64,
+    pub sh_addr: u64,
+    pub sh_offset: u64,
+    pub sh_size: u64,
+    pub sh_link: u32,
+    pub sh_info: u32,
+    pub sh_addralign: u64,
+    pub sh_entsize: u64,
+}
+
+#[repr(C)]
+pub struct elf64_sym_t {
+    pub st_name: u32,
+    pub st_info: u8,
+    pub st_other: u8,
+    pub st_shndx: u16,
+    pub st_value: u64,
+    pub st_size: u64,
+}
+
 pub fn flags_to_mmap_prot(flags: u32) -> i32 {
     (if flags & PF_R != 0 { PROT_READ } else { 0 })
         | (if flags & PF_W != 0 { PROT_WRITE } else { 0 })
@@ -69,3 +100,67 @@ impl elf64_phdr_t {
         *self = unsafe { mem::transmute(buf_phdr_t) };
     }
 }
+
+// read a plain #[repr(C)] ELF structure at offset
+fn read_struct<T>(elf: &File, offset: u64) -> Option<T> {
+    let mut buf = vec![0u8; mem::size_of::<T>()];
+    elf.read_exact_at(&mut buf, offset).ok()?;
+    Some(unsafe { std::ptr::read_unaligned(buf.as_ptr() as *const T) })
+}
+
+// p_type of every program header
+pub fn elf_phdr_types(elf: &File) -> Vec<u32> {
+    let Some(ehdr) = read_struct::<elf64_ehdr_t>(elf, 0) else {
+        return Vec::new();
+    };
+    (0..ehdr.e_phnum as u64)
+        .filter_map(|i| {
+            read_struct::<elf64_phdr_t>(elf, ehdr.e_phoff + i * ehdr.e_phentsize as u64)
+        })
+        .map(|phdr| phdr.p_type)
+        .collect()
+}
+
+// names of the symbols in .symtab, empty for stripped files
+pub fn elf_symbol_names(elf: &File) -> HashSet<Vec<u8>> {
+    elf_symbols(elf).into_keys().collect()
+}
+
+// values of the symbols in .symtab by name
+pub fn elf_symbols(elf: &File) -> HashMap<Vec<u8>, u64> {
+    let mut symbols = HashMap::new();
+    let Some(ehdr) = read_struct::<elf64_ehdr_t>(elf, 0) else {
+        return symbols;
+    };
+    let shdr_at =
+        |i: u64| read_struct::<elf64_shdr_t>(elf, ehdr.e_shoff + i * ehdr.e_shentsize as u64);
+    let Some(symtab) = (0..ehdr.e_shnum as u64)
+        .filter_map(shdr_at)
+        .find(|shdr| shdr.sh_type == SHT_SYMTAB)
+    else {
+        return symbols;
+    };
+    let Some(strtab) = shdr_at(symtab.sh_link as u64) else {
+        return symbols;
+    };
+    let mut strs = vec![0u8; strtab.sh_size as usize];
+    if elf.read_exact_at(&mut strs, strtab.sh_offset).is_err() {
+        return symbols;
+    }
+
+    let mut syms = vec![0u8; symtab.sh_size as usize];
+    if elf.read_exact_at(&mut syms, symtab.sh_offset).is_err() {
+        return symbols;
+    }
+
+    for sym in syms.chunks_exact(mem::size_of::<elf64_sym_t>()) {
+        let sym = unsafe { std::ptr::read_unaligned(sym.as_ptr() as *const elf64_sym_t) };
+        if let Some(name) = strs.get(sym.st_name as usize..) {
+            let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+            if len > 0 {
+                symbols.insert(name[..len].to_vec(), sym.st_value);
+            }
+        }
+    }
+    symbols
+}
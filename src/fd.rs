@@ -0,0 +1,144 @@
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
+
+use libc::{EBADF, EMFILE};
+
+use crate::vfs::{host_file_t, vfile_t};
+
+// an open file description, shared by every fd dup'ed from it
+pub type file_ref_t = Rc<RefCell<Box<dyn vfile_t>>>;
+
+struct fd_entry_t {
+    file: file_ref_t,
+    cloexec: bool,
+}
+
+/*
+    guest file descriptor table, guest fds never refer to emulator
+    descriptors directly. kept sparse, so a dup3 to a high fd costs no
+    more than a low one.
+*/
+pub struct fd_table_t {
+    fds: BTreeMap<u64, fd_entry_t>,
+    pub limit: u64, // RLIMIT_NOFILE
+}
+
+pub const MAX_FDS: u64 = 1024;
+
+impl fd_table_t {
+    // guest stdin, stdout and stderr borrowed from the emulator
+    pub fn new() -> fd_table_t {
+        let mut table = fd_table_t {
+            fds: BTreeMap::new(),
+            limit: MAX_FDS,
+        };
+        for fd in 0..3 {
+            table.set(fd, Box::new(host_file_t::borrowed(fd as i32)), false);
+        }
+        table
+    }
+
+    pub fn get(&self, fd: u64) -> Result<file_ref_t, i32> {
+        match self.fds.get(&fd) {
+            Some(entry) => Ok(entry.file.clone()),
+            _ => Err(EBADF),
+        }
+    }
+
+    // install a file at the lowest free fd
+    pub fn alloc(&mut self, file: Box<dyn vfile_t>, cloexec: bool) -> Result<u64, i32> {
+        self.alloc_entry(
+            fd_entry_t {
+                file: Rc::new(RefCell::new(file)),
+                cloexec,
+            },
+            0,
+        )
+    }
+
+    // lowest free fd not below min_fd
+    fn alloc_entry(&mut self, entry: fd_entry_t, min_fd: u64) -> Result<u64, i32> {
+        // the first gap in the fds from min_fd on
+        let mut fd = min_fd;
+        for &used in self.fds.range(min_fd..).map(|(fd, _)| fd) {
+            if used != fd {
+                break;
+            }
+            fd += 1;
+        }
+        if fd >= self.limit {
+            return Err(EMFILE);
+        }
+        self.place(fd, entry);
+        Ok(fd)
+    }
+
+    fn place(&mut self, fd: u64, entry: fd_entry_t) {
+        self.fds.insert(fd, entry);
+    }
+
+    // replace whatever fd refers to, e.g. to redirect guest stdio
+    pub fn set(&mut self, fd: u64, file: Box<dyn vfile_t>, cloexec: bool) {
+        self.place(
+            fd,
+            fd_entry_t {
+                file: Rc::new(RefCell::new(file)),
+                cloexec,
+            },
+        );
+    }
+
+    pub fn close(&mut self, fd: u64) -> Result<(), i32> {
+        match self.fds.remove(&fd) {
+            Some(_) => Ok(()),
+            None => Err(EBADF),
+        }
+    }
+
+    // dup and fcntl(F_DUPFD)
+    pub fn dup(&mut self, oldfd: u64, min_fd: u64, cloexec: bool) -> Result<u64, i32> {
+        let file = self.get(oldfd)?;
+        self.alloc_entry(fd_entry_t { file, cloexec }, min_fd)
+    }
+
+    // dup3, newfd is closed silently if open
+    pub fn dup_to(&mut self, oldfd: u64, newfd: u64, cloexec: bool) -> Result<u64, i32> {
+        let file = self.get(oldfd)?;
+        if newfd >= self.limit {
+            return Err(EBADF);
+        }
+        self.place(newfd, fd_entry_t { file, cloexec });
+        Ok(newfd)
+    }
+
+    pub fn get_cloexec(&self, fd: u64) -> Result<bool, i32> {
+        match self.fds.get(&fd) {
+            Some(entry) => Ok(entry.cloexec),
+            _ => Err(EBADF),
+        }
+    }
+
+    pub fn set_cloexec(&mut self, fd: u64, cloexec: bool) -> Result<(), i32> {
+        match self.fds.get_mut(&fd) {
+            Some(entry) => {
+                entry.cloexec = cloexec;
+                Ok(())
+            }
+            _ => Err(EBADF),
+        }
+    }
+
+    // (guest fd, host fd) of the host backed fds that survive execve
+    pub fn inherited(&self) -> Vec<(u64, i32)> {
+        self.fds
+            .iter()
+            .filter(|(_, entry)| !entry.cloexec)
+            .filter_map(|(&fd, entry)| entry.file.borrow().host_fd().map(|host_fd| (fd, host_fd)))
+            .collect()
+    }
+}
+
+impl Default for fd_table_t {
+    fn default() -> fd_table_t {
+        fd_table_t::new()
+    }
+}
@@ -0,0 +1,501 @@
+/*
+    IEEE 754 binary16, binary32 and binary64 in software for Zfh, F and D,
+    so that every rounding mode works and fflags accrue exactly as on
+    hardware. values travel as raw bits in a u64 and fmt_t says which
+    format they are in. a finite value is sig * 2^exp with an integer sig,
+    and results are rounded once by round(). sums keep a sticky bit for what
+    is shifted out, which is enough for one correct rounding.
+*/
+
+// fflags
+pub const NX: u64 = 0x01; // inexact
+pub const UF: u64 = 0x02; // underflow
+pub const OF: u64 = 0x04; // overflow
+pub const DZ: u64 = 0x08; // divide by zero
+pub const NV: u64 = 0x10; // invalid operation
+
+// rounding modes
+pub const RNE: u8 = 0;
+pub const RTZ: u8 = 1;
+pub const RDN: u8 = 2;
+pub const RUP: u8 = 3;
+pub const RMM: u8 = 4;
+
+pub struct fmt_t {
+    pub exp_bits: u32,
+    pub man_bits: u32,
+}
+
+pub const F16: fmt_t = fmt_t {
+    exp_bits: 5,
+    man_bits: 10,
+};
+
+pub const F32: fmt_t = fmt_t {
+    exp_bits: 8,
+    man_bits: 23,
+};
+
+pub const F64: fmt_t = fmt_t {
+    exp_bits: 11,
+    man_bits: 52,
+};
+
+impl fmt_t {
+    // the width of a value, below that the register is NaN-boxed
+    pub fn bits(&self) -> u32 {
+        1 + self.exp_bits + self.man_bits
+    }
+
+    fn sign(&self) -> u64 {
+        1 << (self.exp_bits + self.man_bits)
+    }
+
+    fn inf(&self) -> u64 {
+        ((1 << self.exp_bits) - 1) << self.man_bits
+    }
+
+    fn bias(&self) -> i32 {
+        (1 << (self.exp_bits - 1)) - 1
+    }
+
+    // the quiet bit alone, what RISC-V returns for any NaN result
+    pub fn canonical_nan(&self) -> u64 {
+        self.inf() | 1 << (self.man_bits - 1)
+    }
+
+    fn is_nan(&self, x: u64) -> bool {
+        x & !self.sign() > self.inf()
+    }
+
+    fn is_snan(&self, x: u64) -> bool {
+        self.is_nan(x) && x & (1 << (self.man_bits - 1)) == 0
+    }
+
+    fn is_inf(&self, x: u64) -> bool {
+        x & !self.sign() == self.inf()
+    }
+
+    fn is_zero(&self, x: u64) -> bool {
+        x & !self.sign() == 0
+    }
+
+    fn is_neg(&self, x: u64) -> bool {
+        x & self.sign() != 0
+    }
+
+    fn signed(&self, sign: bool, x: u64) -> u64 {
+        if sign { self.sign() | x } else { x }
+    }
+
+    // significand and exponent of a finite value, its magnitude is sig * 2^exp
+    fn unpack(&self, x: u64) -> (u128, i32) {
+        let exp = ((x >> self.man_bits) & ((1 << self.exp_bits) - 1)) as i32;
+        let mant = (x & ((1 << self.man_bits) - 1)) as u128;
+        let shift = self.bias() + self.man_bits as i32;
+        match exp {
+            0 => (mant, 1 - shift),
+            _ => (mant | 1 << self.man_bits, exp - shift),
+        }
+    }
+
+    // the fclass bit, for the scalar and the vector instructions
+    pub fn class(&self, x: u64) -> u64 {
+        let mag = x & !self.sign();
+        // the bits of negative and positive values
+        let (neg, pos) = match mag {
+            _ if self.is_snan(x) => (8, 8),
+            _ if self.is_nan(x) => (9, 9),
+            _ if self.is_inf(x) => (0, 7),
+            0 => (3, 4),
+            _ if mag >> self.man_bits == 0 => (2, 5), // subnormal
+            _ => (1, 6),
+        };
+        1 << if self.is_neg(x) { neg } else { pos }
+    }
+
+    // magnitudes order like the bits, so this orders numbers with -0 == +0
+    fn key(&self, x: u64) -> i128 {
+        let mag = (x & !self.sign()) as i128;
+        if self.is_neg(x) { -mag } else { mag }
+    }
+}
+
+/*
+    drop the low shift bits of sig by rm, returns what is kept and whether
+    anything nonzero was dropped
+*/
+fn round_shift(sig: u128, shift: i32, sign: bool, rm: u8) -> (u128, bool) {
+    if shift <= 0 {
+        return (sig << -shift, false);
+    }
+    let (kept, rem) = match shift {
+        128.. => (0, sig),
+        _ => (sig >> shift, sig & ((1 << shift) - 1)),
+    };
+    // past 128 bits the dropped part is always below half an ulp
+    let half = match shift {
+        129.. => None,
+        _ => Some(1u128 << (shift - 1)),
+    };
+    let up = match rm {
+        RNE => half.is_some_and(|h| rem > h || (rem == h && kept & 1 == 1)),
+        RMM => half.is_some_and(|h| rem >= h),
+        RDN => sign && rem != 0,
+        RUP => !sign && rem != 0,
+        _ => false,
+    };
+    (kept + up as u128, rem != 0)
+}
+
+/*
+    round (-1)^sign * sig * 2^exp to f. tininess is detected after
+    rounding, as RISC-V requires.
+*/
+fn round(f: &fmt_t, sign: bool, sig: u128, exp: i32, rm: u8, flags: &mut u64) -> u64 {
+    if sig == 0 {
+        return f.signed(sign, 0);
+    }
+    let man = f.man_bits as i32;
+    let emin = 1 - f.bias();
+    // the value lies in [2^e, 2^(e + 1))
+    let e = exp + 127 - sig.leading_zeros() as i32;
+    if e > f.bias() {
+        return overflow(f, sign, rm, flags);
+    }
+
+    // man + 1 bits for normals, subnormals keep everything down to 2^(emin - man)
+    let (m, inexact) = round_shift(sig, (e - man).max(emin - man) - exp, sign, rm);
+    let bits = match e >= emin {
+        true => (((e - emin) as u128) << man) + m,
+        false => m,
+    };
+    if bits >= f.inf() as u128 {
+        return overflow(f, sign, rm, flags);
+    }
+    if inexact {
+        *flags |= NX;
+        // tiny unless rounding with an unbounded exponent reaches 2^emin
+        let tiny = e < emin - 1
+            || (e == emin - 1 && round_shift(sig, e - man - exp, sign, rm).0 < 2 << man);
+        if tiny {
+            *flags |= UF;
+        }
+    }
+    f.signed(sign, bits as u64)
+}
+
+fn overflow(f: &fmt_t, sign: bool, rm: u8, flags: &mut u64) -> u64 {
+    *flags |= OF | NX;
+    let inf = match rm {
+        RTZ => false,
+        RDN => sign,
+        RUP => !sign,
+        _ => true,
+    };
+    f.signed(sign, if inf { f.inf() } else { f.inf() - 1 })
+}
+
+/*
+    (-1)^sa * a * 2^ea + (-1)^sb * b * 2^eb. the larger operand is moved to
+    the top of 126 bits and whatever the other loses below that sets the
+    lowest bit, which leaves enough guard bits for any rounding.
+*/
+fn sum(sa: bool, a: u128, ea: i32, sb: bool, b: u128, eb: i32) -> (bool, u128, i32) {
+    if a == 0 {
+        return (sb, b, eb);
+    }
+    if b == 0 {
+        return (sa, a, ea);
+    }
+    let top = |x: u128, e: i32| e + 127 - x.leading_zeros() as i32;
+    let ((sa, a, ea), (sb, b, eb)) = match top(a, ea) >= top(b, eb) {
+        true => ((sa, a, ea), (sb, b, eb)),
+        false => ((sb, b, eb), (sa, a, ea)),
+    };
+    let shift = a.leading_zeros() as i32 - 2;
+    let (a, ea) = (a << shift, ea - shift);
+    let b = match ea - eb {
+        d if d <= 0 => b << -d,
+        d if d >= 128 => 1,
+        d => (b >> d) | (b & ((1 << d) - 1) != 0) as u128,
+    };
+    match (sa == sb, a >= b) {
+        (true, _) => (sa, a + b, ea),
+        (false, true) => (sa, a - b, ea),
+        (false, false) => (sb, b - a, ea),
+    }
+}
+
+// an exact zero sum is -0 only from two -0s, or when rounding down
+fn zero_sign(sa: bool, sb: bool, rm: u8) -> bool {
+    if sa == sb { sa } else { rm == RDN }
+}
+
+fn nan_operands(f: &fmt_t, ops: &[u64], flags: &mut u64) -> bool {
+    if ops.iter().any(|&x| f.is_snan(x)) {
+        *flags |= NV;
+    }
+    ops.iter().any(|&x| f.is_nan(x))
+}
+
+fn invalid(f: &fmt_t, flags: &mut u64) -> u64 {
+    *flags |= NV;
+    f.canonical_nan()
+}
+
+/*
+    arithmetic
+*/
+pub fn add(f: &fmt_t, a: u64, b: u64, rm: u8, flags: &mut u64) -> u64 {
+    if nan_operands(f, &[a, b], flags) {
+        return f.canonical_nan();
+    }
+    let (na, nb) = (f.is_neg(a), f.is_neg(b));
+    match (f.is_inf(a), f.is_inf(b)) {
+        (true, true) if na != nb => return invalid(f, flags),
+        (true, _) => return a,
+        (_, true) => return b,
+        _ => {}
+    }
+    let ((sa, ea), (sb, eb)) = (f.unpack(a), f.unpack(b));
+    match sum(na, sa, ea, nb, sb, eb) {
+        (_, 0, _) => f.signed(zero_sign(na, nb, rm), 0),
+        (sign, sig, exp) => round(f, sign, sig, exp, rm, flags),
+    }
+}
+
+pub fn sub(f: &fmt_t, a: u64, b: u64, rm: u8, flags: &mut u64) -> u64 {
+    add(f, a, b ^ f.sign(), rm, flags)
+}
+
+pub fn mul(f: &fmt_t, a: u64, b: u64, rm: u8, flags: &mut u64) -> u64 {
+    if nan_operands(f, &[a, b], flags) {
+        return f.canonical_nan();
+    }
+    let sign = f.is_neg(a) != f.is_neg(b);
+    if f.is_inf(a) || f.is_inf(b) {
+        if f.is_zero(a) || f.is_zero(b) {
+            return invalid(f, flags);
+        }
+        return f.signed(sign, f.inf());
+    }
+    let ((sa, ea), (sb, eb)) = (f.unpack(a), f.unpack(b));
+    round(f, sign, sa * sb, ea + eb, rm, flags)
+}
+
+pub fn div(f: &fmt_t, a: u64, b: u64, rm: u8, flags: &mut u64) -> u64 {
+    if nan_operands(f, &[a, b], flags) {
+        return f.canonical_nan();
+    }
+    let sign = f.is_neg(a) != f.is_neg(b);
+    match (f.is_inf(a), f.is_inf(b), f.is_zero(a), f.is_zero(b)) {
+        (true, true, _, _) | (_, _, true, true) => return invalid(f, flags),
+        (true, _, _, _) => return f.signed(sign, f.inf()),
+        (_, true, _, _) | (_, _, true, _) => return f.signed(sign, 0),
+        (_, _, _, true) => {
+            *flags |= DZ;
+            return f.signed(sign, f.inf());
+        }
+        _ => {}
+    }
+    // a quotient of at least 73 bits, the remainder is sticky
+    let ((sa, ea), (sb, eb)) = (f.unpack(a), f.unpack(b));
+    let shift = sa.leading_zeros() as i32 - 1;
+    let n = sa << shift;
+    let q = (n / sb) | !n.is_multiple_of(sb) as u128;
+    round(f, sign, q, ea - shift - eb, rm, flags)
+}
+
+pub fn sqrt(f: &fmt_t, a: u64, rm: u8, flags: &mut u64) -> u64 {
+    if nan_operands(f, &[a], flags) {
+        return f.canonical_nan();
+    }
+    if f.is_zero(a) {
+        return a;
+    }
+    if f.is_neg(a) {
+        return invalid(f, flags);
+    }
+    if f.is_inf(a) {
+        return a;
+    }
+    // 125 or 126 bits with an even exponent give a 63 bit root
+    let (sig, exp) = f.unpack(a);
+    let mut shift = sig.leading_zeros() as i32 - 3;
+    if (exp - shift) % 2 != 0 {
+        shift += 1;
+    }
+    let n = sig << shift;
+    let r = isqrt(n);
+    round(
+        f,
+        false,
+        r | (r * r != n) as u128,
+        (exp - shift) / 2,
+        rm,
+        flags,
+    )
+}
+
+// floor(sqrt(n)), from the f64 estimate and one Newton step
+fn isqrt(n: u128) -> u128 {
+    let mut r = (n as f64).sqrt() as u128;
+    r = (r + n / r) / 2;
+    while r * r > n {
+        r -= 1;
+    }
+    while (r + 1) * (r + 1) <= n {
+        r += 1;
+    }
+    r
+}
+
+// a * b + c with a single rounding, the fused forms flip the signs first
+pub fn fma(f: &fmt_t, a: u64, b: u64, c: u64, rm: u8, flags: &mut u64) -> u64 {
+    let nan = nan_operands(f, &[a, b, c], flags);
+    // inf * 0 is invalid even when c is a quiet NaN
+    if (f.is_inf(a) && f.is_zero(b)) || (f.is_zero(a) && f.is_inf(b)) {
+        return invalid(f, flags);
+    }
+    if nan {
+        return f.canonical_nan();
+    }
+    let psign = f.is_neg(a) != f.is_neg(b);
+    let csign = f.is_neg(c);
+    match (f.is_inf(a) || f.is_inf(b), f.is_inf(c)) {
+        (true, true) if psign != csign => return invalid(f, flags),
+        (true, _) => return f.signed(psign, f.inf()),
+        (_, true) => return c,
+        _ => {}
+    }
+    let ((sa, ea), (sb, eb), (sc, ec)) = (f.unpack(a), f.unpack(b), f.unpack(c));
+    match sum(psign, sa * sb, ea + eb, csign, sc, ec) {
+        (_, 0, _) => f.signed(zero_sign(psign, csign, rm), 0),
+        (sign, sig, exp) => round(f, sign, sig, exp, rm, flags),
+    }
+}
+
+/*
+    minimumNumber and maximumNumber, a NaN loses to a number and -0 < +0
+*/
+pub fn min(f: &fmt_t, a: u64, b: u64, flags: &mut u64) -> u64 {
+    minmax(f, a, b, true, flags)
+}
+
+pub fn max(f: &fmt_t, a: u64, b: u64, flags: &mut u64) -> u64 {
+    minmax(f, a, b, false, flags)
+}
+
+fn minmax(f: &fmt_t, a: u64, b: u64, min: bool, flags: &mut u64) -> u64 {
+    nan_operands(f, &[a, b], flags);
+    match (f.is_nan(a), f.is_nan(b)) {
+        (true, true) => f.canonical_nan(),
+        (true, false) => b,
+        (false, true) => a,
+        // only the zeros differ, pick by sign
+        _ if f.key(a) == f.key(b) => {
+            if f.is_neg(a) == min {
+                a
+            } else {
+                b
+            }
+        }
+        _ if (f.key(a) < f.key(b)) == min => a,
+        _ => b,
+    }
+}
+
+/*
+    comparisons, feq only signals on signaling NaNs
+*/
+pub fn eq(f: &fmt_t, a: u64, b: u64, flags: &mut u64) -> bool {
+    !nan_operands(f, &[a, b], flags) && f.key(a) == f.key(b)
+}
+
+pub fn lt(f: &fmt_t, a: u64, b: u64, flags: &mut u64) -> bool {
+    if f.is_nan(a) || f.is_nan(b) {
+        *flags |= NV;
+        return false;
+    }
+    f.key(a) < f.key(b)
+}
+
+pub fn le(f: &fmt_t, a: u64, b: u64, flags: &mut u64) -> bool {
+    if f.is_nan(a) || f.is_nan(b) {
+        *flags |= NV;
+        return false;
+    }
+    f.key(a) <= f.key(b)
+}
+
+/*
+    conversions
+*/
+
+// between formats, NaNs become the canonical NaN of the result
+pub fn convert(from: &fmt_t, to: &fmt_t, x: u64, rm: u8, flags: &mut u64) -> u64 {
+    let sign = from.is_neg(x);
+    if from.is_nan(x) {
+        if from.is_snan(x) {
+            *flags |= NV;
+        }
+        return to.canonical_nan();
+    }
+    if from.is_inf(x) {
+        return to.signed(sign, to.inf());
+    }
+    let (sig, exp) = from.unpack(x);
+    round(to, sign, sig, exp, rm, flags)
+}
+
+/*
+    x is the integer, sign-extended by the caller when signed. the result
+    is its bits
+*/
+pub fn from_int(f: &fmt_t, x: u64, signed: bool, rm: u8, flags: &mut u64) -> u64 {
+    let sign = signed && (x as i64) < 0;
+    let mag = if sign { (x as i64).unsigned_abs() } else { x };
+    round(f, sign, mag as u128, 0, rm, flags)
+}
+
+/*
+    convert to a bits wide integer, saturating out of range values and NaNs
+    with an invalid flag. the result is not extended past bits.
+*/
+pub fn to_int(f: &fmt_t, x: u64, signed: bool, bits: u32, rm: u8, flags: &mut u64) -> u64 {
+    let mask = u64::MAX >> (64 - bits);
+    let max = if signed { mask >> 1 } else { mask };
+    let min = if signed { 1u64 << (bits - 1) } else { 0 }; // magnitude, negative
+    let sign = f.is_neg(x);
+    let invalid = |flags: &mut u64, neg: bool| {
+        *flags |= NV;
+        (if neg { min.wrapping_neg() } else { max }) & mask
+    };
+    if f.is_nan(x) {
+        return invalid(flags, false);
+    }
+    if f.is_inf(x) {
+        return invalid(flags, sign);
+    }
+
+    // 2^64 and up is out of range whatever the rounding
+    let (sig, exp) = f.unpack(x);
+    if exp >= 64 {
+        return invalid(flags, sign);
+    }
+    let (mag, inexact) = round_shift(sig, -exp, sign, rm);
+    let out_of_range = if sign {
+        mag > min as u128 || (!signed && mag != 0)
+    } else {
+        mag > max as u128
+    };
+    if out_of_range {
+        return invalid(flags, sign);
+    }
+    if inexact {
+        *flags |= NX;
+    }
+    let mag = mag as u64;
+    (if sign { mag.wrapping_neg() } else { mag }) & mask
+}
@@ -0,0 +1,124 @@
+use std::fs::File;
+
+use crate::{
+    elf::elf_symbols,
+    machine::machine_t,
+    reg::gp_reg_type_t,
+    syscall::{guest_slice, guest_slice_mut, with_file},
+};
+
+/*
+    the host-target interface of spike and riscv-pk: the guest writes a
+    command to the tohost word and waits for the response in fromhost.
+    bits 63:56 select the device, 55:48 the command and the rest is the
+    payload. tohost is polled at block boundaries, so the guest must spin
+    on fromhost or a branch after the write, as riscv-tests and pk do.
+*/
+pub const HTIF_DEV_SYSCALL: u64 = 0;
+pub const HTIF_DEV_CONSOLE: u64 = 1;
+
+pub const HTIF_CONSOLE_GETCHAR: u64 = 0;
+pub const HTIF_CONSOLE_PUTCHAR: u64 = 1;
+
+// guest addresses of the tohost and fromhost words
+#[derive(Clone, Copy, Debug)]
+pub struct htif_t {
+    pub tohost: u64,
+    pub fromhost: u64,
+}
+
+impl htif_t {
+    // None if the program has no tohost, fromhost is optional
+    pub fn locate(elf: &File) -> Option<htif_t> {
+        let symbols = elf_symbols(elf);
+        let tohost = *symbols.get(b"tohost".as_slice())?;
+        let fromhost = symbols.get(b"fromhost".as_slice()).copied().unwrap_or(0);
+        Some(htif_t { tohost, fromhost })
+    }
+
+    // the command the guest left in tohost, 0 for none or if it was unmapped
+    pub fn pending(&self, m: &machine_t) -> u64 {
+        match guest_slice(m, self.tohost, 8) {
+            Ok(raw) => u64::from_le_bytes(raw.try_into().unwrap()),
+            Err(_) => 0,
+        }
+    }
+}
+
+// a command the emulator cannot serve, the guest would wait forever
+fn htif_error(msg: String) -> ! {
+    eprintln!("rvemu-riir: {}", msg);
+    std::process::exit(1);
+}
+
+// a word of guest memory, which must be mapped
+fn htif_write(m: &machine_t, addr: u64, val: u64) {
+    match guest_slice_mut(m, addr, 8) {
+        Ok(raw) => raw.copy_from_slice(&val.to_le_bytes()),
+        Err(_) => htif_error(format!("HTIF write to unmapped {:#x}", addr)),
+    }
+}
+
+// serve the command in tohost, which is then cleared for the next one
+pub fn htif_call(m: &mut machine_t, htif: htif_t) {
+    let cmd = htif.pending(m);
+    htif_write(m, htif.tohost, 0);
+
+    let device = cmd >> 56;
+    let command = (cmd >> 48) & 0xff;
+    let payload = cmd & 0xffff_ffff_ffff;
+    let resp = match (device, command) {
+        // riscv-tests pass with 1 and fail with the test number shifted left,
+        // which may not fit an exit status
+        (HTIF_DEV_SYSCALL, 0) if payload & 1 != 0 => {
+            if payload >> 1 != 0 {
+                htif_error(format!("tohost exit code {}", payload >> 1));
+            }
+            std::process::exit(0);
+        }
+        (HTIF_DEV_SYSCALL, 0) => htif_syscall(m, payload),
+        (HTIF_DEV_CONSOLE, HTIF_CONSOLE_PUTCHAR) => {
+            let _ = with_file(m, 1, |f| f.write(&[payload as u8]));
+            0
+        }
+        (HTIF_DEV_CONSOLE, HTIF_CONSOLE_GETCHAR) => {
+            let mut c = [0u8];
+            match with_file(m, 0, |f| f.read(&mut c)) {
+                Ok(1) => 0x100 | c[0] as u64,
+                _ => 0,
+            }
+        }
+        _ => htif_error(format!("unknown HTIF command {:#x}", cmd)),
+    };
+
+    if htif.fromhost != 0 {
+        let resp = (device << 56) | (command << 48) | resp;
+        htif_write(m, htif.fromhost, resp);
+    }
+}
+
+/*
+    pk's frontend syscalls: payload points at eight words, the syscall
+    number and a0 to a6. they run through do_syscall as if the guest had
+    made them with ecall, the result goes back into the first word.
+*/
+fn htif_syscall(m: &mut machine_t, magic_mem: u64) -> u64 {
+    let words = match guest_slice(m, magic_mem, 64) {
+        Ok(raw) => raw
+            .chunks_exact(8)
+            .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+            .collect::<Vec<u64>>(),
+        Err(_) => htif_error(format!("HTIF syscall block at unmapped {:#x}", magic_mem)),
+    };
+
+    let saved = m.state.gp_regs;
+    for (i, &arg) in words[1..].iter().enumerate() {
+        m.state.gp_regs[gp_reg_type_t::a0 as usize + i] = arg;
+    }
+    m.state.gp_regs[gp_reg_type_t::a7 as usize] = words[0];
+    let ret = m.do_syscall(words[0]);
+    m.state.gp_regs = saved;
+
+    htif_write(m, magic_mem, ret);
+    1
+}
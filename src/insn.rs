@@ -10,9 +10,13 @@ pub struct insn_t {
     pub type_: insn_type_t,
     pub rvc: bool,  // If is a compressed instruction
     pub cont: bool, // If is a continuation
+    pub vm: bool,   // Vector instruction is unmasked
+    pub funct6: u8, // Vector operation within an OP-V category
+    pub nf: u8,     // Vector load/store fields minus one
+    pub rm: u8,     // Floating point rounding mode
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum insn_type_t {
     insn_lb,
     insn_lh,
@@ -79,6 +83,7 @@ pub enum insn_type_t {
     insn_jalr,
     insn_jal,
     insn_ecall,
+    insn_ebreak,
     insn_csrrc,
     insn_csrrci,
     insn_csrrs,
@@ -147,6 +152,127 @@ pub enum insn_type_t {
     insn_fcvt_d_l,
     insn_fcvt_d_lu,
     insn_fmv_d_x,
+    insn_sh1add,
+    insn_sh2add,
+    insn_sh3add,
+    insn_add_uw,
+    insn_sh1add_uw,
+    insn_sh2add_uw,
+    insn_sh3add_uw,
+    insn_slli_uw,
+    insn_andn,
+    insn_orn,
+    insn_xnor,
+    insn_clz,
+    insn_clzw,
+    insn_ctz,
+    insn_ctzw,
+    insn_cpop,
+    insn_cpopw,
+    insn_max,
+    insn_maxu,
+    insn_min,
+    insn_minu,
+    insn_sext_b,
+    insn_sext_h,
+    insn_zext_h,
+    insn_rol,
+    insn_rolw,
+    insn_ror,
+    insn_rori,
+    insn_roriw,
+    insn_rorw,
+    insn_orc_b,
+    insn_rev8,
+    insn_clmul,
+    insn_clmulh,
+    insn_clmulr,
+    insn_bclr,
+    insn_bclri,
+    insn_bext,
+    insn_bexti,
+    insn_binv,
+    insn_binvi,
+    insn_bset,
+    insn_bseti,
+    insn_vsetvli,
+    insn_vsetivli,
+    insn_vsetvl,
+    insn_vle,
+    insn_vleff,
+    insn_vlm,
+    insn_vlse,
+    insn_vlxei,
+    insn_vlr,
+    insn_vse,
+    insn_vsm,
+    insn_vsse,
+    insn_vsxei,
+    insn_vsr,
+    insn_opivv,
+    insn_opivx,
+    insn_opivi,
+    insn_opmvv,
+    insn_opmvx,
+    insn_opfvv,
+    insn_opfvf,
+    insn_flh,
+    insn_fsh,
+    insn_fmadd_h,
+    insn_fmsub_h,
+    insn_fnmsub_h,
+    insn_fnmadd_h,
+    insn_fadd_h,
+    insn_fsub_h,
+    insn_fmul_h,
+    insn_fdiv_h,
+    insn_fsqrt_h,
+    insn_fsgnj_h,
+    insn_fsgnjn_h,
+    insn_fsgnjx_h,
+    insn_fmin_h,
+    insn_fmax_h,
+    insn_fcvt_s_h,
+    insn_fcvt_h_s,
+    insn_fcvt_d_h,
+    insn_fcvt_h_d,
+    insn_feq_h,
+    insn_flt_h,
+    insn_fle_h,
+    insn_fclass_h,
+    insn_fcvt_w_h,
+    insn_fcvt_wu_h,
+    insn_fcvt_l_h,
+    insn_fcvt_lu_h,
+    insn_fcvt_h_w,
+    insn_fcvt_h_wu,
+    insn_fcvt_h_l,
+    insn_fcvt_h_lu,
+    insn_fmv_x_h,
+    insn_fmv_h_x,
+    insn_lr_w,
+    insn_sc_w,
+    insn_amoswap_w,
+    insn_amoadd_w,
+    insn_amoxor_w,
+    insn_amoand_w,
+    insn_amoor_w,
+    insn_amomin_w,
+    insn_amomax_w,
+    insn_amominu_w,
+    insn_amomaxu_w,
+    insn_lr_d,
+    insn_sc_d,
+    insn_amoswap_d,
+    insn_amoadd_d,
+    insn_amoxor_d,
+    insn_amoand_d,
+    insn_amoor_d,
+    insn_amomin_d,
+    insn_amomax_d,
+    insn_amominu_d,
+    insn_amomaxu_d,
+    insn_illegal, // reserved and unsupported encodings
     num_insns,
 }
 
@@ -162,6 +288,10 @@ impl Default for insn_t {
             type_: insn_type_t::num_insns, // Use a default variant
             rvc: false,
             cont: false,
+            vm: false,
+            funct6: 0,
+            nf: 0,
+            rm: 0,
         }
     }
 }
@@ -196,6 +326,9 @@ pub fn funct7(data: u32) -> u32 {
 pub fn imm116(data: u32) -> u32 {
     (data >> 26) & 0x3f
 }
+pub fn funct6(data: u32) -> u8 {
+    (data >> 26) as u8 & 0x3f
+}
 
 impl insn_t {
     #[inline]
@@ -219,7 +352,7 @@ impl insn_t {
         let imm1912 = (data >> 12) & 0xff;
 
         let imm = (imm20 << 20) | (imm1912 << 12) | (imm11 << 11) | (imm101 << 1);
-        self.imm = (imm as i32) << 12 >> 12;
+        self.imm = (imm as i32) << 11 >> 11;
 
         self.rd = rd(data);
     }
@@ -270,6 +403,16 @@ impl insn_t {
         self.rs2 = rs2(data);
         self.rs3 = rs3(data);
         self.rd = rd(data);
+        self.rm = funct3(data) as u8;
+    }
+
+    #[inline]
+    pub fn insn_vtype_read(&mut self, data: u32) {
+        self.rs1 = rs1(data);
+        self.rs2 = rs2(data);
+        self.rd = rd(data);
+        self.vm = (data >> 25) & 0x1 != 0;
+        self.funct6 = funct6(data);
     }
 }
 
@@ -459,7 +602,7 @@ impl insn_t {
             | (imm98 << 8)
             | (imm4 << 4)
             | (imm11 << 11);
-        self.imm = ((imm as i32) << 21) >> 21;
+        self.imm = ((imm as i32) << 20) >> 20;
         self.rvc = true;
     }
 
@@ -509,7 +652,7 @@ impl insn_t {
 
         let imm = (imm76 << 6) | (imm52 << 2);
 
-        self.imm = (imm as i32) << 26 >> 26;
+        self.imm = imm as i32;
         self.rs2 = rc2(data);
         self.rvc = true;
     }
@@ -1,11 +1,15 @@
-use core::panic;
-use std::mem::{self};
+use std::{
+    mem::{self},
+    sync::atomic::Ordering,
+};
 
 use crate::{
     decode::insn_decode,
+    float::{self, F16, F32, F64, RMM, fmt_t},
     insn::{insn_t, insn_type_t},
     machine::{exit_reason_t, state_t, to_host_addr},
     reg::{csr_t, gp_reg_type_t},
+    vector::{func_opf, func_opi, func_opm, func_vload, func_vset, func_vstore},
 };
 
 type interp_func_t = fn(&mut state_t, &mut insn_t);
@@ -13,10 +17,6 @@ type interp_func_t = fn(&mut state_t, &mut insn_t);
 /*
     load instructions
 */
-fn func_empty(state: &mut state_t, insn: &mut insn_t) {
-    panic!("Unhandled instruction type: {:?}", insn.type_);
-}
-
 fn func_load_template<T: Into<i64> + Copy>(state: &mut state_t, insn: &mut insn_t) {
     // println!();
     // println!("func_load_template invoked: state@{:x} insn@{:x}", state as *const state_t as u64, insn as *const insn_t as u64);
@@ -133,7 +133,7 @@ fn func_slliw(state: &mut state_t, insn: &mut insn_t) {
 
 fn func_srliw(state: &mut state_t, insn: &mut insn_t) {
     state.gp_regs[insn.rd as usize] =
-        ((state.gp_regs[insn.rs1 as usize] as u32) >> (insn.imm & 0x1f)) as u64;
+        ((state.gp_regs[insn.rs1 as usize] as u32) >> (insn.imm & 0x1f)) as i32 as u64;
 }
 
 fn func_sraiw(state: &mut state_t, insn: &mut insn_t) {
@@ -271,7 +271,8 @@ fn func_sllw(state: &mut state_t, insn: &mut insn_t) {
 
 fn func_srlw(state: &mut state_t, insn: &mut insn_t) {
     state.gp_regs[insn.rd as usize] = ((state.gp_regs[insn.rs1 as usize] as u32)
-        >> (state.gp_regs[insn.rs2 as usize] & 0x1f)) as u64;
+        >> (state.gp_regs[insn.rs2 as usize] & 0x1f)) as i32
+        as u64;
 }
 
 fn func_mulw(state: &mut state_t, insn: &mut insn_t) {
@@ -298,7 +299,7 @@ fn func_divuw(state: &mut state_t, insn: &mut insn_t) {
     state.gp_regs[insn.rd as usize] = if rs2 == 0 {
         u64::MAX
     } else {
-        (rs1 / rs2) as u64
+        (rs1 / rs2) as i32 as u64
     };
 }
 
@@ -318,9 +319,9 @@ fn func_remuw(state: &mut state_t, insn: &mut insn_t) {
     let rs1 = state.gp_regs[insn.rs1 as usize] as u32;
     let rs2 = state.gp_regs[insn.rs2 as usize] as u32;
     state.gp_regs[insn.rd as usize] = if rs2 == 0 {
-        rs1 as u64
+        rs1 as i32 as u64
     } else {
-        (rs1 % rs2) as u64
+        (rs1 % rs2) as i32 as u64
     };
 }
 
@@ -450,12 +451,14 @@ fn func_jal(state: &mut state_t, insn: &mut insn_t) {
 }
 
 fn func_jalr(state: &mut state_t, insn: &mut insn_t) {
+    // rd may be rs1
+    let base = state.gp_regs[insn.rs1 as usize];
     state.gp_regs[insn.rd as usize] = state.pc
         + match insn.rvc {
             true => 2,
             false => 4,
         };
-    let target = (state.gp_regs[insn.rs1 as usize] as i64 + insn.imm as i64) as u64;
+    let target = (base as i64 + insn.imm as i64) as u64;
     // println!("rs1={} [rs1]={:x} insn.imm={:x}", insn.rs1 as usize, state.gp_regs[insn.rs1 as usize], insn.imm as i64);
     // println!("func_jalr: target={}", target);
     state.reenter_pc = target & !1;
@@ -466,14 +469,92 @@ fn func_jalr(state: &mut state_t, insn: &mut insn_t) {
 /*
     csr instructions
 */
+fn csr_read(state: &state_t, csr: &csr_t) -> u64 {
+    match csr {
+        csr_t::fflags => state.fflags,
+        csr_t::frm => state.frm,
+        csr_t::fcsr => (state.frm << 5) | state.fflags,
+        csr_t::vstart => state.vec.vstart,
+        csr_t::vxsat => state.vec.vxsat,
+        csr_t::vxrm => state.vec.vxrm,
+        csr_t::vcsr => (state.vec.vxrm << 1) | state.vec.vxsat,
+        csr_t::cycle => state.cycle,
+        csr_t::time => state.clock.ticks(state.instret),
+        csr_t::instret => state.instret,
+        // no events to count
+        csr_t::hpmcounter => 0,
+        csr_t::vl => state.vec.vl,
+        csr_t::vtype => state.vec.vtype,
+        csr_t::vlenb => state.vec.vlenb as u64,
+    }
+}
+
+fn csr_write(state: &mut state_t, csr: &csr_t, val: u64) {
+    match csr {
+        csr_t::fflags => state.fflags = val & 0x1f,
+        csr_t::frm => state.frm = val & 0x7,
+        csr_t::fcsr => {
+            state.frm = (val >> 5) & 0x7;
+            state.fflags = val & 0x1f;
+        }
+        csr_t::vstart => state.vec.vstart = val,
+        csr_t::vxsat => state.vec.vxsat = val & 0x1,
+        csr_t::vxrm => state.vec.vxrm = val & 0x3,
+        csr_t::vcsr => {
+            state.vec.vxrm = (val >> 1) & 0x3;
+            state.vec.vxsat = val & 0x1;
+        }
+        csr_t::vl
+        | csr_t::vtype
+        | csr_t::vlenb
+        | csr_t::cycle
+        | csr_t::time
+        | csr_t::instret
+        | csr_t::hpmcounter => unreachable!(),
+    }
+}
+
 fn func_csr_handler(state: &mut state_t, insn: &mut insn_t) {
-    match csr_t::from(insn.csr) {
-        csr_t::fflags => {}
-        csr_t::frm => {}
-        csr_t::fcsr => {}
-        _ => panic!("unsupported csr"),
+    // csrs that do not exist or belong to a disabled extension
+    let Ok(csr) = csr_t::try_from(insn.csr) else {
+        return func_illegal(state, insn);
+    };
+    let vector = matches!(
+        csr,
+        csr_t::vstart
+            | csr_t::vxsat
+            | csr_t::vxrm
+            | csr_t::vcsr
+            | csr_t::vl
+            | csr_t::vtype
+            | csr_t::vlenb
+    );
+    if vector && !state.isa.v {
+        return func_illegal(state, insn);
+    }
+    let old = csr_read(state, &csr);
+    // the immediate forms take rs1 as a 5-bit value
+    let src = match insn.type_ {
+        insn_type_t::insn_csrrw | insn_type_t::insn_csrrs | insn_type_t::insn_csrrc => {
+            state.gp_regs[insn.rs1 as usize]
+        }
+        _ => insn.rs1 as u64,
+    };
+    // csrrs and csrrc with x0 or 0 only read
+    let new = match insn.type_ {
+        insn_type_t::insn_csrrw | insn_type_t::insn_csrrwi => Some(src),
+        _ if insn.rs1 == 0 => None,
+        insn_type_t::insn_csrrs | insn_type_t::insn_csrrsi => Some(old | src),
+        _ => Some(old & !src),
+    };
+    // the top two bits of the address are set for the read-only ones
+    if new.is_some() && insn.csr >> 10 == 0x3 {
+        return func_illegal(state, insn);
     }
-    state.gp_regs[insn.rd as usize] = 0;
+    if let Some(new) = new {
+        csr_write(state, &csr, new);
+    }
+    state.gp_regs[insn.rd as usize] = old;
 }
 
 fn func_csrrw(state: &mut state_t, insn: &mut insn_t) {
@@ -508,7 +589,7 @@ fn func_lui(state: &mut state_t, insn: &mut insn_t) {
 }
 
 fn func_auipc(state: &mut state_t, insn: &mut insn_t) {
-    state.gp_regs[insn.rd as usize] = state.pc + insn.imm as u64;
+    state.gp_regs[insn.rd as usize] = state.pc.wrapping_add(insn.imm as u64);
 }
 
 fn func_ecall(state: &mut state_t, insn: &mut insn_t) {
@@ -516,54 +597,293 @@ fn func_ecall(state: &mut state_t, insn: &mut insn_t) {
     state.reenter_pc = state.pc + 4;
 }
 
+// stop at the ebreak itself, like a breakpoint
+fn func_ebreak(state: &mut state_t, _insn: &mut insn_t) {
+    state.exit_reason = exit_reason_t::ebreak;
+    state.reenter_pc = state.pc;
+}
+
+// stop at the instruction, machine_illegal raises SIGILL
+pub fn func_illegal(state: &mut state_t, insn: &mut insn_t) {
+    state.exit_reason = exit_reason_t::illegal;
+    state.reenter_pc = state.pc;
+    insn.cont = true;
+}
+
+// one hart and nothing else writing guest memory, so ordering holds already
+fn func_fence(_state: &mut state_t, _insn: &mut insn_t) {}
+
+// instructions are decoded from memory each time they execute
+fn func_fence_i(_state: &mut state_t, _insn: &mut insn_t) {}
+
+/*
+    atomic memory operations, trivially atomic with one hart. lr reserves
+    its address and sc succeeds only on that address, once.
+*/
+fn func_lr_w(state: &mut state_t, insn: &mut insn_t) {
+    let addr = state.gp_regs[insn.rs1 as usize];
+    state.reservation = Some(addr);
+    state.gp_regs[insn.rd as usize] = unsafe { *(to_host_addr(addr) as *const i32) } as u64;
+}
+
+fn func_sc_w(state: &mut state_t, insn: &mut insn_t) {
+    let addr = state.gp_regs[insn.rs1 as usize];
+    let ok = state.reservation.take() == Some(addr);
+    if ok {
+        unsafe { *(to_host_addr(addr) as *mut u32) = state.gp_regs[insn.rs2 as usize] as u32 };
+    }
+    state.gp_regs[insn.rd as usize] = !ok as u64;
+}
+
+fn func_lr_d(state: &mut state_t, insn: &mut insn_t) {
+    let addr = state.gp_regs[insn.rs1 as usize];
+    state.reservation = Some(addr);
+    state.gp_regs[insn.rd as usize] = unsafe { *(to_host_addr(addr) as *const u64) };
+}
+
+fn func_sc_d(state: &mut state_t, insn: &mut insn_t) {
+    let addr = state.gp_regs[insn.rs1 as usize];
+    let ok = state.reservation.take() == Some(addr);
+    if ok {
+        unsafe { *(to_host_addr(addr) as *mut u64) = state.gp_regs[insn.rs2 as usize] };
+    }
+    state.gp_regs[insn.rd as usize] = !ok as u64;
+}
+
+// rd gets the old memory value, sign-extended for the word forms
+fn amo_w(state: &mut state_t, insn: &insn_t, op: fn(i32, i32) -> i32) {
+    let ptr = to_host_addr(state.gp_regs[insn.rs1 as usize]) as *mut i32;
+    let rs2 = state.gp_regs[insn.rs2 as usize] as i32;
+    let old = unsafe { *ptr };
+    unsafe { *ptr = op(old, rs2) };
+    state.gp_regs[insn.rd as usize] = old as u64;
+}
+
+fn amo_d(state: &mut state_t, insn: &insn_t, op: fn(i64, i64) -> i64) {
+    let ptr = to_host_addr(state.gp_regs[insn.rs1 as usize]) as *mut i64;
+    let rs2 = state.gp_regs[insn.rs2 as usize] as i64;
+    let old = unsafe { *ptr };
+    unsafe { *ptr = op(old, rs2) };
+    state.gp_regs[insn.rd as usize] = old as u64;
+}
+
+fn func_amoswap_w(state: &mut state_t, insn: &mut insn_t) {
+    amo_w(state, insn, |_, b| b);
+}
+
+fn func_amoadd_w(state: &mut state_t, insn: &mut insn_t) {
+    amo_w(state, insn, |a, b| a.wrapping_add(b));
+}
+
+fn func_amoxor_w(state: &mut state_t, insn: &mut insn_t) {
+    amo_w(state, insn, |a, b| a ^ b);
+}
+
+fn func_amoand_w(state: &mut state_t, insn: &mut insn_t) {
+    amo_w(state, insn, |a, b| a & b);
+}
+
+fn func_amoor_w(state: &mut state_t, insn: &mut insn_t) {
+    amo_w(state, insn, |a, b| a | b);
+}
+
+fn func_amomin_w(state: &mut state_t, insn: &mut insn_t) {
+    amo_w(state, insn, |a, b| a.min(b));
+}
+
+fn func_amomax_w(state: &mut state_t, insn: &mut insn_t) {
+    amo_w(state, insn, |a, b| a.max(b));
+}
+
+fn func_amominu_w(state: &mut state_t, insn: &mut insn_t) {
+    amo_w(state, insn, |a, b| (a as u32).min(b as u32) as i32);
+}
+
+fn func_amomaxu_w(state: &mut state_t, insn: &mut insn_t) {
+    amo_w(state, insn, |a, b| (a as u32).max(b as u32) as i32);
+}
+
+fn func_amoswap_d(state: &mut state_t, insn: &mut insn_t) {
+    amo_d(state, insn, |_, b| b);
+}
+
+fn func_amoadd_d(state: &mut state_t, insn: &mut insn_t) {
+    amo_d(state, insn, |a, b| a.wrapping_add(b));
+}
+
+fn func_amoxor_d(state: &mut state_t, insn: &mut insn_t) {
+    amo_d(state, insn, |a, b| a ^ b);
+}
+
+fn func_amoand_d(state: &mut state_t, insn: &mut insn_t) {
+    amo_d(state, insn, |a, b| a & b);
+}
+
+fn func_amoor_d(state: &mut state_t, insn: &mut insn_t) {
+    amo_d(state, insn, |a, b| a | b);
+}
+
+fn func_amomin_d(state: &mut state_t, insn: &mut insn_t) {
+    amo_d(state, insn, |a, b| a.min(b));
+}
+
+fn func_amomax_d(state: &mut state_t, insn: &mut insn_t) {
+    amo_d(state, insn, |a, b| a.max(b));
+}
+
+fn func_amominu_d(state: &mut state_t, insn: &mut insn_t) {
+    amo_d(state, insn, |a, b| (a as u64).min(b as u64) as i64);
+}
+
+fn func_amomaxu_d(state: &mut state_t, insn: &mut insn_t) {
+    amo_d(state, insn, |a, b| (a as u64).max(b as u64) as i64);
+}
+
 /*
     floating point instructions
 */
+
+// a single or a half must be NaN-boxed, anything else reads as the canonical NaN
+fn fp_reg(state: &state_t, f: &fmt_t, reg: u8) -> u64 {
+    let v = unsafe { state.fp_regs[reg as usize].v };
+    match f.bits() {
+        64 => v,
+        bits if v >> bits == u64::MAX >> bits => v & (u64::MAX >> (64 - bits)),
+        _ => f.canonical_nan(),
+    }
+}
+
+fn set_fp_reg(state: &mut state_t, f: &fmt_t, reg: u8, v: u64) {
+    state.fp_regs[reg as usize].v = match f.bits() {
+        64 => v,
+        bits => (u64::MAX << bits) | v,
+    };
+}
+
+type fp_op_t = fn(&fmt_t, u64, u64, u8, &mut u64) -> u64;
+
+fn fp_op(state: &mut state_t, insn: &mut insn_t, f: &fmt_t, op: fp_op_t) {
+    let Some(rm) = rounding_mode(state, insn) else {
+        return func_illegal(state, insn);
+    };
+    let (rs1, rs2) = (fp_reg(state, f, insn.rs1), fp_reg(state, f, insn.rs2));
+    let rd = op(f, rs1, rs2, rm, &mut state.fflags);
+    set_fp_reg(state, f, insn.rd, rd);
+}
+
+fn fp_sqrt(state: &mut state_t, insn: &mut insn_t, f: &fmt_t) {
+    let Some(rm) = rounding_mode(state, insn) else {
+        return func_illegal(state, insn);
+    };
+    let rs1 = fp_reg(state, f, insn.rs1);
+    let rd = float::sqrt(f, rs1, rm, &mut state.fflags);
+    set_fp_reg(state, f, insn.rd, rd);
+}
+
+fn fp_minmax(state: &mut state_t, insn: &mut insn_t, f: &fmt_t, min: bool) {
+    let (rs1, rs2) = (fp_reg(state, f, insn.rs1), fp_reg(state, f, insn.rs2));
+    let rd = match min {
+        true => float::min(f, rs1, rs2, &mut state.fflags),
+        false => float::max(f, rs1, rs2, &mut state.fflags),
+    };
+    set_fp_reg(state, f, insn.rd, rd);
+}
+
+// the fused forms flip the signs of the product and the addend
+fn fp_fma(state: &mut state_t, insn: &mut insn_t, f: &fmt_t, neg_prod: bool, neg_add: bool) {
+    let Some(rm) = rounding_mode(state, insn) else {
+        return func_illegal(state, insn);
+    };
+    let sign = 1 << (f.exp_bits + f.man_bits);
+    let rs1 = fp_reg(state, f, insn.rs1) ^ if neg_prod { sign } else { 0 };
+    let rs2 = fp_reg(state, f, insn.rs2);
+    let rs3 = fp_reg(state, f, insn.rs3) ^ if neg_add { sign } else { 0 };
+    let rd = float::fma(f, rs1, rs2, rs3, rm, &mut state.fflags);
+    set_fp_reg(state, f, insn.rd, rd);
+}
+
+fn fp_cmp(
+    state: &mut state_t,
+    insn: &mut insn_t,
+    f: &fmt_t,
+    op: fn(&fmt_t, u64, u64, &mut u64) -> bool,
+) {
+    let (rs1, rs2) = (fp_reg(state, f, insn.rs1), fp_reg(state, f, insn.rs2));
+    state.gp_regs[insn.rd as usize] = op(f, rs1, rs2, &mut state.fflags) as u64;
+}
+
+// the sign of rd from the signs of rs1 and rs2, given as masks
+fn fp_sgnj(state: &mut state_t, insn: &mut insn_t, f: &fmt_t, sign: fn(u64, u64) -> u64) {
+    let mask = 1 << (f.exp_bits + f.man_bits);
+    let (rs1, rs2) = (fp_reg(state, f, insn.rs1), fp_reg(state, f, insn.rs2));
+    let rd = (rs1 & !mask) | (sign(rs1, rs2) & mask);
+    set_fp_reg(state, f, insn.rd, rd);
+}
+
+// 32-bit results are sign-extended even when unsigned
+fn fp_to_int(state: &mut state_t, insn: &mut insn_t, f: &fmt_t, signed: bool, bits: u32) {
+    let Some(rm) = rounding_mode(state, insn) else {
+        return func_illegal(state, insn);
+    };
+    let rs1 = fp_reg(state, f, insn.rs1);
+    let rd = float::to_int(f, rs1, signed, bits, rm, &mut state.fflags);
+    state.gp_regs[insn.rd as usize] = if bits == 32 { rd as i32 as u64 } else { rd };
+}
+
+fn fp_from_int(state: &mut state_t, insn: &mut insn_t, f: &fmt_t, signed: bool, bits: u32) {
+    let Some(rm) = rounding_mode(state, insn) else {
+        return func_illegal(state, insn);
+    };
+    let rs1 = match (signed, bits) {
+        (true, 32) => state.gp_regs[insn.rs1 as usize] as i32 as u64,
+        (false, 32) => state.gp_regs[insn.rs1 as usize] as u32 as u64,
+        _ => state.gp_regs[insn.rs1 as usize],
+    };
+    let rd = float::from_int(f, rs1, signed, rm, &mut state.fflags);
+    set_fp_reg(state, f, insn.rd, rd);
+}
+
+fn fp_convert(state: &mut state_t, insn: &mut insn_t, from: &fmt_t, to: &fmt_t) {
+    let Some(rm) = rounding_mode(state, insn) else {
+        return func_illegal(state, insn);
+    };
+    let rs1 = fp_reg(state, from, insn.rs1);
+    let rd = float::convert(from, to, rs1, rm, &mut state.fflags);
+    set_fp_reg(state, to, insn.rd, rd);
+}
+
 fn func_fadd_s(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].f };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].f };
-    state.fp_regs[insn.rd as usize].f = rs1 + rs2;
+    fp_op(state, insn, &F32, float::add);
 }
 
 fn func_fsub_s(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].f };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].f };
-    state.fp_regs[insn.rd as usize].f = rs1 - rs2;
+    fp_op(state, insn, &F32, float::sub);
 }
 
 fn func_fmul_s(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].f };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].f };
-    state.fp_regs[insn.rd as usize].f = rs1 * rs2;
+    fp_op(state, insn, &F32, float::mul);
 }
 
 fn func_fdiv_s(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].f };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].f };
-    state.fp_regs[insn.rd as usize].f = rs1 / rs2;
+    fp_op(state, insn, &F32, float::div);
 }
 
 fn func_fsqrt_s(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].f };
-    state.fp_regs[insn.rd as usize].f = rs1.sqrt();
+    fp_sqrt(state, insn, &F32);
 }
 
 fn func_fmin_s(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].f };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].f };
-    state.fp_regs[insn.rd as usize].f = rs1.min(rs2);
+    fp_minmax(state, insn, &F32, true);
 }
 
 fn func_fmax_s(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].f };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].f };
-    state.fp_regs[insn.rd as usize].f = rs1.max(rs2);
+    fp_minmax(state, insn, &F32, false);
 }
 
 fn func_flw(state: &mut state_t, insn: &mut insn_t) {
     let addr = (state.gp_regs[insn.rs1 as usize] as i64 + insn.imm as i64) as u64;
     let value = unsafe { *(to_host_addr(addr) as *const u32) };
-    state.fp_regs[insn.rd as usize].v = (value as u64) | (u64::MAX << 32);
+    set_fp_reg(state, &F32, insn.rd, value as u64);
 }
 
 fn func_fld(state: &mut state_t, insn: &mut insn_t) {
@@ -585,278 +905,201 @@ fn func_fsd(state: &mut state_t, insn: &mut insn_t) {
 }
 
 fn func_fmadd_s(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].f };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].f };
-    let rs3 = unsafe { state.fp_regs[insn.rs3 as usize].f };
-    state.fp_regs[insn.rd as usize].f = rs1 * rs2 + rs3;
+    fp_fma(state, insn, &F32, false, false);
 }
 
 fn func_fmsub_s(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].f };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].f };
-    let rs3 = unsafe { state.fp_regs[insn.rs3 as usize].f };
-    state.fp_regs[insn.rd as usize].f = rs1 * rs2 - rs3;
+    fp_fma(state, insn, &F32, false, true);
 }
 
 fn func_fnmsub_s(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].f };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].f };
-    let rs3 = unsafe { state.fp_regs[insn.rs3 as usize].f };
-    state.fp_regs[insn.rd as usize].f = -(rs1 * rs2) + rs3;
+    fp_fma(state, insn, &F32, true, false);
 }
 
 fn func_fnmadd_s(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].f };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].f };
-    let rs3 = unsafe { state.fp_regs[insn.rs3 as usize].f };
-    state.fp_regs[insn.rd as usize].f = -(rs1 * rs2) - rs3;
+    fp_fma(state, insn, &F32, true, true);
 }
 /*
     floating point conversion instructions
 */
 fn func_fcvt_w_s(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].f };
-    state.gp_regs[insn.rd as usize] = rs1 as i32 as u64; // TODO: check if this is correct
+    fp_to_int(state, insn, &F32, true, 32);
 }
 
 fn func_fcvt_wu_s(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].f };
-    state.gp_regs[insn.rd as usize] = rs1 as u32 as u64; // TODO: check if this is correct
+    fp_to_int(state, insn, &F32, false, 32);
 }
 
 fn func_fcvt_s_w(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = state.gp_regs[insn.rs1 as usize] as i32;
-    state.fp_regs[insn.rd as usize].f = rs1 as f32;
+    fp_from_int(state, insn, &F32, true, 32);
 }
 
 fn func_fcvt_s_wu(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = state.gp_regs[insn.rs1 as usize] as u32;
-    state.fp_regs[insn.rd as usize].f = rs1 as f32;
+    fp_from_int(state, insn, &F32, false, 32);
 }
 
 /*
     floating point comparison instructions
 */
 fn func_feq_s(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].f };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].f };
-    state.gp_regs[insn.rd as usize] = (rs1 == rs2) as u64;
+    fp_cmp(state, insn, &F32, float::eq);
 }
 
 fn func_flt_s(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].f };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].f };
-    state.gp_regs[insn.rd as usize] = (rs1 < rs2) as u64;
+    fp_cmp(state, insn, &F32, float::lt);
 }
 
 fn func_fle_s(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].f };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].f };
-    state.gp_regs[insn.rd as usize] = (rs1 <= rs2) as u64;
+    fp_cmp(state, insn, &F32, float::le);
 }
 
 /*
     floating point sign manipulation instructions
 */
 fn func_fsgnj_s(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].f };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].f };
-    state.fp_regs[insn.rd as usize].f = rs1.copysign(rs2);
+    fp_sgnj(state, insn, &F32, |_, rs2| rs2);
 }
 
 fn func_fsgnjn_s(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].f };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].f };
-    state.fp_regs[insn.rd as usize].f = rs1.copysign(-rs2);
+    fp_sgnj(state, insn, &F32, |_, rs2| !rs2);
 }
 
 fn func_fsgnjx_s(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].f };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].f };
-    state.fp_regs[insn.rd as usize].f = rs1.copysign(rs2.abs());
+    fp_sgnj(state, insn, &F32, |rs1, rs2| rs1 ^ rs2);
 }
 
 /*
     floating point classification instructions
 */
 fn func_fclass_s(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].f };
-    state.gp_regs[insn.rd as usize] = rs1.classify() as u64;
+    state.gp_regs[insn.rd as usize] = F32.class(fp_reg(state, &F32, insn.rs1));
 }
 
 /*
     floating point double precision instructions
 */
 fn func_fadd_d(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].d };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].d };
-    state.fp_regs[insn.rd as usize].d = rs1 + rs2;
+    fp_op(state, insn, &F64, float::add);
 }
 
 fn func_fsub_d(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].d };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].d };
-    state.fp_regs[insn.rd as usize].d = rs1 - rs2;
+    fp_op(state, insn, &F64, float::sub);
 }
 
 fn func_fmul_d(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].d };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].d };
-    state.fp_regs[insn.rd as usize].d = rs1 * rs2;
+    fp_op(state, insn, &F64, float::mul);
 }
 
 fn func_fdiv_d(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].d };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].d };
-    state.fp_regs[insn.rd as usize].d = rs1 / rs2;
+    fp_op(state, insn, &F64, float::div);
 }
 
 fn func_fsqrt_d(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].d };
-    state.fp_regs[insn.rd as usize].d = rs1.sqrt();
+    fp_sqrt(state, insn, &F64);
 }
 
 fn func_fmin_d(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].d };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].d };
-    state.fp_regs[insn.rd as usize].d = rs1.min(rs2);
+    fp_minmax(state, insn, &F64, true);
 }
 
 fn func_fmax_d(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].d };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].d };
-    state.fp_regs[insn.rd as usize].d = rs1.max(rs2);
+    fp_minmax(state, insn, &F64, false);
 }
 
 fn func_fmadd_d(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].d };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].d };
-    let rs3 = unsafe { state.fp_regs[insn.rs3 as usize].d };
-    state.fp_regs[insn.rd as usize].d = rs1 * rs2 + rs3;
+    fp_fma(state, insn, &F64, false, false);
 }
 
 fn func_fmsub_d(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].d };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].d };
-    let rs3 = unsafe { state.fp_regs[insn.rs3 as usize].d };
-    state.fp_regs[insn.rd as usize].d = rs1 * rs2 - rs3;
+    fp_fma(state, insn, &F64, false, true);
 }
 
 fn func_fnmsub_d(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].d };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].d };
-    let rs3 = unsafe { state.fp_regs[insn.rs3 as usize].d };
-    state.fp_regs[insn.rd as usize].d = -(rs1 * rs2) + rs3;
+    fp_fma(state, insn, &F64, true, false);
 }
 
 fn func_fnmadd_d(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].d };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].d };
-    let rs3 = unsafe { state.fp_regs[insn.rs3 as usize].d };
-    state.fp_regs[insn.rd as usize].d = -(rs1 * rs2) - rs3;
+    fp_fma(state, insn, &F64, true, true);
 }
 
 /*
     floating point double precision comparison instructions
 */
 fn func_feq_d(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].d };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].d };
-    state.gp_regs[insn.rd as usize] = (rs1 == rs2) as u64;
+    fp_cmp(state, insn, &F64, float::eq);
 }
 
 fn func_flt_d(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].d };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].d };
-    state.gp_regs[insn.rd as usize] = (rs1 < rs2) as u64;
+    fp_cmp(state, insn, &F64, float::lt);
 }
 
 fn func_fle_d(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].d };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].d };
-    state.gp_regs[insn.rd as usize] = (rs1 <= rs2) as u64;
+    fp_cmp(state, insn, &F64, float::le);
 }
 
 /*
     floating point double precision sign manipulation instructions
 */
 fn func_fsgnj_d(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].d };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].d };
-    state.fp_regs[insn.rd as usize].d = rs1.copysign(rs2);
+    fp_sgnj(state, insn, &F64, |_, rs2| rs2);
 }
 
 fn func_fsgnjn_d(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].d };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].d };
-    state.fp_regs[insn.rd as usize].d = rs1.copysign(-rs2);
+    fp_sgnj(state, insn, &F64, |_, rs2| !rs2);
 }
 
 fn func_fsgnjx_d(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].d };
-    let rs2 = unsafe { state.fp_regs[insn.rs2 as usize].d };
-    state.fp_regs[insn.rd as usize].d = rs1.copysign(rs2.abs());
+    fp_sgnj(state, insn, &F64, |rs1, rs2| rs1 ^ rs2);
 }
 
 /*
     floating point double precision classification instructions
 */
 fn func_fclass_d(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].d };
-    state.gp_regs[insn.rd as usize] = rs1.classify() as u64;
+    state.gp_regs[insn.rd as usize] = F64.class(fp_reg(state, &F64, insn.rs1));
 }
 
 /*
     floating point double precision conversion instructions
 */
 fn func_fcvt_d_s(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].f };
-    state.fp_regs[insn.rd as usize].d = rs1 as f64;
+    fp_convert(state, insn, &F32, &F64);
 }
 
 fn func_fcvt_s_d(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].d };
-    state.fp_regs[insn.rd as usize].f = rs1 as f32;
+    fp_convert(state, insn, &F64, &F32);
 }
 
 fn func_fcvt_w_d(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].d };
-    state.gp_regs[insn.rd as usize] = rs1 as i32 as u64; // TODO: check if this is correct
+    fp_to_int(state, insn, &F64, true, 32);
 }
 
 fn func_fcvt_wu_d(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].d };
-    state.gp_regs[insn.rd as usize] = rs1 as u32 as u64; // TODO: check if this is correct
+    fp_to_int(state, insn, &F64, false, 32);
 }
 
 fn func_fcvt_d_w(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = state.gp_regs[insn.rs1 as usize] as i32;
-    state.fp_regs[insn.rd as usize].d = rs1 as f64;
+    fp_from_int(state, insn, &F64, true, 32);
 }
 
 fn func_fcvt_d_wu(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = state.gp_regs[insn.rs1 as usize] as u32;
-    state.fp_regs[insn.rd as usize].d = rs1 as f64;
+    fp_from_int(state, insn, &F64, false, 32);
 }
 
 fn func_fcvt_l_d(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].d };
-    state.gp_regs[insn.rd as usize] = rs1 as i64 as u64; // TODO: check if this is correct
+    fp_to_int(state, insn, &F64, true, 64);
 }
 
 fn func_fcvt_lu_d(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].d };
-    state.gp_regs[insn.rd as usize] = rs1 as u64; // TODO: check if this is correct
+    fp_to_int(state, insn, &F64, false, 64);
 }
 
 fn func_fcvt_d_l(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = state.gp_regs[insn.rs1 as usize] as i64;
-    state.fp_regs[insn.rd as usize].d = rs1 as f64;
+    fp_from_int(state, insn, &F64, true, 64);
 }
 
 fn func_fcvt_d_lu(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = state.gp_regs[insn.rs1 as usize] as u64;
-    state.fp_regs[insn.rd as usize].d = rs1 as f64;
+    fp_from_int(state, insn, &F64, false, 64);
 }
 
 fn func_fmv_x_d(state: &mut state_t, insn: &mut insn_t) {
@@ -868,31 +1111,422 @@ fn func_fmv_d_x(state: &mut state_t, insn: &mut insn_t) {
 }
 
 fn func_fmv_x_w(state: &mut state_t, insn: &mut insn_t) {
-    state.gp_regs[insn.rd as usize] = unsafe { state.fp_regs[insn.rs1 as usize].w } as u64;
+    state.gp_regs[insn.rd as usize] = unsafe { state.fp_regs[insn.rs1 as usize].w } as i32 as u64;
 }
 
 fn func_fmv_w_x(state: &mut state_t, insn: &mut insn_t) {
-    state.fp_regs[insn.rd as usize].w = state.gp_regs[insn.rs1 as usize] as u32;
+    set_fp_reg(
+        state,
+        &F32,
+        insn.rd,
+        state.gp_regs[insn.rs1 as usize] as u32 as u64,
+    );
 }
 
 fn func_fcvt_l_s(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].f };
-    state.gp_regs[insn.rd as usize] = rs1.round() as i64 as u64; // TODO: check if this is correct
+    fp_to_int(state, insn, &F32, true, 64);
 }
 
 fn func_fcvt_lu_s(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = unsafe { state.fp_regs[insn.rs1 as usize].f };
-    state.gp_regs[insn.rd as usize] = rs1.round() as u64; // TODO: check if this is correct
+    fp_to_int(state, insn, &F32, false, 64);
 }
 
 fn func_fcvt_s_l(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = state.gp_regs[insn.rs1 as usize] as i64;
-    state.fp_regs[insn.rd as usize].f = rs1 as f32;
+    fp_from_int(state, insn, &F32, true, 64);
 }
 
 fn func_fcvt_s_lu(state: &mut state_t, insn: &mut insn_t) {
-    let rs1 = state.gp_regs[insn.rs1 as usize] as u64;
-    state.fp_regs[insn.rd as usize].f = rs1 as f32;
+    fp_from_int(state, insn, &F32, false, 64);
+}
+
+// the instruction's rounding mode, dyn defers to frm. None for the reserved ones
+fn rounding_mode(state: &state_t, insn: &insn_t) -> Option<u8> {
+    let rm = if insn.rm == 0x7 {
+        state.frm as u8
+    } else {
+        insn.rm
+    };
+    (rm <= RMM).then_some(rm)
+}
+
+/*
+    floating point half precision instructions
+*/
+fn func_flh(state: &mut state_t, insn: &mut insn_t) {
+    let addr = (state.gp_regs[insn.rs1 as usize] as i64 + insn.imm as i64) as u64;
+    let value = unsafe { *(to_host_addr(addr) as *const u16) };
+    set_fp_reg(state, &F16, insn.rd, value as u64);
+}
+
+fn func_fsh(state: &mut state_t, insn: &mut insn_t) {
+    let addr = (state.gp_regs[insn.rs1 as usize] as i64 + insn.imm as i64) as u64;
+    let value = unsafe { state.fp_regs[insn.rs2 as usize].h };
+    unsafe { *(to_host_addr(addr) as *mut u16) = value };
+}
+
+fn func_fadd_h(state: &mut state_t, insn: &mut insn_t) {
+    fp_op(state, insn, &F16, float::add);
+}
+
+fn func_fsub_h(state: &mut state_t, insn: &mut insn_t) {
+    fp_op(state, insn, &F16, float::sub);
+}
+
+fn func_fmul_h(state: &mut state_t, insn: &mut insn_t) {
+    fp_op(state, insn, &F16, float::mul);
+}
+
+fn func_fdiv_h(state: &mut state_t, insn: &mut insn_t) {
+    fp_op(state, insn, &F16, float::div);
+}
+
+fn func_fsqrt_h(state: &mut state_t, insn: &mut insn_t) {
+    fp_sqrt(state, insn, &F16);
+}
+
+fn func_fmin_h(state: &mut state_t, insn: &mut insn_t) {
+    fp_minmax(state, insn, &F16, true);
+}
+
+fn func_fmax_h(state: &mut state_t, insn: &mut insn_t) {
+    fp_minmax(state, insn, &F16, false);
+}
+
+fn func_fmadd_h(state: &mut state_t, insn: &mut insn_t) {
+    fp_fma(state, insn, &F16, false, false);
+}
+
+fn func_fmsub_h(state: &mut state_t, insn: &mut insn_t) {
+    fp_fma(state, insn, &F16, false, true);
+}
+
+fn func_fnmsub_h(state: &mut state_t, insn: &mut insn_t) {
+    fp_fma(state, insn, &F16, true, false);
+}
+
+fn func_fnmadd_h(state: &mut state_t, insn: &mut insn_t) {
+    fp_fma(state, insn, &F16, true, true);
+}
+
+fn func_feq_h(state: &mut state_t, insn: &mut insn_t) {
+    fp_cmp(state, insn, &F16, float::eq);
+}
+
+fn func_flt_h(state: &mut state_t, insn: &mut insn_t) {
+    fp_cmp(state, insn, &F16, float::lt);
+}
+
+fn func_fle_h(state: &mut state_t, insn: &mut insn_t) {
+    fp_cmp(state, insn, &F16, float::le);
+}
+
+fn func_fsgnj_h(state: &mut state_t, insn: &mut insn_t) {
+    fp_sgnj(state, insn, &F16, |_, rs2| rs2);
+}
+
+fn func_fsgnjn_h(state: &mut state_t, insn: &mut insn_t) {
+    fp_sgnj(state, insn, &F16, |_, rs2| !rs2);
+}
+
+fn func_fsgnjx_h(state: &mut state_t, insn: &mut insn_t) {
+    fp_sgnj(state, insn, &F16, |rs1, rs2| rs1 ^ rs2);
+}
+
+fn func_fclass_h(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] = F16.class(fp_reg(state, &F16, insn.rs1));
+}
+
+fn func_fcvt_s_h(state: &mut state_t, insn: &mut insn_t) {
+    fp_convert(state, insn, &F16, &F32);
+}
+
+fn func_fcvt_h_s(state: &mut state_t, insn: &mut insn_t) {
+    fp_convert(state, insn, &F32, &F16);
+}
+
+fn func_fcvt_d_h(state: &mut state_t, insn: &mut insn_t) {
+    fp_convert(state, insn, &F16, &F64);
+}
+
+fn func_fcvt_h_d(state: &mut state_t, insn: &mut insn_t) {
+    fp_convert(state, insn, &F64, &F16);
+}
+
+fn func_fcvt_w_h(state: &mut state_t, insn: &mut insn_t) {
+    fp_to_int(state, insn, &F16, true, 32);
+}
+
+fn func_fcvt_wu_h(state: &mut state_t, insn: &mut insn_t) {
+    fp_to_int(state, insn, &F16, false, 32);
+}
+
+fn func_fcvt_l_h(state: &mut state_t, insn: &mut insn_t) {
+    fp_to_int(state, insn, &F16, true, 64);
+}
+
+fn func_fcvt_lu_h(state: &mut state_t, insn: &mut insn_t) {
+    fp_to_int(state, insn, &F16, false, 64);
+}
+
+fn func_fcvt_h_w(state: &mut state_t, insn: &mut insn_t) {
+    fp_from_int(state, insn, &F16, true, 32);
+}
+
+fn func_fcvt_h_wu(state: &mut state_t, insn: &mut insn_t) {
+    fp_from_int(state, insn, &F16, false, 32);
+}
+
+fn func_fcvt_h_l(state: &mut state_t, insn: &mut insn_t) {
+    fp_from_int(state, insn, &F16, true, 64);
+}
+
+fn func_fcvt_h_lu(state: &mut state_t, insn: &mut insn_t) {
+    fp_from_int(state, insn, &F16, false, 64);
+}
+
+fn func_fmv_x_h(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] = unsafe { state.fp_regs[insn.rs1 as usize].h } as i16 as u64;
+}
+
+fn func_fmv_h_x(state: &mut state_t, insn: &mut insn_t) {
+    set_fp_reg(
+        state,
+        &F16,
+        insn.rd,
+        state.gp_regs[insn.rs1 as usize] as u16 as u64,
+    );
+}
+
+/* Zba */
+
+fn func_sh1add(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] =
+        (state.gp_regs[insn.rs1 as usize] << 1).wrapping_add(state.gp_regs[insn.rs2 as usize]);
+}
+
+fn func_sh2add(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] =
+        (state.gp_regs[insn.rs1 as usize] << 2).wrapping_add(state.gp_regs[insn.rs2 as usize]);
+}
+
+fn func_sh3add(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] =
+        (state.gp_regs[insn.rs1 as usize] << 3).wrapping_add(state.gp_regs[insn.rs2 as usize]);
+}
+
+fn func_add_uw(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] = (state.gp_regs[insn.rs1 as usize] as u32 as u64)
+        .wrapping_add(state.gp_regs[insn.rs2 as usize]);
+}
+
+fn func_sh1add_uw(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] = ((state.gp_regs[insn.rs1 as usize] as u32 as u64) << 1)
+        .wrapping_add(state.gp_regs[insn.rs2 as usize]);
+}
+
+fn func_sh2add_uw(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] = ((state.gp_regs[insn.rs1 as usize] as u32 as u64) << 2)
+        .wrapping_add(state.gp_regs[insn.rs2 as usize]);
+}
+
+fn func_sh3add_uw(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] = ((state.gp_regs[insn.rs1 as usize] as u32 as u64) << 3)
+        .wrapping_add(state.gp_regs[insn.rs2 as usize]);
+}
+
+fn func_slli_uw(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] =
+        (state.gp_regs[insn.rs1 as usize] as u32 as u64) << (insn.imm & 0x3f);
+}
+
+/* Zbb */
+
+fn func_andn(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] =
+        state.gp_regs[insn.rs1 as usize] & !state.gp_regs[insn.rs2 as usize];
+}
+
+fn func_orn(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] =
+        state.gp_regs[insn.rs1 as usize] | !state.gp_regs[insn.rs2 as usize];
+}
+
+fn func_xnor(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] =
+        !(state.gp_regs[insn.rs1 as usize] ^ state.gp_regs[insn.rs2 as usize]);
+}
+
+fn func_clz(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] = state.gp_regs[insn.rs1 as usize].leading_zeros() as u64;
+}
+
+fn func_clzw(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] =
+        (state.gp_regs[insn.rs1 as usize] as u32).leading_zeros() as u64;
+}
+
+fn func_ctz(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] = state.gp_regs[insn.rs1 as usize].trailing_zeros() as u64;
+}
+
+fn func_ctzw(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] =
+        (state.gp_regs[insn.rs1 as usize] as u32).trailing_zeros() as u64;
+}
+
+fn func_cpop(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] = state.gp_regs[insn.rs1 as usize].count_ones() as u64;
+}
+
+fn func_cpopw(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] = (state.gp_regs[insn.rs1 as usize] as u32).count_ones() as u64;
+}
+
+fn func_max(state: &mut state_t, insn: &mut insn_t) {
+    let rs1 = state.gp_regs[insn.rs1 as usize] as i64;
+    let rs2 = state.gp_regs[insn.rs2 as usize] as i64;
+    state.gp_regs[insn.rd as usize] = rs1.max(rs2) as u64;
+}
+
+fn func_maxu(state: &mut state_t, insn: &mut insn_t) {
+    let rs1 = state.gp_regs[insn.rs1 as usize];
+    let rs2 = state.gp_regs[insn.rs2 as usize];
+    state.gp_regs[insn.rd as usize] = rs1.max(rs2);
+}
+
+fn func_min(state: &mut state_t, insn: &mut insn_t) {
+    let rs1 = state.gp_regs[insn.rs1 as usize] as i64;
+    let rs2 = state.gp_regs[insn.rs2 as usize] as i64;
+    state.gp_regs[insn.rd as usize] = rs1.min(rs2) as u64;
+}
+
+fn func_minu(state: &mut state_t, insn: &mut insn_t) {
+    let rs1 = state.gp_regs[insn.rs1 as usize];
+    let rs2 = state.gp_regs[insn.rs2 as usize];
+    state.gp_regs[insn.rd as usize] = rs1.min(rs2);
+}
+
+fn func_sext_b(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] = state.gp_regs[insn.rs1 as usize] as i8 as i64 as u64;
+}
+
+fn func_sext_h(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] = state.gp_regs[insn.rs1 as usize] as i16 as i64 as u64;
+}
+
+fn func_zext_h(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] = state.gp_regs[insn.rs1 as usize] as u16 as u64;
+}
+
+fn func_rol(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] = state.gp_regs[insn.rs1 as usize]
+        .rotate_left((state.gp_regs[insn.rs2 as usize] & 0x3f) as u32);
+}
+
+fn func_rolw(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] = (state.gp_regs[insn.rs1 as usize] as u32)
+        .rotate_left((state.gp_regs[insn.rs2 as usize] & 0x1f) as u32)
+        as i32 as u64;
+}
+
+fn func_ror(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] = state.gp_regs[insn.rs1 as usize]
+        .rotate_right((state.gp_regs[insn.rs2 as usize] & 0x3f) as u32);
+}
+
+fn func_rori(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] =
+        state.gp_regs[insn.rs1 as usize].rotate_right((insn.imm & 0x3f) as u32);
+}
+
+fn func_roriw(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] = (state.gp_regs[insn.rs1 as usize] as u32)
+        .rotate_right((insn.imm & 0x1f) as u32) as i32 as u64;
+}
+
+fn func_rorw(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] = (state.gp_regs[insn.rs1 as usize] as u32)
+        .rotate_right((state.gp_regs[insn.rs2 as usize] & 0x1f) as u32)
+        as i32 as u64;
+}
+
+fn func_orc_b(state: &mut state_t, insn: &mut insn_t) {
+    let rs1 = state.gp_regs[insn.rs1 as usize];
+    state.gp_regs[insn.rd as usize] =
+        u64::from_le_bytes(rs1.to_le_bytes().map(|b| if b != 0 { 0xff } else { 0 }));
+}
+
+fn func_rev8(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] = state.gp_regs[insn.rs1 as usize].swap_bytes();
+}
+
+/* Zbc */
+
+// full 128-bit carry-less product
+fn clmul128(a: u64, b: u64) -> u128 {
+    let mut result = 0u128;
+    for i in 0..64 {
+        if (b >> i) & 1 != 0 {
+            result ^= (a as u128) << i;
+        }
+    }
+    result
+}
+
+fn func_clmul(state: &mut state_t, insn: &mut insn_t) {
+    let rs1 = state.gp_regs[insn.rs1 as usize];
+    let rs2 = state.gp_regs[insn.rs2 as usize];
+    state.gp_regs[insn.rd as usize] = clmul128(rs1, rs2) as u64;
+}
+
+fn func_clmulh(state: &mut state_t, insn: &mut insn_t) {
+    let rs1 = state.gp_regs[insn.rs1 as usize];
+    let rs2 = state.gp_regs[insn.rs2 as usize];
+    state.gp_regs[insn.rd as usize] = (clmul128(rs1, rs2) >> 64) as u64;
+}
+
+fn func_clmulr(state: &mut state_t, insn: &mut insn_t) {
+    let rs1 = state.gp_regs[insn.rs1 as usize];
+    let rs2 = state.gp_regs[insn.rs2 as usize];
+    state.gp_regs[insn.rd as usize] = (clmul128(rs1, rs2) >> 63) as u64;
+}
+
+/* Zbs */
+
+fn func_bclr(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] =
+        state.gp_regs[insn.rs1 as usize] & !(1 << (state.gp_regs[insn.rs2 as usize] & 0x3f));
+}
+
+fn func_bclri(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] = state.gp_regs[insn.rs1 as usize] & !(1 << (insn.imm & 0x3f));
+}
+
+fn func_bext(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] =
+        (state.gp_regs[insn.rs1 as usize] >> (state.gp_regs[insn.rs2 as usize] & 0x3f)) & 1;
+}
+
+fn func_bexti(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] = (state.gp_regs[insn.rs1 as usize] >> (insn.imm & 0x3f)) & 1;
+}
+
+fn func_binv(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] =
+        state.gp_regs[insn.rs1 as usize] ^ (1 << (state.gp_regs[insn.rs2 as usize] & 0x3f));
+}
+
+fn func_binvi(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] = state.gp_regs[insn.rs1 as usize] ^ (1 << (insn.imm & 0x3f));
+}
+
+fn func_bset(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] =
+        state.gp_regs[insn.rs1 as usize] | (1 << (state.gp_regs[insn.rs2 as usize] & 0x3f));
+}
+
+fn func_bseti(state: &mut state_t, insn: &mut insn_t) {
+    state.gp_regs[insn.rd as usize] = state.gp_regs[insn.rs1 as usize] | (1 << (insn.imm & 0x3f));
 }
 
 static interp_funcs: [interp_func_t; insn_type_t::num_insns as usize] = [
@@ -903,8 +1537,8 @@ static interp_funcs: [interp_func_t; insn_type_t::num_insns as usize] = [
     func_lbu,
     func_lhu,
     func_lwu,
-    func_empty, // fence
-    func_empty, // fence_i
+    func_fence,
+    func_fence_i,
     func_addi,
     func_slli,
     func_slti,
@@ -961,6 +1595,7 @@ static interp_funcs: [interp_func_t; insn_type_t::num_insns as usize] = [
     func_jalr,
     func_jal,
     func_ecall,
+    func_ebreak,
     func_csrrw,
     func_csrrs,
     func_csrrc,
@@ -1029,19 +1664,168 @@ static interp_funcs: [interp_func_t; insn_type_t::num_insns as usize] = [
     func_fcvt_d_l,
     func_fcvt_d_lu,
     func_fmv_d_x,
+    func_sh1add,
+    func_sh2add,
+    func_sh3add,
+    func_add_uw,
+    func_sh1add_uw,
+    func_sh2add_uw,
+    func_sh3add_uw,
+    func_slli_uw,
+    func_andn,
+    func_orn,
+    func_xnor,
+    func_clz,
+    func_clzw,
+    func_ctz,
+    func_ctzw,
+    func_cpop,
+    func_cpopw,
+    func_max,
+    func_maxu,
+    func_min,
+    func_minu,
+    func_sext_b,
+    func_sext_h,
+    func_zext_h,
+    func_rol,
+    func_rolw,
+    func_ror,
+    func_rori,
+    func_roriw,
+    func_rorw,
+    func_orc_b,
+    func_rev8,
+    func_clmul,
+    func_clmulh,
+    func_clmulr,
+    func_bclr,
+    func_bclri,
+    func_bext,
+    func_bexti,
+    func_binv,
+    func_binvi,
+    func_bset,
+    func_bseti,
+    func_vset,   // vsetvli
+    func_vset,   // vsetivli
+    func_vset,   // vsetvl
+    func_vload,  // vle
+    func_vload,  // vleff
+    func_vload,  // vlm
+    func_vload,  // vlse
+    func_vload,  // vlxei
+    func_vload,  // vlr
+    func_vstore, // vse
+    func_vstore, // vsm
+    func_vstore, // vsse
+    func_vstore, // vsxei
+    func_vstore, // vsr
+    func_opi,    // opivv
+    func_opi,    // opivx
+    func_opi,    // opivi
+    func_opm,    // opmvv
+    func_opm,    // opmvx
+    func_opf,    // opfvv
+    func_opf,    // opfvf
+    func_flh,
+    func_fsh,
+    func_fmadd_h,
+    func_fmsub_h,
+    func_fnmsub_h,
+    func_fnmadd_h,
+    func_fadd_h,
+    func_fsub_h,
+    func_fmul_h,
+    func_fdiv_h,
+    func_fsqrt_h,
+    func_fsgnj_h,
+    func_fsgnjn_h,
+    func_fsgnjx_h,
+    func_fmin_h,
+    func_fmax_h,
+    func_fcvt_s_h,
+    func_fcvt_h_s,
+    func_fcvt_d_h,
+    func_fcvt_h_d,
+    func_feq_h,
+    func_flt_h,
+    func_fle_h,
+    func_fclass_h,
+    func_fcvt_w_h,
+    func_fcvt_wu_h,
+    func_fcvt_l_h,
+    func_fcvt_lu_h,
+    func_fcvt_h_w,
+    func_fcvt_h_wu,
+    func_fcvt_h_l,
+    func_fcvt_h_lu,
+    func_fmv_x_h,
+    func_fmv_h_x,
+    func_lr_w,
+    func_sc_w,
+    func_amoswap_w,
+    func_amoadd_w,
+    func_amoxor_w,
+    func_amoand_w,
+    func_amoor_w,
+    func_amomin_w,
+    func_amomax_w,
+    func_amominu_w,
+    func_amomaxu_w,
+    func_lr_d,
+    func_sc_d,
+    func_amoswap_d,
+    func_amoadd_d,
+    func_amoxor_d,
+    func_amoand_d,
+    func_amoor_d,
+    func_amomin_d,
+    func_amomax_d,
+    func_amominu_d,
+    func_amomaxu_d,
+    func_illegal,
 ];
 
+/*
+    cycles an in-order core would take. loads wait on memory, multiplies
+    and divides on their units, everything else issues in one.
+*/
+fn insn_cycles(type_: insn_type_t) -> u64 {
+    use insn_type_t::*;
+    match type_ {
+        insn_lb | insn_lh | insn_lw | insn_ld | insn_lbu | insn_lhu | insn_lwu | insn_flh
+        | insn_flw | insn_fld => 2,
+        insn_lr_w | insn_sc_w | insn_amoswap_w | insn_amoadd_w | insn_amoxor_w | insn_amoand_w
+        | insn_amoor_w | insn_amomin_w | insn_amomax_w | insn_amominu_w | insn_amomaxu_w
+        | insn_lr_d | insn_sc_d | insn_amoswap_d | insn_amoadd_d | insn_amoxor_d
+        | insn_amoand_d | insn_amoor_d | insn_amomin_d | insn_amomax_d | insn_amominu_d
+        | insn_amomaxu_d => 2,
+        insn_mul | insn_mulh | insn_mulhsu | insn_mulhu | insn_mulw | insn_clmul | insn_clmulh
+        | insn_clmulr => 3,
+        insn_fmadd_h | insn_fmsub_h | insn_fnmsub_h | insn_fnmadd_h | insn_fmadd_s
+        | insn_fmsub_s | insn_fnmsub_s | insn_fnmadd_s | insn_fmadd_d | insn_fmsub_d
+        | insn_fnmsub_d | insn_fnmadd_d => 4,
+        insn_div | insn_divu | insn_rem | insn_remu | insn_divw | insn_divuw | insn_remw
+        | insn_remuw => 20,
+        insn_fdiv_h | insn_fsqrt_h | insn_fdiv_s | insn_fsqrt_s | insn_fdiv_d | insn_fsqrt_d => 20,
+        _ => 1,
+    }
+}
+
 pub fn exec_block_interp(state: &mut state_t) {
     loop {
         // println!("pc: {:#x}", state.pc);
         let mut insn: insn_t = unsafe { mem::zeroed() };
         let insn_data = unsafe { std::ptr::read_unaligned(to_host_addr(state.pc) as *const u32) };
-        insn_decode(&mut insn, insn_data);
+        insn_decode(&mut insn, insn_data, &state.isa);
 
         // println!(">>>0x08880201bbc0: {}", unsafe {*(0x08880201bbc0 as *const u64)});
         // println!(">>>222: {}", unsafe {*(0x088802012fd8 as *const u64)});
 
         interp_funcs[insn.type_ as usize](state, &mut insn);
+        state.instret += 1;
+        state.cycle += insn_cycles(insn.type_);
 
         state.gp_regs[gp_reg_type_t::zero as usize] = 0;
 
@@ -1054,6 +1838,17 @@ pub fn exec_block_interp(state: &mut state_t) {
         // println!();
 
         if insn.cont {
+            // the embedder regains control between blocks, ecalls and ebreaks go first
+            if !matches!(
+                state.exit_reason,
+                exit_reason_t::ecall | exit_reason_t::ebreak | exit_reason_t::illegal
+            ) {
+                if state.interrupt.swap(false, Ordering::Relaxed) {
+                    state.exit_reason = exit_reason_t::interrupted;
+                } else if state.instret >= state.stop_at {
+                    state.exit_reason = exit_reason_t::budget_exhausted;
+                }
+            }
             break;
         }
 
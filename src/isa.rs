@@ -0,0 +1,86 @@
+/*
+    optional ISA extensions the decoder accepts, the base RV64IMAFDC is
+    always there. an instruction of a disabled extension decodes like any
+    other unknown encoding.
+*/
+#[derive(Clone, Copy)]
+pub struct isa_t {
+    pub zba: bool,    // address generation
+    pub zbb: bool,    // basic bit manipulation
+    pub zbc: bool,    // carry-less multiplication
+    pub zbs: bool,    // single-bit instructions
+    pub v: bool,      // vectors
+    pub zfhmin: bool, // half-precision loads, stores and conversions
+    pub zfh: bool,    // half-precision arithmetic
+}
+
+impl isa_t {
+    // everything implemented
+    pub fn new() -> isa_t {
+        isa_t {
+            zba: true,
+            zbb: true,
+            zbc: true,
+            zbs: true,
+            v: true,
+            zfhmin: true,
+            zfh: true,
+        }
+    }
+
+    /*
+        parse an ISA string like rv64gc or rv64imafdcbv_zbc, single letters
+        first, then multi-letter extensions separated by underscores
+    */
+    pub fn parse(s: &str) -> Option<isa_t> {
+        let s = s.to_ascii_lowercase();
+        let mut parts = s.strip_prefix("rv64")?.split('_');
+        let mut isa = isa_t {
+            zba: false,
+            zbb: false,
+            zbc: false,
+            zbs: false,
+            v: false,
+            zfhmin: false,
+            zfh: false,
+        };
+
+        for c in parts.next()?.chars() {
+            match c {
+                'i' | 'm' | 'a' | 'f' | 'd' | 'c' | 'g' => {}
+                // B is Zba, Zbb and Zbs
+                'b' => {
+                    isa.zba = true;
+                    isa.zbb = true;
+                    isa.zbs = true;
+                }
+                'v' => isa.v = true,
+                _ => return None,
+            }
+        }
+        for ext in parts {
+            match ext {
+                "zba" => isa.zba = true,
+                "zbb" => isa.zbb = true,
+                "zbc" => isa.zbc = true,
+                "zbs" => isa.zbs = true,
+                // Zfh includes Zfhmin
+                "zfh" => {
+                    isa.zfh = true;
+                    isa.zfhmin = true;
+                }
+                "zfhmin" => isa.zfhmin = true,
+                // implied by rv64g
+                "zicsr" | "zifencei" => {}
+                _ => return None,
+            }
+        }
+        Some(isa)
+    }
+}
+
+impl Default for isa_t {
+    fn default() -> isa_t {
+        isa_t::new()
+    }
+}
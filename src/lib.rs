@@ -0,0 +1,24 @@
+#![allow(non_camel_case_types)]
+
+pub mod clock;
+pub mod config;
+pub mod decode;
+mod elf;
+pub mod fd;
+mod float;
+pub mod htif;
+pub mod insn;
+mod interp;
+pub mod isa;
+pub mod machine;
+pub mod mmu;
+pub mod personality;
+pub mod random;
+pub mod reg;
+pub mod riscv_tests;
+pub mod semihosting;
+pub mod signal;
+pub mod syscall;
+mod utils;
+mod vector;
+pub mod vfs;
@@ -1,14 +1,40 @@
 use crate::{
+    clock::clock_t,
+    config::{config_t, ebreak_policy_t, limits_t, vfs_mode_t},
+    fd::fd_table_t,
+    htif::{htif_call, htif_t},
     interp::exec_block_interp,
+    isa::isa_t,
     mmu::mmu_t,
+    personality::personality_t,
+    random::random_t,
     reg::{fp_reg_t, fp_reg_type_t, gp_reg_type_t},
-    syscall::{OLD_SYSCALL_TABLE, OLD_SYSCALL_THRESHOLD, SYSCALL_TABLE, syscall_t},
+    semihosting::{is_semihosting, semihosting_call},
+    signal::{ILL_ILLOPC, NSIG, TRAP_BRKPT, deliver_signal, sigaction_t},
+    utils::round_down,
+    vector::vstate_t,
+    vfs::{host_file_t, host_vfs_t, mem_vfs_t, overlay_vfs_t, vfile_t, vfs_t},
 };
 use core::panic;
-use std::fs::File;
+use std::{
+    cell::RefCell,
+    fs::{self, File},
+    mem,
+    os::fd::IntoRawFd,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{Arc, atomic::AtomicBool},
+};
 
 const GUEST_MEMORY_OFFSET: u64 = 0x0888_0000_0000;
 
+pub const STACK_SIZE: u64 = 32 * 1024 * 1024;
+pub const STACK_TOP: u64 = 0x40_0000_0000; // end of the sv39 user address space
+pub const RLIM_NLIMITS: usize = 16;
+
+const AT_NULL: u64 = 0;
+const AT_RANDOM: u64 = 25;
+
 #[inline]
 pub fn to_host_addr(addr: u64) -> u64 {
     addr + GUEST_MEMORY_OFFSET
@@ -19,12 +45,53 @@ pub fn to_guest_addr(addr: u64) -> u64 {
     addr - GUEST_MEMORY_OFFSET
 }
 
+// the host's limits, except for what the emulator fixes itself or was told
+fn default_rlimits(limits: &limits_t, insns_per_sec: u64) -> [(u64, u64); RLIM_NLIMITS] {
+    let mut rlimits = [(libc::RLIM_INFINITY, libc::RLIM_INFINITY); RLIM_NLIMITS];
+    for (resource, limit) in rlimits.iter_mut().enumerate() {
+        let mut rlim = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        if unsafe { libc::getrlimit(resource as _, &mut rlim) } == 0 {
+            *limit = (rlim.rlim_cur, rlim.rlim_max);
+        }
+    }
+    rlimits[libc::RLIMIT_STACK as usize] = (limits.stack, limits.stack);
+    rlimits[libc::RLIMIT_NOFILE as usize] = (limits.fds, limits.fds);
+    if let Some(heap) = limits.heap {
+        rlimits[libc::RLIMIT_DATA as usize] = (heap, heap);
+    }
+    if let Some(mem) = limits.mem {
+        rlimits[libc::RLIMIT_AS as usize] = (mem, mem);
+    }
+    if let Some(insns) = limits.insns {
+        let secs = insns.div_ceil(insns_per_sec);
+        rlimits[libc::RLIMIT_CPU as usize] = (secs, secs);
+    }
+    rlimits
+}
+
 #[derive(PartialEq, Debug)]
 pub enum exit_reason_t {
     none,
     direct_branch,
     indirect_branch,
     ecall,
+    ebreak,           // pc is at the ebreak, see machine_ebreak
+    semihosting,      // an ebreak in the semihosting sequence, see machine_semihosting
+    htif,             // the guest wrote tohost, see machine_htif
+    illegal,          // pc is at an illegal instruction, see machine_illegal
+    budget_exhausted, // the machine_set_budget instructions have retired
+    interrupted,      // the machine_interrupt_flag was raised
+}
+
+// the Zicntr counters
+#[derive(Clone, Copy, Debug)]
+pub struct counters_t {
+    pub cycle: u64,
+    pub time: u64, // ticks of the timebase
+    pub instret: u64,
 }
 
 #[repr(C)]
@@ -34,16 +101,99 @@ pub struct state_t {
     pub gp_regs: [u64; gp_reg_type_t::num_gp_regs as usize],
     pub fp_regs: [fp_reg_t; fp_reg_type_t::num_fp_regs as usize],
     pub pc: u64,
+    pub instret: u64,             // retired instructions
+    pub cycle: u64,               // modelled core cycles
+    pub stop_at: u64,             // leave at the next block boundary once instret reaches this
+    pub reservation: Option<u64>, // address of the last lr, for sc
+    pub interrupt: Arc<AtomicBool>,
+    pub isa: isa_t, // extensions the decoder accepts
+    pub vec: vstate_t,
+    pub fflags: u64, // accrued floating point exceptions
+    pub frm: u64,    // dynamic rounding mode
+    pub clock: clock_t,
 }
 
 #[repr(C)]
 pub struct machine_t {
     pub state: state_t,
     pub mmu: mmu_t,
+    pub config: config_t,
+    pub personality: personality_t,
+    pub rlimits: [(u64, u64); RLIM_NLIMITS], // (soft, hard) by RLIMIT_*
+    pub insn_limit: u64,                     // RLIMIT_CPU in instructions
+    pub budget_end: u64,                     // instret where machine_step pauses
+    pub exe_path: PathBuf,                   // the guest's /proc/self/exe
+    pub cmdline: String,                     // argv for SYS_GET_CMDLINE
+    pub semihosting_errno: i32,              // for SYS_ERRNO
+    pub htif: Option<htif_t>,                // with --htif
+    pub sigactions: [sigaction_t; NSIG],     // by signal number - 1
+    pub sigmask: u64,                        // blocked signals
+    pub random: Rc<RefCell<random_t>>,       // shared with open /dev/urandom files
+    pub vfs: Box<dyn vfs_t>,
+    pub fds: fd_table_t,
 }
 
 impl machine_t {
-    pub fn new() -> machine_t {
+    pub fn new(config: config_t) -> machine_t {
+        let mmu = mmu_t::new();
+        // in-memory files count against the guest memory limit
+        let vfs: Box<dyn vfs_t> = match &config.vfs {
+            vfs_mode_t::host => Box::new(host_vfs_t::new(config.sysroot.clone())),
+            vfs_mode_t::mem => {
+                let mut vfs = mem_vfs_t::new();
+                vfs.set_account(mmu.account.clone());
+                Box::new(vfs)
+            }
+            vfs_mode_t::overlay(dir) => match overlay_vfs_t::new(dir) {
+                Ok(mut vfs) => {
+                    vfs.set_account(mmu.account.clone());
+                    Box::new(vfs)
+                }
+                Err(e) => panic!("Error opening overlay directory: {}", e),
+            },
+        };
+
+        let mut fds = fd_table_t::new();
+        for (fd, path) in config.stdio.iter().enumerate() {
+            let Some(path) = path else { continue };
+            let file = if fd == 0 {
+                File::open(path)
+            } else {
+                File::create(path)
+            };
+            match file {
+                Ok(file) => fds.set(
+                    fd as u64,
+                    Box::new(host_file_t::new(file.into_raw_fd())),
+                    false,
+                ),
+                Err(e) => panic!("Error opening {}: {}", path.display(), e),
+            }
+        }
+        for (i, &(guest_fd, host_fd)) in config.inherit_fds.iter().enumerate() {
+            // dup'ed guest fds share one host fd, which must be owned only once
+            match config.inherit_fds[..i].iter().find(|(_, h)| *h == host_fd) {
+                Some(&(first, _)) => {
+                    fds.dup_to(first, guest_fd, false).unwrap();
+                }
+                None => {
+                    unsafe { libc::fcntl(host_fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+                    fds.set(guest_fd, Box::new(host_file_t::new(host_fd)), false);
+                }
+            }
+        }
+
+        let clock = clock_t::new(config.clock, config.timebase);
+        let rlimits = default_rlimits(&config.limits, clock.insns_per_sec());
+        let insn_limit = match config.limits.insns {
+            Some(insns) => insns,
+            None => rlimits[libc::RLIMIT_CPU as usize]
+                .0
+                .saturating_mul(clock.insns_per_sec()),
+        };
+        fds.limit = rlimits[libc::RLIMIT_NOFILE as usize].0;
+        mmu.account.limit.set(rlimits[libc::RLIMIT_AS as usize].0);
+
         machine_t {
             state: state_t {
                 exit_reason: exit_reason_t::none,
@@ -51,21 +201,98 @@ impl machine_t {
                 gp_regs: [0; gp_reg_type_t::num_gp_regs as usize],
                 fp_regs: [fp_reg_t { v: 0 }; fp_reg_type_t::num_fp_regs as usize],
                 pc: 0,
+                instret: 0,
+                cycle: 0,
+                stop_at: u64::MAX,
+                reservation: None,
+                interrupt: Arc::new(AtomicBool::new(false)),
+                isa: config.isa,
+                vec: vstate_t::new(config.vlen),
+                fflags: 0,
+                frm: 0,
+                clock,
             },
-            mmu: mmu_t::new(),
+            mmu,
+            personality: config.personality.unwrap_or(personality_t::newlib),
+            rlimits,
+            insn_limit,
+            budget_end: u64::MAX,
+            exe_path: PathBuf::new(),
+            cmdline: String::new(),
+            semihosting_errno: 0,
+            htif: None,
+            sigactions: [sigaction_t::default(); NSIG],
+            sigmask: 0,
+            random: Rc::new(RefCell::new(random_t::new(config.seed))),
+            config,
+            vfs,
+            fds,
         }
     }
+    pub fn machine_set_vfs(&mut self, vfs: Box<dyn vfs_t>) {
+        self.vfs = vfs;
+    }
+    // point a guest fd, e.g. stdout, at another file such as vfs::mem_buffer()
+    pub fn machine_redirect_fd(&mut self, fd: u64, file: Box<dyn vfile_t>) {
+        self.fds.set(fd, file, false);
+    }
     pub fn machine_load_program(&mut self, prog_path_str: &str) {
         let mut elf_file = match File::open(prog_path_str) {
             Ok(elf_file) => elf_file,
             Err(e) => panic!("Error opening file: {}", e),
         };
 
+        if self.config.personality.is_none() {
+            self.personality = personality_t::detect(&elf_file);
+        }
         self.mmu.mmu_load_elf(&mut elf_file);
+        if self.config.htif {
+            self.htif = match htif_t::locate(&elf_file) {
+                Some(htif) if self.mmu.mmu_valid(htif.tohost, 8) => Some(htif),
+                _ => panic!("No tohost symbol in loaded memory of {}", prog_path_str),
+            };
+        }
+
+        // the absolute path, as seen from inside the sysroot if there is one
+        let path = fs::canonicalize(prog_path_str).unwrap();
+        let sysroot = self
+            .config
+            .sysroot
+            .as_ref()
+            .and_then(|s| fs::canonicalize(s).ok());
+        self.exe_path = match sysroot.as_ref().and_then(|s| path.strip_prefix(s).ok()) {
+            Some(rel) => Path::new("/").join(rel),
+            None => path,
+        };
 
         self.state.pc = self.mmu.get_entry();
     }
+    // pause machine_step after about insns more instructions, None to run freely
+    pub fn machine_set_budget(&mut self, insns: Option<u64>) {
+        self.budget_end = match insns {
+            Some(insns) => self.state.instret.saturating_add(insns),
+            None => u64::MAX,
+        };
+    }
+    // what the guest reads from cycle, time and instret
+    pub fn machine_counters(&self) -> counters_t {
+        counters_t {
+            cycle: self.state.cycle,
+            time: self.state.clock.ticks(self.state.instret),
+            instret: self.state.instret,
+        }
+    }
+    // set from any thread to make machine_step return interrupted
+    pub fn machine_interrupt_flag(&self) -> Arc<AtomicBool> {
+        self.state.interrupt.clone()
+    }
+    /*
+        run until an ecall, the budget runs out or an interrupt arrives.
+        the last two are noticed at block boundaries, pc is where to
+        resume with another machine_step.
+    */
     pub fn machine_step(&mut self) -> exit_reason_t {
+        self.state.stop_at = self.budget_end.min(self.insn_limit);
         loop {
             self.state.exit_reason = exit_reason_t::none;
             exec_block_interp(&mut self.state);
@@ -74,6 +301,10 @@ impl machine_t {
             match self.state.exit_reason {
                 exit_reason_t::indirect_branch | exit_reason_t::direct_branch => {
                     self.state.pc = self.state.reenter_pc;
+                    if self.htif.is_some_and(|htif| htif.pending(self) != 0) {
+                        self.state.exit_reason = exit_reason_t::htif;
+                        break;
+                    }
                     continue;
                 }
                 _ => break,
@@ -81,74 +312,161 @@ impl machine_t {
         }
 
         self.state.pc = self.state.reenter_pc;
-        assert_eq!(self.state.exit_reason, exit_reason_t::ecall);
-        exit_reason_t::ecall
+        if self.state.exit_reason == exit_reason_t::ebreak && is_semihosting(self, self.state.pc) {
+            self.state.exit_reason = exit_reason_t::semihosting;
+        }
+        if self.state.exit_reason == exit_reason_t::budget_exhausted
+            && self.state.instret >= self.insn_limit
+        {
+            self.machine_cpu_limit_exceeded();
+        }
+        mem::replace(&mut self.state.exit_reason, exit_reason_t::none)
+    }
+    // apply the --ebreak policy to the ebreak machine_step stopped at
+    pub fn machine_ebreak(&mut self) {
+        match self.config.ebreak {
+            ebreak_policy_t::sigtrap | ebreak_policy_t::semihosting => {
+                let pc = self.state.pc;
+                if deliver_signal(self, libc::SIGTRAP, TRAP_BRKPT, pc) {
+                    return;
+                }
+                eprintln!("rvemu-riir: ebreak at pc {:#x}", pc);
+                unsafe {
+                    libc::signal(libc::SIGTRAP, libc::SIG_DFL);
+                    libc::raise(libc::SIGTRAP);
+                }
+                std::process::exit(128 + libc::SIGTRAP);
+            }
+            ebreak_policy_t::skip => {
+                // c.ebreak is compressed
+                let insn = unsafe { *(to_host_addr(self.state.pc) as *const u16) };
+                self.state.pc += if insn & 0x3 == 0x3 { 4 } else { 2 };
+            }
+        }
+    }
+    /*
+        run the guest's SIGILL handler for the illegal instruction
+        machine_step stopped at, or die of it like Linux without one
+    */
+    pub fn machine_illegal(&mut self) {
+        let pc = self.state.pc;
+        if deliver_signal(self, libc::SIGILL, ILL_ILLOPC, pc) {
+            return;
+        }
+        // 16 or 32 bits, printed at their width
+        let insn = unsafe { *(to_host_addr(pc) as *const u16) } as u32;
+        let (bits, width) = match insn & 0x3 {
+            0x3 => (
+                unsafe { std::ptr::read_unaligned(to_host_addr(pc) as *const u32) },
+                10,
+            ),
+            _ => (insn, 6),
+        };
+        eprintln!(
+            "rvemu-riir: illegal instruction {:#0w$x} at pc {:#x}",
+            bits,
+            pc,
+            w = width
+        );
+        unsafe {
+            libc::signal(libc::SIGILL, libc::SIG_DFL);
+            libc::raise(libc::SIGILL);
+        }
+        std::process::exit(128 + libc::SIGILL);
+    }
+    // serve the semihosting call machine_step stopped at, if enabled
+    pub fn machine_semihosting(&mut self) {
+        if self.config.ebreak != ebreak_policy_t::semihosting {
+            return self.machine_ebreak();
+        }
+        let ret = semihosting_call(self);
+        self.state.gp_regs[gp_reg_type_t::a0 as usize] = ret;
+        // on to the srai, which does nothing
+        self.state.pc += 4;
+    }
+    // serve the tohost command machine_step stopped at
+    pub fn machine_htif(&mut self) {
+        if let Some(htif) = self.htif {
+            htif_call(self, htif);
+        }
+    }
+    // what Linux does past the RLIMIT_CPU soft limit
+    fn machine_cpu_limit_exceeded(&self) -> ! {
+        eprintln!(
+            "rvemu-riir: instruction limit of {} exceeded",
+            self.insn_limit
+        );
+        unsafe {
+            libc::signal(libc::SIGXCPU, libc::SIG_DFL);
+            libc::raise(libc::SIGXCPU);
+        }
+        std::process::exit(128 + libc::SIGXCPU);
     }
-    pub fn machine_setup(&mut self, argc: u64, argv: &[&str]) {
-        // println!("sysx {:#x}", self.mmu.alloc);
-
-        let stack_size: usize = 32 * 1024 * 1024;
-        let stack: u64 = self.mmu.mmu_alloc(stack_size as i64);
-
-        // println!(">>>>111: {}", unsafe {*(0x088800012c68 as *const u64)});
-        // println!(">>>>222: {}", unsafe {*(0x088802012fd8 as *const u64)});
-
-        // println!(">>>>>Stack: {:#x}", stack);
-        self.state.gp_regs[gp_reg_type_t::sp as usize] = stack + stack_size as u64; // goto stack bottom
-
-        // println!(">>>>{:x}", self.state.gp_regs[gp_reg_type_t::sp as usize]);
-
-        self.state.gp_regs[gp_reg_type_t::sp as usize] -= 8; // auxv
-        self.state.gp_regs[gp_reg_type_t::sp as usize] -= 8; // envp
-        self.state.gp_regs[gp_reg_type_t::sp as usize] -= 8; // argv end
-
-        let args = argc - 1;
 
-        for i in (1..=args).rev() {
-            // println!("sysx {}: {}", i, argv[i as usize]);
-            let len: usize = argv[i as usize].len();
-            // println!("sysx {:#x}", self.mmu.alloc);
+    /*
+        the initial stack as Linux builds it: argv and envp strings and the
+        AT_RANDOM bytes at the top, below them argc, argv, envp and auxv with
+        sp 16-byte aligned.
+    */
+    pub fn machine_setup(&mut self, argv: &[&str]) {
+        let stack_size = self.rlimits[libc::RLIMIT_STACK as usize].0;
+        if !self.mmu.mmu_alloc_stack(STACK_TOP, stack_size) {
+            panic!("Cannot map a stack of {} bytes", stack_size);
+        }
+        let mut sp = STACK_TOP;
+        let mut argv = argv.to_vec();
+        let argv0 = self.config.argv0.clone();
+        if let Some(argv0) = &argv0 {
+            argv[0] = argv0;
+        }
+        self.cmdline = argv.join(" ");
 
-            let addr: u64 = self.mmu.mmu_alloc((len + 1) as i64);
-            // println!("sysx {:#x}", self.mmu.alloc);
+        let mut argv_addrs = Vec::new();
+        for arg in &argv {
+            sp -= arg.len() as u64 + 1;
+            mmu_t::mmu_write(sp, arg.as_bytes());
+            mmu_t::mmu_write(sp + arg.len() as u64, &[0]);
+            argv_addrs.push(sp);
+        }
+        let mut envp_addrs = Vec::new();
+        for env in &self.config.envp {
+            sp -= env.len() as u64 + 1;
+            mmu_t::mmu_write(sp, env.as_bytes());
+            mmu_t::mmu_write(sp + env.len() as u64, &[0]);
+            envp_addrs.push(sp);
+        }
 
-            mmu_t::mmu_write(addr, argv[i as usize].as_bytes());
-            self.state.gp_regs[gp_reg_type_t::sp as usize] -= 8; // argv[i]
-            mmu_t::mmu_write(
-                self.state.gp_regs[gp_reg_type_t::sp as usize],
-                &(addr.to_le_bytes()),
-            );
-            // println!(">{:x}", self.state.gp_regs[gp_reg_type_t::sp as usize]);
+        // 16 random bytes for AT_RANDOM, which libcs seed stack guards from
+        sp -= 16;
+        let at_random = sp;
+        let mut bytes = [0u8; 16];
+        if self.random.borrow_mut().fill(&mut bytes) != Ok(bytes.len()) {
+            panic!("Cannot get random bytes for AT_RANDOM");
         }
+        mmu_t::mmu_write(at_random, &bytes);
 
-        self.state.gp_regs[gp_reg_type_t::sp as usize] -= 8; // argc
-        mmu_t::mmu_write(
-            self.state.gp_regs[gp_reg_type_t::sp as usize],
-            &args.to_le_bytes(),
-        );
-        // println!(">>>>{:x}", self.state.gp_regs[gp_reg_type_t::sp as usize]);
+        let mut words = vec![argv.len() as u64]; // argc
+        words.extend(argv_addrs);
+        words.push(0); // argv end
+        words.extend(envp_addrs);
+        words.push(0); // envp end
+        words.extend([AT_RANDOM, at_random, AT_NULL, 0]); // auxv
 
-        // println!(">>>>{:x}", self.state.gp_regs[gp_reg_type_t::sp as usize]);
+        sp = round_down(sp - words.len() as u64 * 8, 16);
+        for (i, word) in words.iter().enumerate() {
+            mmu_t::mmu_write(sp + i as u64 * 8, &word.to_le_bytes());
+        }
+        self.state.gp_regs[gp_reg_type_t::sp as usize] = sp;
     }
 
     pub fn do_syscall(&mut self, syscall_num: u64) -> u64 {
         // println!("syscall: {}", syscall_num);
 
-        let f: Option<syscall_t>;
-        if syscall_num as u32 <= *(SYSCALL_TABLE.keys().max().unwrap()) {
-            f = Some(SYSCALL_TABLE[&(syscall_num as u32)]);
-        } else if (syscall_num as u32 - OLD_SYSCALL_THRESHOLD)
-            <= *(OLD_SYSCALL_TABLE.keys().max().unwrap())
-        {
-            f = Some(OLD_SYSCALL_TABLE[&(syscall_num as u32 - OLD_SYSCALL_THRESHOLD as u32)]);
-        } else {
-            f = None;
-        }
-
-        if let Some(f) = f {
-            f(self)
-        } else {
-            panic!("Unknown syscall: {}", syscall_num);
+        match self.personality.syscall(syscall_num) {
+            Some(f) => f(self),
+            // Linux programs probe for newer calls and cope with ENOSYS
+            None if self.personality == personality_t::linux => -(libc::ENOSYS as i64) as u64,
+            None => panic!("Unknown syscall: {}", syscall_num),
         }
     }
 
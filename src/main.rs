@@ -1,38 +1,55 @@
-#![allow(non_camel_case_types)]
+use std::sync::atomic::Ordering;
 
-use machine::exit_reason_t;
-use reg::gp_reg_type_t;
-
-mod decode;
-mod elf;
-mod insn;
-mod interp;
-mod machine;
-mod mmu;
-mod reg;
-mod syscall;
-mod utils;
+use rvemu_riir::{
+    config,
+    machine::{self, exit_reason_t},
+    reg::{self, gp_reg_type_t},
+    riscv_tests,
+};
 
 fn main() {
-    if std::env::args().len() < 2 {
-        println!("Usage: {} <program>", std::env::args().nth(0).unwrap());
-        std::process::exit(1);
-    }
     let args: Vec<String> = std::env::args().collect();
-    let args_str: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let (config, prog) = config::parse_args(&args);
+    if !config.riscv_tests.is_empty() {
+        riscv_tests::riscv_tests(&config);
+    }
+    let args_str: Vec<&str> = args[prog..].iter().map(|s| s.as_str()).collect();
 
-    let mut machine: machine::machine_t = machine::machine_t::new();
-    machine.machine_load_program(args_str[1]);
+    let mut machine: machine::machine_t = machine::machine_t::new(config);
+    machine.machine_load_program(args_str[0]);
     // println!("sysx {:#x}", machine.mmu.alloc);
-    machine.machine_setup(args.len() as u64, &args_str);
+    machine.machine_setup(&args_str);
     // println!("sysx {:#x}", machine.mmu.alloc);
 
-    loop {
-        let reason: exit_reason_t = machine.machine_step();
-        assert_eq!(reason, exit_reason_t::ecall);
+    // --timeout raises the interrupt flag from a timer thread
+    if let Some(timeout) = machine.config.timeout {
+        let interrupt = machine.machine_interrupt_flag();
+        std::thread::spawn(move || {
+            std::thread::sleep(timeout);
+            interrupt.store(true, Ordering::Relaxed);
+        });
+    }
 
-        let syscall_num: u64 = machine.machine_get_gp_reg(reg::gp_reg_type_t::a7);
-        let ret: u64 = machine.do_syscall(syscall_num);
-        machine.machine_set_gp_reg(gp_reg_type_t::a0, ret);
+    loop {
+        match machine.machine_step() {
+            exit_reason_t::ecall => {
+                let syscall_num: u64 = machine.machine_get_gp_reg(reg::gp_reg_type_t::a7);
+                let ret: u64 = machine.do_syscall(syscall_num);
+                machine.machine_set_gp_reg(gp_reg_type_t::a0, ret);
+            }
+            exit_reason_t::ebreak => machine.machine_ebreak(),
+            exit_reason_t::semihosting => machine.machine_semihosting(),
+            exit_reason_t::htif => machine.machine_htif(),
+            exit_reason_t::illegal => machine.machine_illegal(),
+            exit_reason_t::interrupted => {
+                eprintln!(
+                    "rvemu-riir: timed out after {:?} at pc {:#x}",
+                    machine.config.timeout.unwrap(),
+                    machine.state.pc
+                );
+                std::process::exit(124);
+            }
+            reason => panic!("unexpected exit reason: {:?}", reason),
+        }
     }
 }
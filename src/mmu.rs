@@ -3,20 +3,76 @@ use crate::{
     utils::{round_down, round_up},
 };
 use std::{
+    cell::{Cell, RefCell},
     cmp,
     fs::File,
     io::{Read, Seek},
     mem,
     os::fd::AsRawFd,
+    rc::{Rc, Weak},
 };
 
 use crate::elf::*;
 
+// the program break may grow this far past the end of the ELF file
+pub const HEAP_SIZE_MAX: u64 = 16 * 1024 * 1024 * 1024;
+
+/*
+    guest memory counted against RLIMIT_AS: the pages the mmu maps and the
+    contents of in-memory files, which the vfs keeps on the host heap.
+    shared by the mmu and the vfs.
+*/
+pub struct mem_account_t {
+    pub limit: Cell<u64>,  // RLIMIT_AS
+    pub mapped: Cell<u64>, // see mmu_mapped
+    files: RefCell<Vec<Weak<RefCell<Vec<u8>>>>>,
+}
+
+impl mem_account_t {
+    pub fn new() -> mem_account_t {
+        mem_account_t {
+            limit: Cell::new(u64::MAX),
+            mapped: Cell::new(0),
+            files: RefCell::new(Vec::new()),
+        }
+    }
+
+    // count a file's contents until the last reference to them is dropped
+    pub fn add_file(&self, data: &Rc<RefCell<Vec<u8>>>) {
+        self.files.borrow_mut().push(Rc::downgrade(data));
+    }
+
+    pub fn file_bytes(&self) -> u64 {
+        let mut files = self.files.borrow_mut();
+        files.retain(|file| file.strong_count() > 0);
+        files
+            .iter()
+            .filter_map(|file| file.upgrade())
+            .map(|data| data.borrow().len() as u64)
+            .sum()
+    }
+
+    // whether len more bytes stay within the limit
+    pub fn fits(&self, len: u64) -> bool {
+        (self.mapped.get() + self.file_bytes()).saturating_add(len) <= self.limit.get()
+    }
+}
+
+impl Default for mem_account_t {
+    fn default() -> mem_account_t {
+        mem_account_t::new()
+    }
+}
+
 pub struct mmu_t {
     pub entry: u64,
-    pub host_alloc: u64,
-    pub alloc: u64,
-    pub base: u64,
+    pub host_alloc: u64, // host end of the pages mapped for the heap
+    pub alloc: u64,      // program break
+    pub base: u64,       // start of the heap, right after the ELF segments
+    pub brk_max: u64,
+    pub account: Rc<mem_account_t>, // bytes of guest memory, RLIMIT_AS
+    pub segments: Vec<(u64, u64)>,  // guest [start, end) of the loaded ELF segments
+    pub stack: (u64, u64),          // guest [start, end) of the stack
 }
 
 impl mmu_t {
@@ -26,6 +82,10 @@ impl mmu_t {
             host_alloc: 0,
             alloc: 0,
             base: 0,
+            brk_max: 0,
+            account: Rc::new(mem_account_t::new()),
+            segments: Vec::new(),
+            stack: (0, 0),
         }
     }
     pub fn mmu_load_elf(&mut self, elf: &mut File) {
@@ -57,6 +117,7 @@ impl mmu_t {
                 self.mmu_load_segment(&phdr_t, elf);
             }
         }
+        self.brk_max = self.base + HEAP_SIZE_MAX;
     }
     pub fn get_entry(&self) -> u64 {
         self.entry
@@ -83,7 +144,8 @@ impl mmu_t {
         };
         assert_eq!(addr, aligned_vaddr);
 
-        let remaining_bss = round_up(memsz, page_size as u64);
+        // the pages past the file backed ones, mapping more would cover the heap
+        let remaining_bss = round_up(memsz, page_size as u64) - round_up(filesz, page_size as u64);
         if remaining_bss as isize > 0 {
             let addr: usize = unsafe {
                 libc::mmap(
@@ -101,49 +163,131 @@ impl mmu_t {
             );
         }
 
+        self.segments.push((
+            to_guest_addr(aligned_vaddr),
+            to_guest_addr(aligned_vaddr + round_up(memsz, page_size as u64)),
+        ));
         self.host_alloc = cmp::max(
             self.host_alloc,
             aligned_vaddr + round_up(memsz, page_size as u64) as u64,
         );
         self.alloc = to_guest_addr(self.host_alloc);
         self.base = to_guest_addr(self.host_alloc);
+        self.account.mapped.set(self.mmu_mapped());
     }
 
-    pub fn mmu_alloc(&mut self, size: i64) -> u64 {
-        let page_size: usize = page_size::get();
-        let base: u64 = self.alloc;
-        assert!(base >= self.base);
-
-        self.alloc = self.alloc.wrapping_add_signed(size);
-        assert!(self.alloc >= self.base);
+    // map fresh zeroed pages at a guest address without replacing anything
+    fn mmu_map_anon(addr: u64, len: u64) -> bool {
+        let host = to_host_addr(addr) as *mut libc::c_void;
+        let ret = unsafe {
+            libc::mmap(
+                host,
+                len as usize,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_FIXED_NOREPLACE,
+                -1,
+                0,
+            )
+        };
+        if ret == libc::MAP_FAILED {
+            return false;
+        }
+        // older kernels take MAP_FIXED_NOREPLACE as a hint
+        if ret != host {
+            unsafe { libc::munmap(ret, len as usize) };
+            return false;
+        }
+        true
+    }
 
-        if size > 0 && self.alloc > to_guest_addr(self.host_alloc) {
-            if unsafe {
-                libc::mmap(
-                    self.host_alloc as *mut libc::c_void,
-                    round_up(size.abs() as u64, page_size as u64) as usize,
-                    libc::PROT_READ | libc::PROT_WRITE,
-                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_FIXED,
-                    -1,
-                    0,
-                )
-            } == libc::MAP_FAILED
+    /*
+        move the program break like Linux brk: returns the new break, or the
+        current one when addr is out of range or the pages can't be mapped.
+        pages given back are unmapped, so growing again yields zeroes.
+    */
+    pub fn mmu_brk(&mut self, addr: u64) -> u64 {
+        let page_size = page_size::get() as u64;
+        if addr < self.base || addr > self.brk_max {
+            return self.alloc;
+        }
+        let mapped_end = to_guest_addr(self.host_alloc);
+        let new_end = round_up(addr, page_size);
+        if new_end > mapped_end {
+            if !self.account.fits(new_end - mapped_end)
+                || !mmu_t::mmu_map_anon(mapped_end, new_end - mapped_end)
             {
-                panic!("mmap failed");
+                return self.alloc;
             }
-
-            self.host_alloc += round_up(size.abs() as u64, page_size as u64);
-        } else if size < 0
-            && round_up(self.alloc, page_size as u64) < to_guest_addr(self.host_alloc)
+        } else if new_end < mapped_end
+            && unsafe {
+                libc::munmap(
+                    to_host_addr(new_end) as *mut libc::c_void,
+                    (mapped_end - new_end) as usize,
+                )
+            } == -1
         {
-            let len: u64 = to_guest_addr(self.host_alloc) - round_up(self.alloc, page_size as u64);
-            if unsafe { libc::munmap(self.host_alloc as *mut libc::c_void, len as usize) } == -1 {
-                panic!("munmap failed");
-            }
-            self.host_alloc -= len;
+            panic!("munmap failed");
         }
-        assert_eq!(self.alloc, base + size.abs() as u64);
-        base
+        self.host_alloc = to_host_addr(new_end);
+        self.alloc = addr;
+        self.account.mapped.set(self.mmu_mapped());
+        addr
+    }
+
+    // bytes mapped for the ELF segments, heap and stack
+    pub fn mmu_mapped(&self) -> u64 {
+        self.segments
+            .iter()
+            .map(|&(start, end)| end - start)
+            .sum::<u64>()
+            + (to_guest_addr(self.host_alloc) - self.base)
+            + (self.stack.1 - self.stack.0)
+    }
+
+    // map a stack of size bytes ending at top, away from the heap
+    pub fn mmu_alloc_stack(&mut self, top: u64, size: u64) -> bool {
+        let size = round_up(size, page_size::get() as u64);
+        if size > top || !self.account.fits(size) || !mmu_t::mmu_map_anon(top - size, size) {
+            return false;
+        }
+        self.stack = (top - size, top);
+        self.account.mapped.set(self.mmu_mapped());
+        true
+    }
+
+    // whether [addr, addr + len) lies in guest memory: ELF segments, heap or stack
+    pub fn mmu_valid(&self, addr: u64, len: u64) -> bool {
+        let Some(end) = addr.checked_add(len) else {
+            return false;
+        };
+        len == 0
+            || (addr >= self.base && end <= to_guest_addr(self.host_alloc))
+            || (addr >= self.stack.0 && end <= self.stack.1)
+            || self
+                .segments
+                .iter()
+                .any(|&(start, seg_end)| addr >= start && end <= seg_end)
+    }
+
+    /**
+        guest memory as a slice, unchecked.
+
+        # Safety
+        the range must be mapped, see mmu_valid and syscall::guest_slice,
+        and the slice must not outlive the mapping.
+    */
+    #[inline]
+    pub unsafe fn mmu_slice<'a>(addr: u64, len: u64) -> &'a [u8] {
+        unsafe { std::slice::from_raw_parts(to_host_addr(addr) as *const u8, len as usize) }
+    }
+
+    /**
+        # Safety
+        as for mmu_slice, and nothing else may refer to the range meanwhile.
+    */
+    #[inline]
+    pub unsafe fn mmu_slice_mut<'a>(addr: u64, len: u64) -> &'a mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(to_host_addr(addr) as *mut u8, len as usize) }
     }
 
     #[inline]
@@ -0,0 +1,151 @@
+use std::fs::File;
+
+use libc::{
+    O_ACCMODE, O_APPEND, O_ASYNC, O_CLOEXEC, O_CREAT, O_DIRECT, O_DIRECTORY, O_DSYNC, O_EXCL,
+    O_LARGEFILE, O_NOATIME, O_NOCTTY, O_NOFOLLOW, O_NONBLOCK, O_PATH, O_SYNC, O_TMPFILE, O_TRUNC,
+};
+
+use crate::{
+    elf::{PT_GNU_STACK, PT_INTERP, PT_TLS, elf_phdr_types, elf_symbol_names},
+    syscall::{LINUX_SYSCALL_TABLE, NEWLIB_SYSCALL_TABLE, syscall_t},
+};
+
+/*
+    The system call ABI a guest program was built against. riscv-pk/newlib
+    programs get pk's calls, the legacy 1024+ ones included, newlib's O_*
+    values and its F_DUPFD_CLOEXEC. Linux programs get the asm-generic
+    calls and values. struct stat needs no translation for either: libgloss
+    declares its kernel_stat with the asm-generic fields and converts it to
+    newlib's struct stat in the guest.
+*/
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum personality_t {
+    newlib,
+    linux,
+}
+
+// the O_* macros is OS dependent.
+// here is a workaround to convert newlib flags to the host.
+pub const NEWLIB_O_APPEND: i32 = 0x8;
+pub const NEWLIB_O_CREAT: i32 = 0x200;
+pub const NEWLIB_O_TRUNC: i32 = 0x400;
+pub const NEWLIB_O_EXCL: i32 = 0x800;
+pub const NEWLIB_O_NONBLOCK: i32 = 0x4000;
+pub const NEWLIB_O_CLOEXEC: i32 = 0x40000;
+pub const NEWLIB_O_NOFOLLOW: i32 = 0x100000;
+pub const NEWLIB_O_DIRECTORY: i32 = 0x200000;
+
+pub const NEWLIB_F_DUPFD_CLOEXEC: i32 = 14;
+
+// (guest, host) pairs, the access mode is the same everywhere
+const NEWLIB_O_FLAGS: [(i32, i32); 8] = [
+    (NEWLIB_O_APPEND, O_APPEND),
+    (NEWLIB_O_CREAT, O_CREAT),
+    (NEWLIB_O_TRUNC, O_TRUNC),
+    (NEWLIB_O_EXCL, O_EXCL),
+    (NEWLIB_O_NONBLOCK, O_NONBLOCK),
+    (NEWLIB_O_CLOEXEC, O_CLOEXEC),
+    (NEWLIB_O_NOFOLLOW, O_NOFOLLOW),
+    (NEWLIB_O_DIRECTORY, O_DIRECTORY),
+];
+
+// asm-generic values, which not every host shares (e.g. aarch64 O_DIRECTORY)
+const LINUX_O_FLAGS: [(i32, i32); 17] = [
+    (0o100, O_CREAT),
+    (0o200, O_EXCL),
+    (0o400, O_NOCTTY),
+    (0o1000, O_TRUNC),
+    (0o2000, O_APPEND),
+    (0o4000, O_NONBLOCK),
+    (0o10000, O_DSYNC),
+    (0o20000, O_ASYNC),
+    (0o40000, O_DIRECT),
+    (0o100000, O_LARGEFILE),
+    (0o200000, O_DIRECTORY),
+    (0o400000, O_NOFOLLOW),
+    (0o1000000, O_NOATIME),
+    (0o2000000, O_CLOEXEC),
+    (0o4010000, O_SYNC),
+    (0o10000000, O_PATH),
+    (0o20200000, O_TMPFILE),
+];
+
+fn map_flags(flags: i32, pairs: &[(i32, i32)], to_host: bool) -> i32 {
+    let mut mapped = flags & O_ACCMODE;
+    for &(guest, host) in pairs {
+        let (from, to) = if to_host {
+            (guest, host)
+        } else {
+            (host, guest)
+        };
+        if from != 0 && flags & from == from {
+            mapped |= to;
+        }
+    }
+    mapped
+}
+
+impl personality_t {
+    pub fn from_name(name: &str) -> Option<personality_t> {
+        match name {
+            "newlib" | "pk" => Some(personality_t::newlib),
+            "linux" => Some(personality_t::linux),
+            _ => None,
+        }
+    }
+
+    /*
+        newlib defines _impure_ptr, glibc and musl __libc_start_main. stripped
+        files fall back to the program headers only Linux toolchains emit,
+        and to newlib as the emulator always did.
+    */
+    pub fn detect(elf: &File) -> personality_t {
+        let symbols = elf_symbol_names(elf);
+        if symbols.contains(b"_impure_ptr".as_slice()) {
+            return personality_t::newlib;
+        }
+        if symbols.contains(b"__libc_start_main".as_slice()) {
+            return personality_t::linux;
+        }
+        if elf_phdr_types(elf)
+            .iter()
+            .any(|&p_type| p_type == PT_INTERP || p_type == PT_TLS || p_type == PT_GNU_STACK)
+        {
+            return personality_t::linux;
+        }
+        personality_t::newlib
+    }
+
+    pub fn syscall(self, num: u64) -> Option<syscall_t> {
+        let table = match self {
+            personality_t::newlib => &NEWLIB_SYSCALL_TABLE,
+            personality_t::linux => &LINUX_SYSCALL_TABLE,
+        };
+        u32::try_from(num)
+            .ok()
+            .and_then(|num| table.get(&num).copied())
+    }
+
+    pub fn open_flags_to_host(self, flags: i32) -> i32 {
+        match self {
+            personality_t::newlib => map_flags(flags, &NEWLIB_O_FLAGS, true),
+            personality_t::linux => map_flags(flags, &LINUX_O_FLAGS, true),
+        }
+    }
+
+    pub fn open_flags_to_guest(self, flags: i32) -> i32 {
+        match self {
+            personality_t::newlib => map_flags(flags, &NEWLIB_O_FLAGS, false),
+            personality_t::linux => map_flags(flags, &LINUX_O_FLAGS, false),
+        }
+    }
+
+    // F_DUPFD..F_SETFL agree, newlib numbers F_DUPFD_CLOEXEC differently
+    pub fn fcntl_cmd_to_host(self, cmd: i32) -> i32 {
+        match (self, cmd) {
+            (personality_t::newlib, NEWLIB_F_DUPFD_CLOEXEC) => libc::F_DUPFD_CLOEXEC,
+            (personality_t::newlib, libc::F_DUPFD_CLOEXEC) => -1,
+            _ => cmd,
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use std::{cell::RefCell, mem, rc::Rc};
+
+use libc::S_IFCHR;
+
+use crate::vfs::vfile_t;
+
+/*
+    guest randomness: getrandom, AT_RANDOM and /dev/(u)random. it comes from
+    the host unless a seed is given, then from xoshiro256** so runs are
+    reproducible.
+*/
+pub enum random_t {
+    host,
+    seeded([u64; 4]),
+}
+
+// expands the seed into the generator state
+fn splitmix64(x: &mut u64) -> u64 {
+    *x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *x;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl random_t {
+    pub fn new(seed: Option<u64>) -> random_t {
+        match seed {
+            None => random_t::host,
+            Some(mut seed) => random_t::seeded([
+                splitmix64(&mut seed),
+                splitmix64(&mut seed),
+                splitmix64(&mut seed),
+                splitmix64(&mut seed),
+            ]),
+        }
+    }
+
+    fn next_u64(s: &mut [u64; 4]) -> u64 {
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        result
+    }
+
+    /*
+        fill buf, or as much of it as the host provides before an error.
+        the error is only reported if nothing was filled: EAGAIN as it is,
+        anything else as EIO.
+    */
+    pub fn fill(&mut self, buf: &mut [u8]) -> Result<usize, i32> {
+        match self {
+            random_t::host => {
+                let mut done = 0;
+                while done < buf.len() {
+                    let ret = unsafe {
+                        libc::getrandom(
+                            buf[done..].as_mut_ptr() as *mut libc::c_void,
+                            buf.len() - done,
+                            0,
+                        )
+                    };
+                    if ret < 0 {
+                        match std::io::Error::last_os_error().raw_os_error() {
+                            Some(libc::EINTR) => continue,
+                            _ if done > 0 => break,
+                            Some(libc::EAGAIN) => return Err(libc::EAGAIN),
+                            _ => return Err(libc::EIO),
+                        }
+                    }
+                    done += ret as usize;
+                }
+                Ok(done)
+            }
+            random_t::seeded(s) => {
+                for chunk in buf.chunks_mut(8) {
+                    let bytes = random_t::next_u64(s).to_le_bytes();
+                    chunk.copy_from_slice(&bytes[..chunk.len()]);
+                }
+                Ok(buf.len())
+            }
+        }
+    }
+}
+
+// /dev/random and /dev/urandom, which never block
+pub struct random_file_t {
+    random: Rc<RefCell<random_t>>,
+    rdev: u64,
+}
+
+impl random_file_t {
+    pub fn new(random: Rc<RefCell<random_t>>, minor: u32) -> random_file_t {
+        random_file_t {
+            random,
+            rdev: libc::makedev(1, minor),
+        }
+    }
+}
+
+impl vfile_t for random_file_t {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, i32> {
+        self.random.borrow_mut().fill(buf)
+    }
+
+    // written bytes would only be mixed into the pool
+    fn write(&mut self, buf: &[u8]) -> Result<usize, i32> {
+        Ok(buf.len())
+    }
+
+    // like Linux, seeking is accepted and does nothing
+    fn lseek(&mut self, _offset: i64, _whence: i32) -> Result<u64, i32> {
+        Ok(0)
+    }
+
+    fn fstat(&mut self) -> Result<libc::stat, i32> {
+        let mut st: libc::stat = unsafe { mem::zeroed() };
+        st.st_mode = S_IFCHR | 0o666;
+        st.st_rdev = self.rdev;
+        st.st_nlink = 1;
+        st.st_blksize = 4096;
+        Ok(st)
+    }
+}
@@ -77,21 +77,46 @@ pub union fp_reg_t {
     pub w: u32,
     pub d: f64,
     pub f: f32,
+    pub h: u16,
 }
 
 pub enum csr_t {
     fflags = 0x001,
     frm = 0x002,
     fcsr = 0x003,
+    vstart = 0x008,
+    vxsat = 0x009,
+    vxrm = 0x00a,
+    vcsr = 0x00f,
+    cycle = 0xc00,
+    time = 0xc01,
+    instret = 0xc02,
+    hpmcounter = 0xc03, // any of hpmcounter3 to hpmcounter31
+    vl = 0xc20,
+    vtype = 0xc21,
+    vlenb = 0xc22,
 }
 
-impl From<u16> for csr_t {
-    fn from(val: u16) -> Self {
-        match val {
+impl TryFrom<u16> for csr_t {
+    type Error = ();
+
+    fn try_from(val: u16) -> Result<Self, ()> {
+        Ok(match val {
             0x001 => csr_t::fflags,
             0x002 => csr_t::frm,
             0x003 => csr_t::fcsr,
-            _ => panic!("Invalid CSR value: {:#06x}", val),
-        }
+            0x008 => csr_t::vstart,
+            0x009 => csr_t::vxsat,
+            0x00a => csr_t::vxrm,
+            0x00f => csr_t::vcsr,
+            0xc00 => csr_t::cycle,
+            0xc01 => csr_t::time,
+            0xc02 => csr_t::instret,
+            0xc03..=0xc1f => csr_t::hpmcounter,
+            0xc20 => csr_t::vl,
+            0xc21 => csr_t::vtype,
+            0xc22 => csr_t::vlenb,
+            _ => return Err(()),
+        })
     }
 }
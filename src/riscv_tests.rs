@@ -0,0 +1,96 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::config::config_t;
+
+/*
+    rvemu-riir [options] --riscv-tests <path>...
+
+    run every ELF file under the paths as a riscv-tests binary, each in its
+    own emulator process with --htif, and report PASS or FAIL per test. a
+    failing test reports its TESTNUM, the number of the failed case, as
+    the tohost exit code.
+*/
+
+// seconds a test may run unless --timeout says otherwise
+const DEFAULT_TIMEOUT: &str = "10";
+
+// the ELF files in path, recursively and sorted
+fn find_elfs(path: &Path, elfs: &mut Vec<PathBuf>) {
+    if path.is_dir() {
+        let mut entries: Vec<PathBuf> = match fs::read_dir(path) {
+            Ok(dir) => dir.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+            Err(e) => panic!("Could not read {}: {}", path.display(), e),
+        };
+        entries.sort();
+        for entry in entries {
+            find_elfs(&entry, elfs);
+        }
+    } else if fs::read(path).is_ok_and(|data| data.starts_with(b"\x7fELF")) {
+        elfs.push(path.to_path_buf());
+    }
+}
+
+pub fn riscv_tests(config: &config_t) -> ! {
+    let mut elfs = Vec::new();
+    for path in &config.riscv_tests {
+        if !path.exists() {
+            eprintln!("rvemu-riir: no such file or directory: {}", path.display());
+            std::process::exit(1);
+        }
+        find_elfs(path, &mut elfs);
+    }
+
+    let exe = std::env::current_exe().expect("Could not find the emulator executable");
+    let mut failed = 0;
+    for elf in &elfs {
+        let mut cmd = Command::new(&exe);
+        if config.timeout.is_none() {
+            cmd.args(["--timeout", DEFAULT_TIMEOUT]);
+        }
+        if !config.htif {
+            cmd.arg("--htif");
+        }
+        cmd.args(&config.emu_args).arg("--").arg(elf);
+        let output = cmd.stdin(Stdio::null()).output();
+
+        // the failing TESTNUM is printed, it may not fit an exit status
+        let testnum = output.as_ref().ok().and_then(|o| {
+            String::from_utf8_lossy(&o.stderr).lines().find_map(|l| {
+                l.strip_prefix("rvemu-riir: tohost exit code ")?
+                    .parse::<u64>()
+                    .ok()
+            })
+        });
+        let result = match (output.as_ref().map(|o| o.status.code()), testnum) {
+            (Ok(Some(0)), _) => {
+                println!("PASS {}", elf.display());
+                continue;
+            }
+            (Ok(Some(124)), _) => String::from("timed out"),
+            (Ok(Some(1)), Some(n)) => format!("test {}", n),
+            (Ok(Some(n)), _) => format!("exit status {}", n),
+            (Ok(None), _) => String::from("killed by a signal"),
+            (Err(e), _) => e.to_string(),
+        };
+        failed += 1;
+        println!("FAIL {} ({})", elf.display(), result);
+        if let Ok(output) = output {
+            // the emulator's own complaints, panics above all
+            for line in String::from_utf8_lossy(&output.stderr).lines() {
+                println!("    {}", line);
+            }
+        }
+    }
+
+    println!(
+        "{} passed, {} failed, {} total",
+        elfs.len() - failed,
+        failed,
+        elfs.len()
+    );
+    std::process::exit((failed > 0 || elfs.is_empty()) as i32);
+}
@@ -0,0 +1,305 @@
+use std::{collections::HashMap, ffi::CString, sync::LazyLock};
+
+use libc::{O_APPEND, O_CREAT, O_RDONLY, O_RDWR, O_TRUNC, O_WRONLY};
+
+use crate::{
+    machine::machine_t,
+    reg::gp_reg_type_t,
+    syscall::{guest_slice, guest_slice_mut, with_file},
+};
+
+/*
+    RISC-V semihosting: the ARM semihosting calls, reached through
+    slli x0, x0, 0x1f; ebreak; srai x0, x0, 7 with the operation in a0 and
+    a pointer to its parameter block in a1. handles are guest fds, so files
+    go through the same vfs and fd table as syscalls.
+*/
+pub const SYS_OPEN: u64 = 0x01;
+pub const SYS_CLOSE: u64 = 0x02;
+pub const SYS_WRITEC: u64 = 0x03;
+pub const SYS_WRITE0: u64 = 0x04;
+pub const SYS_WRITE: u64 = 0x05;
+pub const SYS_READ: u64 = 0x06;
+pub const SYS_READC: u64 = 0x07;
+pub const SYS_ISERROR: u64 = 0x08;
+pub const SYS_ISTTY: u64 = 0x09;
+pub const SYS_SEEK: u64 = 0x0a;
+pub const SYS_FLEN: u64 = 0x0c;
+pub const SYS_TMPNAM: u64 = 0x0d;
+pub const SYS_REMOVE: u64 = 0x0e;
+pub const SYS_RENAME: u64 = 0x0f;
+pub const SYS_CLOCK: u64 = 0x10;
+pub const SYS_TIME: u64 = 0x11;
+pub const SYS_SYSTEM: u64 = 0x12;
+pub const SYS_ERRNO: u64 = 0x13;
+pub const SYS_GET_CMDLINE: u64 = 0x15;
+pub const SYS_HEAPINFO: u64 = 0x16;
+pub const SYS_EXIT: u64 = 0x18;
+pub const SYS_EXIT_EXTENDED: u64 = 0x20;
+pub const SYS_ELAPSED: u64 = 0x30;
+pub const SYS_TICKFREQ: u64 = 0x31;
+
+const ADP_STOPPED_APPLICATION_EXIT: u64 = 0x20026;
+
+// called with the parameter block address, errors become -1 and SYS_ERRNO
+pub type semihosting_t = fn(&mut machine_t, u64) -> Result<u64, i32>;
+
+pub static SEMIHOSTING_TABLE: LazyLock<HashMap<u64, semihosting_t>> = LazyLock::new(|| {
+    let mut table: HashMap<u64, semihosting_t> = HashMap::new();
+
+    table.insert(SYS_OPEN, sh_open);
+    table.insert(SYS_CLOSE, sh_close);
+    table.insert(SYS_WRITEC, sh_writec);
+    table.insert(SYS_WRITE0, sh_write0);
+    table.insert(SYS_WRITE, sh_write);
+    table.insert(SYS_READ, sh_read);
+    table.insert(SYS_READC, sh_readc);
+    table.insert(SYS_ISERROR, sh_iserror);
+    table.insert(SYS_ISTTY, sh_istty);
+    table.insert(SYS_SEEK, sh_seek);
+    table.insert(SYS_FLEN, sh_flen);
+    table.insert(SYS_TMPNAM, sh_tmpnam);
+    table.insert(SYS_REMOVE, sh_remove);
+    table.insert(SYS_RENAME, sh_rename);
+    table.insert(SYS_CLOCK, sh_clock);
+    table.insert(SYS_TIME, sh_time);
+    table.insert(SYS_SYSTEM, sh_system);
+    table.insert(SYS_ERRNO, sh_errno);
+    table.insert(SYS_GET_CMDLINE, sh_get_cmdline);
+    table.insert(SYS_HEAPINFO, sh_heapinfo);
+    table.insert(SYS_EXIT, sh_exit);
+    table.insert(SYS_EXIT_EXTENDED, sh_exit);
+    table.insert(SYS_ELAPSED, sh_elapsed);
+    table.insert(SYS_TICKFREQ, sh_tickfreq);
+    table
+});
+
+// slli x0, x0, 0x1f; ebreak; srai x0, x0, 7, all uncompressed and mapped
+pub fn is_semihosting(m: &machine_t, pc: u64) -> bool {
+    let Ok(seq) = guest_slice(m, pc.wrapping_sub(4), 12) else {
+        return false;
+    };
+    seq == [
+        0x13, 0x10, 0xf0, 0x01, 0x73, 0x00, 0x10, 0x00, 0x13, 0x50, 0x70, 0x40,
+    ]
+}
+
+// the result for a0
+pub fn semihosting_call(m: &mut machine_t) -> u64 {
+    let op = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let args = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+
+    let ret = match SEMIHOSTING_TABLE.get(&op) {
+        Some(f) => f(m, args),
+        None => Err(libc::ENOSYS),
+    };
+    match ret {
+        Ok(ret) => ret,
+        Err(errno) => {
+            m.semihosting_errno = errno;
+            u64::MAX
+        }
+    }
+}
+
+// the first N words of a parameter block
+fn params<const N: usize>(m: &machine_t, addr: u64) -> Result<[u64; N], i32> {
+    let raw = guest_slice(m, addr, N as u64 * 8)?;
+    let mut words = [0; N];
+    for (word, bytes) in words.iter_mut().zip(raw.chunks_exact(8)) {
+        *word = u64::from_le_bytes(bytes.try_into().unwrap());
+    }
+    Ok(words)
+}
+
+// a NUL-terminated string without the NUL, mapped up to the terminator
+fn guest_cstr<'a>(m: &machine_t, addr: u64) -> Result<&'a [u8], i32> {
+    let mut len = 0;
+    while guest_slice(m, addr.wrapping_add(len), 1)?[0] != 0 {
+        len += 1;
+    }
+    guest_slice(m, addr, len)
+}
+
+// names come with their length, not NUL-terminated
+fn guest_name(m: &machine_t, addr: u64, len: u64) -> Result<CString, i32> {
+    CString::new(guest_slice(m, addr, len)?).map_err(|_| libc::EINVAL)
+}
+
+fn sh_open(m: &mut machine_t, args: u64) -> Result<u64, i32> {
+    let [nameptr, mode, len] = params(m, args)?;
+    let name = guest_name(m, nameptr, len)?;
+
+    // fopen modes r, rb, r+, r+b, w, wb, w+, w+b, a, ab, a+, a+b
+    let plus = mode & 0x2 != 0;
+    // :tt is the console, read for stdin and written for stdout or stderr
+    if name.as_bytes() == b":tt" {
+        let fd = match mode >> 2 {
+            0 => 0,
+            1 => 1,
+            _ => 2,
+        };
+        return m.fds.dup(fd, 0, false);
+    }
+    let flags = match (mode >> 2, plus) {
+        (0, false) => O_RDONLY,
+        (0, true) => O_RDWR,
+        (1, false) => O_WRONLY | O_CREAT | O_TRUNC,
+        (1, true) => O_RDWR | O_CREAT | O_TRUNC,
+        (2, false) => O_WRONLY | O_CREAT | O_APPEND,
+        (2, true) => O_RDWR | O_CREAT | O_APPEND,
+        _ => return Err(libc::EINVAL),
+    };
+    let file = m.vfs.open(None, &name, flags, 0o644)?;
+    m.fds.alloc(file, false)
+}
+
+fn sh_close(m: &mut machine_t, args: u64) -> Result<u64, i32> {
+    let [fd] = params(m, args)?;
+    m.fds.close(fd).map(|_| 0)
+}
+
+// console output is guest stdout, wherever that points
+fn sh_writec(m: &mut machine_t, args: u64) -> Result<u64, i32> {
+    let c = guest_slice(m, args, 1)?;
+    with_file(m, 1, |f| f.write(c).map(|_| 0))
+}
+
+fn sh_write0(m: &mut machine_t, args: u64) -> Result<u64, i32> {
+    let s = guest_cstr(m, args)?;
+    with_file(m, 1, |f| f.write(s).map(|_| 0))
+}
+
+// both return how much was left over
+fn sh_write(m: &mut machine_t, args: u64) -> Result<u64, i32> {
+    let [fd, bufptr, len] = params(m, args)?;
+    let buf = guest_slice(m, bufptr, len)?;
+    with_file(m, fd, |f| f.write(buf).map(|n| len - n as u64))
+}
+
+fn sh_read(m: &mut machine_t, args: u64) -> Result<u64, i32> {
+    let [fd, bufptr, len] = params(m, args)?;
+    let buf = guest_slice_mut(m, bufptr, len)?;
+    with_file(m, fd, |f| f.read(buf).map(|n| len - n as u64))
+}
+
+fn sh_readc(m: &mut machine_t, _args: u64) -> Result<u64, i32> {
+    let mut c = [0u8];
+    match with_file(m, 0, |f| f.read(&mut c))? {
+        0 => Err(libc::EIO),
+        _ => Ok(c[0] as u64),
+    }
+}
+
+fn sh_iserror(m: &mut machine_t, args: u64) -> Result<u64, i32> {
+    let [status] = params(m, args)?;
+    Ok(((status as i64) < 0) as u64)
+}
+
+fn sh_istty(m: &mut machine_t, args: u64) -> Result<u64, i32> {
+    let [fd] = params(m, args)?;
+    let host_fd = m.fds.get(fd)?.borrow().host_fd();
+    Ok(host_fd.is_some_and(|fd| unsafe { libc::isatty(fd) } == 1) as u64)
+}
+
+fn sh_seek(m: &mut machine_t, args: u64) -> Result<u64, i32> {
+    let [fd, pos] = params(m, args)?;
+    with_file(m, fd, |f| f.lseek(pos as i64, libc::SEEK_SET).map(|_| 0))
+}
+
+fn sh_flen(m: &mut machine_t, args: u64) -> Result<u64, i32> {
+    let [fd] = params(m, args)?;
+    with_file(m, fd, |f| f.fstat().map(|st| st.st_size as u64))
+}
+
+fn sh_tmpnam(m: &mut machine_t, args: u64) -> Result<u64, i32> {
+    let [bufptr, id, len] = params(m, args)?;
+    let name = format!("/tmp/rvemu-riir-{:02x}.tmp\0", id & 0xff);
+    if name.len() as u64 > len {
+        return Err(libc::ERANGE);
+    }
+    guest_slice_mut(m, bufptr, name.len() as u64)?.copy_from_slice(name.as_bytes());
+    Ok(0)
+}
+
+fn sh_remove(m: &mut machine_t, args: u64) -> Result<u64, i32> {
+    let [nameptr, len] = params(m, args)?;
+    let name = guest_name(m, nameptr, len)?;
+    m.vfs.unlink(None, &name, 0).map(|_| 0)
+}
+
+fn sh_rename(m: &mut machine_t, args: u64) -> Result<u64, i32> {
+    let [oldptr, oldlen, newptr, newlen] = params(m, args)?;
+    let (old, new) = (
+        guest_name(m, oldptr, oldlen)?,
+        guest_name(m, newptr, newlen)?,
+    );
+    m.vfs.rename(None, &old, None, &new, 0).map(|_| 0)
+}
+
+// centiseconds of guest cpu time, like clock()
+fn sh_clock(m: &mut machine_t, _args: u64) -> Result<u64, i32> {
+    let (sec, nsec) = m
+        .state
+        .clock
+        .gettime(libc::CLOCK_PROCESS_CPUTIME_ID, m.state.instret)?;
+    Ok(sec as u64 * 100 + nsec as u64 / 10_000_000)
+}
+
+fn sh_time(m: &mut machine_t, _args: u64) -> Result<u64, i32> {
+    let (sec, _) = m
+        .state
+        .clock
+        .gettime(libc::CLOCK_REALTIME, m.state.instret)?;
+    Ok(sec as u64)
+}
+
+// guests don't get to run host commands
+fn sh_system(_m: &mut machine_t, _args: u64) -> Result<u64, i32> {
+    Err(libc::ENOSYS)
+}
+
+fn sh_errno(m: &mut machine_t, _args: u64) -> Result<u64, i32> {
+    Ok(m.semihosting_errno as u64)
+}
+
+// the command line into [buf, len], len updated to its length
+fn sh_get_cmdline(m: &mut machine_t, args: u64) -> Result<u64, i32> {
+    let [bufptr, len] = params(m, args)?;
+    let cmdline = format!("{}\0", m.cmdline);
+    if cmdline.len() as u64 > len {
+        return Err(libc::ERANGE);
+    }
+    guest_slice_mut(m, bufptr, cmdline.len() as u64)?.copy_from_slice(cmdline.as_bytes());
+    let len = cmdline.len() as u64 - 1;
+    guest_slice_mut(m, args + 8, 8)?.copy_from_slice(&len.to_le_bytes());
+    Ok(0)
+}
+
+// heap and stack are the guest's own business, zeros say unknown
+fn sh_heapinfo(m: &mut machine_t, args: u64) -> Result<u64, i32> {
+    let [blockptr] = params(m, args)?;
+    guest_slice_mut(m, blockptr, 32)?.fill(0);
+    Ok(0)
+}
+
+// [reason, subcode], only a normal application exit passes its code on
+fn sh_exit(m: &mut machine_t, args: u64) -> Result<u64, i32> {
+    let [reason, subcode] = params(m, args)?;
+    let code = match reason {
+        ADP_STOPPED_APPLICATION_EXIT => subcode as libc::c_int,
+        _ => 1,
+    };
+    unsafe { libc::exit(code) };
+}
+
+// ticks of the time CSR
+fn sh_elapsed(m: &mut machine_t, args: u64) -> Result<u64, i32> {
+    let ticks = m.state.clock.ticks(m.state.instret);
+    guest_slice_mut(m, args, 8)?.copy_from_slice(&ticks.to_le_bytes());
+    Ok(0)
+}
+
+fn sh_tickfreq(m: &mut machine_t, _args: u64) -> Result<u64, i32> {
+    Ok(m.state.clock.timebase)
+}
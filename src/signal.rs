@@ -0,0 +1,224 @@
+use crate::{
+    machine::machine_t,
+    reg::gp_reg_type_t,
+    syscall::{guest_slice, guest_slice_mut},
+};
+
+/*
+    guest signal handlers. the emulator delivers only the synchronous
+    SIGILL of an illegal instruction, which is enough for programs that
+    probe for extensions, and the SIGTRAP of an ebreak. the handler runs on the guest stack with the
+    struct rt_sigframe of Linux on RISC-V below sp: siginfo_t, then a
+    ucontext_t with pc and x1-x31, f0-f31 and fcsr, then the
+    li a7, 139; ecall that ra returns through to rt_sigreturn, as on a
+    kernel without a vDSO. handlers may move the saved pc past the
+    instruction or leave with siglongjmp.
+*/
+pub const NSIG: usize = 64;
+
+pub const SIG_DFL: u64 = 0;
+pub const SIG_IGN: u64 = 1;
+
+pub const SA_NODEFER: u64 = 0x40000000;
+pub const SA_RESETHAND: u64 = 0x80000000;
+
+pub const ILL_ILLOPC: i32 = 1;
+pub const TRAP_BRKPT: i32 = 1;
+
+const SIGINFO_SIZE: u64 = 128;
+const UC_SIGMASK: u64 = 40;
+const UC_MCONTEXT: u64 = 176; // aligned to 16 for the fp state
+const UC_FPREGS: u64 = UC_MCONTEXT + 32 * 8;
+const UC_FCSR: u64 = UC_FPREGS + 32 * 8;
+const UCONTEXT_SIZE: u64 = UC_FPREGS + 528;
+const TRAMPOLINE: u64 = SIGINFO_SIZE + UCONTEXT_SIZE;
+const FRAME_SIZE: u64 = TRAMPOLINE + 8;
+
+// li a7, 139 (SYS_RT_SIGRETURN); ecall
+const SIGRETURN_CODE: [u32; 2] = [0x08b00893, 0x00000073];
+
+// struct sigaction of asm-generic, without sa_restorer
+#[derive(Clone, Copy, Debug, Default)]
+pub struct sigaction_t {
+    pub handler: u64,
+    pub flags: u64,
+    pub mask: u64,
+}
+
+#[inline]
+fn sigbit(sig: i32) -> u64 {
+    1 << (sig - 1)
+}
+
+// SIGKILL and SIGSTOP can be neither caught nor blocked
+const UNBLOCKABLE: u64 = (1 << (libc::SIGKILL - 1)) | (1 << (libc::SIGSTOP - 1));
+
+fn read_u64(buf: &[u8], offset: u64) -> u64 {
+    let offset = offset as usize;
+    u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap())
+}
+
+fn write_u64(buf: &mut [u8], offset: u64, val: u64) {
+    let offset = offset as usize;
+    buf[offset..offset + 8].copy_from_slice(&val.to_le_bytes());
+}
+
+/*
+    run the guest's handler for a synchronous signal at addr, false if
+    there is none. like Linux, a blocked or ignored signal is not held
+    back but fatal.
+*/
+pub fn deliver_signal(m: &mut machine_t, sig: i32, code: i32, addr: u64) -> bool {
+    let action = m.sigactions[sig as usize - 1];
+    if action.handler == SIG_DFL || action.handler == SIG_IGN || m.sigmask & sigbit(sig) != 0 {
+        return false;
+    }
+    let sp = m.state.gp_regs[gp_reg_type_t::sp as usize].wrapping_sub(FRAME_SIZE) & !0xf;
+    let Ok(frame) = guest_slice_mut(m, sp, FRAME_SIZE) else {
+        return false;
+    };
+    frame.fill(0);
+
+    // siginfo_t: si_signo, si_errno, si_code, then si_addr
+    frame[0..4].copy_from_slice(&sig.to_le_bytes());
+    frame[8..12].copy_from_slice(&code.to_le_bytes());
+    write_u64(frame, 16, addr);
+
+    let uc = SIGINFO_SIZE;
+    write_u64(frame, uc + UC_SIGMASK, m.sigmask);
+    write_u64(frame, uc + UC_MCONTEXT, m.state.pc);
+    for i in 1..32 {
+        write_u64(frame, uc + UC_MCONTEXT + i * 8, m.state.gp_regs[i as usize]);
+    }
+    for i in 0..32 {
+        let v = unsafe { m.state.fp_regs[i as usize].v };
+        write_u64(frame, uc + UC_FPREGS + i * 8, v);
+    }
+    let fcsr = ((m.state.frm << 5) | m.state.fflags) as u32;
+    let offset = (uc + UC_FCSR) as usize;
+    frame[offset..offset + 4].copy_from_slice(&fcsr.to_le_bytes());
+    for (i, word) in SIGRETURN_CODE.iter().enumerate() {
+        let offset = TRAMPOLINE as usize + i * 4;
+        frame[offset..offset + 4].copy_from_slice(&word.to_le_bytes());
+    }
+
+    m.state.gp_regs[gp_reg_type_t::sp as usize] = sp;
+    m.state.gp_regs[gp_reg_type_t::ra as usize] = sp + TRAMPOLINE;
+    m.state.gp_regs[gp_reg_type_t::a0 as usize] = sig as u64;
+    m.state.gp_regs[gp_reg_type_t::a1 as usize] = sp;
+    m.state.gp_regs[gp_reg_type_t::a2 as usize] = sp + uc;
+    m.state.pc = action.handler;
+
+    m.sigmask |= action.mask;
+    if action.flags & SA_NODEFER == 0 {
+        m.sigmask |= sigbit(sig);
+    }
+    if action.flags & SA_RESETHAND != 0 {
+        m.sigactions[sig as usize - 1] = sigaction_t::default();
+    }
+    true
+}
+
+pub fn sys_rt_sigaction(m: &mut machine_t) -> u64 {
+    let sig = m.state.gp_regs[gp_reg_type_t::a0 as usize] as i32;
+    let act_addr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let oldact_addr: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+    let sigsetsize: u64 = m.state.gp_regs[gp_reg_type_t::a3 as usize];
+
+    if sigsetsize != 8 || !(1..=NSIG as i32).contains(&sig) {
+        return -(libc::EINVAL as i64) as u64;
+    }
+    if act_addr != 0 && UNBLOCKABLE & sigbit(sig) != 0 {
+        return -(libc::EINVAL as i64) as u64;
+    }
+    // act and oldact may be the same struct
+    let act = match act_addr {
+        0 => None,
+        _ => match guest_slice(m, act_addr, 24) {
+            Ok(raw) => Some(sigaction_t {
+                handler: read_u64(raw, 0),
+                flags: read_u64(raw, 8),
+                mask: read_u64(raw, 16),
+            }),
+            Err(errno) => return -(errno as i64) as u64,
+        },
+    };
+    let old = m.sigactions[sig as usize - 1];
+    if oldact_addr != 0 {
+        match guest_slice_mut(m, oldact_addr, 24) {
+            Ok(raw) => {
+                write_u64(raw, 0, old.handler);
+                write_u64(raw, 8, old.flags);
+                write_u64(raw, 16, old.mask);
+            }
+            Err(errno) => return -(errno as i64) as u64,
+        }
+    }
+    if let Some(act) = act {
+        m.sigactions[sig as usize - 1] = act;
+    }
+    0
+}
+
+pub fn sys_rt_sigprocmask(m: &mut machine_t) -> u64 {
+    let how = m.state.gp_regs[gp_reg_type_t::a0 as usize] as i32;
+    let set_addr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let oldset_addr: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+    let sigsetsize: u64 = m.state.gp_regs[gp_reg_type_t::a3 as usize];
+
+    if sigsetsize != 8 {
+        return -(libc::EINVAL as i64) as u64;
+    }
+    let set = match set_addr {
+        0 => None,
+        _ => match guest_slice(m, set_addr, 8) {
+            Ok(raw) => Some(read_u64(raw, 0)),
+            Err(errno) => return -(errno as i64) as u64,
+        },
+    };
+    let old = m.sigmask;
+    let new = match (how, set) {
+        (_, None) => old,
+        (libc::SIG_BLOCK, Some(set)) => old | set,
+        (libc::SIG_UNBLOCK, Some(set)) => old & !set,
+        (libc::SIG_SETMASK, Some(set)) => set,
+        _ => return -(libc::EINVAL as i64) as u64,
+    };
+    if oldset_addr != 0 {
+        match guest_slice_mut(m, oldset_addr, 8) {
+            Ok(raw) => write_u64(raw, 0, old),
+            Err(errno) => return -(errno as i64) as u64,
+        }
+    }
+    m.sigmask = new & !UNBLOCKABLE;
+    0
+}
+
+// back from a handler through the trampoline, sp is at the frame
+pub fn sys_rt_sigreturn(m: &mut machine_t) -> u64 {
+    let sp = m.state.gp_regs[gp_reg_type_t::sp as usize];
+    let frame = match guest_slice(m, sp, FRAME_SIZE) {
+        Ok(frame) => frame,
+        Err(_) => {
+            eprintln!("rvemu-riir: rt_sigreturn with a bad frame at {:#x}", sp);
+            std::process::exit(128 + libc::SIGSEGV);
+        }
+    };
+
+    let uc = SIGINFO_SIZE;
+    m.sigmask = read_u64(frame, uc + UC_SIGMASK) & !UNBLOCKABLE;
+    m.state.pc = read_u64(frame, uc + UC_MCONTEXT);
+    for i in 1..32 {
+        m.state.gp_regs[i as usize] = read_u64(frame, uc + UC_MCONTEXT + i * 8);
+    }
+    for i in 0..32 {
+        m.state.fp_regs[i as usize].v = read_u64(frame, uc + UC_FPREGS + i * 8);
+    }
+    let offset = (uc + UC_FCSR) as usize;
+    let fcsr = u32::from_le_bytes(frame[offset..offset + 4].try_into().unwrap()) as u64;
+    m.state.frm = (fcsr >> 5) & 0x7;
+    m.state.fflags = fcsr & 0x1f;
+
+    // the caller stores the return value in a0, which is restored with it
+    m.state.gp_regs[gp_reg_type_t::a0 as usize]
+}
@@ -1,10 +1,22 @@
-use std::{collections::HashMap, sync::LazyLock};
+use std::{
+    collections::HashMap,
+    ffi::{CStr, CString, OsString},
+    mem,
+    os::unix::ffi::{OsStrExt, OsStringExt},
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
 
-use libc::{O_APPEND, O_CREAT, O_EXCL, O_RDONLY, O_RDWR, O_TRUNC, O_WRONLY};
+use libc::O_CLOEXEC;
 
 use crate::{
-    machine::{machine_t, to_host_addr},
+    clock::{clock_mode_t, timespec_ns},
+    machine::{RLIM_NLIMITS, machine_t, to_host_addr},
+    mmu::mmu_t,
+    random::random_file_t,
     reg::gp_reg_type_t,
+    signal::{sys_rt_sigaction, sys_rt_sigprocmask, sys_rt_sigreturn},
+    vfs::{host_file_t, host_ret, is_empty_path, resolve, vfile_t, vfs_t},
 };
 
 // Copied from https://github.com/riscv-software-src/riscv-pk
@@ -24,6 +36,7 @@ pub const SYS_LINKAT: u32 = 37;
 pub const SYS_UNLINKAT: u32 = 35;
 pub const SYS_MKDIRAT: u32 = 34;
 pub const SYS_RENAMEAT: u32 = 38;
+pub const SYS_RENAMEAT2: u32 = 276;
 pub const SYS_CHDIR: u32 = 49;
 pub const SYS_GETCWD: u32 = 17;
 pub const SYS_FSTAT: u32 = 80;
@@ -45,7 +58,11 @@ pub const SYS_MPROTECT: u32 = 226;
 pub const SYS_PRLIMIT64: u32 = 261;
 pub const SYS_GETMAINVARS: u32 = 2011;
 pub const SYS_RT_SIGACTION: u32 = 134;
+pub const SYS_READV: u32 = 65;
 pub const SYS_WRITEV: u32 = 66;
+pub const SYS_PREADV: u32 = 69;
+pub const SYS_PWRITEV: u32 = 70;
+pub const SYS_SENDFILE: u32 = 71;
 pub const SYS_GETTIMEOFDAY: u32 = 169;
 pub const SYS_TIMES: u32 = 153;
 pub const SYS_FCNTL: u32 = 25;
@@ -55,17 +72,46 @@ pub const SYS_DUP: u32 = 23;
 pub const SYS_DUP3: u32 = 24;
 pub const SYS_READLINKAT: u32 = 78;
 pub const SYS_RT_SIGPROCMASK: u32 = 135;
+pub const SYS_RT_SIGRETURN: u32 = 139;
 pub const SYS_IOCTL: u32 = 29;
 pub const SYS_GETRLIMIT: u32 = 163;
 pub const SYS_SETRLIMIT: u32 = 164;
 pub const SYS_GETRUSAGE: u32 = 165;
 pub const SYS_CLOCK_GETTIME: u32 = 113;
+pub const SYS_CLOCK_GETRES: u32 = 114;
+pub const SYS_CLOCK_NANOSLEEP: u32 = 115;
+pub const SYS_NANOSLEEP: u32 = 101;
 pub const SYS_SET_TID_ADDRESS: u32 = 96;
 pub const SYS_SET_ROBUST_LIST: u32 = 99;
 pub const SYS_MADVISE: u32 = 233;
 pub const SYS_STATX: u32 = 291;
+pub const SYS_EXECVE: u32 = 221;
+pub const SYS_PIPE2: u32 = 59;
+pub const SYS_PSELECT6: u32 = 72;
+pub const SYS_PPOLL: u32 = 73;
+pub const SYS_EVENTFD2: u32 = 19;
+pub const SYS_EPOLL_CREATE1: u32 = 20;
+pub const SYS_EPOLL_CTL: u32 = 21;
+pub const SYS_EPOLL_PWAIT: u32 = 22;
+pub const SYS_SOCKET: u32 = 198;
+pub const SYS_SOCKETPAIR: u32 = 199;
+pub const SYS_BIND: u32 = 200;
+pub const SYS_LISTEN: u32 = 201;
+pub const SYS_ACCEPT: u32 = 202;
+pub const SYS_CONNECT: u32 = 203;
+pub const SYS_GETSOCKNAME: u32 = 204;
+pub const SYS_GETPEERNAME: u32 = 205;
+pub const SYS_SENDTO: u32 = 206;
+pub const SYS_RECVFROM: u32 = 207;
+pub const SYS_SETSOCKOPT: u32 = 208;
+pub const SYS_GETSOCKOPT: u32 = 209;
+pub const SYS_SHUTDOWN: u32 = 210;
+pub const SYS_SENDMSG: u32 = 211;
+pub const SYS_RECVMSG: u32 = 212;
+pub const SYS_ACCEPT4: u32 = 242;
+pub const SYS_GETRANDOM: u32 = 278;
 
-pub const OLD_SYSCALL_THRESHOLD: u32 = 1024;
+// riscv-pk's legacy calls
 pub const SYS_OPEN: u32 = 1024;
 pub const SYS_LINK: u32 = 1025;
 pub const SYS_UNLINK: u32 = 1026;
@@ -77,73 +123,381 @@ pub const SYS_TIME: u32 = 1062;
 
 pub type syscall_t = fn(&mut machine_t) -> u64;
 
-pub static SYSCALL_TABLE: LazyLock<HashMap<u32, syscall_t>> = LazyLock::new(|| {
+// asm-generic calls of the Linux personality
+pub static LINUX_SYSCALL_TABLE: LazyLock<HashMap<u32, syscall_t>> = LazyLock::new(|| {
     let mut table: HashMap<u32, syscall_t> = HashMap::new();
 
     table.insert(SYS_EXIT, sys_exit);
     table.insert(SYS_EXIT_GROUP, sys_exit);
     table.insert(SYS_READ, sys_read);
-    table.insert(SYS_PREAD, sys_unimplemented);
+    table.insert(SYS_PREAD, sys_pread);
+    table.insert(SYS_PWRITE, sys_pwrite);
+    table.insert(SYS_READV, sys_readv);
+    table.insert(SYS_PREADV, sys_preadv);
+    table.insert(SYS_PWRITEV, sys_pwritev);
+    table.insert(SYS_SENDFILE, sys_sendfile);
     table.insert(SYS_WRITE, sys_write);
     table.insert(SYS_OPENAT, sys_openat);
     table.insert(SYS_CLOSE, sys_close);
     table.insert(SYS_FSTAT, sys_fstat);
-    table.insert(SYS_STATX, sys_unimplemented);
+    table.insert(SYS_STATX, sys_enosys);
     table.insert(SYS_LSEEK, sys_lseek);
-    table.insert(SYS_FSTATAT, sys_unimplemented);
-    table.insert(SYS_LINKAT, sys_unimplemented);
-    table.insert(SYS_UNLINKAT, sys_unimplemented);
-    table.insert(SYS_MKDIRAT, sys_unimplemented);
-    table.insert(SYS_RENAMEAT, sys_unimplemented);
-    table.insert(SYS_GETCWD, sys_unimplemented);
+    table.insert(SYS_FSTATAT, sys_fstatat);
+    table.insert(SYS_LINKAT, sys_linkat);
+    table.insert(SYS_UNLINKAT, sys_unlinkat);
+    table.insert(SYS_MKDIRAT, sys_mkdirat);
+    table.insert(SYS_RENAMEAT, sys_renameat);
+    table.insert(SYS_RENAMEAT2, sys_renameat2);
+    table.insert(SYS_GETCWD, sys_getcwd);
     table.insert(SYS_BRK, sys_brk);
-    table.insert(SYS_UNAME, sys_unimplemented);
-    table.insert(SYS_GETPID, sys_unimplemented);
-    table.insert(SYS_GETUID, sys_unimplemented);
-    table.insert(SYS_GETEUID, sys_unimplemented);
-    table.insert(SYS_GETGID, sys_unimplemented);
-    table.insert(SYS_GETEGID, sys_unimplemented);
-    table.insert(SYS_GETTID, sys_unimplemented);
-    table.insert(SYS_TGKILL, sys_unimplemented);
-    table.insert(SYS_MMAP, sys_unimplemented);
-    table.insert(SYS_MUNMAP, sys_unimplemented);
-    table.insert(SYS_MREMAP, sys_unimplemented);
-    table.insert(SYS_MPROTECT, sys_unimplemented);
-    table.insert(SYS_RT_SIGACTION, sys_unimplemented);
+    table.insert(SYS_UNAME, sys_uname);
+    table.insert(SYS_GETPID, sys_getpid);
+    table.insert(SYS_GETUID, sys_getuid);
+    table.insert(SYS_GETEUID, sys_getuid);
+    table.insert(SYS_GETGID, sys_getgid);
+    table.insert(SYS_GETEGID, sys_getgid);
+    table.insert(SYS_GETTID, sys_getpid);
+    table.insert(SYS_TGKILL, sys_enosys);
+    table.insert(SYS_SYSINFO, sys_sysinfo);
+    table.insert(SYS_GETRLIMIT, sys_getrlimit);
+    table.insert(SYS_SETRLIMIT, sys_setrlimit);
+    table.insert(SYS_PRLIMIT64, sys_prlimit64);
+    table.insert(SYS_GETRUSAGE, sys_getrusage);
+    table.insert(SYS_MMAP, sys_enosys);
+    table.insert(SYS_MUNMAP, sys_enosys);
+    table.insert(SYS_MREMAP, sys_enosys);
+    table.insert(SYS_MPROTECT, sys_enosys);
+    table.insert(SYS_RT_SIGACTION, sys_rt_sigaction);
     table.insert(SYS_GETTIMEOFDAY, sys_gettimeofday);
-    table.insert(SYS_TIMES, sys_unimplemented);
-    table.insert(SYS_WRITEV, sys_unimplemented);
-    table.insert(SYS_FACCESSAT, sys_unimplemented);
-    table.insert(SYS_FCNTL, sys_unimplemented);
-    table.insert(SYS_FTRUNCATE, sys_unimplemented);
-    table.insert(SYS_GETDENTS, sys_unimplemented);
-    table.insert(SYS_DUP, sys_unimplemented);
-    table.insert(SYS_DUP3, sys_unimplemented);
-    table.insert(SYS_RT_SIGPROCMASK, sys_unimplemented);
-    table.insert(SYS_CLOCK_GETTIME, sys_unimplemented);
-    table.insert(SYS_CHDIR, sys_unimplemented);
+    table.insert(SYS_TIMES, sys_times);
+    table.insert(SYS_WRITEV, sys_writev);
+    table.insert(SYS_FACCESSAT, sys_faccessat);
+    table.insert(SYS_FCNTL, sys_fcntl);
+    table.insert(SYS_FTRUNCATE, sys_ftruncate);
+    table.insert(SYS_IOCTL, sys_ioctl);
+    table.insert(SYS_GETDENTS, sys_getdents);
+    table.insert(SYS_DUP, sys_dup);
+    table.insert(SYS_DUP3, sys_dup3);
+    table.insert(SYS_RT_SIGPROCMASK, sys_rt_sigprocmask);
+    table.insert(SYS_RT_SIGRETURN, sys_rt_sigreturn);
+    table.insert(SYS_CLOCK_GETTIME, sys_clock_gettime);
+    table.insert(SYS_CLOCK_GETRES, sys_clock_getres);
+    table.insert(SYS_NANOSLEEP, sys_nanosleep);
+    table.insert(SYS_CLOCK_NANOSLEEP, sys_clock_nanosleep);
+    table.insert(SYS_CHDIR, sys_chdir);
+    table.insert(SYS_READLINKAT, sys_readlinkat);
+    table.insert(SYS_EXECVE, sys_execve);
+    table.insert(SYS_PIPE2, sys_pipe2);
+    table.insert(SYS_PSELECT6, sys_pselect6);
+    table.insert(SYS_PPOLL, sys_ppoll);
+    table.insert(SYS_EVENTFD2, sys_eventfd2);
+    table.insert(SYS_EPOLL_CREATE1, sys_epoll_create1);
+    table.insert(SYS_EPOLL_CTL, sys_epoll_ctl);
+    table.insert(SYS_EPOLL_PWAIT, sys_epoll_pwait);
+    table.insert(SYS_SOCKET, sys_socket);
+    table.insert(SYS_SOCKETPAIR, sys_socketpair);
+    table.insert(SYS_BIND, sys_bind);
+    table.insert(SYS_LISTEN, sys_listen);
+    table.insert(SYS_ACCEPT, sys_accept);
+    table.insert(SYS_ACCEPT4, sys_accept4);
+    table.insert(SYS_CONNECT, sys_connect);
+    table.insert(SYS_GETSOCKNAME, sys_getsockname);
+    table.insert(SYS_GETPEERNAME, sys_getpeername);
+    table.insert(SYS_SENDTO, sys_sendto);
+    table.insert(SYS_RECVFROM, sys_recvfrom);
+    table.insert(SYS_SETSOCKOPT, sys_setsockopt);
+    table.insert(SYS_GETSOCKOPT, sys_getsockopt);
+    table.insert(SYS_SHUTDOWN, sys_shutdown);
+    table.insert(SYS_SENDMSG, sys_sendmsg);
+    table.insert(SYS_RECVMSG, sys_recvmsg);
+    table.insert(SYS_GETRANDOM, sys_getrandom);
 
     table
 });
 
-pub static OLD_SYSCALL_TABLE: LazyLock<HashMap<u32, syscall_t>> = LazyLock::new(|| {
+/*
+    the newlib personality gets what riscv-pk serves: the calls of newlib's
+    libgloss and the legacy 1024+ numbers. rt_sigreturn returns from the
+    signal frames the emulator builds for any guest.
+*/
+pub static NEWLIB_SYSCALL_TABLE: LazyLock<HashMap<u32, syscall_t>> = LazyLock::new(|| {
     let mut table: HashMap<u32, syscall_t> = HashMap::new();
-    table.insert(SYS_OPEN - OLD_SYSCALL_THRESHOLD, sys_open);
-    table.insert(SYS_LINK - OLD_SYSCALL_THRESHOLD, sys_unimplemented);
-    table.insert(SYS_UNLINK - OLD_SYSCALL_THRESHOLD, sys_unimplemented);
-    table.insert(SYS_MKDIR - OLD_SYSCALL_THRESHOLD, sys_unimplemented);
-    table.insert(SYS_ACCESS - OLD_SYSCALL_THRESHOLD, sys_unimplemented);
-    table.insert(SYS_STAT - OLD_SYSCALL_THRESHOLD, sys_unimplemented);
-    table.insert(SYS_LSTAT - OLD_SYSCALL_THRESHOLD, sys_unimplemented);
-    table.insert(SYS_TIME - OLD_SYSCALL_THRESHOLD, sys_unimplemented);
+    for num in [
+        SYS_EXIT,
+        SYS_EXIT_GROUP,
+        SYS_GETPID,
+        SYS_TGKILL,
+        SYS_READ,
+        SYS_PREAD,
+        SYS_PWRITE,
+        SYS_WRITE,
+        SYS_OPENAT,
+        SYS_CLOSE,
+        SYS_FSTAT,
+        SYS_LSEEK,
+        SYS_RENAMEAT,
+        SYS_FSTATAT,
+        SYS_LINKAT,
+        SYS_UNLINKAT,
+        SYS_MKDIRAT,
+        SYS_GETCWD,
+        SYS_BRK,
+        SYS_UNAME,
+        SYS_PRLIMIT64,
+        SYS_GETUID,
+        SYS_GETEUID,
+        SYS_GETGID,
+        SYS_GETEGID,
+        SYS_GETTID,
+        SYS_MMAP,
+        SYS_MUNMAP,
+        SYS_MREMAP,
+        SYS_MPROTECT,
+        SYS_RT_SIGACTION,
+        SYS_RT_SIGPROCMASK,
+        SYS_RT_SIGRETURN,
+        SYS_GETTIMEOFDAY,
+        SYS_TIMES,
+        SYS_WRITEV,
+        SYS_FACCESSAT,
+        SYS_FCNTL,
+        SYS_FTRUNCATE,
+        SYS_GETDENTS,
+        SYS_DUP,
+        SYS_DUP3,
+        SYS_READLINKAT,
+        SYS_IOCTL,
+        SYS_CLOCK_GETTIME,
+        SYS_GETRANDOM,
+        SYS_CHDIR,
+        SYS_STATX,
+    ] {
+        table.insert(num, LINUX_SYSCALL_TABLE[&num]);
+    }
+    table.insert(SYS_OPEN, sys_open);
+    table.insert(SYS_LINK, sys_link);
+    table.insert(SYS_UNLINK, sys_unlink);
+    table.insert(SYS_MKDIR, sys_mkdir);
+    table.insert(SYS_ACCESS, sys_access);
+    table.insert(SYS_STAT, sys_stat);
+    table.insert(SYS_LSTAT, sys_lstat);
+    table.insert(SYS_TIME, sys_time);
     table
 });
 
-fn sys_unimplemented(m: &mut machine_t) -> u64 {
-    panic!(
-        "Unimplemented syscall: {}",
-        m.state.gp_regs[gp_reg_type_t::a7 as usize]
-    );
+// host calls report failure through errno, the guest expects -errno in a0
+fn syscall_ret(ret: i64) -> u64 {
+    if ret < 0 {
+        -(std::io::Error::last_os_error().raw_os_error().unwrap() as i64) as u64
+    } else {
+        ret as u64
+    }
+}
+
+// vfs errors are positive errno values
+fn vfs_ret(ret: Result<u64, i32>) -> u64 {
+    match ret {
+        Ok(ret) => ret,
+        Err(errno) => -(errno as i64) as u64,
+    }
+}
+
+pub fn with_file<T>(
+    m: &machine_t,
+    fd: u64,
+    f: impl FnOnce(&mut dyn vfile_t) -> Result<T, i32>,
+) -> Result<T, i32> {
+    let file = m.fds.get(fd)?;
+    let mut file = file.borrow_mut();
+    f(&mut **file)
+}
+
+// run f on the directory a *at call is relative to, None for the cwd
+fn with_dir<T>(
+    m: &mut machine_t,
+    dirfd: u64,
+    f: impl FnOnce(&mut dyn vfs_t, Option<&dyn vfile_t>) -> Result<T, i32>,
+) -> Result<T, i32> {
+    if dirfd as i32 == libc::AT_FDCWD {
+        return f(m.vfs.as_mut(), None);
+    }
+    let dir = m.fds.get(dirfd)?;
+    let dir = dir.borrow();
+    f(m.vfs.as_mut(), Some(&**dir))
+}
+
+// guest buffers must lie in mapped guest memory
+pub fn guest_slice<'a>(m: &machine_t, addr: u64, len: u64) -> Result<&'a [u8], i32> {
+    if m.mmu.mmu_valid(addr, len) {
+        Ok(unsafe { mmu_t::mmu_slice(addr, len) })
+    } else {
+        Err(libc::EFAULT)
+    }
+}
+
+pub fn guest_slice_mut<'a>(m: &machine_t, addr: u64, len: u64) -> Result<&'a mut [u8], i32> {
+    if m.mmu.mmu_valid(addr, len) {
+        Ok(unsafe { mmu_t::mmu_slice_mut(addr, len) })
+    } else {
+        Err(libc::EFAULT)
+    }
+}
+
+const IOV_MAX: u64 = 1024;
+
+// struct iovec array as (base, len) pairs, validated like the kernel does
+fn guest_iovecs(m: &machine_t, addr: u64, count: u64) -> Result<Vec<(u64, u64)>, i32> {
+    if count > IOV_MAX {
+        return Err(libc::EINVAL);
+    }
+    let raw = guest_slice(m, addr, count * 16)?;
+    let mut iovecs = Vec::new();
+    let mut total: u64 = 0;
+    for iov in raw.chunks_exact(16) {
+        let base = u64::from_le_bytes(iov[..8].try_into().unwrap());
+        let len = u64::from_le_bytes(iov[8..].try_into().unwrap());
+        total = total.checked_add(len).ok_or(libc::EINVAL)?;
+        if total > isize::MAX as u64 {
+            return Err(libc::EINVAL);
+        }
+        if !m.mmu.mmu_valid(base, len) {
+            return Err(libc::EFAULT);
+        }
+        iovecs.push((base, len));
+    }
+    Ok(iovecs)
+}
+
+// like with_dir, for the calls taking two paths
+fn with_dirs<T>(
+    m: &mut machine_t,
+    olddirfd: u64,
+    newdirfd: u64,
+    f: impl FnOnce(&mut dyn vfs_t, Option<&dyn vfile_t>, Option<&dyn vfile_t>) -> Result<T, i32>,
+) -> Result<T, i32> {
+    let dir_file = |dirfd: u64| match dirfd as i32 {
+        libc::AT_FDCWD => Ok(None),
+        _ => m.fds.get(dirfd).map(Some),
+    };
+    let (olddir, newdir) = (dir_file(olddirfd)?, dir_file(newdirfd)?);
+    let olddir = olddir.as_ref().map(|dir| dir.borrow());
+    let newdir = newdir.as_ref().map(|dir| dir.borrow());
+    f(
+        m.vfs.as_mut(),
+        olddir.as_deref().map(|dir| &**dir),
+        newdir.as_deref().map(|dir| &**dir),
+    )
+}
+
+/*
+    the absolute guest path a *at call names, without following symlinks.
+    for paths the emulator answers itself rather than the vfs.
+*/
+fn guest_path(m: &mut machine_t, dirfd: u64, path: &CStr) -> Result<PathBuf, i32> {
+    let cwd = PathBuf::from(OsString::from_vec(m.vfs.getcwd()?));
+    if dirfd as i32 == libc::AT_FDCWD || path.to_bytes().starts_with(b"/") {
+        return resolve(&cwd, None, path);
+    }
+    with_file(m, dirfd, |dir| match dir.host_fd() {
+        Some(fd) => match std::fs::read_link(format!("/proc/self/fd/{}", fd)) {
+            Ok(base) => resolve(&base, None, path),
+            Err(_) => Err(libc::ENOTDIR),
+        },
+        None => resolve(&cwd, Some(dir), path),
+    })
+}
+
+// a NUL terminated guest string, checked a page at a time
+pub fn guest_cstr(m: &machine_t, addr: u64) -> Result<CString, i32> {
+    let page_size = page_size::get() as u64;
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut addr = addr;
+    loop {
+        let len = page_size - addr % page_size;
+        let chunk = guest_slice(m, addr, len)?;
+        if let Some(end) = chunk.iter().position(|&b| b == 0) {
+            bytes.extend_from_slice(&chunk[..end]);
+            return Ok(CString::new(bytes).unwrap());
+        }
+        bytes.extend_from_slice(chunk);
+        addr += len;
+    }
+}
+
+// read a NULL terminated array of guest string pointers, e.g. argv and envp
+fn guest_strv(m: &machine_t, addr: u64) -> Result<Vec<CString>, i32> {
+    let mut strv: Vec<CString> = Vec::new();
+    if addr == 0 {
+        return Ok(strv);
+    }
+    let mut ptr = addr;
+    loop {
+        let str_addr = u64::from_le_bytes(guest_slice(m, ptr, 8)?.try_into().unwrap());
+        if str_addr == 0 {
+            break;
+        }
+        strv.push(guest_cstr(m, str_addr)?);
+        ptr += 8;
+    }
+    Ok(strv)
+}
+
+// struct stat of the asm-generic ABI used by RISC-V
+#[repr(C)]
+struct guest_stat_t {
+    st_dev: u64,
+    st_ino: u64,
+    st_mode: u32,
+    st_nlink: u32,
+    st_uid: u32,
+    st_gid: u32,
+    st_rdev: u64,
+    __pad1: u64,
+    st_size: i64,
+    st_blksize: i32,
+    __pad2: i32,
+    st_blocks: i64,
+    st_atime: i64,
+    st_atime_nsec: u64,
+    st_mtime: i64,
+    st_mtime_nsec: u64,
+    st_ctime: i64,
+    st_ctime_nsec: u64,
+    __unused4: u32,
+    __unused5: u32,
+}
+
+fn write_guest_stat(m: &machine_t, addr: u64, st: &libc::stat) -> Result<u64, i32> {
+    let buf = guest_slice_mut(m, addr, std::mem::size_of::<guest_stat_t>() as u64)?;
+    let guest_st = guest_stat_t {
+        st_dev: st.st_dev,
+        st_ino: st.st_ino,
+        st_mode: st.st_mode,
+        st_nlink: st.st_nlink as u32,
+        st_uid: st.st_uid,
+        st_gid: st.st_gid,
+        st_rdev: st.st_rdev,
+        __pad1: 0,
+        st_size: st.st_size,
+        st_blksize: st.st_blksize as i32,
+        __pad2: 0,
+        st_blocks: st.st_blocks,
+        st_atime: st.st_atime,
+        st_atime_nsec: st.st_atime_nsec as u64,
+        st_mtime: st.st_mtime,
+        st_mtime_nsec: st.st_mtime_nsec as u64,
+        st_ctime: st.st_ctime,
+        st_ctime_nsec: st.st_ctime_nsec as u64,
+        __unused4: 0,
+        __unused5: 0,
+    };
+    unsafe { std::ptr::write_unaligned(buf.as_mut_ptr() as *mut guest_stat_t, guest_st) };
+    Ok(0)
+}
+
+// known calls the emulator has no use for, which programs cope without
+fn sys_enosys(_m: &mut machine_t) -> u64 {
+    -(libc::ENOSYS as i64) as u64
 }
 
 fn sys_exit(m: &mut machine_t) -> u64 {
@@ -155,120 +509,465 @@ fn sys_exit(m: &mut machine_t) -> u64 {
 fn sys_close(m: &mut machine_t) -> u64 {
     let fd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
 
-    // println!("sys_close, fd: {}", fd);
+    vfs_ret(m.fds.close(fd).map(|_| 0))
+}
+
+fn sys_dup(m: &mut machine_t) -> u64 {
+    let fd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+
+    vfs_ret(m.fds.dup(fd, 0, false))
+}
+
+fn sys_dup3(m: &mut machine_t) -> u64 {
+    let oldfd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let newfd: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let flags: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
 
-    if fd > 2 {
-        return unsafe { libc::close(fd as libc::c_int) as u64 };
+    let flags = m.personality.open_flags_to_host(flags as i32);
+    if oldfd == newfd || flags & !O_CLOEXEC != 0 {
+        return vfs_ret(Err(libc::EINVAL));
+    }
+    vfs_ret(m.fds.dup_to(oldfd, newfd, flags & O_CLOEXEC != 0))
+}
+
+fn sys_fcntl(m: &mut machine_t) -> u64 {
+    let fd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let cmd: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let arg: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+
+    let personality = m.personality;
+    let ret = match personality.fcntl_cmd_to_host(cmd as i32) {
+        libc::F_DUPFD => m.fds.dup(fd, arg, false),
+        libc::F_DUPFD_CLOEXEC => m.fds.dup(fd, arg, true),
+        libc::F_GETFD => m
+            .fds
+            .get_cloexec(fd)
+            .map(|cloexec| if cloexec { libc::FD_CLOEXEC as u64 } else { 0 }),
+        libc::F_SETFD => m
+            .fds
+            .set_cloexec(fd, arg as i32 & libc::FD_CLOEXEC != 0)
+            .map(|_| 0),
+        libc::F_GETFL => with_file(m, fd, |f| f.get_flags())
+            .map(|flags| personality.open_flags_to_guest(flags) as u64),
+        libc::F_SETFL => with_file(m, fd, |f| {
+            f.set_flags(personality.open_flags_to_host(arg as i32))
+        })
+        .map(|_| 0),
+        _ => Err(libc::EINVAL),
     };
-    return 0;
+    vfs_ret(ret)
 }
 
 fn sys_write(m: &mut machine_t) -> u64 {
     let fd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
     let ptr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
     let len: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
-    // println!("sys_write, fd: {}, ptr: {:#x}, len: {}", fd, to_host_addr(ptr), len);
 
-    return unsafe {
-        libc::write(
-            fd as libc::c_int,
-            to_host_addr(ptr) as *const libc::c_void,
-            len as libc::size_t,
-        )
-    } as u64;
+    let ret = guest_slice(m, ptr, len)
+        .and_then(|buf| with_file(m, fd, |f| f.write(buf).map(|n| n as u64)));
+    vfs_ret(ret)
 }
 
 fn sys_fstat(m: &mut machine_t) -> u64 {
     let fd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
     let addr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
-    // println!("sys_fstat, fd: {}, addr: {}", fd, addr);
-
-    let ret = unsafe { libc::fstat(fd as libc::c_int, to_host_addr(addr) as *mut libc::stat) };
 
-    let stat_info: libc::stat =
-        unsafe { *(to_host_addr(addr) as *mut libc::stat).as_mut().unwrap() };
-    // println!("sys_fstat Device: {}", stat_info.st_dev);
-    // println!("sys_fstat Inode: {}", stat_info.st_ino);
-    // println!("sys_fstat Mode: {}", stat_info.st_mode);
-    // println!("sys_fstat Number of hard links: {}", stat_info.st_nlink);
-    // println!("sys_fstat Owner's user ID: {}", stat_info.st_uid);
-    // println!("sys_fstat Owner's group ID: {}", stat_info.st_gid);
-    // println!("sys_fstat Total size, in bytes: {}", stat_info.st_size);
-    // println!("sys_fstat Last access time: {}", stat_info.st_atime);
-    // println!("sys_fstat Last modification time: {}", stat_info.st_mtime);
-    // println!("sys_fstat Last status change time: {}", stat_info.st_ctime);
+    let st = with_file(m, fd, |f| f.fstat());
+    vfs_ret(st.and_then(|st| write_guest_stat(m, addr, &st)))
+}
 
-    ret as u64
+/*
+    time. all clocks come from m.state.clock, which is either the host's or
+    derived from the retired instruction count.
+*/
+fn read_timespec(m: &machine_t, addr: u64) -> Result<(i64, i64), i32> {
+    let raw = guest_slice(m, addr, 16)?;
+    let sec = i64::from_le_bytes(raw[..8].try_into().unwrap());
+    let nsec = i64::from_le_bytes(raw[8..].try_into().unwrap());
+    if sec < 0 || !(0..1_000_000_000).contains(&nsec) {
+        return Err(libc::EINVAL);
+    }
+    Ok((sec, nsec))
+}
 
-    // return unsafe { libc::fstat(
-    //     fd as libc::c_int,
-    //     to_host_addr(addr) as *mut libc::stat
-    // ) } as u64;
+fn write_pair(m: &machine_t, addr: u64, (a, b): (i64, i64)) -> Result<u64, i32> {
+    let buf = guest_slice_mut(m, addr, 16)?;
+    buf[..8].copy_from_slice(&a.to_le_bytes());
+    buf[8..].copy_from_slice(&b.to_le_bytes());
+    Ok(0)
 }
 
 fn sys_gettimeofday(m: &mut machine_t) -> u64 {
     let tv_addr: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
     let tz_addr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
-    let tv = unsafe { &mut *(to_host_addr(tv_addr) as *mut libc::timeval) };
-    let tz: *mut libc::timezone = if tz_addr != 0 {
-        unsafe { &mut *(to_host_addr(tz_addr) as *mut libc::timezone) }
-    } else {
-        std::ptr::null_mut()
-    };
 
-    return unsafe { libc::gettimeofday(tv, tz) } as u64;
+    let (sec, nsec) = m
+        .state
+        .clock
+        .gettime(libc::CLOCK_REALTIME, m.state.instret)
+        .unwrap();
+    let mut ret = Ok(0);
+    if tv_addr != 0 {
+        ret = write_pair(m, tv_addr, (sec, nsec / 1000));
+    }
+    if tz_addr != 0 {
+        // struct timezone, always UTC
+        ret = ret.and_then(|_| {
+            guest_slice_mut(m, tz_addr, 8)
+                .map(|tz| tz.fill(0))
+                .map(|_| 0)
+        });
+    }
+    vfs_ret(ret)
 }
 
-fn sys_brk(m: &mut machine_t) -> u64 {
-    let mut addr: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
-    if addr == 0 {
-        addr = m.mmu.alloc;
+fn sys_clock_gettime(m: &mut machine_t) -> u64 {
+    let clockid: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let tp_addr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+
+    let ret = m
+        .state
+        .clock
+        .gettime(clockid as i32, m.state.instret)
+        .and_then(|ts| write_pair(m, tp_addr, ts));
+    vfs_ret(ret)
+}
+
+fn sys_clock_getres(m: &mut machine_t) -> u64 {
+    let clockid: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let res_addr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+
+    let ret = m
+        .state
+        .clock
+        .getres(clockid as i32)
+        .and_then(|res| match res_addr {
+            0 => Ok(0),
+            _ => write_pair(m, res_addr, res),
+        });
+    vfs_ret(ret)
+}
+
+fn sys_time(m: &mut machine_t) -> u64 {
+    let tloc: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+
+    let (sec, _) = m
+        .state
+        .clock
+        .gettime(libc::CLOCK_REALTIME, m.state.instret)
+        .unwrap();
+    if tloc != 0 {
+        match guest_slice_mut(m, tloc, 8) {
+            Ok(buf) => buf.copy_from_slice(&sec.to_le_bytes()),
+            Err(errno) => return vfs_ret(Err(errno)),
+        }
     }
-    assert!(addr >= m.mmu.base);
-    let incr: i64 = addr as i64 - m.mmu.alloc as i64;
+    sec as u64
+}
 
-    // println!("sys_brk, addr: {:#x}, incr: {} = {} - {}", to_host_addr(addr), incr, addr, m.mmu.alloc );
+// the guest runs single threaded, all of its time is user time
+fn sys_times(m: &mut machine_t) -> u64 {
+    let tms_addr: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
 
-    m.mmu.mmu_alloc(incr);
-    return addr;
+    let ticks = |(sec, nsec): (i64, i64)| sec * 100 + nsec / 10_000_000;
+    let cpu = ticks(
+        m.state
+            .clock
+            .gettime(libc::CLOCK_PROCESS_CPUTIME_ID, m.state.instret)
+            .unwrap(),
+    );
+    let uptime = ticks(
+        m.state
+            .clock
+            .gettime(libc::CLOCK_MONOTONIC, m.state.instret)
+            .unwrap(),
+    );
+    if tms_addr != 0 {
+        // struct tms: utime, stime, cutime, cstime
+        let ret =
+            write_pair(m, tms_addr, (cpu, 0)).and_then(|_| write_pair(m, tms_addr + 16, (0, 0)));
+        if let Err(errno) = ret {
+            return vfs_ret(Err(errno));
+        }
+    }
+    uptime as u64
 }
 
-// the O_* macros is OS dependent.
-// here is a workaround to convert newlib flags to the host.
-pub const NEWLIB_O_RDONLY: i32 = 0x0;
-pub const NEWLIB_O_WRONLY: i32 = 0x1;
-pub const NEWLIB_O_RDWR: i32 = 0x2;
-pub const NEWLIB_O_APPEND: i32 = 0x8;
-pub const NEWLIB_O_CREAT: i32 = 0x200;
-pub const NEWLIB_O_TRUNC: i32 = 0x400;
-pub const NEWLIB_O_EXCL: i32 = 0x800;
+fn sys_nanosleep(m: &mut machine_t) -> u64 {
+    let req_addr: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+
+    let ret = read_timespec(m, req_addr).map(|(sec, nsec)| {
+        m.state.clock.sleep(timespec_ns(sec, nsec));
+        0
+    });
+    vfs_ret(ret)
+}
 
-fn convert_flags(flags: i32) -> i32 {
-    let mut hostflags: i32 = 0;
+fn sys_clock_nanosleep(m: &mut machine_t) -> u64 {
+    let clockid: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let flags: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let req_addr: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
 
-    if flags & NEWLIB_O_RDONLY != 0 {
-        hostflags |= O_RDONLY;
+    // linux refuses to sleep on the cpu time clocks
+    if clockid as i32 == libc::CLOCK_THREAD_CPUTIME_ID {
+        return vfs_ret(Err(libc::EINVAL));
     }
-    if flags & NEWLIB_O_WRONLY != 0 {
-        hostflags |= O_WRONLY;
+    let ret = m
+        .state
+        .clock
+        .gettime(clockid as i32, m.state.instret)
+        .and_then(|_| read_timespec(m, req_addr))
+        .map(|(sec, nsec)| {
+            if flags as i32 & libc::TIMER_ABSTIME != 0 {
+                m.state
+                    .clock
+                    .sleep_until(clockid as i32, m.state.instret, sec, nsec);
+            } else {
+                m.state.clock.sleep(timespec_ns(sec, nsec));
+            }
+            0
+        });
+    vfs_ret(ret)
+}
+
+/*
+    identity. the guest is a single process whose pid is the emulator's,
+    uname reports a riscv64 Linux with the configured release.
+*/
+// what a guest on the virtual clock sees instead of the host's
+const VIRTUAL_NODENAME: &[u8] = b"rvemu";
+const VIRTUAL_RAM: u64 = 1 << 32;
+
+fn sys_uname(m: &mut machine_t) -> u64 {
+    let addr: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+
+    let mut host: libc::utsname = unsafe { mem::zeroed() };
+    unsafe { libc::uname(&mut host) };
+    let nodename: Vec<u8> = match m.state.clock.is_virtual() {
+        true => VIRTUAL_NODENAME.to_vec(),
+        false => host
+            .nodename
+            .iter()
+            .take_while(|&&c| c != 0)
+            .map(|&c| c as u8)
+            .collect(),
+    };
+
+    // struct utsname is six char[65]
+    let fields: [&[u8]; 6] = [
+        b"Linux",
+        &nodename,
+        m.config.release.as_bytes(),
+        b"#1 SMP",
+        b"riscv64",
+        b"(none)",
+    ];
+    let ret = guest_slice_mut(m, addr, 6 * 65).map(|buf| {
+        buf.fill(0);
+        for (field, value) in buf.chunks_exact_mut(65).zip(fields) {
+            let len = value.len().min(64);
+            field[..len].copy_from_slice(&value[..len]);
+        }
+        0
+    });
+    vfs_ret(ret)
+}
+
+fn sys_getpid(_m: &mut machine_t) -> u64 {
+    unsafe { libc::getpid() as u64 }
+}
+
+fn sys_getuid(m: &mut machine_t) -> u64 {
+    m.config.uid.unwrap_or_else(|| unsafe { libc::getuid() }) as u64
+}
+
+fn sys_getgid(m: &mut machine_t) -> u64 {
+    m.config.gid.unwrap_or_else(|| unsafe { libc::getgid() }) as u64
+}
+
+// struct sysinfo on 64-bit Linux
+#[repr(C)]
+struct guest_sysinfo_t {
+    uptime: i64,
+    loads: [u64; 3],
+    totalram: u64,
+    freeram: u64,
+    sharedram: u64,
+    bufferram: u64,
+    totalswap: u64,
+    freeswap: u64,
+    procs: u16,
+    pad: u16,
+    totalhigh: u64,
+    freehigh: u64,
+    mem_unit: u32,
+    _f: [u8; 4],
+}
+
+fn sys_sysinfo(m: &mut machine_t) -> u64 {
+    let addr: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+
+    let mut host: libc::sysinfo = unsafe { mem::zeroed() };
+    if m.state.clock.is_virtual() {
+        // all memory free and no swap, up to --max-mem
+        host.totalram = m.config.limits.mem.unwrap_or(VIRTUAL_RAM);
+        host.freeram = host.totalram;
+        host.mem_unit = 1;
+    } else {
+        unsafe { libc::sysinfo(&mut host) };
     }
-    if flags & NEWLIB_O_RDWR != 0 {
-        hostflags |= O_RDWR;
+    let (uptime, _) = m
+        .state
+        .clock
+        .gettime(libc::CLOCK_BOOTTIME, m.state.instret)
+        .unwrap();
+    let unit = host.mem_unit as u64;
+    let info = guest_sysinfo_t {
+        uptime,
+        loads: [0; 3],
+        totalram: host.totalram * unit,
+        freeram: host.freeram * unit,
+        sharedram: host.sharedram * unit,
+        bufferram: host.bufferram * unit,
+        totalswap: host.totalswap * unit,
+        freeswap: host.freeswap * unit,
+        procs: 1,
+        pad: 0,
+        totalhigh: 0,
+        freehigh: 0,
+        mem_unit: 1,
+        _f: [0; 4],
+    };
+    let ret = guest_slice_mut(m, addr, mem::size_of::<guest_sysinfo_t>() as u64).map(|buf| {
+        unsafe { std::ptr::write_unaligned(buf.as_mut_ptr() as *mut guest_sysinfo_t, info) };
+        0
+    });
+    vfs_ret(ret)
+}
+
+/*
+    resource limits are kept per guest. the stack and fd limits are
+    the emulator's own, the rest start out as the host's.
+*/
+fn do_prlimit(m: &mut machine_t, resource: u64, new_addr: u64, old_addr: u64) -> Result<u64, i32> {
+    let resource = resource as usize;
+    if resource >= RLIM_NLIMITS {
+        return Err(libc::EINVAL);
     }
-    if flags & NEWLIB_O_APPEND != 0 {
-        hostflags |= O_APPEND;
+    let new = match new_addr {
+        0 => None,
+        _ => {
+            let raw = guest_slice(m, new_addr, 16)?;
+            let soft = u64::from_le_bytes(raw[..8].try_into().unwrap());
+            let hard = u64::from_le_bytes(raw[8..].try_into().unwrap());
+            if soft > hard {
+                return Err(libc::EINVAL);
+            }
+            if hard > m.rlimits[resource].1 {
+                return Err(libc::EPERM);
+            }
+            Some((soft, hard))
+        }
+    };
+    if old_addr != 0 {
+        let (soft, hard) = m.rlimits[resource];
+        write_pair(m, old_addr, (soft as i64, hard as i64))?;
     }
-    if flags & NEWLIB_O_CREAT != 0 {
-        hostflags |= O_CREAT;
+    if let Some(new) = new {
+        m.rlimits[resource] = new;
+        match resource as u32 {
+            libc::RLIMIT_NOFILE => m.fds.limit = new.0,
+            libc::RLIMIT_AS => m.mmu.account.limit.set(new.0),
+            // the seconds are rounded, --max-insns stays the bound
+            libc::RLIMIT_CPU => {
+                m.insn_limit = new
+                    .0
+                    .saturating_mul(m.state.clock.insns_per_sec())
+                    .min(m.config.limits.insns.unwrap_or(u64::MAX));
+            }
+            _ => {}
+        }
     }
-    if flags & NEWLIB_O_TRUNC != 0 {
-        hostflags |= O_TRUNC;
+    Ok(0)
+}
+
+fn sys_getrlimit(m: &mut machine_t) -> u64 {
+    let resource: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let addr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+
+    vfs_ret(do_prlimit(m, resource, 0, addr))
+}
+
+fn sys_setrlimit(m: &mut machine_t) -> u64 {
+    let resource: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let addr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+
+    vfs_ret(do_prlimit(m, resource, addr, 0))
+}
+
+fn sys_prlimit64(m: &mut machine_t) -> u64 {
+    let pid: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let resource: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let new_addr: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+    let old_addr: u64 = m.state.gp_regs[gp_reg_type_t::a3 as usize];
+
+    if pid != 0 && pid != unsafe { libc::getpid() } as u64 {
+        return vfs_ret(Err(libc::ESRCH));
     }
-    if flags & NEWLIB_O_EXCL != 0 {
-        hostflags |= O_EXCL;
+    vfs_ret(do_prlimit(m, resource, new_addr, old_addr))
+}
+
+fn sys_getrusage(m: &mut machine_t) -> u64 {
+    let who: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let addr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+
+    // struct rusage: utime, stime and 14 longs, of which only maxrss is set
+    let ret = match who as i32 {
+        libc::RUSAGE_SELF | libc::RUSAGE_THREAD | libc::RUSAGE_CHILDREN => {
+            guest_slice_mut(m, addr, 144).map(|buf| buf.fill(0))
+        }
+        _ => Err(libc::EINVAL),
+    };
+    let ret = ret.and_then(|_| {
+        if who as i32 == libc::RUSAGE_CHILDREN {
+            return Ok(0);
+        }
+        let (sec, nsec) = m
+            .state
+            .clock
+            .gettime(libc::CLOCK_PROCESS_CPUTIME_ID, m.state.instret)
+            .unwrap();
+        write_pair(m, addr, (sec, nsec / 1000))?;
+        if let clock_mode_t::host = m.state.clock.mode {
+            let mut host: libc::rusage = unsafe { mem::zeroed() };
+            unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut host) };
+            mmu_t::mmu_write(addr + 32, &host.ru_maxrss.to_le_bytes());
+        }
+        Ok(0)
+    });
+    vfs_ret(ret)
+}
+
+fn sys_brk(m: &mut machine_t) -> u64 {
+    let addr: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+
+    // like Linux, a refused request just reports the current break
+    let data_limit = m.rlimits[libc::RLIMIT_DATA as usize].0;
+    if addr.saturating_sub(m.mmu.base) > data_limit {
+        return m.mmu.alloc;
     }
+    m.mmu.mmu_brk(addr)
+}
 
-    hostflags
+// device files the emulator provides whatever the vfs is
+fn open_device(m: &mut machine_t, dirfd: u64, path: &CStr) -> Option<Box<dyn vfile_t>> {
+    let path = guest_path(m, dirfd, path).ok()?;
+    let minor = match path.as_os_str().as_bytes() {
+        b"/dev/random" => 8,
+        b"/dev/urandom" => 9,
+        _ => return None,
+    };
+    Some(Box::new(random_file_t::new(m.random.clone(), minor)))
 }
 
 fn sys_openat(m: &mut machine_t) -> u64 {
@@ -276,29 +975,36 @@ fn sys_openat(m: &mut machine_t) -> u64 {
     let nameptr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
     let flags: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
     let mode: u64 = m.state.gp_regs[gp_reg_type_t::a3 as usize];
+    let path = match guest_cstr(m, nameptr) {
+        Ok(path) => path,
+        Err(errno) => return vfs_ret(Err(errno)),
+    };
 
-    return unsafe {
-        libc::openat(
-            dirfd as libc::c_int,
-            to_host_addr(nameptr) as *const libc::c_char,
-            convert_flags(flags as i32) as libc::c_int,
-            mode as libc::c_int,
-        )
-    } as u64;
+    let flags = m.personality.open_flags_to_host(flags as i32);
+    let file = match open_device(m, dirfd, &path) {
+        Some(file) => Ok(file),
+        None => with_dir(m, dirfd, |vfs, dir| {
+            vfs.open(dir, &path, flags, mode as u32)
+        }),
+    };
+    vfs_ret(file.and_then(|file| m.fds.alloc(file, flags & O_CLOEXEC != 0)))
 }
 
 fn sys_open(m: &mut machine_t) -> u64 {
     let nameptr: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
     let flags: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
     let mode: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+    let path = match guest_cstr(m, nameptr) {
+        Ok(path) => path,
+        Err(errno) => return vfs_ret(Err(errno)),
+    };
 
-    return unsafe {
-        libc::open(
-            to_host_addr(nameptr) as *const libc::c_char,
-            convert_flags(flags as i32) as libc::c_int,
-            mode as libc::c_int,
-        )
-    } as u64;
+    let flags = m.personality.open_flags_to_host(flags as i32);
+    let file = match open_device(m, libc::AT_FDCWD as u64, &path) {
+        Some(file) => Ok(file),
+        None => m.vfs.open(None, &path, flags, mode as u32),
+    };
+    vfs_ret(file.and_then(|file| m.fds.alloc(file, flags & O_CLOEXEC != 0)))
 }
 
 fn sys_lseek(m: &mut machine_t) -> u64 {
@@ -306,13 +1012,7 @@ fn sys_lseek(m: &mut machine_t) -> u64 {
     let offset: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
     let whence: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
 
-    return unsafe {
-        libc::lseek(
-            fd as libc::c_int,
-            offset as libc::off_t,
-            whence as libc::c_int,
-        )
-    } as u64;
+    vfs_ret(with_file(m, fd, |f| f.lseek(offset as i64, whence as i32)))
 }
 
 fn sys_read(m: &mut machine_t) -> u64 {
@@ -320,11 +1020,1591 @@ fn sys_read(m: &mut machine_t) -> u64 {
     let bufptr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
     let count: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
 
-    return unsafe {
-        libc::read(
-            fd as libc::c_int,
-            to_host_addr(bufptr) as *mut libc::c_void,
-            count as libc::size_t,
+    let ret = guest_slice_mut(m, bufptr, count)
+        .and_then(|buf| with_file(m, fd, |f| f.read(buf).map(|n| n as u64)));
+    vfs_ret(ret)
+}
+
+fn sys_pread(m: &mut machine_t) -> u64 {
+    let fd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let bufptr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let count: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+    let offset: u64 = m.state.gp_regs[gp_reg_type_t::a3 as usize];
+
+    if (offset as i64) < 0 {
+        return vfs_ret(Err(libc::EINVAL));
+    }
+    let ret = guest_slice_mut(m, bufptr, count)
+        .and_then(|buf| with_file(m, fd, |f| f.pread(buf, offset).map(|n| n as u64)));
+    vfs_ret(ret)
+}
+
+fn sys_pwrite(m: &mut machine_t) -> u64 {
+    let fd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let bufptr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let count: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+    let offset: u64 = m.state.gp_regs[gp_reg_type_t::a3 as usize];
+
+    if (offset as i64) < 0 {
+        return vfs_ret(Err(libc::EINVAL));
+    }
+    let ret = guest_slice(m, bufptr, count)
+        .and_then(|buf| with_file(m, fd, |f| f.pwrite(buf, offset).map(|n| n as u64)));
+    vfs_ret(ret)
+}
+
+/*
+    vectored I/O goes through the iovecs in order and stops at the first
+    short transfer. an error is only reported if nothing was transferred.
+    offset is None for readv/writev, which use and advance the file offset.
+*/
+fn do_readv(m: &mut machine_t, fd: u64, iovptr: u64, iovcnt: u64, offset: Option<u64>) -> u64 {
+    let ret = guest_iovecs(m, iovptr, iovcnt).and_then(|iovecs| {
+        with_file(m, fd, |f| {
+            let mut done: u64 = 0;
+            for (base, len) in iovecs {
+                // guest_iovecs checked every buffer
+                let buf = unsafe { mmu_t::mmu_slice_mut(base, len) };
+                let n = match offset {
+                    Some(offset) => f.pread(buf, offset + done),
+                    None => f.read(buf),
+                };
+                let n = match n {
+                    Ok(n) => n as u64,
+                    Err(errno) if done == 0 => return Err(errno),
+                    Err(_) => break,
+                };
+                done += n;
+                if n < len {
+                    break;
+                }
+            }
+            Ok(done)
+        })
+    });
+    vfs_ret(ret)
+}
+
+fn do_writev(m: &mut machine_t, fd: u64, iovptr: u64, iovcnt: u64, offset: Option<u64>) -> u64 {
+    let ret = guest_iovecs(m, iovptr, iovcnt).and_then(|iovecs| {
+        with_file(m, fd, |f| {
+            let mut done: u64 = 0;
+            for (base, len) in iovecs {
+                let buf = unsafe { mmu_t::mmu_slice(base, len) };
+                let n = match offset {
+                    Some(offset) => f.pwrite(buf, offset + done),
+                    None => f.write(buf),
+                };
+                let n = match n {
+                    Ok(n) => n as u64,
+                    Err(errno) if done == 0 => return Err(errno),
+                    Err(_) => break,
+                };
+                done += n;
+                if n < len {
+                    break;
+                }
+            }
+            Ok(done)
+        })
+    });
+    vfs_ret(ret)
+}
+
+fn sys_readv(m: &mut machine_t) -> u64 {
+    let fd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let iovptr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let iovcnt: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+
+    do_readv(m, fd, iovptr, iovcnt, None)
+}
+
+fn sys_writev(m: &mut machine_t) -> u64 {
+    let fd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let iovptr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let iovcnt: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+
+    do_writev(m, fd, iovptr, iovcnt, None)
+}
+
+// on RV64 the whole offset is passed in pos_l, pos_h is unused
+fn sys_preadv(m: &mut machine_t) -> u64 {
+    let fd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let iovptr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let iovcnt: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+    let offset: u64 = m.state.gp_regs[gp_reg_type_t::a3 as usize];
+
+    if (offset as i64) < 0 {
+        return vfs_ret(Err(libc::EINVAL));
+    }
+    do_readv(m, fd, iovptr, iovcnt, Some(offset))
+}
+
+fn sys_pwritev(m: &mut machine_t) -> u64 {
+    let fd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let iovptr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let iovcnt: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+    let offset: u64 = m.state.gp_regs[gp_reg_type_t::a3 as usize];
+
+    if (offset as i64) < 0 {
+        return vfs_ret(Err(libc::EINVAL));
+    }
+    do_writev(m, fd, iovptr, iovcnt, Some(offset))
+}
+
+/*
+    sendfile copies through an emulator buffer, the two ends may be
+    any mix of host and virtual files. with offset the input file
+    offset is left alone and *offset is advanced instead.
+*/
+fn sys_sendfile(m: &mut machine_t) -> u64 {
+    let out_fd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let in_fd: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let offptr: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+    let count: u64 = m.state.gp_regs[gp_reg_type_t::a3 as usize];
+
+    let ret = (|| {
+        let out_file = m.fds.get(out_fd)?;
+        let in_file = m.fds.get(in_fd)?;
+        let mut offset = match offptr {
+            0 => None,
+            _ => Some(u64::from_le_bytes(
+                guest_slice(m, offptr, 8)?.try_into().unwrap(),
+            )),
+        };
+        if offset.is_some_and(|offset| (offset as i64) < 0) {
+            return Err(libc::EINVAL);
+        }
+
+        let mut buf = vec![0u8; 64 * 1024];
+        let mut done: u64 = 0;
+        while done < count {
+            let want = buf.len().min((count - done) as usize);
+            let n = {
+                let mut in_file = in_file.borrow_mut();
+                match offset {
+                    Some(offset) => in_file.pread(&mut buf[..want], offset),
+                    None => in_file.read(&mut buf[..want]),
+                }
+            };
+            let n = match n {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(errno) if done == 0 => return Err(errno),
+                Err(_) => break,
+            };
+            let written = match out_file.borrow_mut().write(&buf[..n]) {
+                Ok(written) => written,
+                Err(errno) if done == 0 => return Err(errno),
+                Err(_) => 0,
+            };
+            done += written as u64;
+            if let Some(offset) = offset.as_mut() {
+                *offset += written as u64;
+            } else if written < n {
+                // give back what was read but not written
+                let _ = in_file
+                    .borrow_mut()
+                    .lseek(written as i64 - n as i64, libc::SEEK_CUR);
+            }
+            if written < n {
+                break;
+            }
+        }
+
+        if let Some(offset) = offset {
+            mmu_t::mmu_write(offptr, &offset.to_le_bytes());
+        }
+        Ok(done)
+    })();
+    vfs_ret(ret)
+}
+
+fn sys_getdents(m: &mut machine_t) -> u64 {
+    let fd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let bufptr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let count: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+
+    let ret = guest_slice_mut(m, bufptr, count)
+        .and_then(|buf| with_file(m, fd, |f| f.getdents(buf).map(|n| n as u64)));
+    vfs_ret(ret)
+}
+
+fn sys_fstatat(m: &mut machine_t) -> u64 {
+    let dirfd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let nameptr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let addr: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+    let flags: u64 = m.state.gp_regs[gp_reg_type_t::a3 as usize];
+    let path = match guest_cstr(m, nameptr) {
+        Ok(path) => path,
+        Err(errno) => return vfs_ret(Err(errno)),
+    };
+
+    let st = if is_empty_path(&path, flags as i32) && dirfd as i32 != libc::AT_FDCWD {
+        with_file(m, dirfd, |f| f.fstat())
+    } else {
+        with_dir(m, dirfd, |vfs, dir| vfs.stat(dir, &path, flags as i32))
+    };
+    vfs_ret(st.and_then(|st| write_guest_stat(m, addr, &st)))
+}
+
+fn sys_stat(m: &mut machine_t) -> u64 {
+    let nameptr: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let addr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let path = match guest_cstr(m, nameptr) {
+        Ok(path) => path,
+        Err(errno) => return vfs_ret(Err(errno)),
+    };
+
+    let st = m.vfs.stat(None, &path, 0);
+    vfs_ret(st.and_then(|st| write_guest_stat(m, addr, &st)))
+}
+
+fn sys_lstat(m: &mut machine_t) -> u64 {
+    let nameptr: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let addr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let path = match guest_cstr(m, nameptr) {
+        Ok(path) => path,
+        Err(errno) => return vfs_ret(Err(errno)),
+    };
+
+    let st = m.vfs.stat(None, &path, libc::AT_SYMLINK_NOFOLLOW);
+    vfs_ret(st.and_then(|st| write_guest_stat(m, addr, &st)))
+}
+
+fn sys_faccessat(m: &mut machine_t) -> u64 {
+    let dirfd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let nameptr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let mode: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+    let flags: u64 = m.state.gp_regs[gp_reg_type_t::a3 as usize];
+    let path = match guest_cstr(m, nameptr) {
+        Ok(path) => path,
+        Err(errno) => return vfs_ret(Err(errno)),
+    };
+
+    let ret = with_dir(m, dirfd, |vfs, dir| {
+        vfs.access(dir, &path, mode as i32, flags as i32)
+    });
+    vfs_ret(ret.map(|_| 0))
+}
+
+fn sys_access(m: &mut machine_t) -> u64 {
+    let nameptr: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let mode: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let path = match guest_cstr(m, nameptr) {
+        Ok(path) => path,
+        Err(errno) => return vfs_ret(Err(errno)),
+    };
+
+    vfs_ret(m.vfs.access(None, &path, mode as i32, 0).map(|_| 0))
+}
+
+fn sys_readlinkat(m: &mut machine_t) -> u64 {
+    let dirfd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let nameptr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let bufptr: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+    let bufsz: u64 = m.state.gp_regs[gp_reg_type_t::a3 as usize];
+    let path = match guest_cstr(m, nameptr) {
+        Ok(path) => path,
+        Err(errno) => return vfs_ret(Err(errno)),
+    };
+
+    // the emulator is not what the guest thinks it is running
+    let pid = unsafe { libc::getpid() };
+    let is_exe = guest_path(m, dirfd, &path).is_ok_and(|path| {
+        path == Path::new("/proc/self/exe")
+            || path == Path::new("/proc/thread-self/exe")
+            || path == Path::new(&format!("/proc/{}/exe", pid))
+    });
+    let link = if is_exe {
+        Ok(m.exe_path.as_os_str().as_bytes().to_vec())
+    } else {
+        with_dir(m, dirfd, |vfs, dir| vfs.readlink(dir, &path))
+    };
+    let ret = link.and_then(|link| {
+        if (bufsz as i64) <= 0 {
+            return Err(libc::EINVAL);
+        }
+        let len = link.len().min(bufsz as usize);
+        guest_slice_mut(m, bufptr, len as u64)?.copy_from_slice(&link[..len]);
+        Ok(len as u64)
+    });
+    vfs_ret(ret)
+}
+
+fn sys_mkdirat(m: &mut machine_t) -> u64 {
+    let dirfd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let nameptr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let mode: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+    let path = match guest_cstr(m, nameptr) {
+        Ok(path) => path,
+        Err(errno) => return vfs_ret(Err(errno)),
+    };
+
+    let ret = with_dir(m, dirfd, |vfs, dir| vfs.mkdir(dir, &path, mode as u32));
+    vfs_ret(ret.map(|_| 0))
+}
+
+fn sys_mkdir(m: &mut machine_t) -> u64 {
+    let nameptr: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let mode: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let path = match guest_cstr(m, nameptr) {
+        Ok(path) => path,
+        Err(errno) => return vfs_ret(Err(errno)),
+    };
+
+    vfs_ret(m.vfs.mkdir(None, &path, mode as u32).map(|_| 0))
+}
+
+fn sys_unlinkat(m: &mut machine_t) -> u64 {
+    let dirfd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let nameptr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let flags: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+    let path = match guest_cstr(m, nameptr) {
+        Ok(path) => path,
+        Err(errno) => return vfs_ret(Err(errno)),
+    };
+
+    let ret = with_dir(m, dirfd, |vfs, dir| vfs.unlink(dir, &path, flags as i32));
+    vfs_ret(ret.map(|_| 0))
+}
+
+fn sys_unlink(m: &mut machine_t) -> u64 {
+    let nameptr: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let path = match guest_cstr(m, nameptr) {
+        Ok(path) => path,
+        Err(errno) => return vfs_ret(Err(errno)),
+    };
+
+    vfs_ret(m.vfs.unlink(None, &path, 0).map(|_| 0))
+}
+
+fn sys_renameat(m: &mut machine_t) -> u64 {
+    let olddirfd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let oldptr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let newdirfd: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+    let newptr: u64 = m.state.gp_regs[gp_reg_type_t::a3 as usize];
+    let (oldpath, newpath) = match (guest_cstr(m, oldptr), guest_cstr(m, newptr)) {
+        (Ok(oldpath), Ok(newpath)) => (oldpath, newpath),
+        (Err(errno), _) | (_, Err(errno)) => return vfs_ret(Err(errno)),
+    };
+
+    let ret = with_dirs(m, olddirfd, newdirfd, |vfs, olddir, newdir| {
+        vfs.rename(olddir, &oldpath, newdir, &newpath, 0)
+    });
+    vfs_ret(ret.map(|_| 0))
+}
+
+fn sys_renameat2(m: &mut machine_t) -> u64 {
+    let olddirfd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let oldptr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let newdirfd: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+    let newptr: u64 = m.state.gp_regs[gp_reg_type_t::a3 as usize];
+    let flags: u64 = m.state.gp_regs[gp_reg_type_t::a4 as usize];
+    let (oldpath, newpath) = match (guest_cstr(m, oldptr), guest_cstr(m, newptr)) {
+        (Ok(oldpath), Ok(newpath)) => (oldpath, newpath),
+        (Err(errno), _) | (_, Err(errno)) => return vfs_ret(Err(errno)),
+    };
+
+    let ret = with_dirs(m, olddirfd, newdirfd, |vfs, olddir, newdir| {
+        vfs.rename(olddir, &oldpath, newdir, &newpath, flags as u32)
+    });
+    vfs_ret(ret.map(|_| 0))
+}
+
+fn sys_linkat(m: &mut machine_t) -> u64 {
+    let olddirfd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let oldptr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let newdirfd: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+    let newptr: u64 = m.state.gp_regs[gp_reg_type_t::a3 as usize];
+    let flags: u64 = m.state.gp_regs[gp_reg_type_t::a4 as usize];
+    let (oldpath, newpath) = match (guest_cstr(m, oldptr), guest_cstr(m, newptr)) {
+        (Ok(oldpath), Ok(newpath)) => (oldpath, newpath),
+        (Err(errno), _) | (_, Err(errno)) => return vfs_ret(Err(errno)),
+    };
+
+    if flags as i32 & !(libc::AT_SYMLINK_FOLLOW | libc::AT_EMPTY_PATH) != 0 {
+        return vfs_ret(Err(libc::EINVAL));
+    }
+    let ret = with_dirs(m, olddirfd, newdirfd, |vfs, olddir, newdir| {
+        vfs.link(olddir, &oldpath, newdir, &newpath, flags as i32)
+    });
+    vfs_ret(ret.map(|_| 0))
+}
+
+fn sys_link(m: &mut machine_t) -> u64 {
+    let oldptr: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let newptr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let (oldpath, newpath) = match (guest_cstr(m, oldptr), guest_cstr(m, newptr)) {
+        (Ok(oldpath), Ok(newpath)) => (oldpath, newpath),
+        (Err(errno), _) | (_, Err(errno)) => return vfs_ret(Err(errno)),
+    };
+
+    vfs_ret(m.vfs.link(None, &oldpath, None, &newpath, 0).map(|_| 0))
+}
+
+// the raw syscall returns the length including the NUL
+fn sys_getcwd(m: &mut machine_t) -> u64 {
+    let bufptr: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let size: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+
+    let ret = m.vfs.getcwd().and_then(|mut cwd| {
+        cwd.push(0);
+        if cwd.len() as u64 > size {
+            return Err(libc::ERANGE);
+        }
+        guest_slice_mut(m, bufptr, cwd.len() as u64)?.copy_from_slice(&cwd);
+        Ok(cwd.len() as u64)
+    });
+    vfs_ret(ret)
+}
+
+fn sys_ftruncate(m: &mut machine_t) -> u64 {
+    let fd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let len: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+
+    if (len as i64) < 0 {
+        return vfs_ret(Err(libc::EINVAL));
+    }
+    vfs_ret(with_file(m, fd, |f| f.truncate(len)).map(|_| 0))
+}
+
+/*
+    terminal ioctls, done on the host descriptor behind a guest fd.
+    the guest passes the kernel's struct termios, while the host side goes
+    through libc's, which has a different c_cc layout and extra fields.
+    the flag bits are the asm-generic ones on both ends.
+*/
+pub const TCGETS: u64 = 0x5401;
+pub const TCSETS: u64 = 0x5402;
+pub const TCSETSW: u64 = 0x5403;
+pub const TCSETSF: u64 = 0x5404;
+pub const TIOCGPGRP: u64 = 0x540f;
+pub const TIOCGWINSZ: u64 = 0x5413;
+pub const TIOCSWINSZ: u64 = 0x5414;
+pub const FIONREAD: u64 = 0x541b;
+
+const GUEST_NCCS: usize = 19;
+
+// guest c_cc index -> host c_cc index
+const GUEST_CC: [(usize, usize); 16] = [
+    (0, libc::VINTR),
+    (1, libc::VQUIT),
+    (2, libc::VERASE),
+    (3, libc::VKILL),
+    (4, libc::VEOF),
+    (5, libc::VTIME),
+    (6, libc::VMIN),
+    (8, libc::VSTART),
+    (9, libc::VSTOP),
+    (10, libc::VSUSP),
+    (11, libc::VEOL),
+    (12, libc::VREPRINT),
+    (13, libc::VDISCARD),
+    (14, libc::VWERASE),
+    (15, libc::VLNEXT),
+    (16, libc::VEOL2),
+];
+
+// struct termios of the RISC-V kernel ABI
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct guest_termios_t {
+    c_iflag: u32,
+    c_oflag: u32,
+    c_cflag: u32,
+    c_lflag: u32,
+    c_line: u8,
+    c_cc: [u8; GUEST_NCCS],
+}
+
+fn termios_to_guest(host: &libc::termios) -> guest_termios_t {
+    let mut guest = guest_termios_t {
+        c_iflag: host.c_iflag,
+        c_oflag: host.c_oflag,
+        c_cflag: host.c_cflag,
+        c_lflag: host.c_lflag,
+        c_line: host.c_line,
+        c_cc: [0; GUEST_NCCS],
+    };
+    for (guest_idx, host_idx) in GUEST_CC {
+        guest.c_cc[guest_idx] = host.c_cc[host_idx];
+    }
+    guest
+}
+
+fn termios_to_host(guest: &guest_termios_t, host: &mut libc::termios) {
+    host.c_iflag = guest.c_iflag;
+    host.c_oflag = guest.c_oflag;
+    host.c_cflag = guest.c_cflag;
+    host.c_lflag = guest.c_lflag;
+    host.c_line = guest.c_line;
+    for (guest_idx, host_idx) in GUEST_CC {
+        host.c_cc[host_idx] = guest.c_cc[guest_idx];
+    }
+}
+
+fn do_ioctl(m: &mut machine_t, fd: u64, request: u64, arg: u64) -> Result<u64, i32> {
+    let file = m.fds.get(fd)?;
+    let mut file = file.borrow_mut();
+
+    // regular virtual files still answer FIONREAD
+    let Some(host_fd) = file.host_fd() else {
+        if request != FIONREAD {
+            return Err(libc::ENOTTY);
+        }
+        let st = file.fstat()?;
+        if st.st_mode & libc::S_IFMT != libc::S_IFREG {
+            return Err(libc::ENOTTY);
+        }
+        let pos = file.lseek(0, libc::SEEK_CUR)?;
+        let avail = (st.st_size as u64).saturating_sub(pos) as i32;
+        guest_slice_mut(m, arg, 4)?.copy_from_slice(&avail.to_le_bytes());
+        return Ok(0);
+    };
+
+    let host_ioctl = |req: libc::c_ulong, ptr: *mut libc::c_void| -> Result<u64, i32> {
+        if unsafe { libc::ioctl(host_fd, req, ptr) } < 0 {
+            Err(std::io::Error::last_os_error().raw_os_error().unwrap())
+        } else {
+            Ok(0)
+        }
+    };
+
+    match request {
+        TCGETS => {
+            let buf = guest_slice_mut(m, arg, mem::size_of::<guest_termios_t>() as u64)?;
+            let mut host: libc::termios = unsafe { mem::zeroed() };
+            if unsafe { libc::tcgetattr(host_fd, &mut host) } < 0 {
+                return Err(std::io::Error::last_os_error().raw_os_error().unwrap());
+            }
+            let guest = termios_to_guest(&host);
+            unsafe { std::ptr::write_unaligned(buf.as_mut_ptr() as *mut guest_termios_t, guest) };
+            Ok(0)
+        }
+        TCSETS | TCSETSW | TCSETSF => {
+            let buf = guest_slice(m, arg, mem::size_of::<guest_termios_t>() as u64)?;
+            let guest = unsafe { std::ptr::read_unaligned(buf.as_ptr() as *const guest_termios_t) };
+            let mut host: libc::termios = unsafe { mem::zeroed() };
+            if unsafe { libc::tcgetattr(host_fd, &mut host) } < 0 {
+                return Err(std::io::Error::last_os_error().raw_os_error().unwrap());
+            }
+            termios_to_host(&guest, &mut host);
+            let action = match request {
+                TCSETS => libc::TCSANOW,
+                TCSETSW => libc::TCSADRAIN,
+                _ => libc::TCSAFLUSH,
+            };
+            if unsafe { libc::tcsetattr(host_fd, action, &host) } < 0 {
+                return Err(std::io::Error::last_os_error().raw_os_error().unwrap());
+            }
+            Ok(0)
+        }
+        // struct winsize and int are the same on both ends
+        TIOCGWINSZ => {
+            let buf = guest_slice_mut(m, arg, mem::size_of::<libc::winsize>() as u64)?;
+            host_ioctl(libc::TIOCGWINSZ, buf.as_mut_ptr() as *mut libc::c_void)
+        }
+        TIOCSWINSZ => {
+            let buf = guest_slice_mut(m, arg, mem::size_of::<libc::winsize>() as u64)?;
+            host_ioctl(libc::TIOCSWINSZ, buf.as_mut_ptr() as *mut libc::c_void)
+        }
+        TIOCGPGRP => {
+            let buf = guest_slice_mut(m, arg, 4)?;
+            host_ioctl(libc::TIOCGPGRP, buf.as_mut_ptr() as *mut libc::c_void)
+        }
+        FIONREAD => {
+            let buf = guest_slice_mut(m, arg, 4)?;
+            host_ioctl(libc::FIONREAD, buf.as_mut_ptr() as *mut libc::c_void)
+        }
+        _ => Err(libc::ENOTTY),
+    }
+}
+
+fn sys_ioctl(m: &mut machine_t) -> u64 {
+    let fd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let request: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let arg: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+
+    vfs_ret(do_ioctl(m, fd, request as u32 as u64, arg))
+}
+
+const GRND_NONBLOCK: u64 = 1;
+const GRND_RANDOM: u64 = 2;
+const GRND_INSECURE: u64 = 4;
+
+// the most Linux hands out in one call
+const GETRANDOM_MAX: u64 = 0x1ff_ffff;
+
+fn sys_getrandom(m: &mut machine_t) -> u64 {
+    let bufptr: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let len: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let flags: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+
+    if flags & !(GRND_NONBLOCK | GRND_RANDOM | GRND_INSECURE) != 0
+        || flags & (GRND_RANDOM | GRND_INSECURE) == GRND_RANDOM | GRND_INSECURE
+    {
+        return vfs_ret(Err(libc::EINVAL));
+    }
+    let len = len.min(GETRANDOM_MAX);
+    let ret = guest_slice_mut(m, bufptr, len)
+        .and_then(|buf| m.random.borrow_mut().fill(buf).map(|n| n as u64));
+    vfs_ret(ret)
+}
+
+/*
+    pipes, eventfds and epoll instances are host kernel objects, so
+    blocking, wakeups and execve inheritance behave as on Linux. in-memory
+    files are regular files: always ready for poll and select, refused by
+    epoll_ctl with EPERM.
+*/
+fn sys_pipe2(m: &mut machine_t) -> u64 {
+    let fdsptr: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let flags: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+
+    let flags = m.personality.open_flags_to_host(flags as i32);
+    if flags & !(O_CLOEXEC | libc::O_NONBLOCK | libc::O_DIRECT) != 0 {
+        return vfs_ret(Err(libc::EINVAL));
+    }
+    let ret = guest_slice_mut(m, fdsptr, 8).and_then(|buf| {
+        let mut host = [0; 2];
+        if unsafe { libc::pipe2(host.as_mut_ptr(), flags | O_CLOEXEC) } < 0 {
+            return Err(std::io::Error::last_os_error().raw_os_error().unwrap());
+        }
+        let cloexec = flags & O_CLOEXEC != 0;
+        let (read_end, write_end) = (host_file_t::new(host[0]), host_file_t::new(host[1]));
+        let read_fd = m.fds.alloc(Box::new(read_end), cloexec)?;
+        let write_fd = match m.fds.alloc(Box::new(write_end), cloexec) {
+            Ok(fd) => fd,
+            Err(errno) => {
+                m.fds.close(read_fd)?;
+                return Err(errno);
+            }
+        };
+        buf[..4].copy_from_slice(&(read_fd as i32).to_le_bytes());
+        buf[4..].copy_from_slice(&(write_fd as i32).to_le_bytes());
+        Ok(0)
+    });
+    vfs_ret(ret)
+}
+
+fn sys_eventfd2(m: &mut machine_t) -> u64 {
+    let initval: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let flags: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+
+    // EFD_CLOEXEC and EFD_NONBLOCK are the O_* bits, EFD_SEMAPHORE is 1
+    let flags = m.personality.open_flags_to_host(flags as i32);
+    if flags & !(O_CLOEXEC | libc::O_NONBLOCK | libc::EFD_SEMAPHORE) != 0 {
+        return vfs_ret(Err(libc::EINVAL));
+    }
+    let fd = unsafe { libc::eventfd(initval as u32, flags | O_CLOEXEC) };
+    if fd < 0 {
+        return syscall_ret(fd as i64);
+    }
+    vfs_ret(
+        m.fds
+            .alloc(Box::new(host_file_t::new(fd)), flags & O_CLOEXEC != 0),
+    )
+}
+
+// poll bits are the asm-generic ones everywhere
+const POLL_READY: i16 = libc::POLLIN | libc::POLLOUT | libc::POLLRDNORM | libc::POLLWRNORM;
+
+/*
+    poll over guest fds, pollfds[].fd holds guest fds. the host descriptors
+    are waited on with ppoll, a timeout is in ns and None blocks.
+*/
+fn do_poll(
+    m: &mut machine_t,
+    pollfds: &mut [libc::pollfd],
+    timeout: Option<u64>,
+) -> Result<u64, i32> {
+    let mut host: Vec<(usize, libc::pollfd)> = Vec::new();
+    let mut ready = false;
+    for (i, pollfd) in pollfds.iter_mut().enumerate() {
+        pollfd.revents = 0;
+        if pollfd.fd < 0 {
+            continue;
+        }
+        match m.fds.get(pollfd.fd as u64) {
+            Err(_) => pollfd.revents = libc::POLLNVAL,
+            Ok(file) => match file.borrow().host_fd() {
+                Some(fd) => host.push((
+                    i,
+                    libc::pollfd {
+                        fd,
+                        events: pollfd.events,
+                        revents: 0,
+                    },
+                )),
+                None => pollfd.revents = pollfd.events & POLL_READY,
+            },
+        }
+        ready |= pollfd.revents != 0;
+    }
+    let timeout = if ready { Some(0) } else { timeout };
+
+    // nothing outside the guest can wake it, so only time passes
+    if host.is_empty()
+        && m.state.clock.is_virtual()
+        && let Some(ns) = timeout
+    {
+        m.state.clock.waited(ns);
+        return Ok(pollfds.iter().filter(|pollfd| pollfd.revents != 0).count() as u64);
+    }
+
+    let mut host_pollfds: Vec<libc::pollfd> = host.iter().map(|&(_, pollfd)| pollfd).collect();
+    let ts = timeout.map(|ns| libc::timespec {
+        tv_sec: (ns / 1_000_000_000) as i64,
+        tv_nsec: (ns % 1_000_000_000) as i64,
+    });
+    let ret = unsafe {
+        libc::ppoll(
+            host_pollfds.as_mut_ptr(),
+            host_pollfds.len() as libc::nfds_t,
+            ts.as_ref().map_or(std::ptr::null(), |ts| ts),
+            std::ptr::null(),
+        )
+    };
+    if ret < 0 {
+        return Err(std::io::Error::last_os_error().raw_os_error().unwrap());
+    }
+    if ret == 0 && !ready {
+        m.state.clock.waited(timeout.unwrap_or(0));
+    }
+    for (&(i, _), pollfd) in host.iter().zip(&host_pollfds) {
+        pollfds[i].revents = pollfd.revents;
+    }
+    Ok(pollfds.iter().filter(|pollfd| pollfd.revents != 0).count() as u64)
+}
+
+// an optional struct timespec as a do_poll timeout
+fn read_timeout(m: &machine_t, addr: u64) -> Result<Option<u64>, i32> {
+    if addr == 0 {
+        return Ok(None);
+    }
+    let (sec, nsec) = read_timespec(m, addr)?;
+    Ok(Some(timespec_ns(sec, nsec)))
+}
+
+fn sys_ppoll(m: &mut machine_t) -> u64 {
+    let fdsptr: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let nfds: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let tsptr: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+
+    // struct pollfd is the same on both ends, the sigmask is ignored
+    let size = mem::size_of::<libc::pollfd>() as u64;
+    if nfds > m.fds.limit {
+        return vfs_ret(Err(libc::EINVAL));
+    }
+    let ret = read_timeout(m, tsptr).and_then(|timeout| {
+        let buf = guest_slice_mut(m, fdsptr, nfds * size)?;
+        let mut pollfds: Vec<libc::pollfd> = buf
+            .chunks(size as usize)
+            .map(|raw| unsafe { std::ptr::read_unaligned(raw.as_ptr() as *const libc::pollfd) })
+            .collect();
+        let ready = do_poll(m, &mut pollfds, timeout)?;
+        for (raw, pollfd) in buf.chunks_mut(size as usize).zip(&pollfds) {
+            unsafe { std::ptr::write_unaligned(raw.as_mut_ptr() as *mut libc::pollfd, *pollfd) };
+        }
+        Ok(ready)
+    });
+    vfs_ret(ret)
+}
+
+// fd_set is an array of unsigned long, fd n is bit n % 64 of word n / 64
+fn fd_set_get(set: &[u8], fd: usize) -> bool {
+    set[fd / 8] & (1 << (fd % 8)) != 0
+}
+
+fn fd_set_put(set: &mut [u8], fd: usize, on: bool) {
+    if on {
+        set[fd / 8] |= 1 << (fd % 8);
+    } else {
+        set[fd / 8] &= !(1 << (fd % 8));
+    }
+}
+
+fn sys_pselect6(m: &mut machine_t) -> u64 {
+    let nfds: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let readptr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let writeptr: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+    let exceptptr: u64 = m.state.gp_regs[gp_reg_type_t::a3 as usize];
+    let tsptr: u64 = m.state.gp_regs[gp_reg_type_t::a4 as usize];
+
+    let Ok(nfds) = usize::try_from(nfds as i32) else {
+        return vfs_ret(Err(libc::EINVAL));
+    };
+    let nfds = nfds.min(m.fds.limit as usize);
+    let len = nfds.div_ceil(64) as u64 * 8;
+    let ret = read_timeout(m, tsptr).and_then(|timeout| {
+        // (set, events that poll checks, revents that mark the fd)
+        let mut sets = Vec::new();
+        for (addr, events, revents) in [
+            (
+                readptr,
+                libc::POLLIN,
+                libc::POLLIN | libc::POLLRDNORM | libc::POLLHUP | libc::POLLERR,
+            ),
+            (
+                writeptr,
+                libc::POLLOUT,
+                libc::POLLOUT | libc::POLLWRNORM | libc::POLLERR,
+            ),
+            (exceptptr, libc::POLLPRI, libc::POLLPRI),
+        ] {
+            if addr != 0 {
+                sets.push((guest_slice_mut(m, addr, len)?, events, revents));
+            }
+        }
+
+        let mut pollfds = Vec::new();
+        for fd in 0..nfds {
+            let events = sets
+                .iter()
+                .filter(|(set, _, _)| fd_set_get(set, fd))
+                .fold(0, |acc, &(_, events, _)| acc | events);
+            if events != 0 {
+                pollfds.push(libc::pollfd {
+                    fd: fd as i32,
+                    events,
+                    revents: 0,
+                });
+            }
+        }
+        do_poll(m, &mut pollfds, timeout)?;
+        if pollfds
+            .iter()
+            .any(|pollfd| pollfd.revents & libc::POLLNVAL != 0)
+        {
+            return Err(libc::EBADF);
+        }
+
+        let mut ready = 0;
+        for (set, _, revents) in sets.iter_mut() {
+            set.fill(0);
+            for pollfd in &pollfds {
+                if pollfd.revents & *revents != 0 {
+                    fd_set_put(set, pollfd.fd as usize, true);
+                    ready += 1;
+                }
+            }
+        }
+        Ok(ready)
+    });
+    vfs_ret(ret)
+}
+
+/*
+    struct epoll_event is only packed on x86_64, the RISC-V one has
+    natural alignment and is 16 bytes.
+*/
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct guest_epoll_event_t {
+    events: u32,
+    data: u64,
+}
+
+fn host_fd_of(m: &machine_t, fd: u64, errno: i32) -> Result<i32, i32> {
+    with_file(m, fd, |file| file.host_fd().ok_or(errno))
+}
+
+fn sys_epoll_create1(m: &mut machine_t) -> u64 {
+    let flags: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+
+    let flags = m.personality.open_flags_to_host(flags as i32);
+    if flags & !O_CLOEXEC != 0 {
+        return vfs_ret(Err(libc::EINVAL));
+    }
+    let fd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+    if fd < 0 {
+        return syscall_ret(fd as i64);
+    }
+    vfs_ret(
+        m.fds
+            .alloc(Box::new(host_file_t::new(fd)), flags & O_CLOEXEC != 0),
+    )
+}
+
+fn sys_epoll_ctl(m: &mut machine_t) -> u64 {
+    let epfd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let op: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let fd: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+    let eventptr: u64 = m.state.gp_regs[gp_reg_type_t::a3 as usize];
+
+    let ret = (|| {
+        let host_epfd = host_fd_of(m, epfd, libc::EINVAL)?;
+        let host_fd = host_fd_of(m, fd, libc::EPERM)?;
+        // the guest's data is handed back untouched by epoll_pwait
+        let mut event = libc::epoll_event { events: 0, u64: 0 };
+        if op as i32 != libc::EPOLL_CTL_DEL {
+            let raw = guest_slice(m, eventptr, mem::size_of::<guest_epoll_event_t>() as u64)?;
+            let guest =
+                unsafe { std::ptr::read_unaligned(raw.as_ptr() as *const guest_epoll_event_t) };
+            event = libc::epoll_event {
+                events: guest.events,
+                u64: guest.data,
+            };
+        }
+        let ret = unsafe { libc::epoll_ctl(host_epfd, op as i32, host_fd, &mut event) };
+        if ret < 0 {
+            return Err(std::io::Error::last_os_error().raw_os_error().unwrap());
+        }
+        Ok(0)
+    })();
+    vfs_ret(ret)
+}
+
+fn sys_epoll_pwait(m: &mut machine_t) -> u64 {
+    let epfd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let eventsptr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let maxevents: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+    let timeout: u64 = m.state.gp_regs[gp_reg_type_t::a3 as usize];
+
+    let (maxevents, timeout) = (maxevents as i32, timeout as i32);
+    if maxevents <= 0 {
+        return vfs_ret(Err(libc::EINVAL));
+    }
+    let size = mem::size_of::<guest_epoll_event_t>();
+    let ret = host_fd_of(m, epfd, libc::EINVAL).and_then(|host_epfd| {
+        let buf = guest_slice_mut(m, eventsptr, maxevents as u64 * size as u64)?;
+        let mut events = vec![libc::epoll_event { events: 0, u64: 0 }; maxevents as usize];
+        let ret = unsafe { libc::epoll_wait(host_epfd, events.as_mut_ptr(), maxevents, timeout) };
+        if ret < 0 {
+            return Err(std::io::Error::last_os_error().raw_os_error().unwrap());
+        }
+        if ret == 0 && timeout > 0 {
+            m.state.clock.waited(timeout as u64 * 1_000_000);
+        }
+        for (raw, event) in buf.chunks_mut(size).zip(&events[..ret as usize]) {
+            let guest = guest_epoll_event_t {
+                events: event.events,
+                data: event.u64,
+            };
+            unsafe {
+                std::ptr::write_unaligned(raw.as_mut_ptr() as *mut guest_epoll_event_t, guest)
+            };
+        }
+        Ok(ret as u64)
+    });
+    vfs_ret(ret)
+}
+
+/*
+    sockets are host sockets. struct sockaddr, the socket options and the
+    MSG_* flags are the same for every Linux, only Unix domain socket
+    paths go through the vfs and fds passed with SCM_RIGHTS are mapped
+    between the guest and host tables.
+*/
+const SOCKADDR_MAX: u64 = mem::size_of::<libc::sockaddr_storage>() as u64;
+const SUN_PATH_MAX: usize = 108;
+
+// a guest struct sockaddr as the host sees it
+fn read_sockaddr(m: &mut machine_t, addr: u64, len: u64) -> Result<Vec<u8>, i32> {
+    if len > SOCKADDR_MAX {
+        return Err(libc::EINVAL);
+    }
+    let mut sockaddr = guest_slice(m, addr, len)?.to_vec();
+    if sockaddr.len() <= 2
+        || u16::from_le_bytes([sockaddr[0], sockaddr[1]]) != libc::AF_UNIX as u16
+        || sockaddr[2] == 0
+    {
+        return Ok(sockaddr); // also unnamed and abstract Unix sockets
+    }
+    let path = &sockaddr[2..];
+    let path = &path[..path.iter().position(|&b| b == 0).unwrap_or(path.len())];
+    let sun_path = m.vfs.socket_path(&CString::new(path).unwrap())?;
+    sockaddr.truncate(2);
+    sockaddr.extend_from_slice(&sun_path);
+    if sun_path.first() != Some(&0) {
+        sockaddr.push(0);
+    }
+    if sockaddr.len() > 2 + SUN_PATH_MAX {
+        return Err(libc::ENAMETOOLONG);
+    }
+    Ok(sockaddr)
+}
+
+// store a host struct sockaddr truncated to cap bytes, returns its full length
+fn write_sockaddr(m: &machine_t, sockaddr: &[u8], addr: u64, cap: u32) -> Result<u32, i32> {
+    let mut sockaddr = sockaddr.to_vec();
+    if sockaddr.len() > 2 && u16::from_le_bytes([sockaddr[0], sockaddr[1]]) == libc::AF_UNIX as u16
+    {
+        let sun_path = &sockaddr[2..];
+        let sun_path = match sun_path.first() {
+            Some(0) => sun_path,
+            _ => {
+                &sun_path[..sun_path
+                    .iter()
+                    .position(|&b| b == 0)
+                    .unwrap_or(sun_path.len())]
+            }
+        };
+        let mut path = m.vfs.socket_guest_path(sun_path);
+        if path.first() != Some(&0) {
+            path.push(0);
+        }
+        sockaddr.truncate(2);
+        sockaddr.extend_from_slice(&path);
+    }
+    let n = sockaddr.len().min(cap as usize);
+    guest_slice_mut(m, addr, n as u64)?.copy_from_slice(&sockaddr[..n]);
+    Ok(sockaddr.len() as u32)
+}
+
+// write_sockaddr for the calls returning the length through a socklen_t *
+fn write_sockaddr_len(m: &machine_t, sockaddr: &[u8], addr: u64, lenptr: u64) -> Result<(), i32> {
+    if addr == 0 {
+        return Ok(());
+    }
+    let lenbuf = guest_slice_mut(m, lenptr, 4)?;
+    let cap = u32::from_le_bytes(lenbuf[..4].try_into().unwrap());
+    if cap as i32 >= 0 {
+        let len = write_sockaddr(m, sockaddr, addr, cap)?;
+        lenbuf.copy_from_slice(&len.to_le_bytes());
+        Ok(())
+    } else {
+        Err(libc::EINVAL)
+    }
+}
+
+fn sockaddr_ptr(sockaddr: &[u8]) -> *const libc::sockaddr {
+    if sockaddr.is_empty() {
+        std::ptr::null()
+    } else {
+        sockaddr.as_ptr() as *const libc::sockaddr
+    }
+}
+
+// SOCK_NONBLOCK and SOCK_CLOEXEC as host bits and whether the guest fd is CLOEXEC
+fn socket_flags(m: &machine_t, flags: i32) -> Result<(i32, bool), i32> {
+    let flags = m.personality.open_flags_to_host(flags);
+    if flags & !(O_CLOEXEC | libc::O_NONBLOCK) != 0 {
+        return Err(libc::EINVAL);
+    }
+    Ok((flags & libc::O_NONBLOCK, flags & O_CLOEXEC != 0))
+}
+
+fn sys_socket(m: &mut machine_t) -> u64 {
+    let domain: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let type_: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let protocol: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+
+    let ret = socket_flags(m, type_ as i32 & !0xf).and_then(|(nonblock, cloexec)| {
+        let fd = unsafe {
+            libc::socket(
+                domain as i32,
+                type_ as i32 & 0xf | nonblock | libc::SOCK_CLOEXEC,
+                protocol as i32,
+            )
+        };
+        host_ret(fd as i64)?;
+        m.fds.alloc(Box::new(host_file_t::new(fd)), cloexec)
+    });
+    vfs_ret(ret)
+}
+
+fn sys_socketpair(m: &mut machine_t) -> u64 {
+    let domain: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let type_: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let protocol: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+    let svptr: u64 = m.state.gp_regs[gp_reg_type_t::a3 as usize];
+
+    let ret = socket_flags(m, type_ as i32 & !0xf).and_then(|(nonblock, cloexec)| {
+        let buf = guest_slice_mut(m, svptr, 8)?;
+        let mut host = [0; 2];
+        host_ret(unsafe {
+            libc::socketpair(
+                domain as i32,
+                type_ as i32 & 0xf | nonblock | libc::SOCK_CLOEXEC,
+                protocol as i32,
+                host.as_mut_ptr(),
+            )
+        } as i64)?;
+        let (first, second) = (host_file_t::new(host[0]), host_file_t::new(host[1]));
+        let first_fd = m.fds.alloc(Box::new(first), cloexec)?;
+        let second_fd = match m.fds.alloc(Box::new(second), cloexec) {
+            Ok(fd) => fd,
+            Err(errno) => {
+                m.fds.close(first_fd)?;
+                return Err(errno);
+            }
+        };
+        buf[..4].copy_from_slice(&(first_fd as i32).to_le_bytes());
+        buf[4..].copy_from_slice(&(second_fd as i32).to_le_bytes());
+        Ok(0)
+    });
+    vfs_ret(ret)
+}
+
+fn sys_bind(m: &mut machine_t) -> u64 {
+    let fd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let addr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let len: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+
+    let ret = host_fd_of(m, fd, libc::ENOTSOCK).and_then(|host_fd| {
+        let sockaddr = read_sockaddr(m, addr, len as u32 as u64)?;
+        host_ret(unsafe {
+            libc::bind(
+                host_fd,
+                sockaddr_ptr(&sockaddr),
+                sockaddr.len() as libc::socklen_t,
+            )
+        } as i64)
+    });
+    vfs_ret(ret)
+}
+
+fn sys_connect(m: &mut machine_t) -> u64 {
+    let fd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let addr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let len: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+
+    let ret = host_fd_of(m, fd, libc::ENOTSOCK).and_then(|host_fd| {
+        let sockaddr = read_sockaddr(m, addr, len as u32 as u64)?;
+        host_ret(unsafe {
+            libc::connect(
+                host_fd,
+                sockaddr_ptr(&sockaddr),
+                sockaddr.len() as libc::socklen_t,
+            )
+        } as i64)
+    });
+    vfs_ret(ret)
+}
+
+fn sys_listen(m: &mut machine_t) -> u64 {
+    let fd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let backlog: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+
+    let ret = host_fd_of(m, fd, libc::ENOTSOCK)
+        .and_then(|host_fd| host_ret(unsafe { libc::listen(host_fd, backlog as i32) } as i64));
+    vfs_ret(ret)
+}
+
+fn do_accept(m: &mut machine_t, fd: u64, addr: u64, lenptr: u64, flags: i32) -> Result<u64, i32> {
+    let host_fd = host_fd_of(m, fd, libc::ENOTSOCK)?;
+    let (nonblock, cloexec) = socket_flags(m, flags)?;
+    let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut len = SOCKADDR_MAX as libc::socklen_t;
+    let conn = unsafe {
+        libc::accept4(
+            host_fd,
+            &mut storage as *mut _ as *mut libc::sockaddr,
+            &mut len,
+            nonblock | libc::SOCK_CLOEXEC,
         )
-    } as u64;
+    };
+    host_ret(conn as i64)?;
+    let conn = host_file_t::new(conn);
+    let sockaddr =
+        unsafe { std::slice::from_raw_parts(&storage as *const _ as *const u8, len as usize) };
+    write_sockaddr_len(m, sockaddr, addr, lenptr)?;
+    m.fds.alloc(Box::new(conn), cloexec)
+}
+
+fn sys_accept(m: &mut machine_t) -> u64 {
+    let fd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let addr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let lenptr: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+
+    vfs_ret(do_accept(m, fd, addr, lenptr, 0))
+}
+
+fn sys_accept4(m: &mut machine_t) -> u64 {
+    let fd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let addr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let lenptr: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+    let flags: u64 = m.state.gp_regs[gp_reg_type_t::a3 as usize];
+
+    vfs_ret(do_accept(m, fd, addr, lenptr, flags as i32))
+}
+
+type sockname_fn_t = unsafe extern "C" fn(i32, *mut libc::sockaddr, *mut libc::socklen_t) -> i32;
+
+fn do_getname(m: &mut machine_t, getname: sockname_fn_t) -> u64 {
+    let fd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let addr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let lenptr: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+
+    let ret = host_fd_of(m, fd, libc::ENOTSOCK).and_then(|host_fd| {
+        let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        let mut len = SOCKADDR_MAX as libc::socklen_t;
+        host_ret(unsafe {
+            getname(
+                host_fd,
+                &mut storage as *mut _ as *mut libc::sockaddr,
+                &mut len,
+            )
+        } as i64)?;
+        let sockaddr =
+            unsafe { std::slice::from_raw_parts(&storage as *const _ as *const u8, len as usize) };
+        write_sockaddr_len(m, sockaddr, addr, lenptr).map(|_| 0)
+    });
+    vfs_ret(ret)
+}
+
+fn sys_getsockname(m: &mut machine_t) -> u64 {
+    do_getname(m, libc::getsockname)
+}
+
+fn sys_getpeername(m: &mut machine_t) -> u64 {
+    do_getname(m, libc::getpeername)
+}
+
+fn sys_sendto(m: &mut machine_t) -> u64 {
+    let fd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let bufptr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let len: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+    let flags: u64 = m.state.gp_regs[gp_reg_type_t::a3 as usize];
+    let addr: u64 = m.state.gp_regs[gp_reg_type_t::a4 as usize];
+    let addrlen: u64 = m.state.gp_regs[gp_reg_type_t::a5 as usize];
+
+    let ret = host_fd_of(m, fd, libc::ENOTSOCK).and_then(|host_fd| {
+        let buf = guest_slice(m, bufptr, len)?;
+        let sockaddr = match addr {
+            0 => Vec::new(),
+            _ => read_sockaddr(m, addr, addrlen as u32 as u64)?,
+        };
+        host_ret(unsafe {
+            libc::sendto(
+                host_fd,
+                buf.as_ptr() as *const libc::c_void,
+                buf.len(),
+                flags as i32,
+                sockaddr_ptr(&sockaddr),
+                sockaddr.len() as libc::socklen_t,
+            )
+        } as i64)
+    });
+    vfs_ret(ret)
+}
+
+fn sys_recvfrom(m: &mut machine_t) -> u64 {
+    let fd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let bufptr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let len: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+    let flags: u64 = m.state.gp_regs[gp_reg_type_t::a3 as usize];
+    let addr: u64 = m.state.gp_regs[gp_reg_type_t::a4 as usize];
+    let lenptr: u64 = m.state.gp_regs[gp_reg_type_t::a5 as usize];
+
+    let ret = host_fd_of(m, fd, libc::ENOTSOCK).and_then(|host_fd| {
+        let buf = guest_slice_mut(m, bufptr, len)?;
+        let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        let mut socklen = SOCKADDR_MAX as libc::socklen_t;
+        let n = host_ret(unsafe {
+            libc::recvfrom(
+                host_fd,
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+                flags as i32,
+                &mut storage as *mut _ as *mut libc::sockaddr,
+                &mut socklen,
+            )
+        } as i64)?;
+        // connected sockets leave the address alone
+        let sockaddr = unsafe {
+            std::slice::from_raw_parts(&storage as *const _ as *const u8, socklen as usize)
+        };
+        write_sockaddr_len(m, sockaddr, addr, lenptr)?;
+        Ok(n)
+    });
+    vfs_ret(ret)
+}
+
+fn sys_setsockopt(m: &mut machine_t) -> u64 {
+    let fd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let level: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let name: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+    let optptr: u64 = m.state.gp_regs[gp_reg_type_t::a3 as usize];
+    let optlen: u64 = m.state.gp_regs[gp_reg_type_t::a4 as usize];
+
+    let ret = host_fd_of(m, fd, libc::ENOTSOCK).and_then(|host_fd| {
+        let opt = guest_slice(m, optptr, optlen as u32 as u64)?;
+        host_ret(unsafe {
+            libc::setsockopt(
+                host_fd,
+                level as i32,
+                name as i32,
+                opt.as_ptr() as *const libc::c_void,
+                opt.len() as libc::socklen_t,
+            )
+        } as i64)
+    });
+    vfs_ret(ret)
+}
+
+fn sys_getsockopt(m: &mut machine_t) -> u64 {
+    let fd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let level: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let name: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+    let optptr: u64 = m.state.gp_regs[gp_reg_type_t::a3 as usize];
+    let lenptr: u64 = m.state.gp_regs[gp_reg_type_t::a4 as usize];
+
+    let ret = host_fd_of(m, fd, libc::ENOTSOCK).and_then(|host_fd| {
+        let lenbuf = guest_slice_mut(m, lenptr, 4)?;
+        let mut len = u32::from_le_bytes(lenbuf[..4].try_into().unwrap());
+        if (len as i32) < 0 {
+            return Err(libc::EINVAL);
+        }
+        let opt = guest_slice_mut(m, optptr, len as u64)?;
+        host_ret(unsafe {
+            libc::getsockopt(
+                host_fd,
+                level as i32,
+                name as i32,
+                opt.as_mut_ptr() as *mut libc::c_void,
+                &mut len,
+            )
+        } as i64)?;
+        lenbuf.copy_from_slice(&len.to_le_bytes());
+        Ok(0)
+    });
+    vfs_ret(ret)
+}
+
+fn sys_shutdown(m: &mut machine_t) -> u64 {
+    let fd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let how: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+
+    let ret = host_fd_of(m, fd, libc::ENOTSOCK)
+        .and_then(|host_fd| host_ret(unsafe { libc::shutdown(host_fd, how as i32) } as i64));
+    vfs_ret(ret)
+}
+
+/*
+    struct msghdr and struct cmsghdr have the same layout on every 64-bit
+    Linux, so the guest's control data is used in place once the
+    SCM_RIGHTS fds in it are swapped.
+*/
+const MSGHDR_SIZE: u64 = 56;
+const CMSGHDR_SIZE: usize = 16;
+
+// (offset of the data, data length) of every SCM_RIGHTS message in control
+fn scm_rights(control: &[u8]) -> Vec<(usize, usize)> {
+    let mut rights = Vec::new();
+    let mut off = 0;
+    while off + CMSGHDR_SIZE <= control.len() {
+        let len = u64::from_le_bytes(control[off..off + 8].try_into().unwrap()) as usize;
+        let level = i32::from_le_bytes(control[off + 8..off + 12].try_into().unwrap());
+        let type_ = i32::from_le_bytes(control[off + 12..off + 16].try_into().unwrap());
+        if len < CMSGHDR_SIZE || off + len > control.len() {
+            break;
+        }
+        if level == libc::SOL_SOCKET && type_ == libc::SCM_RIGHTS {
+            rights.push((off + CMSGHDR_SIZE, len - CMSGHDR_SIZE));
+        }
+        off += len.next_multiple_of(8);
+    }
+    rights
+}
+
+// the struct msghdr fields the host call needs, in guest terms
+struct guest_msghdr_t {
+    name: u64,
+    namelen: u32,
+    iovecs: Vec<(u64, u64)>,
+    control: u64,
+    controllen: u64,
+}
+
+fn read_msghdr(m: &machine_t, msgptr: u64) -> Result<guest_msghdr_t, i32> {
+    let raw = guest_slice(m, msgptr, MSGHDR_SIZE)?;
+    let field = |off: usize| u64::from_le_bytes(raw[off..off + 8].try_into().unwrap());
+    Ok(guest_msghdr_t {
+        name: field(0),
+        namelen: field(8) as u32,
+        iovecs: guest_iovecs(m, field(16), field(24))?,
+        control: field(32),
+        controllen: field(40),
+    })
+}
+
+fn host_iovecs(iovecs: &[(u64, u64)]) -> Vec<libc::iovec> {
+    iovecs
+        .iter()
+        .map(|&(base, len)| libc::iovec {
+            iov_base: to_host_addr(base) as *mut libc::c_void,
+            iov_len: len as usize,
+        })
+        .collect()
+}
+
+fn sys_sendmsg(m: &mut machine_t) -> u64 {
+    let fd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let msgptr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let flags: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+
+    let ret = host_fd_of(m, fd, libc::ENOTSOCK).and_then(|host_fd| {
+        let hdr = read_msghdr(m, msgptr)?;
+        let mut sockaddr = match hdr.name {
+            0 => Vec::new(),
+            _ => read_sockaddr(m, hdr.name, hdr.namelen as u64)?,
+        };
+        let mut control = guest_slice(m, hdr.control, hdr.controllen)?.to_vec();
+        for (off, len) in scm_rights(&control) {
+            for fd in control[off..off + len].chunks_exact_mut(4) {
+                let guest_fd = i32::from_le_bytes(fd.try_into().unwrap());
+                let host_fd = host_fd_of(m, guest_fd as u64, libc::EOPNOTSUPP)?;
+                fd.copy_from_slice(&host_fd.to_le_bytes());
+            }
+        }
+        let mut iov = host_iovecs(&hdr.iovecs);
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_name = match sockaddr.len() {
+            0 => std::ptr::null_mut(),
+            _ => sockaddr.as_mut_ptr() as *mut libc::c_void,
+        };
+        msg.msg_namelen = sockaddr.len() as libc::socklen_t;
+        msg.msg_iov = iov.as_mut_ptr();
+        msg.msg_iovlen = iov.len();
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = control.len();
+        host_ret(unsafe { libc::sendmsg(host_fd, &msg, flags as i32) } as i64)
+    });
+    vfs_ret(ret)
+}
+
+fn sys_recvmsg(m: &mut machine_t) -> u64 {
+    let fd: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let msgptr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let flags: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+
+    let ret = host_fd_of(m, fd, libc::ENOTSOCK).and_then(|host_fd| {
+        let hdr = read_msghdr(m, msgptr)?;
+        let control = guest_slice_mut(m, hdr.control, hdr.controllen)?;
+        let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        let mut iov = host_iovecs(&hdr.iovecs);
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_name = &mut storage as *mut _ as *mut libc::c_void;
+        msg.msg_namelen = SOCKADDR_MAX as libc::socklen_t;
+        msg.msg_iov = iov.as_mut_ptr();
+        msg.msg_iovlen = iov.len();
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = control.len();
+        // received host fds only become visible to the guest once mapped
+        let n = host_ret(unsafe {
+            libc::recvmsg(host_fd, &mut msg, flags as i32 | libc::MSG_CMSG_CLOEXEC)
+        } as i64)?;
+
+        // the host echoes MSG_CMSG_CLOEXEC, which the guest may not have asked for
+        let cloexec = flags as i32 & libc::MSG_CMSG_CLOEXEC != 0;
+        let mut msg_flags = match cloexec {
+            true => msg.msg_flags,
+            false => msg.msg_flags & !libc::MSG_CMSG_CLOEXEC,
+        };
+        let control = &mut control[..msg.msg_controllen];
+        for (off, len) in scm_rights(control) {
+            for fd in control[off..off + len].chunks_exact_mut(4) {
+                let host_fd = host_file_t::new(i32::from_le_bytes(fd.try_into().unwrap()));
+                let guest_fd = m.fds.alloc(Box::new(host_fd), cloexec).unwrap_or_else(|_| {
+                    msg_flags |= libc::MSG_CTRUNC;
+                    u64::MAX
+                });
+                fd.copy_from_slice(&(guest_fd as i32).to_le_bytes());
+            }
+        }
+
+        let raw = guest_slice_mut(m, msgptr, MSGHDR_SIZE)?;
+        if hdr.name != 0 {
+            let sockaddr = unsafe {
+                std::slice::from_raw_parts(
+                    &storage as *const _ as *const u8,
+                    msg.msg_namelen as usize,
+                )
+            };
+            let len = write_sockaddr(m, sockaddr, hdr.name, hdr.namelen)?;
+            raw[8..12].copy_from_slice(&len.to_le_bytes());
+        }
+        raw[40..48].copy_from_slice(&(msg.msg_controllen as u64).to_le_bytes());
+        raw[48..52].copy_from_slice(&msg_flags.to_le_bytes());
+        Ok(n)
+    });
+    vfs_ret(ret)
+}
+
+fn sys_chdir(m: &mut machine_t) -> u64 {
+    let nameptr: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let path = match guest_cstr(m, nameptr) {
+        Ok(path) => path,
+        Err(errno) => return vfs_ret(Err(errno)),
+    };
+
+    vfs_ret(m.vfs.chdir(&path).map(|_| 0))
+}
+
+/*
+    the host cannot run a RISC-V program itself, so execve re-runs the
+    emulator with the same options on the (redirected) guest program.
+    host backed fds without CLOEXEC are handed over with --inherit-fd,
+    in-memory files are lost. the guest's envp goes to the new guest with
+    --env, the emulator itself keeps its own environment.
+*/
+fn sys_execve(m: &mut machine_t) -> u64 {
+    let nameptr: u64 = m.state.gp_regs[gp_reg_type_t::a0 as usize];
+    let argvptr: u64 = m.state.gp_regs[gp_reg_type_t::a1 as usize];
+    let envpptr: u64 = m.state.gp_regs[gp_reg_type_t::a2 as usize];
+
+    let strings = (
+        guest_cstr(m, nameptr),
+        guest_strv(m, argvptr),
+        guest_strv(m, envpptr),
+    );
+    let (path, argv, envp) = match strings {
+        (Ok(path), Ok(argv), Ok(envp)) => (path, argv, envp),
+        (Err(errno), _, _) | (_, Err(errno), _) | (_, _, Err(errno)) => {
+            return vfs_ret(Err(errno));
+        }
+    };
+    let path = match m.vfs.exec_path(&path) {
+        Ok(path) => path,
+        Err(errno) => return vfs_ret(Err(errno)),
+    };
+    let exe = match std::env::current_exe() {
+        Ok(exe) => CString::new(exe.into_os_string().into_vec()).unwrap(),
+        Err(e) => return vfs_ret(Err(e.raw_os_error().unwrap_or(libc::ENOEXEC))),
+    };
+
+    let mut args: Vec<CString> = vec![exe.clone()];
+    for arg in &m.config.emu_args {
+        args.push(CString::new(arg.as_str()).unwrap());
+    }
+    // host fds the new emulator needs open, with the flags to restore on failure
+    let mut cleared: Vec<(i32, i32)> = Vec::new();
+    for (guest_fd, host_fd) in m.fds.inherited() {
+        if guest_fd == host_fd as u64 && guest_fd < 3 {
+            continue;
+        }
+        if !cleared.iter().any(|&(fd, _)| fd == host_fd) {
+            let flags = unsafe { libc::fcntl(host_fd, libc::F_GETFD) };
+            unsafe { libc::fcntl(host_fd, libc::F_SETFD, 0) };
+            cleared.push((host_fd, flags));
+        }
+        args.push(CString::new("--inherit-fd").unwrap());
+        args.push(CString::new(format!("{}:{}", guest_fd, host_fd)).unwrap());
+    }
+    // the guest's argv[0] need not be the path it runs
+    let mut argv = argv.into_iter();
+    if let Some(argv0) = argv.next() {
+        args.push(CString::new("--argv0").unwrap());
+        args.push(argv0);
+    }
+    for env in envp {
+        args.push(CString::new("--env").unwrap());
+        args.push(env);
+    }
+    args.push(CString::new("--").unwrap());
+    args.push(path);
+    args.extend(argv);
+
+    let mut arg_ptrs: Vec<*const libc::c_char> = args.iter().map(|a| a.as_ptr()).collect();
+    arg_ptrs.push(std::ptr::null());
+
+    let ret = unsafe { libc::execv(exe.as_ptr(), arg_ptrs.as_ptr()) };
+    let ret = syscall_ret(ret as i64);
+    for (fd, flags) in cleared {
+        unsafe { libc::fcntl(fd, libc::F_SETFD, flags) };
+    }
+    ret
 }
@@ -0,0 +1,1156 @@
+use std::ptr;
+
+use crate::{
+    float::{self, F32, F64, NX, RMM, RNE, RTZ, fmt_t},
+    insn::{insn_t, insn_type_t},
+    interp::func_illegal,
+    machine::{state_t, to_host_addr},
+    utils::round_down,
+};
+
+const VTYPE_VILL: u64 = 1 << 63;
+
+/*
+    the vector unit, v0-v31 are stored back to back so a register group
+    starting at vN is one contiguous slice and element i of the group is at
+    byte i * width from the start of vN
+*/
+pub struct vstate_t {
+    pub vlenb: usize, // VLEN in bytes
+    pub regs: Vec<u8>,
+    pub vl: u64,
+    pub vtype: u64,
+    pub vstart: u64,
+    pub vxrm: u64,  // fixed-point rounding mode
+    pub vxsat: u64, // fixed-point saturation flag
+}
+
+impl vstate_t {
+    pub fn new(vlen: u64) -> vstate_t {
+        let vlenb = (vlen / 8) as usize;
+        vstate_t {
+            vlenb,
+            regs: vec![0; 32 * vlenb],
+            vl: 0,
+            vtype: VTYPE_VILL,
+            vstart: 0,
+            vxrm: 0,
+            vxsat: 0,
+        }
+    }
+
+    // SEW in bytes
+    fn sew(&self) -> usize {
+        1 << ((self.vtype >> 3) & 0x7)
+    }
+
+    fn lmul_log2(&self) -> i32 {
+        ((self.vtype as i32) << 29) >> 29
+    }
+
+    fn vlmax(&self) -> u64 {
+        let n = (self.vlenb / self.sew()) as u64;
+        match self.lmul_log2() {
+            l if l >= 0 => n << l,
+            l => n >> -l,
+        }
+    }
+
+    // set vtype and vl from the requested vtype and application vector length
+    fn vset(&mut self, avl: u64, vtype: u64) {
+        let vlmul = vtype & 0x7;
+        let vsew = (vtype >> 3) & 0x7;
+        let lmul_log2 = ((vlmul as i32) << 29) >> 29;
+        // SEW may not exceed ELEN * LMUL for fractional LMUL
+        if vtype >> 8 == 0 && vlmul != 4 && vsew as i32 <= 3 + lmul_log2.min(0) {
+            self.vtype = vtype;
+            self.vl = avl.min(self.vlmax());
+        } else {
+            self.vtype = VTYPE_VILL;
+            self.vl = 0;
+        }
+        self.vstart = 0;
+    }
+
+    // element i of w bytes in the group starting at reg
+    fn get(&self, reg: u8, i: u64, w: usize) -> u64 {
+        let off = reg as usize * self.vlenb + i as usize * w;
+        let mut bytes = [0u8; 8];
+        bytes[..w].copy_from_slice(&self.regs[off..off + w]);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn set(&mut self, reg: u8, i: u64, w: usize, val: u64) {
+        let off = reg as usize * self.vlenb + i as usize * w;
+        self.regs[off..off + w].copy_from_slice(&val.to_le_bytes()[..w]);
+    }
+
+    fn mask(&self, reg: u8, i: u64) -> bool {
+        (self.regs[reg as usize * self.vlenb + i as usize / 8] >> (i % 8)) & 1 != 0
+    }
+
+    fn set_mask(&mut self, reg: u8, i: u64, bit: bool) {
+        let byte = &mut self.regs[reg as usize * self.vlenb + i as usize / 8];
+        *byte = (*byte & !(1 << (i % 8))) | ((bit as u8) << (i % 8));
+    }
+
+    // unmasked or enabled by v0, masked off elements are left undisturbed
+    fn active(&self, insn: &insn_t, i: u64) -> bool {
+        insn.vm || self.mask(0, i)
+    }
+}
+
+/*
+    the checks return None for a reserved encoding, which the public
+    functions turn into an illegal instruction before touching any state
+*/
+fn legal(state: &mut state_t, insn: &mut insn_t, f: fn(&mut state_t, &insn_t) -> Option<()>) {
+    if f(state, insn).is_none() {
+        func_illegal(state, insn);
+    }
+}
+
+fn check_vtype(state: &state_t) -> Option<()> {
+    (state.vec.vtype & VTYPE_VILL == 0).then_some(())
+}
+
+// results twice SEW wide need 2 * SEW <= ELEN and LMUL < 8
+fn check_widen(state: &state_t) -> Option<()> {
+    (state.vec.sew() != 8 && state.vec.lmul_log2() != 3).then_some(())
+}
+
+// a group of w byte elements starting at reg, EMUL = LMUL * w / SEW
+fn check_group(state: &state_t, reg: u8, w: usize) -> Option<()> {
+    let emul_log2 =
+        state.vec.lmul_log2() + w.trailing_zeros() as i32 - state.vec.sew().trailing_zeros() as i32;
+    check_regs(reg, 1 << emul_log2.max(0))
+}
+
+// n whole registers from reg, n is 1, 2, 4 or 8 and reg a multiple of it
+fn check_regs(reg: u8, n: usize) -> Option<()> {
+    (matches!(n, 1 | 2 | 4 | 8) && (reg as usize).is_multiple_of(n)).then_some(())
+}
+
+// vs1 when it names a vector, the unary groups use the field as an opcode
+fn check_vs1(state: &state_t, insn: &insn_t, w: usize) -> Option<()> {
+    use insn_type_t::*;
+    match insn.type_ {
+        insn_opivv | insn_opmvv | insn_opfvv if !matches!(insn.funct6, 0x12 | 0x13) => {
+            check_group(state, insn.rs1, w)
+        }
+        _ => Some(()),
+    }
+}
+
+// all bits of a w byte element
+fn ones(w: usize) -> u64 {
+    if w >= 8 { u64::MAX } else { (1 << (w * 8)) - 1 }
+}
+
+fn sext(v: u64, w: usize) -> i64 {
+    let shift = 64 - w as u32 * 8;
+    ((v << shift) as i64) >> shift
+}
+
+fn min_signed(w: usize) -> i128 {
+    -(1i128 << (w * 8 - 1))
+}
+
+fn max_signed(w: usize) -> i128 {
+    (1i128 << (w * 8 - 1)) - 1
+}
+
+/* vsetvli, vsetivli and vsetvl */
+
+pub fn func_vset(state: &mut state_t, insn: &mut insn_t) {
+    let (avl, vtype) = match insn.type_ {
+        insn_type_t::insn_vsetivli => (insn.rs1 as u64, insn.imm as u64),
+        _ => {
+            let avl = if insn.rs1 != 0 {
+                state.gp_regs[insn.rs1 as usize]
+            } else if insn.rd != 0 {
+                u64::MAX
+            } else {
+                state.vec.vl
+            };
+            let vtype = match insn.type_ {
+                insn_type_t::insn_vsetvl => state.gp_regs[insn.rs2 as usize],
+                _ => insn.imm as u64,
+            };
+            (avl, vtype)
+        }
+    };
+    state.vec.vset(avl, vtype);
+    state.gp_regs[insn.rd as usize] = state.vec.vl;
+}
+
+/* loads and stores */
+
+fn mem_read(addr: u64, w: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    unsafe { ptr::copy_nonoverlapping(to_host_addr(addr) as *const u8, bytes.as_mut_ptr(), w) };
+    u64::from_le_bytes(bytes)
+}
+
+fn mem_write(addr: u64, w: usize, val: u64) {
+    let bytes = val.to_le_bytes();
+    unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), to_host_addr(addr) as *mut u8, w) };
+}
+
+// fault-only-first loads stop before an element on an unmapped page
+fn mapped(addr: u64, w: usize) -> bool {
+    let page_size = page_size::get() as u64;
+    let start = round_down(to_host_addr(addr), page_size);
+    let len = to_host_addr(addr) + w as u64 - start;
+    let mut vec = [0u8; 2];
+    unsafe { libc::mincore(start as *mut libc::c_void, len as usize, vec.as_mut_ptr()) == 0 }
+}
+
+pub fn func_vload(state: &mut state_t, insn: &mut insn_t) {
+    legal(state, insn, |state, insn| vmem(state, insn, false));
+}
+
+pub fn func_vstore(state: &mut state_t, insn: &mut insn_t) {
+    legal(state, insn, |state, insn| vmem(state, insn, true));
+}
+
+fn vmem(state: &mut state_t, insn: &insn_t, store: bool) -> Option<()> {
+    use insn_type_t::*;
+    let base = state.gp_regs[insn.rs1 as usize];
+    let nf = insn.nf as u64 + 1;
+
+    // whole registers ignore vtype and vl
+    if matches!(insn.type_, insn_vlr | insn_vsr) {
+        check_regs(insn.rd, nf as usize)?;
+        let vlenb = state.vec.vlenb;
+        let start = insn.rd as usize * vlenb;
+        let regs = &mut state.vec.regs[start..start + nf as usize * vlenb];
+        let host = to_host_addr(base) as *mut u8;
+        match store {
+            true => unsafe { ptr::copy_nonoverlapping(regs.as_ptr(), host, regs.len()) },
+            false => unsafe { ptr::copy_nonoverlapping(host, regs.as_mut_ptr(), regs.len()) },
+        }
+        state.vec.vstart = 0;
+        return Some(());
+    }
+
+    check_vtype(state)?;
+    let sew = state.vec.sew();
+    let (w, field_regs, evl) = match insn.type_ {
+        // masks are vl bits, stored as bytes
+        insn_vlm | insn_vsm => (1, 1, state.vec.vl.div_ceil(8)),
+        // the encoded width is the index width, data elements are SEW wide
+        insn_vlxei | insn_vsxei => {
+            check_group(state, insn.rs2, insn.imm as usize)?;
+            (sew, 1 << state.vec.lmul_log2().max(0), state.vec.vl)
+        }
+        _ => {
+            let w = insn.imm as usize;
+            let emul_log2 =
+                state.vec.lmul_log2() + w.trailing_zeros() as i32 - sew.trailing_zeros() as i32;
+            if !(-3..=3).contains(&emul_log2) {
+                return None;
+            }
+            (w, 1 << emul_log2.max(0), state.vec.vl)
+        }
+    };
+    if nf * field_regs > 8 || insn.rd as u64 + nf * field_regs > 32 {
+        return None;
+    }
+    check_regs(insn.rd, field_regs as usize)?;
+
+    'elements: for i in state.vec.vstart..evl {
+        if !state.vec.active(insn, i) {
+            continue;
+        }
+        for f in 0..nf {
+            let addr = match insn.type_ {
+                insn_vlse | insn_vsse => {
+                    base.wrapping_add(i.wrapping_mul(state.gp_regs[insn.rs2 as usize]))
+                }
+                insn_vlxei | insn_vsxei => {
+                    base.wrapping_add(state.vec.get(insn.rs2, i, insn.imm as usize))
+                }
+                _ => base.wrapping_add(i * nf * w as u64),
+            }
+            .wrapping_add(f * w as u64);
+            let reg = insn.rd + (f * field_regs) as u8;
+            if store {
+                mem_write(addr, w, state.vec.get(reg, i, w));
+            } else {
+                if matches!(insn.type_, insn_vleff) && i > 0 && !mapped(addr, w) {
+                    state.vec.vl = i;
+                    break 'elements;
+                }
+                state.vec.set(reg, i, w, mem_read(addr, w));
+            }
+        }
+    }
+    state.vec.vstart = 0;
+    Some(())
+}
+
+/* operand access */
+
+// vs1, rs1, fs1 or the immediate as a w byte element
+fn op1(state: &state_t, insn: &insn_t, i: u64, w: usize) -> u64 {
+    use insn_type_t::*;
+    match insn.type_ {
+        insn_opivv | insn_opmvv | insn_opfvv => state.vec.get(insn.rs1, i, w),
+        insn_opivx | insn_opmvx => state.gp_regs[insn.rs1 as usize] & ones(w),
+        // a scalar narrower than FLEN must be NaN-boxed
+        insn_opfvf => match unsafe { state.fp_regs[insn.rs1 as usize].v } {
+            v if v | ones(w) == u64::MAX => v & ones(w),
+            _ => fmt(w).canonical_nan(),
+        },
+        _ => insn.imm as u64 & ones(w),
+    }
+}
+
+/*
+    vd[i] = f(vs2[i], op1[i], vd[i]) for the active elements, with vd wd
+    bytes, vs2 w2 bytes and op1 SEW wide
+*/
+fn arith(
+    state: &mut state_t,
+    insn: &insn_t,
+    wd: usize,
+    w2: usize,
+    mut f: impl FnMut(u64, u64, u64) -> u64,
+) -> Option<()> {
+    let sew = state.vec.sew();
+    check_group(state, insn.rd, wd)?;
+    check_group(state, insn.rs2, w2)?;
+    check_vs1(state, insn, sew)?;
+    for i in state.vec.vstart..state.vec.vl {
+        if !state.vec.active(insn, i) {
+            continue;
+        }
+        let a = state.vec.get(insn.rs2, i, w2);
+        let b = op1(state, insn, i, sew);
+        let d = state.vec.get(insn.rd, i, wd);
+        state.vec.set(insn.rd, i, wd, f(a, b, d) & ones(wd));
+    }
+    Some(())
+}
+
+// mask bit vd[i] = f(vs2[i], op1[i])
+fn compare(state: &mut state_t, insn: &insn_t, mut f: impl FnMut(u64, u64) -> bool) -> Option<()> {
+    let sew = state.vec.sew();
+    check_group(state, insn.rs2, sew)?;
+    check_vs1(state, insn, sew)?;
+    for i in state.vec.vstart..state.vec.vl {
+        if !state.vec.active(insn, i) {
+            continue;
+        }
+        let a = state.vec.get(insn.rs2, i, sew);
+        let b = op1(state, insn, i, sew);
+        state.vec.set_mask(insn.rd, i, f(a, b));
+    }
+    Some(())
+}
+
+// vd[0] = f(...f(vs1[0], vs2[0])..., vs2[vl - 1]) over the active elements
+fn reduce(
+    state: &mut state_t,
+    insn: &insn_t,
+    wd: usize,
+    w2: usize,
+    mut f: impl FnMut(u64, u64) -> u64,
+) -> Option<()> {
+    check_group(state, insn.rs2, w2)?;
+    if state.vec.vl == 0 {
+        return Some(());
+    }
+    let mut acc = state.vec.get(insn.rs1, 0, wd);
+    for i in 0..state.vec.vl {
+        if state.vec.active(insn, i) {
+            acc = f(acc, state.vec.get(insn.rs2, i, w2)) & ones(wd);
+        }
+    }
+    state.vec.set(insn.rd, 0, wd, acc);
+    Some(())
+}
+
+// vmerge and vmv.v, vfmerge and vfmv.v.f
+fn merge(state: &mut state_t, insn: &insn_t) -> Option<()> {
+    let sew = state.vec.sew();
+    check_group(state, insn.rd, sew)?;
+    check_group(state, insn.rs2, sew)?;
+    check_vs1(state, insn, sew)?;
+    for i in state.vec.vstart..state.vec.vl {
+        let val = match state.vec.active(insn, i) {
+            true => op1(state, insn, i, sew),
+            false => state.vec.get(insn.rs2, i, sew),
+        };
+        state.vec.set(insn.rd, i, sew, val);
+    }
+    Some(())
+}
+
+/* permutations */
+
+// vrgather and vrgatherei16, index elements iw bytes wide
+fn gather(state: &mut state_t, insn: &insn_t, iw: usize) -> Option<()> {
+    let sew = state.vec.sew();
+    check_group(state, insn.rd, sew)?;
+    check_group(state, insn.rs2, sew)?;
+    check_vs1(state, insn, iw)?;
+    let vlmax = state.vec.vlmax();
+    for i in state.vec.vstart..state.vec.vl {
+        if !state.vec.active(insn, i) {
+            continue;
+        }
+        let idx = match insn.type_ {
+            insn_type_t::insn_opivv => state.vec.get(insn.rs1, i, iw),
+            insn_type_t::insn_opivx => state.gp_regs[insn.rs1 as usize],
+            _ => insn.imm as u64,
+        };
+        let val = match idx < vlmax {
+            true => state.vec.get(insn.rs2, idx, sew),
+            false => 0,
+        };
+        state.vec.set(insn.rd, i, sew, val);
+    }
+    Some(())
+}
+
+fn slide_offset(state: &state_t, insn: &insn_t) -> u64 {
+    match insn.type_ {
+        insn_type_t::insn_opivx => state.gp_regs[insn.rs1 as usize],
+        _ => insn.imm as u64,
+    }
+}
+
+fn slideup(state: &mut state_t, insn: &insn_t) -> Option<()> {
+    let sew = state.vec.sew();
+    check_group(state, insn.rd, sew)?;
+    check_group(state, insn.rs2, sew)?;
+    let offset = slide_offset(state, insn);
+    for i in state.vec.vstart.max(offset)..state.vec.vl {
+        if state.vec.active(insn, i) {
+            let val = state.vec.get(insn.rs2, i - offset, sew);
+            state.vec.set(insn.rd, i, sew, val);
+        }
+    }
+    Some(())
+}
+
+fn slidedown(state: &mut state_t, insn: &insn_t) -> Option<()> {
+    let sew = state.vec.sew();
+    check_group(state, insn.rd, sew)?;
+    check_group(state, insn.rs2, sew)?;
+    let offset = slide_offset(state, insn);
+    let vlmax = state.vec.vlmax();
+    for i in state.vec.vstart..state.vec.vl {
+        if !state.vec.active(insn, i) {
+            continue;
+        }
+        let val = match i.checked_add(offset).filter(|&j| j < vlmax) {
+            Some(j) => state.vec.get(insn.rs2, j, sew),
+            None => 0,
+        };
+        state.vec.set(insn.rd, i, sew, val);
+    }
+    Some(())
+}
+
+// vslide1up and vfslide1up, the scalar goes in at element 0
+fn slide1up(state: &mut state_t, insn: &insn_t) -> Option<()> {
+    let sew = state.vec.sew();
+    check_group(state, insn.rd, sew)?;
+    check_group(state, insn.rs2, sew)?;
+    let x = op1(state, insn, 0, sew);
+    for i in state.vec.vstart..state.vec.vl {
+        if state.vec.active(insn, i) {
+            let val = match i {
+                0 => x,
+                _ => state.vec.get(insn.rs2, i - 1, sew),
+            };
+            state.vec.set(insn.rd, i, sew, val);
+        }
+    }
+    Some(())
+}
+
+// vslide1down and vfslide1down, the scalar goes in at element vl - 1
+fn slide1down(state: &mut state_t, insn: &insn_t) -> Option<()> {
+    let sew = state.vec.sew();
+    check_group(state, insn.rd, sew)?;
+    check_group(state, insn.rs2, sew)?;
+    let x = op1(state, insn, 0, sew);
+    for i in state.vec.vstart..state.vec.vl {
+        if state.vec.active(insn, i) {
+            let val = match i + 1 < state.vec.vl {
+                true => state.vec.get(insn.rs2, i + 1, sew),
+                false => x,
+            };
+            state.vec.set(insn.rd, i, sew, val);
+        }
+    }
+    Some(())
+}
+
+/* fixed point */
+
+// rounding increment for shifting v right by d bits under vxrm
+fn round_incr(v: u128, d: u32, vxrm: u64) -> u128 {
+    if d == 0 {
+        return 0;
+    }
+    let bit = |n: u32| (v >> n) & 1;
+    let below = |n: u32| (v & ((1 << n) - 1) != 0) as u128;
+    match vxrm & 0x3 {
+        0 => bit(d - 1),                           // rnu
+        1 => bit(d - 1) & (below(d - 1) | bit(d)), // rne
+        2 => 0,                                    // rdn
+        _ => (bit(d) ^ 1) & below(d),              // rod
+    }
+}
+
+fn roundoff_unsigned(v: u128, d: u32, vxrm: u64) -> u128 {
+    (v >> d) + round_incr(v, d, vxrm)
+}
+
+fn roundoff_signed(v: i128, d: u32, vxrm: u64) -> i128 {
+    (v >> d) + round_incr(v as u128, d, vxrm) as i128
+}
+
+// clamp to w bytes signed, noting saturation
+fn clip_signed(v: i128, w: usize, sat: &mut bool) -> u64 {
+    let clamped = v.clamp(min_signed(w), max_signed(w));
+    *sat |= clamped != v;
+    clamped as u64
+}
+
+fn clip_unsigned(v: i128, w: usize, sat: &mut bool) -> u64 {
+    let clamped = v.clamp(0, ones(w) as i128);
+    *sat |= clamped != v;
+    clamped as u64
+}
+
+/* integer operations, OPIVV, OPIVX and OPIVI */
+
+pub fn func_opi(state: &mut state_t, insn: &mut insn_t) {
+    legal(state, insn, opi);
+}
+
+fn opi(state: &mut state_t, insn: &insn_t) -> Option<()> {
+    // vmv<nr>r.v copies whole registers whatever vtype is
+    if insn.funct6 == 0x27 && matches!(insn.type_, insn_type_t::insn_opivi) {
+        let vlenb = state.vec.vlenb;
+        let nr = insn.imm as usize + 1;
+        check_regs(insn.rd, nr)?;
+        check_regs(insn.rs2, nr)?;
+        let src = insn.rs2 as usize * vlenb;
+        state
+            .vec
+            .regs
+            .copy_within(src..src + nr * vlenb, insn.rd as usize * vlenb);
+        state.vec.vstart = 0;
+        return Some(());
+    }
+
+    check_vtype(state)?;
+    let sew = state.vec.sew();
+    let bits = sew as u32 * 8;
+    let vxrm = state.vec.vxrm;
+    let s = |v: u64| sext(v, sew) as i128;
+    let mut sat = false;
+
+    match insn.funct6 {
+        0x00 => arith(state, insn, sew, sew, |a, b, _| a.wrapping_add(b))?,
+        0x02 => arith(state, insn, sew, sew, |a, b, _| a.wrapping_sub(b))?,
+        0x03 => arith(state, insn, sew, sew, |a, b, _| b.wrapping_sub(a))?,
+        0x04 => arith(state, insn, sew, sew, |a, b, _| a.min(b))?,
+        0x05 => arith(state, insn, sew, sew, |a, b, _| s(a).min(s(b)) as u64)?,
+        0x06 => arith(state, insn, sew, sew, |a, b, _| a.max(b))?,
+        0x07 => arith(state, insn, sew, sew, |a, b, _| s(a).max(s(b)) as u64)?,
+        0x09 => arith(state, insn, sew, sew, |a, b, _| a & b)?,
+        0x0a => arith(state, insn, sew, sew, |a, b, _| a | b)?,
+        0x0b => arith(state, insn, sew, sew, |a, b, _| a ^ b)?,
+        0x0c => gather(state, insn, sew)?,
+        0x0e if matches!(insn.type_, insn_type_t::insn_opivv) => gather(state, insn, 2)?,
+        0x0e => slideup(state, insn)?,
+        0x0f => slidedown(state, insn)?,
+        // vadc, vsbc, vmadc and vmsbc, the carry or borrow comes from v0
+        0x10..=0x13 => {
+            if matches!(insn.funct6, 0x10 | 0x12) {
+                check_group(state, insn.rd, sew)?;
+            }
+            check_group(state, insn.rs2, sew)?;
+            check_vs1(state, insn, sew)?;
+            for i in state.vec.vstart..state.vec.vl {
+                let a = state.vec.get(insn.rs2, i, sew) as i128;
+                let b = op1(state, insn, i, sew) as i128;
+                let c = (!insn.vm && state.vec.mask(0, i)) as i128;
+                match insn.funct6 {
+                    0x10 => state
+                        .vec
+                        .set(insn.rd, i, sew, (a + b + c) as u64 & ones(sew)),
+                    0x11 => state.vec.set_mask(insn.rd, i, (a + b + c) >> bits != 0),
+                    0x12 => state
+                        .vec
+                        .set(insn.rd, i, sew, (a - b - c) as u64 & ones(sew)),
+                    _ => state.vec.set_mask(insn.rd, i, a - b - c < 0),
+                }
+            }
+        }
+        0x17 => merge(state, insn)?,
+        0x18 => compare(state, insn, |a, b| a == b)?,
+        0x19 => compare(state, insn, |a, b| a != b)?,
+        0x1a => compare(state, insn, |a, b| a < b)?,
+        0x1b => compare(state, insn, |a, b| s(a) < s(b))?,
+        0x1c => compare(state, insn, |a, b| a <= b)?,
+        0x1d => compare(state, insn, |a, b| s(a) <= s(b))?,
+        0x1e => compare(state, insn, |a, b| a > b)?,
+        0x1f => compare(state, insn, |a, b| s(a) > s(b))?,
+        0x20 => arith(state, insn, sew, sew, |a, b, _| {
+            clip_unsigned(a as i128 + b as i128, sew, &mut sat)
+        })?,
+        0x21 => arith(state, insn, sew, sew, |a, b, _| {
+            clip_signed(s(a) + s(b), sew, &mut sat)
+        })?,
+        0x22 => arith(state, insn, sew, sew, |a, b, _| {
+            clip_unsigned(a as i128 - b as i128, sew, &mut sat)
+        })?,
+        0x23 => arith(state, insn, sew, sew, |a, b, _| {
+            clip_signed(s(a) - s(b), sew, &mut sat)
+        })?,
+        0x25 => arith(state, insn, sew, sew, |a, b, _| {
+            a << (b & (bits as u64 - 1))
+        })?,
+        // vsmul
+        0x27 => arith(state, insn, sew, sew, |a, b, _| {
+            clip_signed(roundoff_signed(s(a) * s(b), bits - 1, vxrm), sew, &mut sat)
+        })?,
+        0x28 => arith(state, insn, sew, sew, |a, b, _| {
+            a >> (b & (bits as u64 - 1))
+        })?,
+        0x29 => arith(state, insn, sew, sew, |a, b, _| {
+            (s(a) >> (b & (bits as u64 - 1))) as u64
+        })?,
+        0x2a => arith(state, insn, sew, sew, |a, b, _| {
+            roundoff_unsigned(a as u128, (b & (bits as u64 - 1)) as u32, vxrm) as u64
+        })?,
+        0x2b => arith(state, insn, sew, sew, |a, b, _| {
+            roundoff_signed(s(a), (b & (bits as u64 - 1)) as u32, vxrm) as u64
+        })?,
+        // narrowing shifts and clips, vs2 is 2 * SEW wide
+        0x2c..=0x2f => {
+            check_widen(state)?;
+            let shamt = move |b: u64| (b & (2 * bits as u64 - 1)) as u32;
+            let wide = move |a: u64| sext(a, 2 * sew) as i128;
+            match insn.funct6 {
+                0x2c => arith(state, insn, sew, 2 * sew, |a, b, _| a >> shamt(b))?,
+                0x2d => arith(state, insn, sew, 2 * sew, |a, b, _| {
+                    (wide(a) >> shamt(b)) as u64
+                })?,
+                0x2e => arith(state, insn, sew, 2 * sew, |a, b, _| {
+                    let v = roundoff_unsigned(a as u128, shamt(b), vxrm);
+                    clip_unsigned(v as i128, sew, &mut sat)
+                })?,
+                _ => arith(state, insn, sew, 2 * sew, |a, b, _| {
+                    clip_signed(roundoff_signed(wide(a), shamt(b), vxrm), sew, &mut sat)
+                })?,
+            }
+        }
+        0x30 => {
+            check_widen(state)?;
+            reduce(state, insn, 2 * sew, sew, |acc, a| acc.wrapping_add(a))?;
+        }
+        0x31 => {
+            check_widen(state)?;
+            reduce(state, insn, 2 * sew, sew, |acc, a| {
+                acc.wrapping_add(sext(a, sew) as u64)
+            })?;
+        }
+        _ => unreachable!(),
+    }
+
+    if sat {
+        state.vec.vxsat = 1;
+    }
+    state.vec.vstart = 0;
+    Some(())
+}
+
+/* integer operations, OPMVV and OPMVX */
+
+pub fn func_opm(state: &mut state_t, insn: &mut insn_t) {
+    legal(state, insn, opm);
+}
+
+fn opm(state: &mut state_t, insn: &insn_t) -> Option<()> {
+    check_vtype(state)?;
+    let sew = state.vec.sew();
+    let bits = sew as u32 * 8;
+    let vxrm = state.vec.vxrm;
+    let s = |v: u64| sext(v, sew) as i128;
+    let vx = matches!(insn.type_, insn_type_t::insn_opmvx);
+
+    match insn.funct6 {
+        0x00 => reduce(state, insn, sew, sew, |acc, a| acc.wrapping_add(a))?,
+        0x01 => reduce(state, insn, sew, sew, |acc, a| acc & a)?,
+        0x02 => reduce(state, insn, sew, sew, |acc, a| acc | a)?,
+        0x03 => reduce(state, insn, sew, sew, |acc, a| acc ^ a)?,
+        0x04 => reduce(state, insn, sew, sew, |acc, a| acc.min(a))?,
+        0x05 => reduce(state, insn, sew, sew, |acc, a| s(acc).min(s(a)) as u64)?,
+        0x06 => reduce(state, insn, sew, sew, |acc, a| acc.max(a))?,
+        0x07 => reduce(state, insn, sew, sew, |acc, a| s(acc).max(s(a)) as u64)?,
+        // averaging add and subtract
+        0x08 => arith(state, insn, sew, sew, |a, b, _| {
+            roundoff_unsigned(a as u128 + b as u128, 1, vxrm) as u64
+        })?,
+        0x09 => arith(state, insn, sew, sew, |a, b, _| {
+            roundoff_signed(s(a) + s(b), 1, vxrm) as u64
+        })?,
+        0x0a => arith(state, insn, sew, sew, |a, b, _| {
+            roundoff_signed(a as i128 - b as i128, 1, vxrm) as u64
+        })?,
+        0x0b => arith(state, insn, sew, sew, |a, b, _| {
+            roundoff_signed(s(a) - s(b), 1, vxrm) as u64
+        })?,
+        0x0e => slide1up(state, insn)?,
+        0x0f => slide1down(state, insn)?,
+        // vmv.s.x
+        0x10 if vx => {
+            if state.vec.vstart < state.vec.vl {
+                let x = op1(state, insn, 0, sew);
+                state.vec.set(insn.rd, 0, sew, x);
+            }
+        }
+        0x10 => {
+            let val = match insn.rs1 {
+                // vmv.x.s
+                0x00 => sext(state.vec.get(insn.rs2, 0, sew), sew) as u64,
+                // vcpop.m
+                0x10 => (0..state.vec.vl)
+                    .filter(|&i| state.vec.active(insn, i) && state.vec.mask(insn.rs2, i))
+                    .count() as u64,
+                // vfirst.m
+                _ => (0..state.vec.vl)
+                    .find(|&i| state.vec.active(insn, i) && state.vec.mask(insn.rs2, i))
+                    .unwrap_or(u64::MAX),
+            };
+            state.gp_regs[insn.rd as usize] = val;
+        }
+        // vzext and vsext by 8, 4 or 2
+        0x12 => {
+            let w2 = sew >> (4 - insn.rs1 / 2);
+            if w2 == 0 {
+                return None;
+            }
+            let signed = insn.rs1 & 1 != 0;
+            arith(state, insn, sew, w2, |a, _, _| match signed {
+                true => sext(a, w2) as u64,
+                false => a,
+            })?;
+        }
+        0x14 => match insn.rs1 {
+            // vmsbf, vmsof and vmsif
+            0x01..=0x03 => {
+                let mut found = false;
+                for i in 0..state.vec.vl {
+                    if !state.vec.active(insn, i) {
+                        continue;
+                    }
+                    let bit = state.vec.mask(insn.rs2, i);
+                    let val = match insn.rs1 {
+                        0x01 => !found && !bit,
+                        0x02 => !found && bit,
+                        _ => !found,
+                    };
+                    state.vec.set_mask(insn.rd, i, val);
+                    found |= bit;
+                }
+            }
+            // viota
+            0x10 => {
+                check_group(state, insn.rd, sew)?;
+                let mut count = 0;
+                for i in 0..state.vec.vl {
+                    if state.vec.active(insn, i) {
+                        state.vec.set(insn.rd, i, sew, count);
+                        count += state.vec.mask(insn.rs2, i) as u64;
+                    }
+                }
+            }
+            // vid
+            _ => {
+                check_group(state, insn.rd, sew)?;
+                for i in state.vec.vstart..state.vec.vl {
+                    if state.vec.active(insn, i) {
+                        state.vec.set(insn.rd, i, sew, i & ones(sew));
+                    }
+                }
+            }
+        },
+        // vcompress
+        0x17 => {
+            check_group(state, insn.rd, sew)?;
+            check_group(state, insn.rs2, sew)?;
+            let mut j = 0;
+            for i in 0..state.vec.vl {
+                if state.vec.mask(insn.rs1, i) {
+                    let val = state.vec.get(insn.rs2, i, sew);
+                    state.vec.set(insn.rd, j, sew, val);
+                    j += 1;
+                }
+            }
+        }
+        // mask logical operations
+        0x18..=0x1f => {
+            for i in state.vec.vstart..state.vec.vl {
+                let a = state.vec.mask(insn.rs2, i);
+                let b = state.vec.mask(insn.rs1, i);
+                let val = match insn.funct6 {
+                    0x18 => a & !b,
+                    0x19 => a & b,
+                    0x1a => a | b,
+                    0x1b => a ^ b,
+                    0x1c => a | !b,
+                    0x1d => !(a & b),
+                    0x1e => !(a | b),
+                    _ => !(a ^ b),
+                };
+                state.vec.set_mask(insn.rd, i, val);
+            }
+        }
+        0x20 => arith(state, insn, sew, sew, |a, b, _| {
+            a.checked_div(b).unwrap_or(u64::MAX)
+        })?,
+        0x21 => arith(state, insn, sew, sew, |a, b, _| {
+            let (a, b) = (sext(a, sew), sext(b, sew));
+            if b == 0 {
+                u64::MAX
+            } else {
+                a.wrapping_div(b) as u64
+            }
+        })?,
+        0x22 => arith(state, insn, sew, sew, |a, b, _| {
+            a.checked_rem(b).unwrap_or(a)
+        })?,
+        0x23 => arith(state, insn, sew, sew, |a, b, _| {
+            let (a, b) = (sext(a, sew), sext(b, sew));
+            if b == 0 {
+                a as u64
+            } else {
+                a.wrapping_rem(b) as u64
+            }
+        })?,
+        0x24 => arith(state, insn, sew, sew, |a, b, _| {
+            ((a as u128 * b as u128) >> bits) as u64
+        })?,
+        0x25 => arith(state, insn, sew, sew, |a, b, _| a.wrapping_mul(b))?,
+        0x26 => arith(state, insn, sew, sew, |a, b, _| {
+            ((s(a) * b as i128) >> bits) as u64
+        })?,
+        0x27 => arith(state, insn, sew, sew, |a, b, _| {
+            ((s(a) * s(b)) >> bits) as u64
+        })?,
+        // vmadd, vnmsub, vmacc and vnmsac
+        0x29 => arith(state, insn, sew, sew, |a, b, d| {
+            b.wrapping_mul(d).wrapping_add(a)
+        })?,
+        0x2b => arith(state, insn, sew, sew, |a, b, d| {
+            a.wrapping_sub(b.wrapping_mul(d))
+        })?,
+        0x2d => arith(state, insn, sew, sew, |a, b, d| {
+            b.wrapping_mul(a).wrapping_add(d)
+        })?,
+        0x2f => arith(state, insn, sew, sew, |a, b, d| {
+            d.wrapping_sub(b.wrapping_mul(a))
+        })?,
+        // widening, the .w forms take vs2 at 2 * SEW
+        0x30..=0x3f => {
+            check_widen(state)?;
+            let w = 2 * sew;
+            match insn.funct6 {
+                0x30 => arith(state, insn, w, sew, |a, b, _| a + b)?,
+                0x31 => arith(state, insn, w, sew, |a, b, _| (s(a) + s(b)) as u64)?,
+                0x32 => arith(state, insn, w, sew, |a, b, _| a.wrapping_sub(b))?,
+                0x33 => arith(state, insn, w, sew, |a, b, _| (s(a) - s(b)) as u64)?,
+                0x34 => arith(state, insn, w, w, |a, b, _| a.wrapping_add(b))?,
+                0x35 => arith(state, insn, w, w, |a, b, _| a.wrapping_add(s(b) as u64))?,
+                0x36 => arith(state, insn, w, w, |a, b, _| a.wrapping_sub(b))?,
+                0x37 => arith(state, insn, w, w, |a, b, _| a.wrapping_sub(s(b) as u64))?,
+                0x38 => arith(state, insn, w, sew, |a, b, _| a * b)?,
+                0x3a => arith(state, insn, w, sew, |a, b, _| (s(a) * b as i128) as u64)?,
+                0x3b => arith(state, insn, w, sew, |a, b, _| (s(a) * s(b)) as u64)?,
+                0x3c => arith(state, insn, w, sew, |a, b, d| d.wrapping_add(a * b))?,
+                0x3d => arith(state, insn, w, sew, |a, b, d| {
+                    d.wrapping_add((s(a) * s(b)) as u64)
+                })?,
+                0x3e => arith(state, insn, w, sew, |a, b, d| {
+                    d.wrapping_add((s(a) * b as i128) as u64)
+                })?,
+                0x3f => arith(state, insn, w, sew, |a, b, d| {
+                    d.wrapping_add((a as i128 * s(b)) as u64)
+                })?,
+                _ => unreachable!(),
+            }
+        }
+        _ => unreachable!(),
+    }
+
+    state.vec.vstart = 0;
+    Some(())
+}
+
+/* floating point */
+
+// the format of a w byte float, no Zvfh so f32 or f64
+fn fmt(w: usize) -> &'static fmt_t {
+    match w {
+        4 => &F32,
+        _ => &F64,
+    }
+}
+
+// an f32 operand of a widening operation as f64, exact but for sNaN
+fn widen(a: u64, flags: &mut u64) -> u64 {
+    float::convert(&F32, &F64, a, RNE, flags)
+}
+
+// a w byte float to a wd byte integer, saturating
+fn f2i(a: u64, w: usize, signed: bool, wd: usize, rm: u8, flags: &mut u64) -> u64 {
+    float::to_int(fmt(w), a, signed, wd as u32 * 8, rm, flags)
+}
+
+// a w byte integer to a wd byte float
+fn i2f(a: u64, w: usize, signed: bool, wd: usize, rm: u8, flags: &mut u64) -> u64 {
+    let a = if signed { sext(a, w) as u64 } else { a };
+    float::from_int(fmt(wd), a, signed, rm, flags)
+}
+
+// f64 to f32 rounding to odd, for vfncvt.rod.f.f.w
+fn f64_to_f32_odd(a: u64, flags: &mut u64) -> u64 {
+    let mut inexact = 0;
+    let r = float::convert(&F64, &F32, a, RTZ, &mut inexact);
+    *flags |= inexact;
+    // truncated towards zero, an inexact result is made odd
+    if inexact & NX != 0 { r | 1 } else { r }
+}
+
+/* floating point operations, OPFVV and OPFVF */
+
+pub fn func_opf(state: &mut state_t, insn: &mut insn_t) {
+    legal(state, insn, opf);
+}
+
+fn opf(state: &mut state_t, insn: &insn_t) -> Option<()> {
+    check_vtype(state)?;
+    // every vector floating point instruction is reserved under a bad frm
+    let rm = state.frm as u8;
+    if rm > RMM {
+        return None;
+    }
+    let sew = state.vec.sew();
+    // no Zvfh, floats are f32 or f64 and only conversions see narrower integers
+    let fw = match (insn.funct6, insn.rs1) {
+        (0x12, 0x0a | 0x0b | 0x10 | 0x11 | 0x16 | 0x17) => 2 * sew,
+        _ => sew,
+    };
+    if fw < 4 {
+        return None;
+    }
+    let sign = 1u64 << (sew * 8 - 1);
+    let ft = fmt(sew);
+    let vf = matches!(insn.type_, insn_type_t::insn_opfvf);
+    let mut flags = 0;
+
+    match insn.funct6 {
+        0x00 => arith(state, insn, sew, sew, |a, b, _| {
+            float::add(ft, a, b, rm, &mut flags)
+        })?,
+        0x01 | 0x03 => reduce(state, insn, sew, sew, |acc, a| {
+            float::add(ft, acc, a, rm, &mut flags)
+        })?,
+        0x02 => arith(state, insn, sew, sew, |a, b, _| {
+            float::sub(ft, a, b, rm, &mut flags)
+        })?,
+        0x04 => arith(state, insn, sew, sew, |a, b, _| {
+            float::min(ft, a, b, &mut flags)
+        })?,
+        0x05 => reduce(state, insn, sew, sew, |acc, a| {
+            float::min(ft, acc, a, &mut flags)
+        })?,
+        0x06 => arith(state, insn, sew, sew, |a, b, _| {
+            float::max(ft, a, b, &mut flags)
+        })?,
+        0x07 => reduce(state, insn, sew, sew, |acc, a| {
+            float::max(ft, acc, a, &mut flags)
+        })?,
+        0x08 => arith(state, insn, sew, sew, |a, b, _| (a & !sign) | (b & sign))?,
+        0x09 => arith(state, insn, sew, sew, |a, b, _| (a & !sign) | (!b & sign))?,
+        0x0a => arith(state, insn, sew, sew, |a, b, _| a ^ (b & sign))?,
+        0x0e => slide1up(state, insn)?,
+        0x0f => slide1down(state, insn)?,
+        // vfmv.s.f
+        0x10 if vf => {
+            if state.vec.vstart < state.vec.vl {
+                let x = op1(state, insn, 0, sew);
+                state.vec.set(insn.rd, 0, sew, x);
+            }
+        }
+        // vfmv.f.s, NaN-boxing single precision
+        0x10 => {
+            let val = state.vec.get(insn.rs2, 0, sew);
+            state.fp_regs[insn.rd as usize].v = val | !ones(sew);
+        }
+        // conversions, the vs1 field selects which
+        0x12 => {
+            let sel = insn.rs1;
+            let rm = match sel {
+                0x06 | 0x07 | 0x0e | 0x0f | 0x16 | 0x17 => RTZ,
+                _ => rm,
+            };
+            let signed = sel & 1 != 0;
+            let fl = &mut flags;
+            match sel {
+                0x00..=0x07 => match sel {
+                    0x02 | 0x03 => arith(state, insn, sew, sew, |a, _, _| {
+                        i2f(a, sew, signed, sew, rm, fl)
+                    })?,
+                    _ => arith(state, insn, sew, sew, |a, _, _| {
+                        f2i(a, sew, signed, sew, rm, fl)
+                    })?,
+                },
+                0x08..=0x0f => {
+                    check_widen(state)?;
+                    let w = 2 * sew;
+                    match sel {
+                        0x0a | 0x0b => arith(state, insn, w, sew, |a, _, _| {
+                            i2f(a, sew, signed, w, rm, fl)
+                        })?,
+                        0x0c => arith(state, insn, w, sew, |a, _, _| widen(a, fl))?,
+                        _ => arith(state, insn, w, sew, |a, _, _| {
+                            f2i(a, sew, signed, w, rm, fl)
+                        })?,
+                    }
+                }
+                _ => {
+                    check_widen(state)?;
+                    let w = 2 * sew;
+                    match sel {
+                        0x12 | 0x13 => arith(state, insn, sew, w, |a, _, _| {
+                            i2f(a, w, signed, sew, rm, fl)
+                        })?,
+                        0x14 => arith(state, insn, sew, w, |a, _, _| {
+                            float::convert(&F64, &F32, a, rm, fl)
+                        })?,
+                        0x15 => arith(state, insn, sew, w, |a, _, _| f64_to_f32_odd(a, fl))?,
+                        _ => arith(state, insn, sew, w, |a, _, _| {
+                            f2i(a, w, signed, sew, rm, fl)
+                        })?,
+                    }
+                }
+            }
+        }
+        0x13 => match insn.rs1 {
+            0x00 => arith(state, insn, sew, sew, |a, _, _| {
+                float::sqrt(ft, a, rm, &mut flags)
+            })?,
+            _ => arith(state, insn, sew, sew, |a, _, _| fmt(sew).class(a))?,
+        },
+        0x17 => merge(state, insn)?,
+        // only vmfeq and vmfne are quiet, vmfgt and vmfge swap the operands
+        0x18 => compare(state, insn, |a, b| float::eq(ft, a, b, &mut flags))?,
+        0x19 => compare(state, insn, |a, b| float::le(ft, a, b, &mut flags))?,
+        0x1b => compare(state, insn, |a, b| float::lt(ft, a, b, &mut flags))?,
+        0x1c => compare(state, insn, |a, b| !float::eq(ft, a, b, &mut flags))?,
+        0x1d => compare(state, insn, |a, b| float::lt(ft, b, a, &mut flags))?,
+        0x1f => compare(state, insn, |a, b| float::le(ft, b, a, &mut flags))?,
+        0x20 => arith(state, insn, sew, sew, |a, b, _| {
+            float::div(ft, a, b, rm, &mut flags)
+        })?,
+        0x21 => arith(state, insn, sew, sew, |a, b, _| {
+            float::div(ft, b, a, rm, &mut flags)
+        })?,
+        0x24 => arith(state, insn, sew, sew, |a, b, _| {
+            float::mul(ft, a, b, rm, &mut flags)
+        })?,
+        0x27 => arith(state, insn, sew, sew, |a, b, _| {
+            float::sub(ft, b, a, rm, &mut flags)
+        })?,
+        // vfmadd, vfnmadd, vfmsub and vfnmsub overwrite the multiplicand
+        0x28 => arith(state, insn, sew, sew, |a, b, d| {
+            float::fma(ft, b, d, a, rm, &mut flags)
+        })?,
+        0x29 => arith(state, insn, sew, sew, |a, b, d| {
+            float::fma(ft, b ^ sign, d, a ^ sign, rm, &mut flags)
+        })?,
+        0x2a => arith(state, insn, sew, sew, |a, b, d| {
+            float::fma(ft, b, d, a ^ sign, rm, &mut flags)
+        })?,
+        0x2b => arith(state, insn, sew, sew, |a, b, d| {
+            float::fma(ft, b ^ sign, d, a, rm, &mut flags)
+        })?,
+        // vfmacc, vfnmacc, vfmsac and vfnmsac overwrite the addend
+        0x2c => arith(state, insn, sew, sew, |a, b, d| {
+            float::fma(ft, b, a, d, rm, &mut flags)
+        })?,
+        0x2d => arith(state, insn, sew, sew, |a, b, d| {
+            float::fma(ft, b ^ sign, a, d ^ sign, rm, &mut flags)
+        })?,
+        0x2e => arith(state, insn, sew, sew, |a, b, d| {
+            float::fma(ft, b, a, d ^ sign, rm, &mut flags)
+        })?,
+        0x2f => arith(state, insn, sew, sew, |a, b, d| {
+            float::fma(ft, b ^ sign, a, d, rm, &mut flags)
+        })?,
+        // widening, f32 sources become f64 exactly so one rounding at 2 * SEW
+        0x30..=0x3f => {
+            check_widen(state)?;
+            let w = 2 * sew;
+            let wsign = 1u64 << 63;
+            let fl = &mut flags;
+            match insn.funct6 {
+                0x30 => arith(state, insn, w, sew, |a, b, _| {
+                    let (a, b) = (widen(a, fl), widen(b, fl));
+                    float::add(&F64, a, b, rm, fl)
+                })?,
+                0x31 | 0x33 => reduce(state, insn, w, sew, |acc, a| {
+                    let a = widen(a, fl);
+                    float::add(&F64, acc, a, rm, fl)
+                })?,
+                0x32 => arith(state, insn, w, sew, |a, b, _| {
+                    let (a, b) = (widen(a, fl), widen(b, fl));
+                    float::sub(&F64, a, b, rm, fl)
+                })?,
+                0x34 => arith(state, insn, w, w, |a, b, _| {
+                    let b = widen(b, fl);
+                    float::add(&F64, a, b, rm, fl)
+                })?,
+                0x36 => arith(state, insn, w, w, |a, b, _| {
+                    let b = widen(b, fl);
+                    float::sub(&F64, a, b, rm, fl)
+                })?,
+                0x38 => arith(state, insn, w, sew, |a, b, _| {
+                    let (a, b) = (widen(a, fl), widen(b, fl));
+                    float::mul(&F64, a, b, rm, fl)
+                })?,
+                // the product's sign flips with the negated vs1 or rs1
+                0x3c..=0x3f => {
+                    let (neg_prod, neg_add) = match insn.funct6 {
+                        0x3c => (0, 0),
+                        0x3d => (wsign, wsign),
+                        0x3e => (0, wsign),
+                        _ => (wsign, 0),
+                    };
+                    arith(state, insn, w, sew, |a, b, d| {
+                        let (a, b) = (widen(a, fl), widen(b, fl));
+                        float::fma(&F64, b ^ neg_prod, a, d ^ neg_add, rm, fl)
+                    })?
+                }
+                _ => unreachable!(),
+            }
+        }
+        _ => unreachable!(),
+    }
+
+    state.fflags |= flags;
+    state.vec.vstart = 0;
+    Some(())
+}
@@ -0,0 +1,1545 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashSet},
+    ffi::{CStr, CString, OsStr},
+    fs, mem,
+    os::unix::ffi::{OsStrExt, OsStringExt},
+    path::{Component, Path, PathBuf},
+    rc::Rc,
+};
+
+use libc::{
+    AT_EMPTY_PATH, AT_FDCWD, AT_REMOVEDIR, AT_SYMLINK_NOFOLLOW, EACCES, EBADF, EEXIST, EFBIG,
+    EINVAL, EISDIR, ENOENT, ENOSPC, ENOTDIR, ENOTEMPTY, EROFS, F_GETFL, F_SETFL, O_ACCMODE,
+    O_APPEND, O_CLOEXEC, O_CREAT, O_DIRECTORY, O_EXCL, O_NOFOLLOW, O_PATH, O_RDONLY, O_RDWR,
+    O_TRUNC, O_WRONLY, S_IFDIR, S_IFMT, S_IFREG, SEEK_CUR, SEEK_END, SEEK_SET,
+};
+
+use crate::mmu::mem_account_t;
+
+/*
+    Every guest file operation goes through a vfs_t (path based calls) and
+    the vfile_t objects it hands out (fd based calls). Errors are positive
+    errno values, the syscall layer negates them for the guest.
+*/
+pub trait vfile_t {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, i32>;
+    fn write(&mut self, buf: &[u8]) -> Result<usize, i32>;
+    fn lseek(&mut self, offset: i64, whence: i32) -> Result<u64, i32>;
+    fn fstat(&mut self) -> Result<libc::stat, i32>;
+
+    // positional I/O, the file offset is left unchanged
+    fn pread(&mut self, buf: &mut [u8], offset: u64) -> Result<usize, i32> {
+        let pos = self.lseek(0, SEEK_CUR)?;
+        self.lseek(offset as i64, SEEK_SET)?;
+        let ret = self.read(buf);
+        self.lseek(pos as i64, SEEK_SET)?;
+        ret
+    }
+
+    fn pwrite(&mut self, buf: &[u8], offset: u64) -> Result<usize, i32> {
+        let pos = self.lseek(0, SEEK_CUR)?;
+        self.lseek(offset as i64, SEEK_SET)?;
+        let ret = self.write(buf);
+        self.lseek(pos as i64, SEEK_SET)?;
+        ret
+    }
+
+    fn truncate(&mut self, _len: u64) -> Result<(), i32> {
+        Err(EINVAL)
+    }
+
+    // fill buf with struct linux_dirent64 records
+    fn getdents(&mut self, _buf: &mut [u8]) -> Result<usize, i32> {
+        Err(ENOTDIR)
+    }
+
+    // the host descriptor backing this file, if any
+    fn host_fd(&self) -> Option<i32> {
+        None
+    }
+
+    // the absolute guest path of a virtual directory, used as base for *at calls
+    fn path(&self) -> Option<&Path> {
+        None
+    }
+
+    // file status flags for fcntl(F_GETFL/F_SETFL)
+    fn get_flags(&self) -> Result<i32, i32> {
+        Ok(O_RDONLY)
+    }
+
+    fn set_flags(&mut self, _flags: i32) -> Result<(), i32> {
+        Ok(())
+    }
+}
+
+pub trait vfs_t {
+    fn open(
+        &mut self,
+        dir: Option<&dyn vfile_t>,
+        path: &CStr,
+        flags: i32,
+        mode: u32,
+    ) -> Result<Box<dyn vfile_t>, i32>;
+    fn stat(
+        &mut self,
+        dir: Option<&dyn vfile_t>,
+        path: &CStr,
+        flags: i32,
+    ) -> Result<libc::stat, i32>;
+    fn access(
+        &mut self,
+        dir: Option<&dyn vfile_t>,
+        path: &CStr,
+        mode: i32,
+        flags: i32,
+    ) -> Result<(), i32>;
+    fn readlink(&mut self, dir: Option<&dyn vfile_t>, path: &CStr) -> Result<Vec<u8>, i32>;
+    fn mkdir(&mut self, dir: Option<&dyn vfile_t>, path: &CStr, mode: u32) -> Result<(), i32>;
+    fn unlink(&mut self, dir: Option<&dyn vfile_t>, path: &CStr, flags: i32) -> Result<(), i32>;
+    fn rename(
+        &mut self,
+        olddir: Option<&dyn vfile_t>,
+        oldpath: &CStr,
+        newdir: Option<&dyn vfile_t>,
+        newpath: &CStr,
+        flags: u32,
+    ) -> Result<(), i32>;
+    fn link(
+        &mut self,
+        olddir: Option<&dyn vfile_t>,
+        oldpath: &CStr,
+        newdir: Option<&dyn vfile_t>,
+        newpath: &CStr,
+        flags: i32,
+    ) -> Result<(), i32>;
+    fn chdir(&mut self, path: &CStr) -> Result<(), i32>;
+    fn getcwd(&mut self) -> Result<Vec<u8>, i32>;
+
+    // host path handed to a re-executed emulator on execve
+    fn exec_path(&mut self, path: &CStr) -> Result<CString, i32>;
+
+    // host sun_path of a Unix domain socket at path, and back
+    fn socket_path(&mut self, path: &CStr) -> Result<Vec<u8>, i32>;
+    fn socket_guest_path(&self, sun_path: &[u8]) -> Vec<u8>;
+}
+
+fn last_errno() -> i32 {
+    std::io::Error::last_os_error().raw_os_error().unwrap()
+}
+
+pub fn host_ret(ret: i64) -> Result<u64, i32> {
+    if ret < 0 {
+        Err(last_errno())
+    } else {
+        Ok(ret as u64)
+    }
+}
+
+fn path_cstring(path: &Path) -> CString {
+    CString::new(path.as_os_str().as_bytes()).unwrap()
+}
+
+/*
+    host files
+*/
+pub struct host_file_t {
+    fd: i32,
+    owned: bool, // stdio is borrowed from the emulator and never closed
+}
+
+impl host_file_t {
+    pub fn new(fd: i32) -> host_file_t {
+        host_file_t { fd, owned: true }
+    }
+
+    pub fn borrowed(fd: i32) -> host_file_t {
+        host_file_t { fd, owned: false }
+    }
+}
+
+impl Drop for host_file_t {
+    fn drop(&mut self) {
+        if self.owned {
+            unsafe { libc::close(self.fd) };
+        }
+    }
+}
+
+impl vfile_t for host_file_t {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, i32> {
+        let ret = unsafe { libc::read(self.fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+        host_ret(ret as i64).map(|n| n as usize)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, i32> {
+        let ret = unsafe { libc::write(self.fd, buf.as_ptr() as *const libc::c_void, buf.len()) };
+        host_ret(ret as i64).map(|n| n as usize)
+    }
+
+    fn lseek(&mut self, offset: i64, whence: i32) -> Result<u64, i32> {
+        host_ret(unsafe { libc::lseek(self.fd, offset, whence) })
+    }
+
+    fn pread(&mut self, buf: &mut [u8], offset: u64) -> Result<usize, i32> {
+        let ret = unsafe {
+            libc::pread(
+                self.fd,
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+                offset as i64,
+            )
+        };
+        host_ret(ret as i64).map(|n| n as usize)
+    }
+
+    fn pwrite(&mut self, buf: &[u8], offset: u64) -> Result<usize, i32> {
+        let ret = unsafe {
+            libc::pwrite(
+                self.fd,
+                buf.as_ptr() as *const libc::c_void,
+                buf.len(),
+                offset as i64,
+            )
+        };
+        host_ret(ret as i64).map(|n| n as usize)
+    }
+
+    fn fstat(&mut self) -> Result<libc::stat, i32> {
+        let mut st: libc::stat = unsafe { mem::zeroed() };
+        host_ret(unsafe { libc::fstat(self.fd, &mut st) } as i64)?;
+        Ok(st)
+    }
+
+    fn truncate(&mut self, len: u64) -> Result<(), i32> {
+        host_ret(unsafe { libc::ftruncate(self.fd, len as i64) } as i64).map(|_| ())
+    }
+
+    fn getdents(&mut self, buf: &mut [u8]) -> Result<usize, i32> {
+        // struct linux_dirent64 is the same on every Linux architecture
+        let ret =
+            unsafe { libc::syscall(libc::SYS_getdents64, self.fd, buf.as_mut_ptr(), buf.len()) };
+        host_ret(ret).map(|n| n as usize)
+    }
+
+    fn host_fd(&self) -> Option<i32> {
+        Some(self.fd)
+    }
+
+    fn get_flags(&self) -> Result<i32, i32> {
+        host_ret(unsafe { libc::fcntl(self.fd, F_GETFL) } as i64).map(|flags| flags as i32)
+    }
+
+    fn set_flags(&mut self, flags: i32) -> Result<(), i32> {
+        host_ret(unsafe { libc::fcntl(self.fd, F_SETFL, flags) } as i64).map(|_| ())
+    }
+}
+
+fn host_dirfd(dir: Option<&dyn vfile_t>) -> Result<i32, i32> {
+    match dir {
+        None => Ok(AT_FDCWD),
+        Some(dir) => dir.host_fd().ok_or(ENOTDIR),
+    }
+}
+
+/*
+    host passthrough, optionally looking up absolute paths in a sysroot first
+*/
+pub struct host_vfs_t {
+    sysroot: Option<PathBuf>,
+}
+
+impl host_vfs_t {
+    pub fn new(sysroot: Option<PathBuf>) -> host_vfs_t {
+        host_vfs_t { sysroot }
+    }
+
+    /*
+        like qemu-user's -L, an absolute guest path is looked up in the sysroot
+        first and falls back to the host path when nothing exists there.
+    */
+    fn translate(&self, path: &CStr) -> CString {
+        let sysroot = match &self.sysroot {
+            Some(sysroot) => sysroot,
+            None => return path.to_owned(),
+        };
+        let bytes = path.to_bytes();
+        if bytes.first() != Some(&b'/') {
+            return path.to_owned();
+        }
+
+        let redirected = sysroot.join(OsStr::from_bytes(&bytes[1..]));
+        if fs::symlink_metadata(&redirected).is_ok() {
+            path_cstring(&redirected)
+        } else {
+            path.to_owned()
+        }
+    }
+}
+
+impl vfs_t for host_vfs_t {
+    fn open(
+        &mut self,
+        dir: Option<&dyn vfile_t>,
+        path: &CStr,
+        flags: i32,
+        mode: u32,
+    ) -> Result<Box<dyn vfile_t>, i32> {
+        let dirfd = host_dirfd(dir)?;
+        let path = self.translate(path);
+        let fd = unsafe { libc::openat(dirfd, path.as_ptr(), flags | O_CLOEXEC, mode) };
+        host_ret(fd as i64)?;
+        Ok(Box::new(host_file_t::new(fd)))
+    }
+
+    fn stat(
+        &mut self,
+        dir: Option<&dyn vfile_t>,
+        path: &CStr,
+        flags: i32,
+    ) -> Result<libc::stat, i32> {
+        let dirfd = host_dirfd(dir)?;
+        let path = self.translate(path);
+        let mut st: libc::stat = unsafe { mem::zeroed() };
+        host_ret(unsafe { libc::fstatat(dirfd, path.as_ptr(), &mut st, flags) } as i64)?;
+        Ok(st)
+    }
+
+    fn access(
+        &mut self,
+        dir: Option<&dyn vfile_t>,
+        path: &CStr,
+        mode: i32,
+        flags: i32,
+    ) -> Result<(), i32> {
+        let dirfd = host_dirfd(dir)?;
+        let path = self.translate(path);
+        host_ret(unsafe { libc::faccessat(dirfd, path.as_ptr(), mode, flags) } as i64)?;
+        Ok(())
+    }
+
+    fn readlink(&mut self, dir: Option<&dyn vfile_t>, path: &CStr) -> Result<Vec<u8>, i32> {
+        let dirfd = host_dirfd(dir)?;
+        let path = self.translate(path);
+        let mut buf = vec![0u8; libc::PATH_MAX as usize];
+        let ret = unsafe {
+            libc::readlinkat(
+                dirfd,
+                path.as_ptr(),
+                buf.as_mut_ptr() as *mut libc::c_char,
+                buf.len(),
+            )
+        };
+        buf.truncate(host_ret(ret as i64)? as usize);
+        Ok(buf)
+    }
+
+    fn mkdir(&mut self, dir: Option<&dyn vfile_t>, path: &CStr, mode: u32) -> Result<(), i32> {
+        let dirfd = host_dirfd(dir)?;
+        let path = self.translate(path);
+        host_ret(unsafe { libc::mkdirat(dirfd, path.as_ptr(), mode) } as i64)?;
+        Ok(())
+    }
+
+    fn unlink(&mut self, dir: Option<&dyn vfile_t>, path: &CStr, flags: i32) -> Result<(), i32> {
+        let dirfd = host_dirfd(dir)?;
+        let path = self.translate(path);
+        host_ret(unsafe { libc::unlinkat(dirfd, path.as_ptr(), flags) } as i64)?;
+        Ok(())
+    }
+
+    fn rename(
+        &mut self,
+        olddir: Option<&dyn vfile_t>,
+        oldpath: &CStr,
+        newdir: Option<&dyn vfile_t>,
+        newpath: &CStr,
+        flags: u32,
+    ) -> Result<(), i32> {
+        let (olddirfd, newdirfd) = (host_dirfd(olddir)?, host_dirfd(newdir)?);
+        let (oldpath, newpath) = (self.translate(oldpath), self.translate(newpath));
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_renameat2,
+                olddirfd,
+                oldpath.as_ptr(),
+                newdirfd,
+                newpath.as_ptr(),
+                flags,
+            )
+        };
+        host_ret(ret).map(|_| ())
+    }
+
+    fn link(
+        &mut self,
+        olddir: Option<&dyn vfile_t>,
+        oldpath: &CStr,
+        newdir: Option<&dyn vfile_t>,
+        newpath: &CStr,
+        flags: i32,
+    ) -> Result<(), i32> {
+        let (olddirfd, newdirfd) = (host_dirfd(olddir)?, host_dirfd(newdir)?);
+        let (oldpath, newpath) = (self.translate(oldpath), self.translate(newpath));
+        let ret = unsafe {
+            libc::linkat(
+                olddirfd,
+                oldpath.as_ptr(),
+                newdirfd,
+                newpath.as_ptr(),
+                flags,
+            )
+        };
+        host_ret(ret as i64).map(|_| ())
+    }
+
+    fn chdir(&mut self, path: &CStr) -> Result<(), i32> {
+        let path = self.translate(path);
+        host_ret(unsafe { libc::chdir(path.as_ptr()) } as i64)?;
+        Ok(())
+    }
+
+    // a cwd inside the sysroot is shown as the guest path
+    fn getcwd(&mut self) -> Result<Vec<u8>, i32> {
+        let cwd = std::env::current_dir().map_err(|e| e.raw_os_error().unwrap_or(ENOENT))?;
+        let sysroot = self.sysroot.as_ref().and_then(|s| fs::canonicalize(s).ok());
+        match sysroot.as_ref().and_then(|s| cwd.strip_prefix(s).ok()) {
+            Some(rel) => Ok(Path::new("/").join(rel).into_os_string().into_vec()),
+            None => Ok(cwd.into_os_string().into_vec()),
+        }
+    }
+
+    fn exec_path(&mut self, path: &CStr) -> Result<CString, i32> {
+        let path = self.translate(path);
+        host_ret(unsafe { libc::access(path.as_ptr(), libc::X_OK) } as i64)?;
+        Ok(path)
+    }
+
+    fn socket_path(&mut self, path: &CStr) -> Result<Vec<u8>, i32> {
+        Ok(self.translate(path).into_bytes())
+    }
+
+    fn socket_guest_path(&self, sun_path: &[u8]) -> Vec<u8> {
+        let path = Path::new(OsStr::from_bytes(sun_path));
+        match self
+            .sysroot
+            .as_ref()
+            .and_then(|s| path.strip_prefix(s).ok())
+        {
+            Some(rel) => Path::new("/").join(rel).into_os_string().into_vec(),
+            None => sun_path.to_vec(),
+        }
+    }
+}
+
+/*
+    helpers shared by the virtual filesystems
+*/
+const DT_DIR: u8 = 4;
+const DT_REG: u8 = 8;
+
+/*
+    a virtual filesystem has nowhere to put socket files, its Unix sockets
+    live in the abstract namespace under a name private to this emulator.
+*/
+fn abstract_socket_prefix() -> Vec<u8> {
+    format!("\0rvemu-riir/{}", std::process::id()).into_bytes()
+}
+
+fn abstract_socket_path(path: &Path) -> Vec<u8> {
+    let mut name = abstract_socket_prefix();
+    name.extend_from_slice(path.as_os_str().as_bytes());
+    name
+}
+
+fn abstract_socket_guest_path(sun_path: &[u8]) -> Vec<u8> {
+    match sun_path.strip_prefix(abstract_socket_prefix().as_slice()) {
+        Some(path) => path.to_vec(),
+        None => sun_path.to_vec(),
+    }
+}
+
+struct dirent_t {
+    ino: u64,
+    type_: u8,
+    name: Vec<u8>,
+}
+
+// lexically resolve a guest path against the cwd or an open directory
+pub fn resolve(cwd: &Path, dir: Option<&dyn vfile_t>, path: &CStr) -> Result<PathBuf, i32> {
+    let bytes = path.to_bytes();
+    if bytes.is_empty() {
+        return Err(ENOENT);
+    }
+    let base = if bytes[0] == b'/' {
+        Path::new("/")
+    } else {
+        match dir {
+            Some(dir) => dir.path().ok_or(ENOTDIR)?,
+            None => cwd,
+        }
+    };
+
+    let mut resolved = PathBuf::from("/");
+    for component in base.join(OsStr::from_bytes(bytes)).components() {
+        match component {
+            Component::Normal(name) => resolved.push(name),
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            _ => {}
+        }
+    }
+    Ok(resolved)
+}
+
+fn synth_stat(ino: u64, mode: u32, size: u64) -> libc::stat {
+    let mut st: libc::stat = unsafe { mem::zeroed() };
+    st.st_ino = ino;
+    st.st_mode = mode;
+    st.st_nlink = if mode & S_IFMT == S_IFDIR { 2 } else { 1 };
+    st.st_size = size as i64;
+    st.st_blksize = 4096;
+    st.st_blocks = size.div_ceil(512) as i64;
+    st
+}
+
+fn check_access(mode: u32, want: i32) -> Result<(), i32> {
+    let perm = (mode >> 6) & 0x7; // everything in a virtual fs belongs to the guest
+    if (want & libc::R_OK != 0 && perm & 0x4 == 0)
+        || (want & libc::W_OK != 0 && perm & 0x2 == 0)
+        || (want & libc::X_OK != 0 && perm & 0x1 == 0)
+    {
+        return Err(EACCES);
+    }
+    Ok(())
+}
+
+fn seek_pos(pos: u64, size: u64, offset: i64, whence: i32) -> Result<u64, i32> {
+    let base = match whence {
+        SEEK_SET => 0,
+        SEEK_CUR => pos as i64,
+        SEEK_END => size as i64,
+        _ => return Err(EINVAL),
+    };
+    match base.checked_add(offset) {
+        Some(new_pos) if new_pos >= 0 => Ok(new_pos as u64),
+        _ => Err(EINVAL),
+    }
+}
+
+/*
+    a directory handle listing a snapshot of its entries
+*/
+struct dir_file_t {
+    path: PathBuf,
+    ino: u64,
+    mode: u32,
+    entries: Vec<dirent_t>,
+    pos: usize,
+}
+
+impl vfile_t for dir_file_t {
+    fn read(&mut self, _buf: &mut [u8]) -> Result<usize, i32> {
+        Err(EISDIR)
+    }
+
+    fn write(&mut self, _buf: &[u8]) -> Result<usize, i32> {
+        Err(EBADF)
+    }
+
+    fn lseek(&mut self, offset: i64, whence: i32) -> Result<u64, i32> {
+        let pos = seek_pos(self.pos as u64, self.entries.len() as u64, offset, whence)?;
+        self.pos = pos as usize;
+        Ok(pos)
+    }
+
+    fn fstat(&mut self) -> Result<libc::stat, i32> {
+        Ok(synth_stat(self.ino, self.mode, 4096))
+    }
+
+    fn getdents(&mut self, buf: &mut [u8]) -> Result<usize, i32> {
+        let mut len: usize = 0;
+        while let Some(entry) = self.entries.get(self.pos) {
+            // d_ino, d_off, d_reclen, d_type, d_name
+            let reclen = (8 + 8 + 2 + 1 + entry.name.len() + 1).next_multiple_of(8);
+            if len + reclen > buf.len() {
+                if len == 0 {
+                    return Err(EINVAL);
+                }
+                break;
+            }
+            let rec = &mut buf[len..len + reclen];
+            rec.fill(0);
+            rec[0..8].copy_from_slice(&entry.ino.to_le_bytes());
+            rec[8..16].copy_from_slice(&(self.pos as u64 + 1).to_le_bytes());
+            rec[16..18].copy_from_slice(&(reclen as u16).to_le_bytes());
+            rec[18] = entry.type_;
+            rec[19..19 + entry.name.len()].copy_from_slice(&entry.name);
+            len += reclen;
+            self.pos += 1;
+        }
+        Ok(len)
+    }
+
+    fn path(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+}
+
+/*
+    in-memory files
+*/
+// the largest in-memory file, like the s_maxbytes of a filesystem
+pub const MEM_FILE_MAX: u64 = 1024 * 1024 * 1024;
+
+struct mem_file_t {
+    data: Rc<RefCell<Vec<u8>>>,
+    account: Rc<mem_account_t>,
+    ino: u64,
+    mode: u32,
+    pos: u64,
+    flags: i32,
+}
+
+impl mem_file_t {
+    // zero fill the file up to len bytes, if the guest memory allows
+    fn grow(&mut self, len: u64) -> Result<(), i32> {
+        let old_len = self.data.borrow().len() as u64;
+        if len <= old_len {
+            return Ok(());
+        }
+        if len > MEM_FILE_MAX {
+            return Err(EFBIG);
+        }
+        if !self.account.fits(len - old_len) {
+            return Err(ENOSPC);
+        }
+        let mut data = self.data.borrow_mut();
+        data.try_reserve_exact((len - old_len) as usize)
+            .map_err(|_| ENOSPC)?;
+        data.resize(len as usize, 0);
+        Ok(())
+    }
+}
+
+impl vfile_t for mem_file_t {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, i32> {
+        if self.flags & O_ACCMODE == O_WRONLY {
+            return Err(EBADF);
+        }
+        let data = self.data.borrow();
+        let start = (self.pos as usize).min(data.len());
+        let len = buf.len().min(data.len() - start);
+        buf[..len].copy_from_slice(&data[start..start + len]);
+        self.pos += len as u64;
+        Ok(len)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, i32> {
+        if self.flags & O_ACCMODE == O_RDONLY {
+            return Err(EBADF);
+        }
+        if self.flags & O_APPEND != 0 {
+            self.pos = self.data.borrow().len() as u64;
+        }
+        self.grow(self.pos.saturating_add(buf.len() as u64))?;
+        let start = self.pos as usize;
+        self.data.borrow_mut()[start..start + buf.len()].copy_from_slice(buf);
+        self.pos += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn lseek(&mut self, offset: i64, whence: i32) -> Result<u64, i32> {
+        self.pos = seek_pos(self.pos, self.data.borrow().len() as u64, offset, whence)?;
+        Ok(self.pos)
+    }
+
+    fn fstat(&mut self) -> Result<libc::stat, i32> {
+        Ok(synth_stat(
+            self.ino,
+            self.mode,
+            self.data.borrow().len() as u64,
+        ))
+    }
+
+    fn truncate(&mut self, len: u64) -> Result<(), i32> {
+        if self.flags & O_ACCMODE == O_RDONLY {
+            return Err(EINVAL);
+        }
+        self.grow(len)?;
+        self.data.borrow_mut().truncate(len as usize);
+        Ok(())
+    }
+
+    fn get_flags(&self) -> Result<i32, i32> {
+        Ok(self.flags)
+    }
+
+    fn set_flags(&mut self, flags: i32) -> Result<(), i32> {
+        self.flags = (self.flags & !O_APPEND) | (flags & O_APPEND);
+        Ok(())
+    }
+}
+
+// a read/write file over a shared buffer, for redirecting guest stdio into memory
+pub fn mem_buffer(data: Rc<RefCell<Vec<u8>>>) -> Box<dyn vfile_t> {
+    Box::new(mem_file_t {
+        data,
+        account: Rc::new(mem_account_t::new()),
+        ino: 0,
+        mode: S_IFREG | 0o600,
+        pos: 0,
+        flags: O_RDWR,
+    })
+}
+
+/*
+    in-memory filesystem
+*/
+#[derive(Clone)]
+enum mem_node_t {
+    dir,
+    file(Rc<RefCell<Vec<u8>>>),
+}
+
+#[derive(Clone)]
+struct mem_entry_t {
+    node: mem_node_t,
+    ino: u64,
+    mode: u32,
+}
+
+pub struct mem_vfs_t {
+    nodes: BTreeMap<PathBuf, mem_entry_t>,
+    next_ino: u64,
+    cwd: PathBuf,
+    account: Rc<mem_account_t>, // where the file contents are counted
+}
+
+impl mem_vfs_t {
+    pub fn new() -> mem_vfs_t {
+        let mut vfs = mem_vfs_t {
+            nodes: BTreeMap::new(),
+            next_ino: 1,
+            cwd: PathBuf::from("/"),
+            account: Rc::new(mem_account_t::new()),
+        };
+        vfs.insert(PathBuf::from("/"), mem_node_t::dir, S_IFDIR | 0o755);
+        vfs
+    }
+
+    // create a file, and any missing parent directory, before running the guest
+    pub fn add_file(&mut self, path: &str, data: &[u8]) {
+        let path = resolve(Path::new("/"), None, &CString::new(path).unwrap()).unwrap();
+        self.add_dir_all(path.parent().unwrap());
+        let data = Rc::new(RefCell::new(data.to_vec()));
+        self.insert(path, mem_node_t::file(data), S_IFREG | 0o644);
+    }
+
+    pub fn add_dir(&mut self, path: &str) {
+        let path = resolve(Path::new("/"), None, &CString::new(path).unwrap()).unwrap();
+        self.add_dir_all(&path);
+    }
+
+    // count the files against the memory of a machine, see mmu_t::account
+    pub fn set_account(&mut self, account: Rc<mem_account_t>) {
+        for entry in self.nodes.values() {
+            if let mem_node_t::file(data) = &entry.node {
+                account.add_file(data);
+            }
+        }
+        self.account = account;
+    }
+
+    // contents of a file, e.g. to collect the guest's output after it ran
+    pub fn read_file(&self, path: &str) -> Option<Vec<u8>> {
+        match self.nodes.get(Path::new(path)) {
+            Some(mem_entry_t {
+                node: mem_node_t::file(data),
+                ..
+            }) => Some(data.borrow().clone()),
+            _ => None,
+        }
+    }
+
+    fn add_dir_all(&mut self, path: &Path) {
+        for ancestor in path.ancestors().collect::<Vec<_>>().into_iter().rev() {
+            if !self.nodes.contains_key(ancestor) {
+                self.insert(ancestor.to_path_buf(), mem_node_t::dir, S_IFDIR | 0o755);
+            }
+        }
+    }
+
+    fn insert(&mut self, path: PathBuf, node: mem_node_t, mode: u32) -> mem_entry_t {
+        if let mem_node_t::file(data) = &node {
+            self.account.add_file(data);
+        }
+        let entry = mem_entry_t {
+            node,
+            ino: self.next_ino,
+            mode,
+        };
+        self.next_ino += 1;
+        self.nodes.insert(path, entry.clone());
+        entry
+    }
+
+    fn lookup(&self, path: &Path) -> Result<&mem_entry_t, i32> {
+        self.nodes.get(path).ok_or(ENOENT)
+    }
+
+    fn check_parent(&self, path: &Path) -> Result<(), i32> {
+        match path.parent() {
+            None => Err(EEXIST), // "/"
+            Some(parent) => match self.lookup(parent)?.node {
+                mem_node_t::dir => Ok(()),
+                mem_node_t::file(_) => Err(ENOTDIR),
+            },
+        }
+    }
+
+    fn children(&self, path: &Path) -> impl Iterator<Item = (&PathBuf, &mem_entry_t)> {
+        self.nodes
+            .range(path.to_path_buf()..)
+            .filter(move |(p, _)| p.parent() == Some(path))
+    }
+
+    fn list(&self, path: &Path) -> Vec<dirent_t> {
+        self.children(path)
+            .map(|(p, e)| dirent_t {
+                ino: e.ino,
+                type_: match e.node {
+                    mem_node_t::dir => DT_DIR,
+                    mem_node_t::file(_) => DT_REG,
+                },
+                name: p.file_name().unwrap().as_bytes().to_vec(),
+            })
+            .collect()
+    }
+
+    fn open_path(&mut self, path: PathBuf, flags: i32, mode: u32) -> Result<Box<dyn vfile_t>, i32> {
+        let entry = match self.nodes.get(&path) {
+            Some(_) if flags & O_CREAT != 0 && flags & O_EXCL != 0 => return Err(EEXIST),
+            Some(entry) => entry.clone(),
+            None if flags & O_CREAT != 0 => {
+                self.check_parent(&path)?;
+                let data = Rc::new(RefCell::new(Vec::new()));
+                self.insert(
+                    path.clone(),
+                    mem_node_t::file(data),
+                    S_IFREG | (mode & 0o777),
+                )
+            }
+            None => return Err(ENOENT),
+        };
+
+        match entry.node {
+            mem_node_t::dir => {
+                if flags & O_ACCMODE != O_RDONLY {
+                    return Err(EISDIR);
+                }
+                let mut entries = vec![
+                    dirent_t {
+                        ino: entry.ino,
+                        type_: DT_DIR,
+                        name: b".".to_vec(),
+                    },
+                    dirent_t {
+                        ino: self.lookup(path.parent().unwrap_or(&path))?.ino,
+                        type_: DT_DIR,
+                        name: b"..".to_vec(),
+                    },
+                ];
+                entries.extend(self.list(&path));
+                Ok(Box::new(dir_file_t {
+                    path,
+                    ino: entry.ino,
+                    mode: entry.mode,
+                    entries,
+                    pos: 0,
+                }))
+            }
+            mem_node_t::file(data) => {
+                if flags & O_DIRECTORY != 0 {
+                    return Err(ENOTDIR);
+                }
+                if flags & O_TRUNC != 0 && flags & O_ACCMODE != O_RDONLY {
+                    data.borrow_mut().clear();
+                }
+                Ok(Box::new(mem_file_t {
+                    data,
+                    account: self.account.clone(),
+                    ino: entry.ino,
+                    mode: entry.mode,
+                    pos: 0,
+                    flags,
+                }))
+            }
+        }
+    }
+
+    fn stat_path(&self, path: &Path) -> Result<libc::stat, i32> {
+        let entry = self.lookup(path)?;
+        let size = match &entry.node {
+            mem_node_t::dir => 4096,
+            mem_node_t::file(data) => data.borrow().len() as u64,
+        };
+        Ok(synth_stat(entry.ino, entry.mode, size))
+    }
+
+    fn mkdir_path(&mut self, path: PathBuf, mode: u32) -> Result<(), i32> {
+        if self.nodes.contains_key(&path) {
+            return Err(EEXIST);
+        }
+        self.check_parent(&path)?;
+        self.insert(path, mem_node_t::dir, S_IFDIR | (mode & 0o777));
+        Ok(())
+    }
+
+    fn unlink_path(&mut self, path: &Path, flags: i32) -> Result<(), i32> {
+        match self.lookup(path)?.node {
+            mem_node_t::dir if flags & AT_REMOVEDIR == 0 => return Err(EISDIR),
+            mem_node_t::dir if path == Path::new("/") => return Err(libc::EBUSY),
+            mem_node_t::dir if self.children(path).next().is_some() => return Err(ENOTEMPTY),
+            mem_node_t::file(_) if flags & AT_REMOVEDIR != 0 => return Err(ENOTDIR),
+            _ => {}
+        }
+        self.nodes.remove(path);
+        Ok(())
+    }
+}
+
+impl Default for mem_vfs_t {
+    fn default() -> mem_vfs_t {
+        mem_vfs_t::new()
+    }
+}
+
+impl mem_vfs_t {
+    fn rename_path(&mut self, old: &Path, new: &Path, flags: u32) -> Result<(), i32> {
+        let entry = self.lookup(old)?.clone();
+        self.check_parent(new)?;
+        if flags & !libc::RENAME_NOREPLACE != 0 {
+            return Err(EINVAL);
+        }
+        if old == new {
+            return Ok(());
+        }
+        if new.starts_with(old) || old == Path::new("/") {
+            return Err(EINVAL);
+        }
+        if let Some(target) = self.nodes.get(new) {
+            if flags & libc::RENAME_NOREPLACE != 0 {
+                return Err(EEXIST);
+            }
+            match (&entry.node, &target.node) {
+                (mem_node_t::dir, mem_node_t::file(_)) => return Err(ENOTDIR),
+                (mem_node_t::file(_), mem_node_t::dir) => return Err(EISDIR),
+                (mem_node_t::dir, mem_node_t::dir) if self.children(new).next().is_some() => {
+                    return Err(ENOTEMPTY);
+                }
+                _ => {}
+            }
+        }
+
+        // move the node and everything below it
+        let moved: Vec<PathBuf> = self
+            .nodes
+            .range(old.to_path_buf()..)
+            .take_while(|(p, _)| p.starts_with(old))
+            .map(|(p, _)| p.clone())
+            .collect();
+        // join("") would add a trailing slash to new itself
+        let moved_to = |path: &Path| match path.strip_prefix(old).unwrap() {
+            rel if rel.as_os_str().is_empty() => new.to_path_buf(),
+            rel => new.join(rel),
+        };
+        for path in moved {
+            let entry = self.nodes.remove(&path).unwrap();
+            self.nodes.insert(moved_to(&path), entry);
+        }
+        // the cwd may be anywhere below old
+        if self.cwd.starts_with(old) {
+            self.cwd = moved_to(&self.cwd);
+        }
+        Ok(())
+    }
+
+    // a hard link shares the data and inode number of the original
+    fn link_path(&mut self, old: &Path, new: &Path) -> Result<(), i32> {
+        let entry = self.lookup(old)?.clone();
+        if matches!(entry.node, mem_node_t::dir) {
+            return Err(libc::EPERM);
+        }
+        if self.nodes.contains_key(new) {
+            return Err(EEXIST);
+        }
+        self.check_parent(new)?;
+        self.nodes.insert(new.to_path_buf(), entry);
+        Ok(())
+    }
+}
+
+impl vfs_t for mem_vfs_t {
+    fn open(
+        &mut self,
+        dir: Option<&dyn vfile_t>,
+        path: &CStr,
+        flags: i32,
+        mode: u32,
+    ) -> Result<Box<dyn vfile_t>, i32> {
+        let path = resolve(&self.cwd, dir, path)?;
+        self.open_path(path, flags, mode)
+    }
+
+    fn stat(
+        &mut self,
+        dir: Option<&dyn vfile_t>,
+        path: &CStr,
+        _flags: i32,
+    ) -> Result<libc::stat, i32> {
+        let path = resolve(&self.cwd, dir, path)?;
+        self.stat_path(&path)
+    }
+
+    fn access(
+        &mut self,
+        dir: Option<&dyn vfile_t>,
+        path: &CStr,
+        mode: i32,
+        _flags: i32,
+    ) -> Result<(), i32> {
+        let path = resolve(&self.cwd, dir, path)?;
+        check_access(self.lookup(&path)?.mode, mode)
+    }
+
+    fn readlink(&mut self, dir: Option<&dyn vfile_t>, path: &CStr) -> Result<Vec<u8>, i32> {
+        let path = resolve(&self.cwd, dir, path)?;
+        self.lookup(&path)?;
+        Err(EINVAL) // no symlinks in memory
+    }
+
+    fn mkdir(&mut self, dir: Option<&dyn vfile_t>, path: &CStr, mode: u32) -> Result<(), i32> {
+        let path = resolve(&self.cwd, dir, path)?;
+        self.mkdir_path(path, mode)
+    }
+
+    fn unlink(&mut self, dir: Option<&dyn vfile_t>, path: &CStr, flags: i32) -> Result<(), i32> {
+        let path = resolve(&self.cwd, dir, path)?;
+        self.unlink_path(&path, flags)
+    }
+
+    fn rename(
+        &mut self,
+        olddir: Option<&dyn vfile_t>,
+        oldpath: &CStr,
+        newdir: Option<&dyn vfile_t>,
+        newpath: &CStr,
+        flags: u32,
+    ) -> Result<(), i32> {
+        let old = resolve(&self.cwd, olddir, oldpath)?;
+        let new = resolve(&self.cwd, newdir, newpath)?;
+        self.rename_path(&old, &new, flags)
+    }
+
+    fn link(
+        &mut self,
+        olddir: Option<&dyn vfile_t>,
+        oldpath: &CStr,
+        newdir: Option<&dyn vfile_t>,
+        newpath: &CStr,
+        _flags: i32,
+    ) -> Result<(), i32> {
+        let old = resolve(&self.cwd, olddir, oldpath)?;
+        let new = resolve(&self.cwd, newdir, newpath)?;
+        self.link_path(&old, &new)
+    }
+
+    fn getcwd(&mut self) -> Result<Vec<u8>, i32> {
+        Ok(self.cwd.as_os_str().as_bytes().to_vec())
+    }
+
+    fn chdir(&mut self, path: &CStr) -> Result<(), i32> {
+        let path = resolve(&self.cwd, None, path)?;
+        match self.lookup(&path)?.node {
+            mem_node_t::dir => {
+                self.cwd = path;
+                Ok(())
+            }
+            mem_node_t::file(_) => Err(ENOTDIR),
+        }
+    }
+
+    fn exec_path(&mut self, _path: &CStr) -> Result<CString, i32> {
+        Err(EACCES) // the emulator can only load programs from the host
+    }
+
+    fn socket_path(&mut self, path: &CStr) -> Result<Vec<u8>, i32> {
+        Ok(abstract_socket_path(&resolve(&self.cwd, None, path)?))
+    }
+
+    fn socket_guest_path(&self, sun_path: &[u8]) -> Vec<u8> {
+        abstract_socket_guest_path(sun_path)
+    }
+}
+
+/*
+    a host directory seen read-only, with every change kept in memory on top.
+    Lookups in the directory are confined to it with RESOLVE_IN_ROOT, so
+    neither ".." nor symlinks can reach the rest of the host.
+*/
+pub struct overlay_vfs_t {
+    lower: fs::File,
+    upper: mem_vfs_t,
+    whiteouts: HashSet<PathBuf>,
+    exec_file: Option<host_file_t>, // the program of a pending execve
+}
+
+impl overlay_vfs_t {
+    pub fn new(lower_path: &Path) -> std::io::Result<overlay_vfs_t> {
+        let lower = fs::File::open(lower_path)?;
+        if !lower.metadata()?.is_dir() {
+            return Err(std::io::Error::from_raw_os_error(ENOTDIR));
+        }
+        let mut upper = mem_vfs_t::new();
+        upper.nodes.clear(); // only holds what was changed
+        Ok(overlay_vfs_t {
+            lower,
+            upper,
+            whiteouts: HashSet::new(),
+            exec_file: None,
+        })
+    }
+
+    // count the changed files against the memory of a machine
+    pub fn set_account(&mut self, account: Rc<mem_account_t>) {
+        self.upper.set_account(account);
+    }
+
+    fn lower_open(&self, path: &Path, flags: i32) -> Result<host_file_t, i32> {
+        if self.is_whiteout(path) {
+            return Err(ENOENT);
+        }
+        let rel = path_cstring(&Path::new(".").join(path.strip_prefix("/").unwrap()));
+        let mut how: libc::open_how = unsafe { mem::zeroed() };
+        how.flags = (flags | O_CLOEXEC) as u64;
+        how.resolve = libc::RESOLVE_IN_ROOT;
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_openat2,
+                std::os::fd::AsRawFd::as_raw_fd(&self.lower),
+                rel.as_ptr(),
+                &how as *const libc::open_how,
+                mem::size_of::<libc::open_how>(),
+            )
+        };
+        host_ret(fd)?;
+        Ok(host_file_t::new(fd as i32))
+    }
+
+    fn lower_stat(&self, path: &Path, flags: i32) -> Result<libc::stat, i32> {
+        let nofollow = if flags & AT_SYMLINK_NOFOLLOW != 0 {
+            O_NOFOLLOW
+        } else {
+            0
+        };
+        self.lower_open(path, O_PATH | nofollow)?.fstat()
+    }
+
+    fn is_whiteout(&self, path: &Path) -> bool {
+        path.ancestors().any(|p| self.whiteouts.contains(p))
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.upper.nodes.contains_key(path) || self.lower_stat(path, 0).is_ok()
+    }
+
+    fn is_dir(&self, path: &Path) -> Result<bool, i32> {
+        match self.upper.nodes.get(path) {
+            Some(entry) => Ok(matches!(entry.node, mem_node_t::dir)),
+            None => Ok(self.lower_stat(path, 0)?.st_mode & S_IFMT == S_IFDIR),
+        }
+    }
+
+    fn check_parent(&self, path: &Path) -> Result<(), i32> {
+        match path.parent() {
+            None => Err(EEXIST),
+            Some(parent) if self.is_dir(parent)? => Ok(()),
+            Some(_) => Err(ENOTDIR),
+        }
+    }
+
+    // mirror the lower directories of path in the upper layer
+    fn copy_up_dirs(&mut self, path: &Path) {
+        for ancestor in path.ancestors().collect::<Vec<_>>().into_iter().rev() {
+            if !self.upper.nodes.contains_key(ancestor) {
+                let mode = match self.lower_stat(ancestor, 0) {
+                    Ok(st) => st.st_mode,
+                    Err(_) => S_IFDIR | 0o755,
+                };
+                self.upper
+                    .insert(ancestor.to_path_buf(), mem_node_t::dir, mode);
+            }
+        }
+    }
+
+    fn copy_up(&mut self, path: &Path) -> Result<(), i32> {
+        let mut lower = self.lower_open(path, O_RDONLY)?;
+        let st = lower.fstat()?;
+        if st.st_mode & S_IFMT != S_IFREG {
+            return Err(EROFS);
+        }
+        if st.st_size as u64 > MEM_FILE_MAX {
+            return Err(EFBIG);
+        }
+        if !self.upper.account.fits(st.st_size as u64) {
+            return Err(ENOSPC);
+        }
+        let mut data = Vec::with_capacity(st.st_size as usize);
+        let mut buf = [0u8; 4096];
+        loop {
+            let len = lower.read(&mut buf)?;
+            if len == 0 {
+                break;
+            }
+            data.extend_from_slice(&buf[..len]);
+        }
+        self.copy_up_dirs(path.parent().unwrap());
+        let data = Rc::new(RefCell::new(data));
+        self.upper
+            .insert(path.to_path_buf(), mem_node_t::file(data), st.st_mode);
+        Ok(())
+    }
+
+    fn list(&self, path: &Path) -> Result<Vec<dirent_t>, i32> {
+        let mut entries = self.upper.list(path);
+        let mut names: HashSet<Vec<u8>> = entries.iter().map(|e| e.name.clone()).collect();
+
+        let mut lower = match self.lower_open(path, O_RDONLY | O_DIRECTORY) {
+            Ok(lower) => lower,
+            Err(ENOENT) => return Ok(entries),
+            Err(e) => return Err(e),
+        };
+        let mut buf = vec![0u8; 16384];
+        loop {
+            let len = lower.getdents(&mut buf)?;
+            if len == 0 {
+                break;
+            }
+            let mut off = 0;
+            while off < len {
+                let rec = &buf[off..];
+                let reclen = u16::from_le_bytes([rec[16], rec[17]]) as usize;
+                let name = CStr::from_bytes_until_nul(&rec[19..reclen])
+                    .unwrap()
+                    .to_bytes();
+                off += reclen;
+                if name == b"." || name == b".." || names.contains(name) {
+                    continue;
+                }
+                if self.whiteouts.contains(&path.join(OsStr::from_bytes(name))) {
+                    continue;
+                }
+                names.insert(name.to_vec());
+                entries.push(dirent_t {
+                    ino: u64::from_le_bytes(rec[0..8].try_into().unwrap()),
+                    type_: rec[18],
+                    name: name.to_vec(),
+                });
+            }
+        }
+        Ok(entries)
+    }
+}
+
+impl vfs_t for overlay_vfs_t {
+    fn open(
+        &mut self,
+        dir: Option<&dyn vfile_t>,
+        path: &CStr,
+        flags: i32,
+        mode: u32,
+    ) -> Result<Box<dyn vfile_t>, i32> {
+        let path = resolve(&self.upper.cwd, dir, path)?;
+        let writing = flags & O_ACCMODE != O_RDONLY || flags & O_TRUNC != 0;
+
+        if !self.upper.nodes.contains_key(&path) {
+            let exists = self.exists(&path);
+            if exists && flags & O_CREAT != 0 && flags & O_EXCL != 0 {
+                return Err(EEXIST);
+            }
+            if exists && self.is_dir(&path)? {
+                if writing {
+                    return Err(EISDIR);
+                }
+                let st = self.lower_stat(&path, 0)?;
+                let mut entries = vec![
+                    dirent_t {
+                        ino: st.st_ino,
+                        type_: DT_DIR,
+                        name: b".".to_vec(),
+                    },
+                    dirent_t {
+                        ino: st.st_ino,
+                        type_: DT_DIR,
+                        name: b"..".to_vec(),
+                    },
+                ];
+                entries.extend(self.list(&path)?);
+                return Ok(Box::new(dir_file_t {
+                    path,
+                    ino: st.st_ino,
+                    mode: st.st_mode,
+                    entries,
+                    pos: 0,
+                }));
+            }
+            if exists && !writing {
+                return Ok(Box::new(self.lower_open(&path, flags & O_DIRECTORY)?));
+            }
+            if exists {
+                self.copy_up(&path)?;
+            } else if flags & O_CREAT != 0 {
+                self.check_parent(&path)?;
+                self.copy_up_dirs(path.parent().unwrap());
+                self.whiteouts.remove(&path);
+            } else {
+                return Err(ENOENT);
+            }
+        } else if let Some(mem_entry_t {
+            node: mem_node_t::dir,
+            ..
+        }) = self.upper.nodes.get(&path)
+        {
+            // merge the listing of both layers
+            if writing {
+                return Err(EISDIR);
+            }
+            let entry = self.upper.nodes[&path].clone();
+            let mut entries = vec![
+                dirent_t {
+                    ino: entry.ino,
+                    type_: DT_DIR,
+                    name: b".".to_vec(),
+                },
+                dirent_t {
+                    ino: entry.ino,
+                    type_: DT_DIR,
+                    name: b"..".to_vec(),
+                },
+            ];
+            entries.extend(self.list(&path)?);
+            return Ok(Box::new(dir_file_t {
+                path,
+                ino: entry.ino,
+                mode: entry.mode,
+                entries,
+                pos: 0,
+            }));
+        }
+        self.upper.open_path(path, flags, mode)
+    }
+
+    fn stat(
+        &mut self,
+        dir: Option<&dyn vfile_t>,
+        path: &CStr,
+        flags: i32,
+    ) -> Result<libc::stat, i32> {
+        let path = resolve(&self.upper.cwd, dir, path)?;
+        match self.upper.nodes.contains_key(&path) {
+            true => self.upper.stat_path(&path),
+            false => self.lower_stat(&path, flags),
+        }
+    }
+
+    fn access(
+        &mut self,
+        dir: Option<&dyn vfile_t>,
+        path: &CStr,
+        mode: i32,
+        _flags: i32,
+    ) -> Result<(), i32> {
+        let path = resolve(&self.upper.cwd, dir, path)?;
+        match self.upper.nodes.get(&path) {
+            Some(entry) => check_access(entry.mode, mode),
+            None => check_access(self.lower_stat(&path, 0)?.st_mode, mode),
+        }
+    }
+
+    fn readlink(&mut self, dir: Option<&dyn vfile_t>, path: &CStr) -> Result<Vec<u8>, i32> {
+        let path = resolve(&self.upper.cwd, dir, path)?;
+        if self.upper.nodes.contains_key(&path) {
+            return Err(EINVAL);
+        }
+        let link = self.lower_open(&path, O_PATH | O_NOFOLLOW)?;
+        let mut buf = vec![0u8; libc::PATH_MAX as usize];
+        let ret = unsafe {
+            libc::readlinkat(
+                link.fd,
+                c"".as_ptr(),
+                buf.as_mut_ptr() as *mut libc::c_char,
+                buf.len(),
+            )
+        };
+        buf.truncate(host_ret(ret as i64).map_err(|_| EINVAL)? as usize);
+        Ok(buf)
+    }
+
+    fn mkdir(&mut self, dir: Option<&dyn vfile_t>, path: &CStr, mode: u32) -> Result<(), i32> {
+        let path = resolve(&self.upper.cwd, dir, path)?;
+        if self.exists(&path) {
+            return Err(EEXIST);
+        }
+        self.check_parent(&path)?;
+        self.copy_up_dirs(path.parent().unwrap());
+        self.whiteouts.remove(&path);
+        self.upper.mkdir_path(path, mode)
+    }
+
+    fn unlink(&mut self, dir: Option<&dyn vfile_t>, path: &CStr, flags: i32) -> Result<(), i32> {
+        let path = resolve(&self.upper.cwd, dir, path)?;
+        // a symlink is removed itself, even when it dangles
+        let is_dir = match self.upper.nodes.get(&path) {
+            Some(entry) => matches!(entry.node, mem_node_t::dir),
+            None => self.lower_stat(&path, AT_SYMLINK_NOFOLLOW)?.st_mode & S_IFMT == S_IFDIR,
+        };
+        if is_dir && flags & AT_REMOVEDIR == 0 {
+            return Err(EISDIR);
+        }
+        if !is_dir && flags & AT_REMOVEDIR != 0 {
+            return Err(ENOTDIR);
+        }
+        if is_dir && !self.list(&path)?.is_empty() {
+            return Err(ENOTEMPTY);
+        }
+        if self.upper.nodes.contains_key(&path) {
+            self.upper.nodes.remove(&path);
+        }
+        if self.lower_stat(&path, AT_SYMLINK_NOFOLLOW).is_ok() {
+            self.whiteouts.insert(path);
+        }
+        Ok(())
+    }
+
+    /*
+        like overlayfs without redirect_dir, directories of the lower layer
+        cannot be renamed and the guest gets EXDEV to copy them instead.
+    */
+    fn rename(
+        &mut self,
+        olddir: Option<&dyn vfile_t>,
+        oldpath: &CStr,
+        newdir: Option<&dyn vfile_t>,
+        newpath: &CStr,
+        flags: u32,
+    ) -> Result<(), i32> {
+        let old = resolve(&self.upper.cwd, olddir, oldpath)?;
+        let new = resolve(&self.upper.cwd, newdir, newpath)?;
+        if !self.exists(&old) {
+            return Err(ENOENT);
+        }
+        self.check_parent(&new)?;
+        let old_is_dir = self.is_dir(&old)?;
+        if self.exists(&new) {
+            if flags & libc::RENAME_NOREPLACE != 0 {
+                return Err(EEXIST);
+            }
+            match (old_is_dir, self.is_dir(&new)?) {
+                (true, false) => return Err(ENOTDIR),
+                (false, true) => return Err(EISDIR),
+                (true, true) if !self.list(&new)?.is_empty() => return Err(ENOTEMPTY),
+                _ => {}
+            }
+        }
+        if old == new {
+            return Ok(());
+        }
+        let in_lower = self.lower_stat(&old, AT_SYMLINK_NOFOLLOW).is_ok();
+        if old_is_dir && in_lower {
+            return Err(libc::EXDEV);
+        }
+        if !self.upper.nodes.contains_key(&old) {
+            self.copy_up(&old)?;
+        }
+        self.copy_up_dirs(new.parent().unwrap());
+        self.upper.nodes.remove(&new);
+        self.upper.rename_path(&old, &new, flags)?;
+        self.whiteouts.remove(&new);
+        if in_lower {
+            self.whiteouts.insert(old);
+        }
+        Ok(())
+    }
+
+    fn link(
+        &mut self,
+        olddir: Option<&dyn vfile_t>,
+        oldpath: &CStr,
+        newdir: Option<&dyn vfile_t>,
+        newpath: &CStr,
+        _flags: i32,
+    ) -> Result<(), i32> {
+        let old = resolve(&self.upper.cwd, olddir, oldpath)?;
+        let new = resolve(&self.upper.cwd, newdir, newpath)?;
+        if self.is_dir(&old)? {
+            return Err(libc::EPERM);
+        }
+        if self.exists(&new) {
+            return Err(EEXIST);
+        }
+        self.check_parent(&new)?;
+        if !self.upper.nodes.contains_key(&old) {
+            self.copy_up(&old)?;
+        }
+        self.copy_up_dirs(new.parent().unwrap());
+        self.whiteouts.remove(&new);
+        self.upper.link_path(&old, &new)
+    }
+
+    fn getcwd(&mut self) -> Result<Vec<u8>, i32> {
+        self.upper.getcwd()
+    }
+
+    fn chdir(&mut self, path: &CStr) -> Result<(), i32> {
+        let path = resolve(&self.upper.cwd, None, path)?;
+        if !self.is_dir(&path)? {
+            return Err(ENOTDIR);
+        }
+        self.upper.cwd = path;
+        Ok(())
+    }
+
+    fn exec_path(&mut self, path: &CStr) -> Result<CString, i32> {
+        let path = resolve(&self.upper.cwd, None, path)?;
+        if self.upper.nodes.contains_key(&path) {
+            return Err(EACCES); // written by the guest, only exists in memory
+        }
+        /*
+            the program is opened inside the directory and handed over as
+            /proc/self/fd/N, a host path could follow a symlink out of it.
+            the fd must survive the execve, it is closed by the next call.
+        */
+        let mut file = self.lower_open(&path, O_RDONLY)?;
+        check_access(file.fstat()?.st_mode, libc::X_OK)?;
+        let fd = file.host_fd().unwrap();
+        host_ret(unsafe { libc::fcntl(fd, libc::F_SETFD, 0) } as i64)?;
+        self.exec_file = Some(file);
+        Ok(CString::new(format!("/proc/self/fd/{}", fd)).unwrap())
+    }
+
+    fn socket_path(&mut self, path: &CStr) -> Result<Vec<u8>, i32> {
+        self.upper.socket_path(path)
+    }
+
+    fn socket_guest_path(&self, sun_path: &[u8]) -> Vec<u8> {
+        self.upper.socket_guest_path(sun_path)
+    }
+}
+
+// handle AT_EMPTY_PATH for the *at calls that accept it
+pub fn is_empty_path(path: &CStr, flags: i32) -> bool {
+    path.is_empty() && flags & AT_EMPTY_PATH != 0
+}
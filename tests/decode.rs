@@ -0,0 +1,323 @@
+#![allow(non_camel_case_types)]
+
+use rvemu_riir::{
+    decode::insn_decode,
+    insn::{insn_t, insn_type_t, insn_type_t::*},
+    isa::isa_t,
+};
+
+// a field the decoder leaves alone, or fills with something unused
+const N: i64 = i64::MIN;
+
+type case_t = (u32, insn_type_t, i64, i64, i64, i64); // bits, type, rd, rs1, rs2, imm
+
+// encodings by llvm-mc, the comment is the instruction they came from
+const CASES: &[case_t] = &[
+    (0x12345537, insn_lui, 10, 0, 0, 0x12345000), // lui a0, 0x12345
+    (0xfffff2b7, insn_lui, 5, 0, 0, -0x1000),     // lui t0, 0xfffff
+    (0x80000097, insn_auipc, 1, 0, 0, -0x80000000), // auipc ra, 0x80000
+    (0x001000ef, insn_jal, 1, 0, 0, 2048),        // jal ra, 2048
+    (0xffdff06f, insn_jal, 0, 0, 0, -4),          // jal zero, -4
+    (0x0008006f, insn_jal, 0, 0, 0, 0x80000),     // jal zero, 524288
+    (0x8000006f, insn_jal, 0, 0, 0, -0x100000),   // jal zero, -1048576
+    (0x010780e7, insn_jalr, 1, 15, 0, 16),        // jalr ra, 16(a5)
+    (0xfeb50ce3, insn_beq, 0, 10, 11, -8),        // beq a0, a1, -8
+    (0x7e629fe3, insn_bne, 0, 5, 6, 4094),        // bne t0, t1, 4094
+    (0x80944063, insn_blt, 0, 8, 9, -0x1000),     // blt s0, s1, -4096
+    (0x00d65663, insn_bge, 0, 12, 13, 12),        // bge a2, a3, 12
+    (0x00f76863, insn_bltu, 0, 14, 15, 16),       // bltu a4, a5, 16
+    (0x01187a63, insn_bgeu, 0, 16, 17, 20),       // bgeu a6, a7, 20
+    (0xfff10503, insn_lb, 10, 2, 0, -1),          // lb a0, -1(sp)
+    (0x00211503, insn_lh, 10, 2, 0, 2),           // lh a0, 2(sp)
+    (0x7ff12503, insn_lw, 10, 2, 0, 2047),        // lw a0, 2047(sp)
+    (0x80013503, insn_ld, 10, 2, 0, -2048),       // ld a0, -2048(sp)
+    (0x0003c303, insn_lbu, 6, 7, 0, 0),           // lbu t1, 0(t2)
+    (0x0043d303, insn_lhu, 6, 7, 0, 4),           // lhu t1, 4(t2)
+    (0x0083e303, insn_lwu, 6, 7, 0, 8),           // lwu t1, 8(t2)
+    (0xfeb50fa3, insn_sb, 0, 10, 11, -1),         // sb a1, -1(a0)
+    (0x00b51123, insn_sh, 0, 10, 11, 2),          // sh a1, 2(a0)
+    (0x7eb52fa3, insn_sw, 0, 10, 11, 2047),       // sw a1, 2047(a0)
+    (0x80b53023, insn_sd, 0, 10, 11, -2048),      // sd a1, -2048(a0)
+    (0xfff58513, insn_addi, 10, 11, 0, -1),       // addi a0, a1, -1
+    (0x0055a513, insn_slti, 10, 11, 0, 5),        // slti a0, a1, 5
+    (0xffb5b513, insn_sltiu, 10, 11, 0, -5),      // sltiu a0, a1, -5
+    (0x7ff5c513, insn_xori, 10, 11, 0, 2047),     // xori a0, a1, 0x7ff
+    (0x8005e513, insn_ori, 10, 11, 0, -2048),     // ori a0, a1, -2048
+    (0x0ff5f513, insn_andi, 10, 11, 0, 255),      // andi a0, a1, 255
+    (0x03f59513, insn_slli, 10, 11, 0, 63),       // slli a0, a1, 63
+    (0x0215d513, insn_srli, 10, 11, 0, 33),       // srli a0, a1, 33
+    (0x4015d513, insn_srai, 10, 11, 0, 1),        // srai a0, a1, 1
+    (0x00c58533, insn_add, 10, 11, 12, 0),        // add a0, a1, a2
+    (0x40c58533, insn_sub, 10, 11, 12, 0),        // sub a0, a1, a2
+    (0x01499933, insn_sll, 18, 19, 20, 0),        // sll s2, s3, s4
+    (0x0149a933, insn_slt, 18, 19, 20, 0),        // slt s2, s3, s4
+    (0x0149b933, insn_sltu, 18, 19, 20, 0),       // sltu s2, s3, s4
+    (0x01eece33, insn_xor, 28, 29, 30, 0),        // xor t3, t4, t5
+    (0x01eede33, insn_srl, 28, 29, 30, 0),        // srl t3, t4, t5
+    (0x41eede33, insn_sra, 28, 29, 30, 0),        // sra t3, t4, t5
+    (0x01feee33, insn_or, 28, 29, 31, 0),         // or t3, t4, t6
+    (0x01fefe33, insn_and, 28, 29, 31, 0),        // and t3, t4, t6
+    (0xfff5051b, insn_addiw, 10, 10, 0, -1),      // addiw a0, a0, -1
+    (0x01f5951b, insn_slliw, 10, 11, 0, 31),      // slliw a0, a1, 31
+    (0x01f5d51b, insn_srliw, 10, 11, 0, 31),      // srliw a0, a1, 31
+    (0x41f5d51b, insn_sraiw, 10, 11, 0, N),       // sraiw a0, a1, 31
+    (0x00c5853b, insn_addw, 10, 11, 12, 0),       // addw a0, a1, a2
+    (0x40c5853b, insn_subw, 10, 11, 12, 0),       // subw a0, a1, a2
+    (0x00c5953b, insn_sllw, 10, 11, 12, 0),       // sllw a0, a1, a2
+    (0x00c5d53b, insn_srlw, 10, 11, 12, 0),       // srlw a0, a1, a2
+    (0x40c5d53b, insn_sraw, 10, 11, 12, 0),       // sraw a0, a1, a2
+    (0x0ff0000f, insn_fence, N, N, N, N),         // fence
+    (0x0000100f, insn_fence_i, N, N, N, N),       // fence.i
+    (0x00000073, insn_ecall, N, N, N, N),         // ecall
+    (0x00100073, insn_ebreak, N, N, N, N),        // ebreak
+    (0x02c58533, insn_mul, 10, 11, 12, 0),        // mul a0, a1, a2
+    (0x02c59533, insn_mulh, 10, 11, 12, 0),       // mulh a0, a1, a2
+    (0x02c5a533, insn_mulhsu, 10, 11, 12, 0),     // mulhsu a0, a1, a2
+    (0x02c5b533, insn_mulhu, 10, 11, 12, 0),      // mulhu a0, a1, a2
+    (0x02c5c533, insn_div, 10, 11, 12, 0),        // div a0, a1, a2
+    (0x02c5d533, insn_divu, 10, 11, 12, 0),       // divu a0, a1, a2
+    (0x02c5e533, insn_rem, 10, 11, 12, 0),        // rem a0, a1, a2
+    (0x02c5f533, insn_remu, 10, 11, 12, 0),       // remu a0, a1, a2
+    (0x02c5853b, insn_mulw, 10, 11, 12, 0),       // mulw a0, a1, a2
+    (0x02c5c53b, insn_divw, 10, 11, 12, 0),       // divw a0, a1, a2
+    (0x02c5d53b, insn_divuw, 10, 11, 12, 0),      // divuw a0, a1, a2
+    (0x02c5e53b, insn_remw, 10, 11, 12, 0),       // remw a0, a1, a2
+    (0x02c5f53b, insn_remuw, 10, 11, 12, 0),      // remuw a0, a1, a2
+    (0x1005a52f, insn_lr_w, 10, 11, 0, 0),        // lr.w a0, (a1)
+    (0x18c5a52f, insn_sc_w, 10, 11, 12, 0),       // sc.w a0, a2, (a1)
+    (0x0cc5a52f, insn_amoswap_w, 10, 11, 12, 0),  // amoswap.w.aq a0, a2, (a1)
+    (0x00c5a52f, insn_amoadd_w, 10, 11, 12, 0),   // amoadd.w a0, a2, (a1)
+    (0xe6c5a52f, insn_amomaxu_w, 10, 11, 12, 0),  // amomaxu.w.aqrl a0, a2, (a1)
+    (0x1605b52f, insn_lr_d, 10, 11, 0, 0),        // lr.d.aqrl a0, (a1)
+    (0x1ac5b52f, insn_sc_d, 10, 11, 12, 0),       // sc.d.rl a0, a2, (a1)
+    (0x20c5b52f, insn_amoxor_d, 10, 11, 12, 0),   // amoxor.d a0, a2, (a1)
+    (0x60c5b52f, insn_amoand_d, 10, 11, 12, 0),   // amoand.d a0, a2, (a1)
+    (0x40c5b52f, insn_amoor_d, 10, 11, 12, 0),    // amoor.d a0, a2, (a1)
+    (0x80c5b52f, insn_amomin_d, 10, 11, 12, 0),   // amomin.d a0, a2, (a1)
+    (0xa0c5b52f, insn_amomax_d, 10, 11, 12, 0),   // amomax.d a0, a2, (a1)
+    (0xc0c5b52f, insn_amominu_d, 10, 11, 12, 0),  // amominu.d a0, a2, (a1)
+    (0x00359573, insn_csrrw, 10, 11, 0, 0),       // csrrw a0, fcsr, a1
+    (0xc0002573, insn_csrrs, 10, 0, 0, 0),        // csrrs a0, cycle, zero
+    (0x0015b573, insn_csrrc, 10, 11, 0, 0),       // csrrc a0, fflags, a1
+    (0x0021d573, insn_csrrwi, 10, 3, 0, 0),       // csrrwi a0, frm, 3
+    (0x0010e573, insn_csrrsi, 10, 1, 0, 0),       // csrrsi a0, fflags, 1
+    (0x001ff573, insn_csrrci, 10, 31, 0, 0),      // csrrci a0, fflags, 31
+    (0xffc52007, insn_flw, 0, 10, 0, -4),         // flw ft0, -4(a0)
+    (0x00152427, insn_fsw, 0, 10, 1, 8),          // fsw ft1, 8(a0)
+    (0x01013507, insn_fld, 10, 2, 0, 16),         // fld fa0, 16(sp)
+    (0xfeb13827, insn_fsd, 0, 2, 11, -16),        // fsd fa1, -16(sp)
+    (0x00c5f553, insn_fadd_s, 10, 11, 12, N),     // fadd.s fa0, fa1, fa2
+    (0x0ac59553, insn_fsub_d, 10, 11, 12, N),     // fsub.d fa0, fa1, fa2, rtz
+    (0x1020f053, insn_fmul_s, 0, 1, 2, N),        // fmul.s ft0, ft1, ft2
+    (0x1a20f053, insn_fdiv_d, 0, 1, 2, N),        // fdiv.d ft0, ft1, ft2
+    (0x5804f453, insn_fsqrt_s, 8, 9, 0, N),       // fsqrt.s fs0, fs1
+    (0x5a04f453, insn_fsqrt_d, 8, 9, 0, N),       // fsqrt.d fs0, fs1
+    (0x20c58553, insn_fsgnj_s, 10, 11, 12, N),    // fsgnj.s fa0, fa1, fa2
+    (0x22c59553, insn_fsgnjn_d, 10, 11, 12, N),   // fsgnjn.d fa0, fa1, fa2
+    (0x20c5a553, insn_fsgnjx_s, 10, 11, 12, N),   // fsgnjx.s fa0, fa1, fa2
+    (0x2ac58553, insn_fmin_d, 10, 11, 12, N),     // fmin.d fa0, fa1, fa2
+    (0x28c59553, insn_fmax_s, 10, 11, 12, N),     // fmax.s fa0, fa1, fa2
+    (0xc0059553, insn_fcvt_w_s, 10, 11, N, N),    // fcvt.w.s a0, fa1, rtz
+    (0xc215f553, insn_fcvt_wu_d, 10, 11, N, N),   // fcvt.wu.d a0, fa1
+    (0xc025f553, insn_fcvt_l_s, 10, 11, N, N),    // fcvt.l.s a0, fa1
+    (0xc235f553, insn_fcvt_lu_d, 10, 11, N, N),   // fcvt.lu.d a0, fa1
+    (0xd005f553, insn_fcvt_s_w, 10, 11, N, N),    // fcvt.s.w fa0, a1
+    (0xd2158553, insn_fcvt_d_wu, 10, 11, N, N),   // fcvt.d.wu fa0, a1
+    (0xd025f553, insn_fcvt_s_l, 10, 11, N, N),    // fcvt.s.l fa0, a1
+    (0xd235f553, insn_fcvt_d_lu, 10, 11, N, N),   // fcvt.d.lu fa0, a1
+    (0x4015f553, insn_fcvt_s_d, 10, 11, N, N),    // fcvt.s.d fa0, fa1
+    (0x42058553, insn_fcvt_d_s, 10, 11, N, N),    // fcvt.d.s fa0, fa1
+    (0xe0058553, insn_fmv_x_w, 10, 11, N, N),     // fmv.x.w a0, fa1
+    (0xf0058553, insn_fmv_w_x, 10, 11, N, N),     // fmv.w.x fa0, a1
+    (0xe2058553, insn_fmv_x_d, 10, 11, N, N),     // fmv.x.d a0, fa1
+    (0xf2058553, insn_fmv_d_x, 10, 11, N, N),     // fmv.d.x fa0, a1
+    (0xa0c5a553, insn_feq_s, 10, 11, 12, N),      // feq.s a0, fa1, fa2
+    (0xa2c59553, insn_flt_d, 10, 11, 12, N),      // flt.d a0, fa1, fa2
+    (0xa0c58553, insn_fle_s, 10, 11, 12, N),      // fle.s a0, fa1, fa2
+    (0xe2059553, insn_fclass_d, 10, 11, N, N),    // fclass.d a0, fa1
+    (0x68c5f543, insn_fmadd_s, 10, 11, 12, N),    // fmadd.s fa0, fa1, fa2, fa3
+    (0x6ac5f547, insn_fmsub_d, 10, 11, 12, N),    // fmsub.d fa0, fa1, fa2, fa3
+    (0x68c5f54b, insn_fnmsub_s, 10, 11, 12, N),   // fnmsub.s fa0, fa1, fa2, fa3
+    (0x6ac5f54f, insn_fnmadd_d, 10, 11, 12, N),   // fnmadd.d fa0, fa1, fa2, fa3
+    (0x00259507, insn_flh, 10, 11, 0, 2),         // flh fa0, 2(a1)
+    (0xfea59f27, insn_fsh, 0, 11, 10, -2),        // fsh fa0, -2(a1)
+    (0x04c5f553, insn_fadd_h, 10, 11, 12, N),     // fadd.h fa0, fa1, fa2
+    (0x6cc5f543, insn_fmadd_h, 10, 11, 12, N),    // fmadd.h fa0, fa1, fa2, fa3
+    (0x40258553, insn_fcvt_s_h, 10, 11, N, N),    // fcvt.s.h fa0, fa1
+    (0x4415f553, insn_fcvt_h_d, 10, 11, N, N),    // fcvt.h.d fa0, fa1
+    (0xc405f553, insn_fcvt_w_h, 10, 11, N, N),    // fcvt.w.h a0, fa1
+    (0xe4058553, insn_fmv_x_h, 10, 11, N, N),     // fmv.x.h a0, fa1
+    (0xf4058553, insn_fmv_h_x, 10, 11, N, N),     // fmv.h.x fa0, a1
+    (0x20c5a533, insn_sh1add, 10, 11, 12, 0),     // sh1add a0, a1, a2
+    (0x20c5e53b, insn_sh3add_uw, 10, 11, 12, 0),  // sh3add.uw a0, a1, a2
+    (0x08c5853b, insn_add_uw, 10, 11, 12, 0),     // add.uw a0, a1, a2
+    (0x0a85951b, insn_slli_uw, 10, 11, N, N),     // slli.uw a0, a1, 40
+    (0x40c5f533, insn_andn, 10, 11, 12, 0),       // andn a0, a1, a2
+    (0x40c5e533, insn_orn, 10, 11, 12, 0),        // orn a0, a1, a2
+    (0x40c5c533, insn_xnor, 10, 11, 12, 0),       // xnor a0, a1, a2
+    (0x60059513, insn_clz, 10, 11, N, N),         // clz a0, a1
+    (0x6015951b, insn_ctzw, 10, 11, N, N),        // ctzw a0, a1
+    (0x60259513, insn_cpop, 10, 11, N, N),        // cpop a0, a1
+    (0x0ac5e533, insn_max, 10, 11, 12, 0),        // max a0, a1, a2
+    (0x0ac5d533, insn_minu, 10, 11, 12, 0),       // minu a0, a1, a2
+    (0x60459513, insn_sext_b, 10, 11, N, N),      // sext.b a0, a1
+    (0x60559513, insn_sext_h, 10, 11, N, N),      // sext.h a0, a1
+    (0x0805c53b, insn_zext_h, 10, 11, N, N),      // zext.h a0, a1
+    (0x60c59533, insn_rol, 10, 11, 12, 0),        // rol a0, a1, a2
+    (0x60c5d53b, insn_rorw, 10, 11, 12, 0),       // rorw a0, a1, a2
+    (0x63f5d513, insn_rori, 10, 11, N, N),        // rori a0, a1, 63
+    (0x61f5d51b, insn_roriw, 10, 11, N, N),       // roriw a0, a1, 31
+    (0x2875d513, insn_orc_b, 10, 11, N, N),       // orc.b a0, a1
+    (0x6b85d513, insn_rev8, 10, 11, N, N),        // rev8 a0, a1
+    (0x0ac59533, insn_clmul, 10, 11, 12, 0),      // clmul a0, a1, a2
+    (0x0ac5b533, insn_clmulh, 10, 11, 12, 0),     // clmulh a0, a1, a2
+    (0x0ac5a533, insn_clmulr, 10, 11, 12, 0),     // clmulr a0, a1, a2
+    (0x48c59533, insn_bclr, 10, 11, 12, 0),       // bclr a0, a1, a2
+    (0x4bf5d513, insn_bexti, 10, 11, N, N),       // bexti a0, a1, 63
+    (0x68c59533, insn_binv, 10, 11, 12, 0),       // binv a0, a1, a2
+    (0x28759513, insn_bseti, 10, 11, N, N),       // bseti a0, a1, 7
+    (0x0d05f557, insn_vsetvli, 10, 11, N, N),     // vsetvli a0, a1, e32, m1, ta, ma
+    (0xc0027557, insn_vsetivli, 10, N, N, N),     // vsetivli a0, 4, e8, m1, tu, mu
+    (0x80c5f557, insn_vsetvl, 10, 11, 12, N),     // vsetvl a0, a1, a2
+    (0x02056087, insn_vle, 1, 10, N, N),          // vle32.v v1, (a0)
+    (0x02057127, insn_vse, 2, 10, N, N),          // vse64.v v2, (a0)
+    (0x0ab55087, insn_vlse, 1, 10, 11, N),        // vlse16.v v1, (a0), a1
+    (0x022180d7, insn_opivv, 1, 3, 2, N),         // vadd.vv v1, v2, v3
+    (0x022540d7, insn_opivx, 1, 10, 2, N),        // vadd.vx v1, v2, a0
+    (0x0222b0d7, insn_opivi, 1, N, 2, N),         // vadd.vi v1, v2, 5
+    (0x9621a0d7, insn_opmvv, 1, 3, 2, N),         // vmul.vv v1, v2, v3
+    (0x022550d7, insn_opfvf, 1, 10, 2, N),        // vfadd.vf v1, v2, fa0
+];
+
+// compressed encodings decode to the instruction they expand to
+const COMPRESSED_CASES: &[case_t] = &[
+    (0x1fe8, insn_addi, 10, 2, 0, 1020),   // c.addi4spn a0, sp, 1020
+    (0x3de8, insn_fld, 10, 11, 0, 248),    // c.fld fa0, 248(a1)
+    (0x5de8, insn_lw, 10, 11, 0, 124),     // c.lw a0, 124(a1)
+    (0x7de8, insn_ld, 10, 11, 0, 248),     // c.ld a0, 248(a1)
+    (0xa588, insn_fsd, 0, 11, 10, 8),      // c.fsd fa0, 8(a1)
+    (0xc1c8, insn_sw, 0, 11, 10, 4),       // c.sw a0, 4(a1)
+    (0xe588, insn_sd, 0, 11, 10, 8),       // c.sd a0, 8(a1)
+    (0x0001, insn_addi, 0, 0, 0, 0),       // c.nop
+    (0x1501, insn_addi, 10, 10, 0, -32),   // c.addi a0, -32
+    (0x257d, insn_addiw, 10, 10, 0, 31),   // c.addiw a0, 31
+    (0x52fd, insn_addi, 5, 0, 0, -1),      // c.li t0, -1
+    (0x7101, insn_addi, 2, 2, 0, -512),    // c.addi16sp sp, -512
+    (0x617d, insn_addi, 2, 2, 0, 496),     // c.addi16sp sp, 496
+    (0x7285, insn_lui, 5, 0, 0, -0x1f000), // c.lui t0, 0xfffe1
+    (0x6285, insn_lui, 5, 0, 0, 0x1000),   // c.lui t0, 1
+    (0x907d, insn_srli, 8, 8, 0, 63),      // c.srli s0, 63
+    (0x8405, insn_srai, 8, 8, 0, 1),       // c.srai s0, 1
+    (0x98fd, insn_andi, 9, 9, 0, -1),      // c.andi s1, -1
+    (0x8d0d, insn_sub, 10, 10, 11, 0),     // c.sub a0, a1
+    (0x8d2d, insn_xor, 10, 10, 11, 0),     // c.xor a0, a1
+    (0x8d4d, insn_or, 10, 10, 11, 0),      // c.or a0, a1
+    (0x8d6d, insn_and, 10, 10, 11, 0),     // c.and a0, a1
+    (0x9d0d, insn_subw, 10, 10, 11, 0),    // c.subw a0, a1
+    (0x9d2d, insn_addw, 10, 10, 11, 0),    // c.addw a0, a1
+    (0xb001, insn_jal, 0, 0, 0, -2048),    // c.j -2048
+    (0xa101, insn_jal, 0, 0, 0, 1024),     // c.j 1024
+    (0xd001, insn_beq, 0, 8, 0, -256),     // c.beqz s0, -256
+    (0xec7d, insn_bne, 0, 8, 0, 254),      // c.bnez s0, 254
+    (0x12fe, insn_slli, 5, 5, 0, 63),      // c.slli t0, 63
+    (0x357e, insn_fld, 10, 2, 0, 504),     // c.fldsp fa0, 504(sp)
+    (0x557e, insn_lw, 10, 2, 0, 252),      // c.lwsp a0, 252(sp)
+    (0x757e, insn_ld, 10, 2, 0, 504),      // c.ldsp a0, 504(sp)
+    (0x8082, insn_jalr, 0, 1, 0, 0),       // c.jr ra
+    (0x852e, insn_add, 10, 0, 11, 0),      // c.mv a0, a1
+    (0x9002, insn_ebreak, N, N, N, N),     // c.ebreak
+    (0x9282, insn_jalr, 1, 5, 0, 0),       // c.jalr t0
+    (0x952e, insn_add, 10, 10, 11, 0),     // c.add a0, a1
+    (0xbfaa, insn_fsd, 0, 2, 10, 504),     // c.fsdsp fa0, 504(sp)
+    (0xdfaa, insn_sw, 0, 2, 10, 252),      // c.swsp a0, 252(sp)
+    (0xffaa, insn_sd, 0, 2, 10, 504),      // c.sdsp a0, 504(sp)
+];
+
+fn decode(bits: u32) -> insn_t {
+    let mut insn = insn_t::default();
+    insn_decode(&mut insn, bits, &isa_t::new());
+    insn
+}
+
+fn check(cases: &[case_t]) {
+    for &(bits, type_, rd, rs1, rs2, imm) in cases {
+        let insn = decode(bits);
+        // shifts only use the low six bits of imm
+        let shamt = match type_ {
+            insn_slli | insn_srli | insn_srai => insn.imm & 0x3f,
+            _ => insn.imm,
+        };
+        assert_eq!(insn.type_, type_, "type of {:#x}", bits);
+        assert_eq!(insn.rvc, bits & 0x3 != 0x3, "rvc of {:#x}", bits);
+        for (name, got, want) in [
+            ("rd", insn.rd as i64, rd),
+            ("rs1", insn.rs1 as i64, rs1),
+            ("rs2", insn.rs2 as i64, rs2),
+            ("imm", shamt as i64, imm),
+        ] {
+            if want != N {
+                assert_eq!(got, want, "{} of {:#x} ({:?})", name, bits, type_);
+            }
+        }
+    }
+}
+
+#[test]
+fn decode_32bit() {
+    check(CASES);
+}
+
+#[test]
+fn decode_compressed() {
+    check(COMPRESSED_CASES);
+}
+
+#[test]
+fn decode_csr() {
+    assert_eq!(decode(0x00359573).csr, 0x003); // csrrw a0, fcsr, a1
+    assert_eq!(decode(0xc0002573).csr, 0xc00); // csrrs a0, cycle, zero
+}
+
+#[test]
+fn decode_fp_fields() {
+    let insn = decode(0x68c5f543); // fmadd.s fa0, fa1, fa2, fa3, dyn
+    assert_eq!((insn.rs3, insn.rm), (13, 7));
+    let insn = decode(0x0ac59553); // fsub.d fa0, fa1, fa2, rtz
+    assert_eq!(insn.rm, 1);
+}
+
+// reserved encodings, the comment says why
+const ILLEGAL: &[u32] = &[
+    0x0000,     // c.addi4spn with a zero immediate, all zeros
+    0x8000,     // quadrant 0, funct3 4
+    0x2001,     // c.addiw with rd x0
+    0x6101,     // c.addi16sp with a zero immediate
+    0x6081,     // c.lui with a zero immediate
+    0x9c41,     // c.subw group, funct2 2
+    0x4002,     // c.lwsp with rd x0
+    0x6002,     // c.ldsp with rd x0
+    0x8002,     // c.jr with rs1 x0
+    0x0000000b, // custom-0
+    0x00007003, // load, funct3 7
+    0x0000200f, // misc-mem, funct3 2
+    0x00001067, // jalr, funct3 1
+    0x10500073, // wfi, not in user mode
+    0x00004073, // system, funct3 4
+    0x58100053, // fsqrt.s with rs2 x1
+    0xe0002053, // fmv.x.w and fclass.s, funct3 2
+];
+
+#[test]
+fn decode_illegal() {
+    for &bits in ILLEGAL {
+        assert_eq!(decode(bits).type_, insn_illegal, "type of {:#x}", bits);
+    }
+    // instructions of a disabled extension
+    let isa = isa_t {
+        zba: false,
+        ..isa_t::new()
+    };
+    let mut insn = insn_t::default();
+    insn_decode(&mut insn, 0x20c5a533, &isa); // sh1add a0, a1, a2
+    assert_eq!(insn.type_, insn_illegal);
+}
@@ -0,0 +1,54 @@
+use std::{sync::atomic::Ordering, thread};
+
+use rvemu_riir::{
+    config::config_t,
+    machine::{exit_reason_t, machine_t},
+    reg::gp_reg_type_t,
+};
+
+/*
+    drive the machine the way an embedder does, on tests/riscv-tests/limits/cpu
+    which counts t0 down from 5000000. there is one guest address space per
+    process, so everything happens in this one test.
+*/
+#[test]
+fn budget_and_interrupt() {
+    let elf = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/riscv-tests/limits/cpu");
+    let mut config = config_t::new();
+    config.htif = true;
+    let mut machine = machine_t::new(config);
+    machine.machine_load_program(elf);
+    machine.machine_setup(&[elf]);
+
+    // the budget pauses at the first block boundary after it runs out
+    machine.machine_set_budget(Some(1000));
+    assert_eq!(machine.machine_step(), exit_reason_t::budget_exhausted);
+    let instret = machine.machine_counters().instret;
+    assert!((1000..1010).contains(&instret), "instret {}", instret);
+    let t0 = machine.machine_get_gp_reg(gp_reg_type_t::t0);
+    assert!(t0 < 5000000);
+
+    // and the loop goes on from there
+    machine.machine_set_budget(Some(1000));
+    assert_eq!(machine.machine_step(), exit_reason_t::budget_exhausted);
+    let instret = machine.machine_counters().instret - instret;
+    assert!((1000..1010).contains(&instret), "instret {}", instret);
+    assert_eq!(
+        t0 - machine.machine_get_gp_reg(gp_reg_type_t::t0),
+        instret / 2
+    );
+
+    // the interrupt flag is raised from another thread, and consumed
+    machine.machine_set_budget(None);
+    let interrupt = machine.machine_interrupt_flag();
+    thread::spawn(move || interrupt.store(true, Ordering::Relaxed))
+        .join()
+        .unwrap();
+    assert_eq!(machine.machine_step(), exit_reason_t::interrupted);
+    assert!(!machine.machine_interrupt_flag().load(Ordering::Relaxed));
+    assert_ne!(machine.machine_get_gp_reg(gp_reg_type_t::t0), 0);
+
+    // until the test writes tohost, machine_htif would exit the process
+    assert_eq!(machine.machine_step(), exit_reason_t::htif);
+    assert_eq!(machine.machine_get_gp_reg(gp_reg_type_t::t0), 0);
+}
@@ -0,0 +1,200 @@
+#!/usr/bin/env python3
+"""
+Rebuild the prebuilt conformance tests: every <suite>/<test>.S becomes the
+static ELF <suite>/<test>, linked at 0x80000000 like the p environment of
+riscv-tests. Needs only cpp and llvm-mc, the few relocations a single
+object file has are resolved here instead of by a RISC-V linker.
+
+usage: build.py [suite/test.S ...]    (default: all of them)
+"""
+import glob
+import os
+import struct
+import subprocess
+import sys
+
+HERE = os.path.dirname(os.path.abspath(__file__))
+BASE = 0x80000000
+
+# -mattr for each suite, the way riscv-tests picks -march
+MATTR = {
+    "rv64ui": "+m,+a,+f,+d",
+    "rv64um": "+m,+a,+f,+d",
+    "rv64ua": "+m,+a,+f,+d",
+    "rv64uf": "+m,+a,+f,+d",
+    "rv64ud": "+m,+a,+f,+d",
+    "rv64uc": "+m,+a,+f,+d,+c",
+    "rv64ub": "+m,+a,+f,+d,+zba,+zbb,+zbc,+zbs",
+    "rv64uv": "+m,+a,+f,+d,+v",
+    "rv64uzfh": "+m,+a,+f,+d,+zfh",
+    "sigill": "+m,+a,+f,+d,+c,+v",
+    "brk": "+m,+a,+f,+d",
+    "exec": "+m,+a,+f,+d",
+    "isa": "+m,+a,+f,+d,+zba,+zbb,+zbc,+zbs",
+    "replay": "+m,+a,+f,+d",
+    "limits": "+m,+a,+f,+d",
+    "vfs": "+m,+a,+f,+d",
+    "ebreak": "+m,+a,+f,+d,+c",
+    "personality": "+m,+a,+f,+d",
+    "syscall": "+m,+a,+f,+d",
+    "tty": "+m,+a,+f,+d",
+}
+
+R_RISCV_32 = 1
+R_RISCV_64 = 2
+R_RISCV_BRANCH = 16
+R_RISCV_JAL = 17
+R_RISCV_CALL = 18
+R_RISCV_CALL_PLT = 19
+R_RISCV_PCREL_HI20 = 23
+R_RISCV_PCREL_LO12_I = 24
+R_RISCV_PCREL_LO12_S = 25
+R_RISCV_HI20 = 26
+R_RISCV_LO12_I = 27
+R_RISCV_LO12_S = 28
+R_RISCV_RVC_BRANCH = 44
+R_RISCV_RVC_JUMP = 45
+R_RISCV_RELAX = 51
+
+
+def assemble(src, mattr):
+    asm = subprocess.run(
+        ["cpp", "-P", "-x", "assembler-with-cpp", "-I", os.path.join(HERE, "env"), src],
+        check=True, capture_output=True, text=True).stdout
+    obj = subprocess.run(
+        ["llvm-mc", "-triple=riscv64", "-mattr=" + mattr + ",-relax", "-filetype=obj", "-o", "-"],
+        check=True, capture_output=True, input=asm.encode()).stdout
+    return obj
+
+
+def hi20(d):
+    return ((d + 0x800) >> 12) & 0xfffff
+
+
+def lo12(d):
+    return d - (((d + 0x800) >> 12) << 12)
+
+
+def link(obj):
+    shoff, = struct.unpack_from("<Q", obj, 0x28)
+    shnum, = struct.unpack_from("<H", obj, 0x3c)
+    secs = [struct.unpack_from("<IIQQQQIIQQ", obj, shoff + i * 64) for i in range(shnum)]
+
+    def cstr(off):
+        return obj[off:obj.index(b"\0", off)].decode()
+
+    # lay the allocated sections out one after another
+    image = bytearray()
+    addr = {}
+    for i, (_, sh_type, flags, _, off, size, _, _, align, _) in enumerate(secs):
+        if sh_type in (1, 8) and flags & 2:
+            image += b"\0" * (-len(image) % max(align, 1))
+            addr[i] = BASE + len(image)
+            image += obj[off:off + size] if sh_type == 1 else b"\0" * size
+
+    symtab = next(s for s in secs if s[1] == 2)
+    strtab = secs[symtab[6]]
+    syms = []
+    for k in range(symtab[5] // 24):
+        st_name, _, _, shndx, value, _ = struct.unpack_from("<IBBHQQ", obj, symtab[4] + k * 24)
+        syms.append((cstr(strtab[4] + st_name), addr.get(shndx, 0) + value, shndx in addr))
+
+    def rd(a, n):
+        return int.from_bytes(image[a - BASE:a - BASE + n], "little")
+
+    def wr(a, n, v):
+        image[a - BASE:a - BASE + n] = (v & ((1 << (8 * n)) - 1)).to_bytes(n, "little")
+
+    relocs = []
+    for sh_type, off, size, info in ((s[1], s[4], s[5], s[7]) for s in secs):
+        if sh_type == 4 and info in addr:
+            for k in range(size // 24):
+                r_off, r_info, r_add = struct.unpack_from("<QQq", obj, off + k * 24)
+                relocs.append((addr[info] + r_off, r_info & 0xffffffff, syms[r_info >> 32][1] + r_add))
+
+    # %pcrel_lo refers to the auipc's label, not to the symbol itself
+    pcrel_hi = {p: v - p for p, t, v in relocs if t == R_RISCV_PCREL_HI20}
+
+    for p, t, v in relocs:
+        ins = rd(p, 4)
+        d = v - p
+        if t == R_RISCV_64:
+            wr(p, 8, v)
+        elif t == R_RISCV_32:
+            wr(p, 4, v)
+        elif t == R_RISCV_BRANCH:
+            wr(p, 4, (ins & 0x1fff07f) | (d >> 12 & 1) << 31 | (d >> 5 & 0x3f) << 25
+               | (d >> 1 & 0xf) << 8 | (d >> 11 & 1) << 7)
+        elif t == R_RISCV_JAL:
+            wr(p, 4, (ins & 0xfff) | (d >> 20 & 1) << 31 | (d >> 1 & 0x3ff) << 21
+               | (d >> 11 & 1) << 20 | (d >> 12 & 0xff) << 12)
+        elif t in (R_RISCV_CALL, R_RISCV_CALL_PLT):
+            wr(p, 4, (ins & 0xfff) | hi20(d) << 12)
+            wr(p + 4, 4, (rd(p + 4, 4) & 0xfffff) | (lo12(d) & 0xfff) << 20)
+        elif t in (R_RISCV_PCREL_HI20, R_RISCV_HI20):
+            wr(p, 4, (ins & 0xfff) | hi20(d if t == R_RISCV_PCREL_HI20 else v) << 12)
+        elif t in (R_RISCV_PCREL_LO12_I, R_RISCV_LO12_I):
+            l = lo12(pcrel_hi[v] if t == R_RISCV_PCREL_LO12_I else v)
+            wr(p, 4, (ins & 0xfffff) | (l & 0xfff) << 20)
+        elif t in (R_RISCV_PCREL_LO12_S, R_RISCV_LO12_S):
+            l = lo12(pcrel_hi[v] if t == R_RISCV_PCREL_LO12_S else v)
+            wr(p, 4, (ins & 0x1fff07f) | (l >> 5 & 0x7f) << 25 | (l & 0x1f) << 7)
+        elif t == R_RISCV_RVC_BRANCH:
+            c = rd(p, 2)
+            wr(p, 2, (c & 0xe383) | (d >> 8 & 1) << 12 | (d >> 3 & 3) << 10
+               | (d >> 6 & 3) << 5 | (d >> 1 & 3) << 3 | (d >> 5 & 1) << 2)
+        elif t == R_RISCV_RVC_JUMP:
+            c = rd(p, 2)
+            wr(p, 2, (c & 0xe003) | (d >> 11 & 1) << 12 | (d >> 4 & 1) << 11
+               | (d >> 8 & 3) << 9 | (d >> 10 & 1) << 8 | (d >> 6 & 1) << 7
+               | (d >> 7 & 1) << 6 | (d >> 1 & 7) << 3 | (d >> 5 & 1) << 2)
+        elif t != R_RISCV_RELAX:
+            sys.exit("unsupported relocation %d" % t)
+
+    entry = next(v for n, v, _ in syms if n == "_start")
+    return bytes(image), entry, [(n, v) for n, v, defined in syms
+                                  if n and defined and not n.startswith(".L")]
+
+
+def write_elf(path, image, entry, syms):
+    code_off = 0x1000
+    strtab = b"\0"
+    symtab = b"\0" * 24
+    for name, value in syms:
+        symtab += struct.pack("<IBBHQQ", len(strtab), 0x10, 0, 1, value, 0)
+        strtab += name.encode() + b"\0"
+    shstrtab = b"\0.text\0.symtab\0.strtab\0.shstrtab\0"
+    sym_off = (code_off + len(image) + 7) & ~7
+    str_off = sym_off + len(symtab)
+    shstr_off = str_off + len(strtab)
+    sh_off = (shstr_off + len(shstrtab) + 7) & ~7
+
+    ehdr = b"\x7fELF" + bytes([2, 1, 1, 0]) + b"\0" * 8
+    ehdr += struct.pack("<HHIQQQIHHHHHH", 2, 243, 1, entry, 64, sh_off, 5, 64, 56, 1, 64, 5, 4)
+    phdr = struct.pack("<IIQQQQQQ", 1, 7, code_off, BASE, BASE, len(image), len(image), 0x1000)
+    shdrs = b"\0" * 64
+    shdrs += struct.pack("<IIQQQQIIQQ", 1, 1, 7, BASE, code_off, len(image), 0, 0, 16, 0)
+    shdrs += struct.pack("<IIQQQQIIQQ", 7, 2, 0, 0, sym_off, len(symtab), 3, 1, 8, 24)
+    shdrs += struct.pack("<IIQQQQIIQQ", 15, 3, 0, 0, str_off, len(strtab), 0, 0, 1, 0)
+    shdrs += struct.pack("<IIQQQQIIQQ", 23, 3, 0, 0, shstr_off, len(shstrtab), 0, 0, 1, 0)
+
+    elf = bytearray(ehdr + phdr)
+    elf += b"\0" * (code_off - len(elf)) + image
+    elf += b"\0" * (sym_off - len(elf)) + symtab + strtab + shstrtab
+    elf += b"\0" * (sh_off - len(elf)) + shdrs
+    with open(path, "wb") as f:
+        f.write(elf)
+    os.chmod(path, 0o755)
+
+
+def main():
+    srcs = sys.argv[1:] or sorted(
+        src for suite in MATTR for src in glob.glob(os.path.join(HERE, suite, "*.S")))
+    for src in srcs:
+        suite = os.path.basename(os.path.dirname(os.path.abspath(src)))
+        image, entry, syms = link(assemble(src, MATTR[suite]))
+        write_elf(src[:-2], image, entry, syms)
+
+
+if __name__ == "__main__":
+    main()
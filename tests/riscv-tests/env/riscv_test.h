@@ -0,0 +1,54 @@
+#ifndef _ENV_RVEMU_RISCV_TEST_H
+#define _ENV_RVEMU_RISCV_TEST_H
+
+//-----------------------------------------------------------------------
+// User-mode environment for rvemu-riir --htif
+//-----------------------------------------------------------------------
+
+// Tests start at _start in U mode and finish like the p environment:
+// tohost gets 1 for a pass and (TESTNUM << 1) | 1 for a failure, which
+// the emulator prints as "tohost exit code N" before exiting with status
+// 1, and --riscv-tests reports as the failing test. There is no trap
+// handler, so ecall and misaligned accesses are not tested. The other
+// suites, such as sigill, make Linux system calls instead, e.g. to catch
+// SIGILL with a signal handler.
+
+#define RVTEST_RV64U
+#define RVTEST_RV64UF
+
+#define TESTNUM gp
+
+#define RVTEST_CODE_BEGIN                                               \
+        .text;                                                          \
+        .globl _start;                                                  \
+_start:                                                                 \
+        li TESTNUM, 0;
+
+#define RVTEST_CODE_END                                                 \
+write_tohost:                                                           \
+        la t5, tohost;                                                  \
+        sd TESTNUM, 0(t5);                                              \
+        j write_tohost;
+
+#define RVTEST_PASS                                                     \
+        fence;                                                          \
+        li TESTNUM, 1;                                                  \
+        j write_tohost;
+
+#define RVTEST_FAIL                                                     \
+        fence;                                                          \
+1:      beqz TESTNUM, 1b;                                               \
+        sll TESTNUM, TESTNUM, 1;                                        \
+        or TESTNUM, TESTNUM, 1;                                         \
+        j write_tohost;
+
+#define RVTEST_DATA_BEGIN                                               \
+        .pushsection .tohost,"aw",@progbits;                            \
+        .align 6; .globl tohost; tohost: .dword 0;                      \
+        .align 6; .globl fromhost; fromhost: .dword 0;                  \
+        .popsection;                                                    \
+        .align 4; .globl begin_signature; begin_signature:
+
+#define RVTEST_DATA_END .align 4; .globl end_signature; end_signature:
+
+#endif
@@ -0,0 +1,494 @@
+#ifndef __TEST_MACROS_SCALAR_H
+#define __TEST_MACROS_SCALAR_H
+
+//-----------------------------------------------------------------------
+// Helper macros
+//-----------------------------------------------------------------------
+
+#define MASK_XLEN(x) ((x) & 0xffffffffffffffff)
+
+#define TEST_CASE( testnum, testreg, correctval, code... ) \
+test_ ## testnum: \
+    li  TESTNUM, testnum; \
+    code; \
+    li  x7, MASK_XLEN(correctval); \
+    bne testreg, x7, fail;
+
+#-----------------------------------------------------------------------
+# Tests for instructions with immediate operand
+#-----------------------------------------------------------------------
+
+#define SEXT_IMM(x) ((x) | (-(((x) >> 11) & 1) << 11))
+
+#define TEST_IMM_OP( testnum, inst, result, val1, imm ) \
+    TEST_CASE( testnum, x14, result, \
+      li  x1, MASK_XLEN(val1); \
+      inst x14, x1, SEXT_IMM(imm); \
+    )
+
+#define TEST_IMM_SRC1_EQ_DEST( testnum, inst, result, val1, imm ) \
+    TEST_CASE( testnum, x1, result, \
+      li  x1, MASK_XLEN(val1); \
+      inst x1, x1, SEXT_IMM(imm); \
+    )
+
+#define TEST_IMM_ZEROSRC1( testnum, inst, result, imm ) \
+    TEST_CASE( testnum, x1, result, \
+      inst x1, x0, SEXT_IMM(imm); \
+    )
+
+#define TEST_IMM_ZERODEST( testnum, inst, val1, imm ) \
+    TEST_CASE( testnum, x0, 0, \
+      li  x1, MASK_XLEN(val1); \
+      inst x0, x1, SEXT_IMM(imm); \
+    )
+
+#-----------------------------------------------------------------------
+# Tests for register-register instructions
+#-----------------------------------------------------------------------
+
+#define TEST_R_OP( testnum, inst, result, val1 ) \
+    TEST_CASE( testnum, x14, result, \
+      li  x1, MASK_XLEN(val1); \
+      inst x14, x1; \
+    )
+
+#define TEST_RR_OP( testnum, inst, result, val1, val2 ) \
+    TEST_CASE( testnum, x14, result, \
+      li  x1, MASK_XLEN(val1); \
+      li  x2, MASK_XLEN(val2); \
+      inst x14, x1, x2; \
+    )
+
+#define TEST_RR_SRC1_EQ_DEST( testnum, inst, result, val1, val2 ) \
+    TEST_CASE( testnum, x1, result, \
+      li  x1, MASK_XLEN(val1); \
+      li  x2, MASK_XLEN(val2); \
+      inst x1, x1, x2; \
+    )
+
+#define TEST_RR_SRC2_EQ_DEST( testnum, inst, result, val1, val2 ) \
+    TEST_CASE( testnum, x2, result, \
+      li  x1, MASK_XLEN(val1); \
+      li  x2, MASK_XLEN(val2); \
+      inst x2, x1, x2; \
+    )
+
+#define TEST_RR_SRC12_EQ_DEST( testnum, inst, result, val1 ) \
+    TEST_CASE( testnum, x1, result, \
+      li  x1, MASK_XLEN(val1); \
+      inst x1, x1, x1; \
+    )
+
+#define TEST_RR_ZEROSRC1( testnum, inst, result, val ) \
+    TEST_CASE( testnum, x2, result, \
+      li x1, MASK_XLEN(val); \
+      inst x2, x0, x1; \
+    )
+
+#define TEST_RR_ZEROSRC2( testnum, inst, result, val ) \
+    TEST_CASE( testnum, x2, result, \
+      li x1, MASK_XLEN(val); \
+      inst x2, x1, x0; \
+    )
+
+#define TEST_RR_ZEROSRC12( testnum, inst, result ) \
+    TEST_CASE( testnum, x1, result, \
+      inst x1, x0, x0; \
+    )
+
+#define TEST_RR_ZERODEST( testnum, inst, val1, val2 ) \
+    TEST_CASE( testnum, x0, 0, \
+      li x1, MASK_XLEN(val1); \
+      li x2, MASK_XLEN(val2); \
+      inst x0, x1, x2; \
+    )
+
+#-----------------------------------------------------------------------
+# Tests for loads and stores
+#-----------------------------------------------------------------------
+
+#define TEST_LD_OP( testnum, inst, result, offset, base ) \
+    TEST_CASE( testnum, x14, result, \
+      la  x2, base; \
+      inst x14, offset(x2); \
+    )
+
+#define TEST_ST_OP( testnum, load_inst, store_inst, result, offset, base ) \
+    TEST_CASE( testnum, x14, result, \
+      la  x2, base; \
+      li  x1, result; \
+      store_inst x1, offset(x2); \
+      load_inst x14, offset(x2); \
+    )
+
+#-----------------------------------------------------------------------
+# Tests for branch instructions
+#-----------------------------------------------------------------------
+
+#define TEST_BR2_OP_TAKEN( testnum, inst, val1, val2 ) \
+test_ ## testnum: \
+    li  TESTNUM, testnum; \
+    li  x1, val1; \
+    li  x2, val2; \
+    inst x1, x2, 2f; \
+    bne x0, TESTNUM, fail; \
+1:  bne x0, TESTNUM, 3f; \
+2:  inst x1, x2, 1b; \
+    bne x0, TESTNUM, fail; \
+3:
+
+#define TEST_BR2_OP_NOTTAKEN( testnum, inst, val1, val2 ) \
+test_ ## testnum: \
+    li  TESTNUM, testnum; \
+    li  x1, val1; \
+    li  x2, val2; \
+    inst x1, x2, 1f; \
+    bne x0, TESTNUM, 2f; \
+1:  bne x0, TESTNUM, fail; \
+2:  inst x1, x2, 1b; \
+3:
+
+#-----------------------------------------------------------------------
+# Tests floating-point instructions
+#-----------------------------------------------------------------------
+
+# Results are compared bit for bit, so a NaN result must be the canonical
+# NaN, and fflags must hold exactly the flags given. qNaNf, sNaNf, qNaN and
+# sNaN stand for the bits llvm-mc has no float syntax for. A failure jumps
+# to fail, which long suites put out of reach of a branch.
+
+.macro fp_s val
+.ifc \val, qNaNf
+  .word 0x7fc00000
+.else
+.ifc \val, sNaNf
+  .word 0x7f800001
+.else
+  .float \val
+.endif
+.endif
+.endm
+
+.macro fp_d val
+.ifc \val, qNaN
+  .dword 0x7ff8000000000000
+.else
+.ifc \val, sNaN
+  .dword 0x7ff0000000000001
+.else
+  .double \val
+.endif
+.endif
+.endm
+
+#define TEST_FP_OP_S_INTERNAL( testnum, flags, result, val1, val2, val3, code... ) \
+test_ ## testnum: \
+  li  TESTNUM, testnum; \
+  la  a0, test_ ## testnum ## _data ;\
+  flw f0, 0(a0); \
+  flw f1, 4(a0); \
+  flw f2, 8(a0); \
+  lw  a3, 12(a0); \
+  code; \
+  fsflags a1, x0; \
+  li a2, flags; \
+  bne a0, a3, 1f; \
+  beq a1, a2, 2f; \
+1:  j fail; \
+2: \
+  .pushsection .data; \
+  .align 2; \
+  test_ ## testnum ## _data: \
+  fp_s val1; \
+  fp_s val2; \
+  fp_s val3; \
+  result; \
+  .popsection
+
+#define TEST_FP_OP_D_INTERNAL( testnum, flags, result, val1, val2, val3, code... ) \
+test_ ## testnum: \
+  li  TESTNUM, testnum; \
+  la  a0, test_ ## testnum ## _data ;\
+  fld f0, 0(a0); \
+  fld f1, 8(a0); \
+  fld f2, 16(a0); \
+  ld  a3, 24(a0); \
+  code; \
+  fsflags a1, x0; \
+  li a2, flags; \
+  bne a0, a3, 1f; \
+  beq a1, a2, 2f; \
+1:  j fail; \
+2: \
+  .pushsection .data; \
+  .align 3; \
+  test_ ## testnum ## _data: \
+  fp_d val1; \
+  fp_d val2; \
+  fp_d val3; \
+  result; \
+  .popsection
+
+#define TEST_FCVT_S_D( testnum, flags, result, val1 ) \
+  TEST_FP_OP_D_INTERNAL( testnum, flags, fp_d result, val1, 0.0, 0.0, \
+                    fcvt.s.d f3, f0; fcvt.d.s f3, f3; fmv.x.d a0, f3)
+
+#define TEST_FCVT_D_S( testnum, flags, result, val1 ) \
+  TEST_FP_OP_S_INTERNAL( testnum, flags, fp_s result, val1, 0.0, 0.0, \
+                    fcvt.d.s f3, f0; fcvt.s.d f3, f3; fmv.x.w a0, f3)
+
+#define TEST_FP_OP1_S( testnum, inst, flags, result, val1 ) \
+  TEST_FP_OP_S_INTERNAL( testnum, flags, fp_s result, val1, 0.0, 0.0, \
+                    inst f3, f0; fmv.x.w a0, f3)
+
+#define TEST_FP_OP1_D( testnum, inst, flags, result, val1 ) \
+  TEST_FP_OP_D_INTERNAL( testnum, flags, fp_d result, val1, 0.0, 0.0, \
+                    inst f3, f0; fmv.x.d a0, f3)
+
+#define TEST_FP_OP2_S( testnum, inst, flags, result, val1, val2 ) \
+  TEST_FP_OP_S_INTERNAL( testnum, flags, fp_s result, val1, val2, 0.0, \
+                    inst f3, f0, f1; fmv.x.w a0, f3)
+
+#define TEST_FP_OP2_D( testnum, inst, flags, result, val1, val2 ) \
+  TEST_FP_OP_D_INTERNAL( testnum, flags, fp_d result, val1, val2, 0.0, \
+                    inst f3, f0, f1; fmv.x.d a0, f3)
+
+#define TEST_FP_OP3_S( testnum, inst, flags, result, val1, val2, val3 ) \
+  TEST_FP_OP_S_INTERNAL( testnum, flags, fp_s result, val1, val2, val3, \
+                    inst f3, f0, f1, f2; fmv.x.w a0, f3)
+
+#define TEST_FP_OP3_D( testnum, inst, flags, result, val1, val2, val3 ) \
+  TEST_FP_OP_D_INTERNAL( testnum, flags, fp_d result, val1, val2, val3, \
+                    inst f3, f0, f1, f2; fmv.x.d a0, f3)
+
+#define TEST_FP_INT_OP_S( testnum, inst, flags, result, val1, rm ) \
+  TEST_FP_OP_S_INTERNAL( testnum, flags, .word 0, val1, 0.0, 0.0, \
+                    inst a0, f0, rm; li a3, result)
+
+#define TEST_FP_INT_OP_D( testnum, inst, flags, result, val1, rm ) \
+  TEST_FP_OP_D_INTERNAL( testnum, flags, .dword 0, val1, 0.0, 0.0, \
+                    inst a0, f0, rm; li a3, result)
+
+#define TEST_FP_CMP_OP_S( testnum, inst, flags, result, val1, val2 ) \
+  TEST_FP_OP_S_INTERNAL( testnum, flags, .word 0, val1, val2, 0.0, \
+                    inst a0, f0, f1; li a3, result)
+
+#define TEST_FP_CMP_OP_D( testnum, inst, flags, result, val1, val2 ) \
+  TEST_FP_OP_D_INTERNAL( testnum, flags, .dword 0, val1, val2, 0.0, \
+                    inst a0, f0, f1; li a3, result)
+
+#define TEST_FCLASS_S(testnum, correct, input) \
+  TEST_CASE(testnum, a0, correct, li a0, input; fmv.w.x fa0, a0; \
+                    fclass.s a0, fa0)
+
+#define TEST_FCLASS_D(testnum, correct, input) \
+  TEST_CASE(testnum, a0, correct, li a0, input; fmv.d.x fa0, a0; \
+                    fclass.d a0, fa0)
+
+#define TEST_INT_FP_OP_S( testnum, inst, flags, result, val1 ) \
+test_ ## testnum: \
+  li  TESTNUM, testnum; \
+  la  a0, test_ ## testnum ## _data ;\
+  lw  a3, 0(a0); \
+  li  a0, val1; \
+  inst f0, a0; \
+  fsflags a1, x0; \
+  li a2, flags; \
+  fmv.x.w a0, f0; \
+  bne a0, a3, 1f; \
+  beq a1, a2, 2f; \
+1:  j fail; \
+2: \
+  .pushsection .data; \
+  .align 2; \
+  test_ ## testnum ## _data: \
+  fp_s result; \
+  .popsection
+
+#define TEST_INT_FP_OP_D( testnum, inst, flags, result, val1 ) \
+test_ ## testnum: \
+  li  TESTNUM, testnum; \
+  la  a0, test_ ## testnum ## _data ;\
+  ld  a3, 0(a0); \
+  li  a0, val1; \
+  inst f0, a0; \
+  fsflags a1, x0; \
+  li a2, flags; \
+  fmv.x.d a0, f0; \
+  bne a0, a3, 1f; \
+  beq a1, a2, 2f; \
+1:  j fail; \
+2: \
+  .pushsection .data; \
+  .align 3; \
+  test_ ## testnum ## _data: \
+  fp_d result; \
+  .popsection
+
+# llvm-mc has no directive for half precision, so the values of the _H
+# tests are their bits.
+
+#define TEST_FP_OP_H_INTERNAL( testnum, flags, result, val1, val2, val3, code... ) \
+test_ ## testnum: \
+  li  TESTNUM, testnum; \
+  la  a0, test_ ## testnum ## _data ;\
+  flh f0, 0(a0); \
+  flh f1, 2(a0); \
+  flh f2, 4(a0); \
+  lh  a3, 6(a0); \
+  code; \
+  fsflags a1, x0; \
+  li a2, flags; \
+  bne a0, a3, 1f; \
+  beq a1, a2, 2f; \
+1:  j fail; \
+2: \
+  .pushsection .data; \
+  .align 1; \
+  test_ ## testnum ## _data: \
+  .half val1; \
+  .half val2; \
+  .half val3; \
+  result; \
+  .popsection
+
+#define TEST_FP_OP1_H( testnum, inst, flags, result, val1 ) \
+  TEST_FP_OP_H_INTERNAL( testnum, flags, .half result, val1, 0, 0, \
+                    inst f3, f0; fmv.x.h a0, f3)
+
+#define TEST_FP_OP2_H( testnum, inst, flags, result, val1, val2 ) \
+  TEST_FP_OP_H_INTERNAL( testnum, flags, .half result, val1, val2, 0, \
+                    inst f3, f0, f1; fmv.x.h a0, f3)
+
+#define TEST_FP_OP3_H( testnum, inst, flags, result, val1, val2, val3 ) \
+  TEST_FP_OP_H_INTERNAL( testnum, flags, .half result, val1, val2, val3, \
+                    inst f3, f0, f1, f2; fmv.x.h a0, f3)
+
+#define TEST_FP_INT_OP_H( testnum, inst, flags, result, val1, rm ) \
+  TEST_FP_OP_H_INTERNAL( testnum, flags, .half 0, val1, 0, 0, \
+                    inst a0, f0, rm; li a3, result)
+
+#define TEST_FP_CMP_OP_H( testnum, inst, flags, result, val1, val2 ) \
+  TEST_FP_OP_H_INTERNAL( testnum, flags, .half 0, val1, val2, 0, \
+                    inst a0, f0, f1; li a3, result)
+
+#define TEST_FCLASS_H(testnum, correct, input) \
+  TEST_CASE(testnum, a0, correct, li a0, input; fmv.h.x fa0, a0; \
+                    fclass.h a0, fa0)
+
+#define TEST_INT_FP_OP_H( testnum, inst, flags, result, val1 ) \
+test_ ## testnum: \
+  li  TESTNUM, testnum; \
+  la  a0, test_ ## testnum ## _data ;\
+  lh  a3, 0(a0); \
+  li  a0, val1; \
+  inst f0, a0; \
+  fsflags a1, x0; \
+  li a2, flags; \
+  fmv.x.h a0, f0; \
+  bne a0, a3, 1f; \
+  beq a1, a2, 2f; \
+1:  j fail; \
+2: \
+  .pushsection .data; \
+  .align 1; \
+  test_ ## testnum ## _data: \
+  .half result; \
+  .popsection
+
+#-----------------------------------------------------------------------
+# Tests for vector instructions
+#-----------------------------------------------------------------------
+
+# The code leaves its result in v8, whose first bytes must match the ones
+# at correct. v8 is spilled below the stack pointer to compare them.
+
+#define TEST_VEC( testnum, bytes, correct, code... ) \
+test_ ## testnum: \
+    li  TESTNUM, testnum; \
+    code; \
+    csrr t2, vlenb; \
+    sub a0, sp, t2; \
+    vs1r.v v8, (a0); \
+    la  a1, correct; \
+    li  a2, bytes; \
+1:  lbu t0, 0(a0); \
+    lbu t1, 0(a1); \
+    bne t0, t1, fail; \
+    addi a0, a0, 1; \
+    addi a1, a1, 1; \
+    addi a2, a2, -1; \
+    bnez a2, 1b;
+
+# vl elements of sew bits from src1_<testnum>, and src2_<testnum> or a
+# scalar, give result_<testnum>, which the test puts in its data.
+
+#define TEST_VV_OP( testnum, inst, sew, vl ) \
+    TEST_VEC( testnum, (vl) * (sew) / 8, result_ ## testnum, \
+      vsetivli x0, vl, e ## sew, m1, ta, ma; \
+      la  a0, src1_ ## testnum; \
+      vle ## sew.v v4, (a0); \
+      la  a0, src2_ ## testnum; \
+      vle ## sew.v v5, (a0); \
+      inst v8, v4, v5; \
+    )
+
+#define TEST_VX_OP( testnum, inst, sew, vl, val2 ) \
+    TEST_VEC( testnum, (vl) * (sew) / 8, result_ ## testnum, \
+      vsetivli x0, vl, e ## sew, m1, ta, ma; \
+      la  a0, src1_ ## testnum; \
+      vle ## sew.v v4, (a0); \
+      li  a1, MASK_XLEN(val2); \
+      inst v8, v4, a1; \
+    )
+
+#define TEST_VI_OP( testnum, inst, sew, vl, imm ) \
+    TEST_VEC( testnum, (vl) * (sew) / 8, result_ ## testnum, \
+      vsetivli x0, vl, e ## sew, m1, ta, ma; \
+      la  a0, src1_ ## testnum; \
+      vle ## sew.v v4, (a0); \
+      inst v8, v4, imm; \
+    )
+
+#-----------------------------------------------------------------------
+# Tests for system calls
+#-----------------------------------------------------------------------
+
+# The code leaves the address of a NUL terminated string in a0, which
+# must equal str.
+
+#define TEST_STR( testnum, str, code... ) \
+test_ ## testnum: \
+    li  TESTNUM, testnum; \
+    code; \
+    .pushsection .rodata; \
+1:  .asciz str; \
+    .popsection; \
+    la  a1, 1b; \
+2:  lbu t0, 0(a0); \
+    lbu t1, 0(a1); \
+    bne t0, t1, fail; \
+    addi a0, a0, 1; \
+    addi a1, a1, 1; \
+    bnez t0, 2b;
+
+#-----------------------------------------------------------------------
+# Pass and fail code (assumes test num is in TESTNUM)
+#-----------------------------------------------------------------------
+
+#define TEST_PASSFAIL \
+        bne x0, TESTNUM, pass; \
+fail: \
+        RVTEST_FAIL; \
+pass: \
+        RVTEST_PASS \
+
+
+#-----------------------------------------------------------------------
+# Test data section
+#-----------------------------------------------------------------------
+
+#define TEST_DATA
+
+#endif
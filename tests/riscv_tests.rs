@@ -0,0 +1,286 @@
+use std::{fs, os::unix::process::ExitStatusExt, path::Path, process::Command};
+
+// run one suite of tests/riscv-tests with --riscv-tests, and these options
+fn run_suite(suite: &str, options: &[&str]) {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/riscv-tests/");
+    let output = Command::new(env!("CARGO_BIN_EXE_rvemu-riir"))
+        .args(options)
+        .args(["--riscv-tests", &format!("{}{}", dir, suite)])
+        .output()
+        .unwrap();
+    let report = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{} failed:\n{}", suite, report);
+}
+
+// run one test of tests/riscv-tests as a program, returns what it printed
+fn run_test(test: &str, options: &[&str]) -> Vec<u8> {
+    let elf = format!("{}/tests/riscv-tests/{}", env!("CARGO_MANIFEST_DIR"), test);
+    let output = Command::new(env!("CARGO_BIN_EXE_rvemu-riir"))
+        .args(["--personality", "linux", "--htif"])
+        .args(options)
+        .args(["--", &elf])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{} failed:\n{}", test, stderr);
+    output.stdout
+}
+
+#[test]
+fn rv64ui() {
+    run_suite("rv64ui", &[]);
+}
+
+#[test]
+fn rv64um() {
+    run_suite("rv64um", &[]);
+}
+
+#[test]
+fn rv64ua() {
+    run_suite("rv64ua", &[]);
+}
+
+#[test]
+fn rv64uf() {
+    run_suite("rv64uf", &[]);
+}
+
+#[test]
+fn rv64ud() {
+    run_suite("rv64ud", &[]);
+}
+
+#[test]
+fn rv64uc() {
+    run_suite("rv64uc", &[]);
+}
+
+#[test]
+fn rv64ub() {
+    run_suite("rv64ub", &[]);
+}
+
+#[test]
+fn rv64uv() {
+    run_suite("rv64uv", &[]);
+}
+
+#[test]
+fn rv64uzfh() {
+    run_suite("rv64uzfh", &[]);
+}
+
+#[test]
+fn sigill() {
+    run_suite("sigill", &[]);
+}
+
+#[test]
+fn ebreak_sigtrap() {
+    run_suite("ebreak/sigtrap", &["--personality", "linux"]);
+}
+
+#[test]
+fn ebreak_skip() {
+    run_suite("ebreak/skip", &["--ebreak", "skip"]);
+}
+
+#[test]
+fn semihosting() {
+    let elf = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/riscv-tests/ebreak/semihosting"
+    );
+    let output = Command::new(env!("CARGO_BIN_EXE_rvemu-riir"))
+        .args(["--htif", "--ebreak", "semihosting", "--", elf])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // SYS_EXIT with status 7, before the test could pass
+    assert_eq!(output.status.code(), Some(7), "{}", stderr);
+    assert_eq!(output.stdout, b"hello, semihosting\n");
+}
+
+#[test]
+fn personality() {
+    run_suite("personality", &[]);
+}
+
+#[test]
+fn brk() {
+    run_suite("brk", &["--personality", "linux"]);
+}
+
+#[test]
+fn limits() {
+    let options = [
+        "--personality",
+        "linux",
+        "--max-heap",
+        "1M",
+        "--max-fds",
+        "16",
+        "--stack-size",
+        "1M",
+        "--max-mem",
+        "64M",
+    ];
+    run_suite("limits/limits", &options);
+}
+
+#[test]
+fn sparse_fds() {
+    let options = ["--personality", "linux", "--max-fds", "1000000000000"];
+    run_suite("limits/fds", &options);
+}
+
+#[test]
+fn max_insns() {
+    let elf = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/riscv-tests/limits/cpu");
+    let output = Command::new(env!("CARGO_BIN_EXE_rvemu-riir"))
+        .args(["--htif", "--max-insns", "100000", "--", elf])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.signal(), Some(libc::SIGXCPU));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("instruction limit of 100000 exceeded"));
+}
+
+#[test]
+fn exec() {
+    run_suite("exec", &["--personality", "linux"]);
+}
+
+#[test]
+fn isa_without_zb() {
+    run_suite("isa", &["--isa", "rv64gc"]);
+}
+
+#[test]
+fn vfs_mem() {
+    run_suite("vfs/mem", &["--personality", "linux", "--vfs", "mem"]);
+}
+
+#[test]
+fn vfs_big() {
+    let options = [
+        "--personality",
+        "linux",
+        "--vfs",
+        "mem",
+        "--max-mem",
+        "64M",
+        "--stack-size",
+        "1M",
+    ];
+    run_suite("vfs/big", &options);
+}
+
+#[test]
+fn vfs_efault() {
+    run_suite("vfs/efault", &["--personality", "linux"]);
+}
+
+#[test]
+fn vfs_overlay() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("overlay");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir(&dir).unwrap();
+    fs::write(dir.join("hello"), "hello\n").unwrap();
+    std::os::unix::fs::symlink("/etc/passwd", dir.join("escape")).unwrap();
+    std::os::unix::fs::symlink("..", dir.join("up")).unwrap();
+
+    let vfs = format!("overlay:{}", dir.display());
+    run_suite("vfs/overlay", &["--personality", "linux", "--vfs", &vfs]);
+
+    // the guest's writes stayed in memory
+    assert_eq!(fs::read_to_string(dir.join("hello")).unwrap(), "hello\n");
+    assert!(!dir.join("new").exists());
+    assert!(dir.join("escape").symlink_metadata().is_ok());
+}
+
+#[test]
+fn vfs_sysroot() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("sysroot");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("etc")).unwrap();
+    fs::create_dir_all(dir.join("sub")).unwrap();
+    fs::write(dir.join("etc/passwd"), "sysroot\n").unwrap();
+    fs::write(dir.join("sub/hello"), "hello\n").unwrap();
+
+    let sysroot = dir.to_str().unwrap();
+    run_suite("vfs/sysroot", &["--personality", "linux", "-L", sysroot]);
+}
+
+#[test]
+fn syscall() {
+    run_suite("syscall", &["--personality", "linux", "--vfs", "mem"]);
+}
+
+#[test]
+fn termios() {
+    let (mut master, mut slave) = (0, 0);
+    let ret = unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            std::ptr::null(),
+        )
+    };
+    assert_eq!(ret, 0);
+    assert_eq!(
+        unsafe { libc::write(master, b"abc\n".as_ptr().cast(), 4) },
+        4
+    );
+    // the line reaches the slave asynchronously
+    let mut avail: libc::c_int = 0;
+    while avail < 4 {
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        assert_eq!(unsafe { libc::ioctl(slave, libc::FIONREAD, &mut avail) }, 0);
+    }
+    let pts = fs::read_link(format!("/proc/self/fd/{}", slave)).unwrap();
+
+    // --stdin is the emulator's own, the runner does not pass it on
+    run_test("tty/termios", &["--stdin", pts.to_str().unwrap()]);
+
+    // the guest's settings, as libc sees them
+    let mut tio: libc::termios = unsafe { std::mem::zeroed() };
+    assert_eq!(unsafe { libc::tcgetattr(slave, &mut tio) }, 0);
+    assert_eq!(tio.c_lflag & libc::ECHO, 0);
+    assert_eq!(tio.c_cc[libc::VMIN], 5);
+    assert_eq!(tio.c_cc[libc::VTIME], 3);
+    let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+    assert_eq!(unsafe { libc::ioctl(master, libc::TIOCGWINSZ, &mut ws) }, 0);
+    assert_eq!((ws.ws_row, ws.ws_col), (24, 80));
+    unsafe {
+        libc::close(master);
+        libc::close(slave);
+    }
+}
+
+#[test]
+fn replay() {
+    let options = ["--virtual-clock", "--clock-epoch", "1000000", "--seed", "1"];
+    let first = run_test("replay/replay", &options);
+    assert_eq!(first, run_test("replay/replay", &options));
+
+    let words: Vec<u64> = first
+        .chunks(8)
+        .map(|w| u64::from_le_bytes(w.try_into().unwrap()))
+        .collect();
+    // two samples of realtime, monotonic, rdtime and rdcycle, then random bytes
+    assert_eq!(words.len(), 16);
+    assert_eq!(words[0], 1000000);
+    for i in 3..6 {
+        assert!(words[i + 6] > words[i]);
+    }
+
+    // only the random bytes follow the seed
+    let options = ["--virtual-clock", "--clock-epoch", "1000000", "--seed", "2"];
+    let other = run_test("replay/replay", &options);
+    assert_eq!(first[..96], other[..96]);
+    assert_ne!(first[96..], other[96..]);
+}